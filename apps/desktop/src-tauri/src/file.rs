@@ -31,7 +31,10 @@ pub async fn open_file_path(
 					.map(|(id, maybe_path)| {
 						if let Some(path) = maybe_path {
 							opener::open(path)
-								.map(|_| OpenFilePathResult::AllGood(id))
+								.map(|_| {
+									library.validate_on_access(id);
+									OpenFilePathResult::AllGood(id)
+								})
 								.unwrap_or_else(|e| {
 									OpenFilePathResult::OpenError(id, e.to_string())
 								})
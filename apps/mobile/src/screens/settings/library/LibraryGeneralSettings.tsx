@@ -33,7 +33,14 @@ const LibraryGeneralSettingsScreen = ({
 	const { mutate: editLibrary } = useBridgeMutation('library.edit');
 
 	useAutoForm(form, (value) => {
-		editLibrary({ description: value.description, name: value.name, id: library.uuid });
+		editLibrary({
+			description: value.description,
+			name: value.name,
+			id: library.uuid,
+			default_checksum_algorithm: null,
+			validate_on_access: null,
+			checksum_on_change: null
+		});
 		// console.log('Updated', value);
 		// TODO: Show toast
 	});
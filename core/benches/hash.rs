@@ -0,0 +1,114 @@
+//! Throughput of the object validator's checksums, to compare changes to the hashing path.
+//! Run with `cargo bench -p sd-core --bench hash`, criterion reports the MiB/s of each
+//! algorithm, read length and file size.
+
+use std::{
+	fs::File,
+	io::{BufWriter, Write},
+	path::{Path, PathBuf},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use sd_core::hash::{file_checksum_with, ChecksumAlgorithm, ReadOptions};
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+const KB: u64 = 1024;
+const MB: u64 = 1024 * KB;
+
+const ALGORITHMS: [ChecksumAlgorithm; 2] = [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256];
+
+/// Read lengths, `None` being the default one
+const BLOCK_LENS: [Option<usize>; 3] = [Some(64 * KB as usize), None, Some(8 * MB as usize)];
+
+/// Files the checksums are computed of, written once for all the benchmarks
+struct Fixture {
+	name: &'static str,
+	path: PathBuf,
+	len: u64,
+}
+
+impl Fixture {
+	/// Bytes that don't compress, so file systems compressing data can't make reads cheaper
+	fn dense(dir: &Path, name: &'static str, len: u64) -> Self {
+		let path = dir.join(name);
+		let mut writer = BufWriter::new(File::create(&path).expect("failed to create fixture"));
+
+		let mut state = 0x2545_f491_4f6c_dd1d_u64;
+		for _ in 0..len / 8 {
+			// xorshift
+			state ^= state << 13;
+			state ^= state >> 7;
+			state ^= state << 17;
+			writer
+				.write_all(&state.to_le_bytes())
+				.expect("failed to write fixture");
+		}
+		writer
+			.write_all(&vec![0; (len % 8) as usize])
+			.expect("failed to write fixture");
+		writer.flush().expect("failed to write fixture");
+
+		Self { name, path, len }
+	}
+
+	/// A single hole, where file systems supporting them hand zeros over without reading
+	/// the device
+	fn sparse(dir: &Path, name: &'static str, len: u64) -> Self {
+		let path = dir.join(name);
+		File::create(&path)
+			.and_then(|file| file.set_len(len))
+			.expect("failed to create fixture");
+
+		Self { name, path, len }
+	}
+}
+
+fn checksums(c: &mut Criterion) {
+	let dir = TempDir::new().expect("failed to create fixtures directory");
+	let fixtures = [
+		Fixture::dense(dir.path(), "1KB", KB),
+		Fixture::dense(dir.path(), "1MB", MB),
+		Fixture::dense(dir.path(), "100MB", 100 * MB),
+		Fixture::sparse(dir.path(), "100MB-sparse", 100 * MB),
+	];
+	let runtime = Runtime::new().expect("failed to start the runtime");
+
+	for algorithm in ALGORITHMS {
+		let mut group = c.benchmark_group(format!("file_checksum/{}", algorithm.as_str()));
+
+		for fixture in &fixtures {
+			group.throughput(Throughput::Bytes(fixture.len));
+			// big files take long enough to measure in fewer runs
+			group.sample_size(if fixture.len >= 100 * MB { 10 } else { 100 });
+
+			for block_len in BLOCK_LENS {
+				let options = ReadOptions {
+					algorithm,
+					block_len,
+					..Default::default()
+				};
+
+				group.bench_with_input(
+					BenchmarkId::new(
+						block_len.map_or("default".to_string(), |len| format!("{}KB", len / 1024)),
+						fixture.name,
+					),
+					&fixture.path,
+					|b, path| {
+						b.to_async(&runtime).iter(|| async {
+							file_checksum_with(path, options)
+								.await
+								.expect("failed to checksum fixture")
+						})
+					},
+				);
+			}
+		}
+
+		group.finish();
+	}
+}
+
+criterion_group!(benches, checksums);
+criterion_main!(benches);
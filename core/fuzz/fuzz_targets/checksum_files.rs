@@ -0,0 +1,57 @@
+//! Parses arbitrary bytes as each kind of checksum file we read, which come from other tools or
+//! authorities and can hold anything: signed manifests, their chunk manifests, and the listings of
+//! git-annex and rclone. Malformed ones must fail to parse, never panic.
+//!
+//! Run with `cargo +nightly fuzz run checksum_files corpus/checksum_files seeds/checksum_files`
+//! from `core/fuzz`, the seeds being examples of each format.
+
+#![no_main]
+
+use std::path::{Path, PathBuf};
+
+use libfuzzer_sys::fuzz_target;
+use sd_core::{
+	hash::{is_valid_checksum, ChecksumAlgorithm},
+	ExternalChecksumSource, SignedManifest,
+};
+
+fuzz_target!(|data: &[u8]| {
+	let content = String::from_utf8_lossy(data);
+
+	for algorithm in [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256] {
+		let manifest = SignedManifest {
+			path: PathBuf::from("manifest.txt"),
+			signature_path: PathBuf::from("manifest.txt.sig"),
+			public_key: String::new(),
+			algorithm,
+			chunks_path: None,
+		};
+
+		if let Ok(checksums) = manifest.parse(data) {
+			for (relative_path, checksum) in checksums {
+				assert!(!relative_path.starts_with('/'), "{relative_path}");
+				assert!(is_valid_checksum(&checksum, algorithm), "{checksum}");
+			}
+		}
+
+		if let Ok(chunks) = manifest.parse_chunks(Path::new("chunks.txt"), &content) {
+			for file_chunks in chunks.values() {
+				assert!(file_chunks
+					.windows(2)
+					.all(|pair| pair[0].offset <= pair[1].offset));
+			}
+		}
+	}
+
+	for source in [
+		ExternalChecksumSource::GitAnnex {
+			path: PathBuf::from("annex.txt"),
+		},
+		ExternalChecksumSource::Rclone {
+			path: PathBuf::from("rclone.txt"),
+			hash: "sha256".to_string(),
+		},
+	] {
+		source.parse(&content).ok();
+	}
+});
@@ -0,0 +1,24 @@
+//! Decodes arbitrary strings as `algorithm:checksum` ones, which are synced from other devices so
+//! they can hold anything. Whatever decodes must encode back to the same string, and encoding
+//! anything must decode back to it.
+//!
+//! Run with `cargo +nightly fuzz run decode_checksum corpus/decode_checksum seeds/decode_checksum`
+//! from `core/fuzz`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sd_core::hash::{decode_checksum, encode_checksum, ChecksumAlgorithm};
+
+fuzz_target!(|encoded: &str| {
+	if let Some((algorithm, checksum)) = decode_checksum(encoded) {
+		assert_eq!(encode_checksum(algorithm, checksum), encoded);
+	}
+
+	for algorithm in [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256] {
+		assert_eq!(
+			decode_checksum(&encode_checksum(algorithm, encoded)),
+			Some((algorithm, encoded))
+		);
+	}
+});
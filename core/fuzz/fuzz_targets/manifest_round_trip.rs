@@ -0,0 +1,50 @@
+//! Writes arbitrary checksums and paths as a `sha256sum` style manifest and checks that parsing
+//! it gives them back. Paths are restricted to the ones manifests can hold as they are, without
+//! line breaks nor a leading `./` or `/`, which are stripped.
+//!
+//! Run with `cargo +nightly fuzz run manifest_round_trip` from `core/fuzz`. Inputs are structured,
+//! so there are no seeds.
+
+#![no_main]
+
+use std::{collections::HashMap, path::PathBuf};
+
+use libfuzzer_sys::fuzz_target;
+use sd_core::{hash::ChecksumAlgorithm, SignedManifest};
+
+fuzz_target!(|entries: Vec<([u8; 32], String)>| {
+	let entries = entries
+		.into_iter()
+		.filter(|(_, relative_path)| {
+			!relative_path.is_empty()
+				&& !relative_path.starts_with("./")
+				&& !relative_path.starts_with('/')
+				&& !relative_path.contains(|c: char| c == '\n' || c == '\r')
+		})
+		.map(|(digest, relative_path)| (relative_path, hex(&digest)))
+		.collect::<Vec<_>>();
+
+	let content = entries
+		.iter()
+		.map(|(relative_path, checksum)| format!("{checksum}  {relative_path}\n"))
+		.collect::<String>();
+	// a path listed twice has its last checksum, as when parsing
+	let expected = entries.into_iter().collect::<HashMap<_, _>>();
+
+	// both digests are 32 bytes long
+	for algorithm in [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256] {
+		let manifest = SignedManifest {
+			path: PathBuf::from("manifest.txt"),
+			signature_path: PathBuf::from("manifest.txt.sig"),
+			public_key: String::new(),
+			algorithm,
+			chunks_path: None,
+		};
+
+		assert_eq!(manifest.parse(content.as_bytes()).unwrap(), expected);
+	}
+});
+
+fn hex(digest: &[u8]) -> String {
+	digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
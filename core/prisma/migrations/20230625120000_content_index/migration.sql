@@ -0,0 +1,9 @@
+-- CreateTable
+CREATE TABLE "content_index" (
+    "file_path_id" INTEGER NOT NULL PRIMARY KEY,
+    "checksum" TEXT NOT NULL,
+    CONSTRAINT "content_index_file_path_id_fkey" FOREIGN KEY ("file_path_id") REFERENCES "file_path" ("id") ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+-- CreateIndex
+CREATE INDEX "content_index_checksum_idx" ON "content_index"("checksum");
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "integrity_checksum_source" TEXT;
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "date_checksummed" DATETIME;
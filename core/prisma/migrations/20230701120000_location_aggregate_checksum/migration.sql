@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "location" ADD COLUMN "aggregate_checksum" TEXT;
+ALTER TABLE "location" ADD COLUMN "aggregate_checksum_algorithm" TEXT;
@@ -0,0 +1,4 @@
+-- AlterTable
+ALTER TABLE "file_path" ADD COLUMN "acknowledged_checksum" TEXT;
+ALTER TABLE "file_path" ADD COLUMN "acknowledged_by" TEXT;
+ALTER TABLE "file_path" ADD COLUMN "date_acknowledged" DATETIME;
@@ -8,20 +8,28 @@ use crate::{
 		},
 		find_location, LocationError,
 	},
-	object::fs::{
-		copy::FileCopierJobInit, cut::FileCutterJobInit, delete::FileDeleterJobInit,
-		erase::FileEraserJobInit,
+	object::{
+		fs::{
+			copy::FileCopierJobInit, cut::FileCutterJobInit, delete::FileDeleterJobInit,
+			erase::FileEraserJobInit,
+		},
+		validation::{
+			acknowledge_mismatch, audit_stored_checksums, checksum_statuses,
+			hash::{checksum_stream, ChecksumAlgorithm, HashEvent, ReadOptions},
+			list_unvalidated, verify_tar_archive, ChecksumProblemCounts, MalformedChecksum,
+			UnvalidatedPage, ValidatorError,
+		},
 	},
 	prisma::{file_path, location, object},
 };
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use chrono::Utc;
-use futures::future::join_all;
+use futures::{future::join_all, StreamExt};
 use regex::Regex;
 use rspc::{alpha::AlphaRouter, ErrorCode};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use specta::Type;
 use tokio::fs;
 use tracing::error;
@@ -46,6 +54,142 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						.await?)
 				})
 		})
+		.procedure("getChecksumStatus", {
+			R.with2(library()).query(
+				|(_, library), file_path_ids: Vec<file_path::id::Type>| async move {
+					Ok(checksum_statuses(&library.db, file_path_ids).await?)
+				},
+			)
+		})
+		.procedure("auditStoredChecksums", {
+			#[derive(Type, Serialize)]
+			pub struct StoredChecksumsAudit {
+				pub malformed: Vec<MalformedChecksum>,
+				pub counts: ChecksumProblemCounts,
+			}
+
+			R.with2(library())
+				.query(|(_, library), location_id: location::id::Type| async move {
+					let malformed = audit_stored_checksums(&library, location_id).await?;
+
+					Ok(StoredChecksumsAudit {
+						counts: ChecksumProblemCounts::new(&malformed),
+						malformed,
+					})
+				})
+		})
+		.procedure("listUnvalidated", {
+			#[derive(Type, Deserialize)]
+			pub struct ListUnvalidatedArgs {
+				pub location_id: location::id::Type,
+				pub sub_path: Option<PathBuf>,
+				pub page: UnvalidatedPage,
+			}
+
+			#[derive(Type, Serialize)]
+			pub struct UnvalidatedFiles {
+				pub file_paths: Vec<file_path::Data>,
+				pub total: u32,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: ListUnvalidatedArgs| async move {
+					let location = find_location(&library, args.location_id)
+						.exec()
+						.await?
+						.ok_or(LocationError::IdNotFound(args.location_id))?;
+
+					let (file_paths, total) =
+						list_unvalidated(&library, &location, args.sub_path.as_deref(), args.page)
+							.await?;
+
+					Ok(UnvalidatedFiles { file_paths, total })
+				})
+		})
+		.procedure("verifyArchive", {
+			#[derive(Type, Deserialize)]
+			pub struct VerifyArchiveArgs {
+				pub location_id: location::id::Type,
+				pub archive_path: PathBuf,
+				/// directory of the archive holding the location's files, its root by default
+				pub root: Option<String>,
+			}
+
+			R.with2(library())
+				.query(|(_, library), args: VerifyArchiveArgs| async move {
+					verify_tar_archive(
+						&library,
+						args.location_id,
+						&args.archive_path,
+						args.root.as_deref(),
+					)
+					.await
+					.map_err(Into::into)
+				})
+		})
+		.procedure("streamChecksum", {
+			/// Progress of checksumming a single file, bytes are `f64` as rspc doesn't support
+			/// bigints, which is exact for files of up to 8 PiB
+			#[derive(Type, Serialize)]
+			#[serde(tag = "type")]
+			pub enum ChecksumStreamEvent {
+				Progress {
+					bytes_done: f64,
+					total: f64,
+				},
+				Done {
+					checksum: String,
+					algorithm: ChecksumAlgorithm,
+				},
+				Failed {
+					reason: String,
+				},
+			}
+
+			// the file is read as the client takes the events and unsubscribing stops the read,
+			// see `checksum_stream`
+			R.with2(library()).subscription(
+				|(_, library), file_path_id: file_path::id::Type| async move {
+					let path = library
+						.get_file_paths(vec![file_path_id])
+						.await?
+						.remove(&file_path_id)
+						.flatten()
+						.ok_or(ValidatorError::FilePathNotFound(file_path_id))?;
+					let algorithm = library.config.default_checksum_algorithm;
+
+					Ok(async_stream::stream! {
+						let mut events = Box::pin(checksum_stream(
+							&path,
+							ReadOptions {
+								algorithm,
+								..Default::default()
+							},
+						));
+
+						while let Some(event) = events.next().await {
+							match event {
+								Ok(HashEvent::Progress(progress)) => {
+									yield ChecksumStreamEvent::Progress {
+										bytes_done: progress.bytes_done as f64,
+										total: progress.total as f64,
+									};
+								}
+								Ok(HashEvent::Done(checksum)) => {
+									yield ChecksumStreamEvent::Done { checksum, algorithm };
+								}
+								Err(e) => {
+									error!("Failed to checksum {}: {e}", path.display());
+									yield ChecksumStreamEvent::Failed {
+										reason: e.to_string(),
+									};
+								}
+							}
+						}
+					})
+				},
+			)
+		})
 		.procedure("setNote", {
 			#[derive(Type, Deserialize)]
 			pub struct SetNoteArgs {
@@ -71,6 +215,49 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					Ok(())
 				})
 		})
+		.procedure("acknowledgeChecksumMismatch", {
+			#[derive(Type, Deserialize)]
+			pub struct AcknowledgeChecksumMismatchArgs {
+				pub file_path_id: file_path::id::Type,
+				/// the checksum of the file the user was shown
+				pub checksum: String,
+			}
+
+			R.with2(library()).mutation(
+				|(_, library), args: AcknowledgeChecksumMismatchArgs| async move {
+					acknowledge_mismatch(&library, args.file_path_id, &args.checksum).await?;
+
+					invalidate_query!(library, "search.paths");
+
+					Ok(())
+				},
+			)
+		})
+		.procedure("setExcludedFromValidation", {
+			#[derive(Type, Deserialize)]
+			pub struct SetExcludedFromValidationArgs {
+				pub file_path_ids: Vec<file_path::id::Type>,
+				pub excluded: bool,
+			}
+
+			R.with2(library()).mutation(
+				|(_, library), args: SetExcludedFromValidationArgs| async move {
+					library
+						.db
+						.file_path()
+						.update_many(
+							vec![file_path::id::in_vec(args.file_path_ids)],
+							vec![file_path::exclude_from_validation::set(Some(args.excluded))],
+						)
+						.exec()
+						.await?;
+
+					invalidate_query!(library, "search.paths");
+
+					Ok(())
+				},
+			)
+		})
 		.procedure("setFavorite", {
 			#[derive(Type, Deserialize)]
 			pub struct SetFavoriteArgs {
@@ -1,6 +1,7 @@
 use std::{
 	collections::{hash_map::Entry, HashMap},
 	path::PathBuf,
+	sync::Arc,
 };
 
 use crate::{
@@ -9,17 +10,22 @@ use crate::{
 	location::{find_location, LocationError},
 	object::{
 		file_identifier::file_identifier_job::FileIdentifierJobInit,
-		preview::thumbnailer_job::ThumbnailerJobInit,
-		validation::validator_job::ObjectValidatorJobInit,
+		preview::{
+			thumbnail_validator_job::ThumbnailValidatorJobInit, thumbnailer_job::ThumbnailerJobInit,
+		},
+		validation::{
+			coverage_job::ChecksumCoverageJobInit, validator_job::ObjectValidatorJobInit,
+			S3RemoteChecksums, SignedManifest,
+		},
 	},
 	prisma::{job, location, SortOrder},
 };
 
 use chrono::{DateTime, Utc};
-use rspc::alpha::AlphaRouter;
+use rspc::{alpha::AlphaRouter, ErrorCode};
 use serde::{Deserialize, Serialize};
 use specta::Type;
-
+use url::Url;
 use uuid::Uuid;
 
 use super::{utils::library, CoreEvent, Ctx, R};
@@ -200,6 +206,13 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 					JobManager::resume(&ctx.jobs, id).await.map_err(Into::into)
 				})
 		})
+		.procedure("setLowPower", {
+			R.with2(library())
+				.mutation(|(_, library), low_power: bool| async move {
+					library.background_policy.set_low_power(low_power);
+					Ok(())
+				})
+		})
 		.procedure("generateThumbsForLocation", {
 			#[derive(Type, Deserialize)]
 			pub struct GenerateThumbsForLocationArgs {
@@ -228,6 +241,19 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 			pub struct ObjectValidatorArgs {
 				pub id: location::id::Type,
 				pub path: PathBuf,
+				/// only validate the files of favorite objects
+				#[serde(default)]
+				pub favorites_only: bool,
+				/// only validate the files with these pub ids, like the selected ones
+				#[serde(default)]
+				pub file_path_pub_ids: Option<Vec<Vec<u8>>>,
+				/// audit the files against this manifest
+				#[serde(default)]
+				pub verify_against: Option<SignedManifest>,
+				/// the bucket the location is mounted from, to audit with the checksums S3 keeps
+				/// instead of reading the files
+				#[serde(default)]
+				pub remote_checksums_url: Option<String>,
 			}
 
 			R.with2(library())
@@ -239,11 +265,45 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 						return Err(LocationError::IdNotFound(args.id).into());
 					};
 
+					let mut builder = ObjectValidatorJobInit::builder(location)
+						.sub_path(args.path)
+						.favorites_only(args.favorites_only);
+					if let Some(file_path_pub_ids) = args.file_path_pub_ids {
+						builder = builder.file_path_pub_ids(file_path_pub_ids);
+					}
+					if let Some(manifest) = args.verify_against {
+						builder = builder.verify_against(manifest);
+					}
+					if let Some(url) = args.remote_checksums_url {
+						let url = Url::parse(&url).map_err(|e| {
+							rspc::Error::new(
+								ErrorCode::BadRequest,
+								format!("Invalid remote checksums url: {e}"),
+							)
+						})?;
+						builder = builder.remote_checksums(Arc::new(S3RemoteChecksums::new(url)));
+					}
+
 					library
-						.spawn_job(ObjectValidatorJobInit {
-							location,
-							sub_path: Some(args.path),
-						})
+						.spawn_job(builder.build())
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("checksumCoverage", {
+			R.with2(library())
+				.mutation(|(_, library), _: ()| async move {
+					library
+						.spawn_job(ChecksumCoverageJobInit {})
+						.await
+						.map_err(Into::into)
+				})
+		})
+		.procedure("validateThumbnails", {
+			R.with2(library())
+				.mutation(|(_, library), _: ()| async move {
+					library
+						.spawn_job(ThumbnailValidatorJobInit {})
 						.await
 						.map_err(Into::into)
 				})
@@ -1,5 +1,6 @@
 use crate::{
 	library::LibraryConfig,
+	object::validation::hash::ChecksumAlgorithm,
 	prisma::statistics,
 	util::MaybeUndefined,
 	volume::{get_volumes, save_volume},
@@ -113,12 +114,22 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				pub id: Uuid,
 				pub name: Option<String>,
 				pub description: MaybeUndefined<String>,
+				pub default_checksum_algorithm: Option<ChecksumAlgorithm>,
+				pub validate_on_access: Option<bool>,
+				pub checksum_on_change: Option<bool>,
 			}
 
 			R.mutation(|ctx, args: EditLibraryArgs| async move {
 				Ok(ctx
 					.library_manager
-					.edit(args.id, args.name, args.description)
+					.edit(
+						args.id,
+						args.name,
+						args.description,
+						args.default_checksum_algorithm,
+						args.validate_on_access,
+						args.checksum_on_change,
+					)
 					.await?)
 			})
 		})
@@ -97,8 +97,9 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		.procedure("create", {
 			R.with2(library())
 				.mutation(|(_, library), args: LocationCreateArgs| async move {
+					let validation = args.validate_after_indexing;
 					if let Some(location) = args.create(&library).await? {
-						scan_location(&library, location).await?;
+						scan_location(&library, location, validation).await?;
 						invalidate_query!(library, "locations.list");
 					}
 
@@ -133,8 +134,9 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 		.procedure("addLibrary", {
 			R.with2(library())
 				.mutation(|(_, library), args: LocationCreateArgs| async move {
+					let validation = args.validate_after_indexing;
 					if let Some(location) = args.add_library(&library).await? {
-						scan_location(&library, location).await?;
+						scan_location(&library, location, validation).await?;
 						invalidate_query!(library, "locations.list");
 					}
 					Ok(())
@@ -151,6 +153,7 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 							.exec()
 							.await?
 							.ok_or(LocationError::IdNotFound(location_id))?,
+						None,
 					)
 					.await
 					.map_err(Into::into)
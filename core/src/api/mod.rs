@@ -1,4 +1,9 @@
-use crate::{job::JobProgressEvent, node::SanitisedNodeConfig, Node};
+use crate::{
+	job::JobProgressEvent,
+	node::SanitisedNodeConfig,
+	object::validation::{FileCorruptedEvent, ValidationCompletedEvent},
+	Node,
+};
 use rspc::{alpha::Rspc, Config};
 use serde::{Deserialize, Serialize};
 use specta::Type;
@@ -17,6 +22,8 @@ pub type Router = rspc::Router<Ctx>;
 pub enum CoreEvent {
 	NewThumbnail { thumb_key: Vec<String> },
 	JobProgress(JobProgressEvent),
+	ValidationCompleted(ValidationCompletedEvent),
+	FileCorrupted(FileCorruptedEvent),
 	InvalidateOperation(InvalidateOperationEvent),
 }
 
@@ -5,7 +5,7 @@ use specta::Type;
 use std::path::PathBuf;
 use uuid::Uuid;
 
-use crate::p2p::P2PEvent;
+use crate::{object::validation::compare_checksums_with_peer, p2p::P2PEvent, prisma::location};
 
 use super::{utils::library, Ctx, R};
 
@@ -74,6 +74,24 @@ pub(crate) fn mount() -> AlphaRouter<Ctx> {
 				})
 			})
 		})
+		.procedure("compareChecksums", {
+			#[derive(Type, Deserialize)]
+			pub struct CompareChecksumsArgs {
+				peer_id: PeerId,
+				location_id: location::id::Type,
+			}
+
+			R.with2(library())
+				.query(|(ctx, library), args: CompareChecksumsArgs| async move {
+					Ok(compare_checksums_with_peer(
+						&library,
+						&ctx.p2p,
+						args.peer_id,
+						args.location_id,
+					)
+					.await?)
+				})
+		})
 		.procedure("pair", {
 			R.with2(library())
 				.mutation(|(ctx, lib), id: PeerId| async move { ctx.p2p.pair(id, lib) })
@@ -8,7 +8,7 @@ use crate::{
 		file_path_helper::{check_file_path_exists, IsolatedFilePathData},
 		find_location, LocationError,
 	},
-	object::preview::get_thumb_key,
+	object::{preview::get_thumb_key, validation::VerificationResult},
 	prisma::{self, file_path, location, object, tag, tag_on_object},
 	util::db::chain_optional_iter,
 };
@@ -139,12 +139,14 @@ struct FilePathSearchArgs {
 #[serde(rename_all = "camelCase")]
 enum ObjectSearchOrdering {
 	DateAccessed(SortOrder),
+	LastVerifiedAt(SortOrder),
 }
 
 impl ObjectSearchOrdering {
 	fn get_sort_order(&self) -> prisma::SortOrder {
 		(*match self {
 			Self::DateAccessed(v) => v,
+			Self::LastVerifiedAt(v) => v,
 		})
 		.into()
 	}
@@ -154,6 +156,7 @@ impl ObjectSearchOrdering {
 		use object::*;
 		match self {
 			Self::DateAccessed(_) => date_accessed::order(dir),
+			Self::LastVerifiedAt(_) => last_verified_at::order(dir),
 		}
 	}
 }
@@ -193,6 +196,8 @@ struct ObjectFilterArgs {
 	tags: Vec<i32>,
 	#[specta(optional)]
 	category: Option<Category>,
+	#[specta(optional)]
+	last_verified_result: Option<VerificationResult>,
 }
 
 impl ObjectFilterArgs {
@@ -214,6 +219,8 @@ impl ObjectFilterArgs {
 					tags::some(vec![tags_on_object])
 				}),
 				self.category.map(Category::to_where_param),
+				self.last_verified_result
+					.map(|result| last_verified_result::equals(Some(result as i32))),
 			],
 		)
 	}
@@ -196,6 +196,9 @@ async fn handle_file(
 			let location = maybe_missing(&file_path.location, "file_path.location")?;
 			let path = maybe_missing(&location.path, "file_path.location.path")?;
 
+			// files only miss the cache the first time they're previewed, or long after
+			library.validate_on_access(file_path_id);
+
 			let lru_entry = (
 				Path::new(path).join(IsolatedFilePathData::try_from((location_id, &file_path))?),
 				maybe_missing(file_path.extension, "extension")?,
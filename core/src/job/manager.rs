@@ -7,8 +7,10 @@ use crate::{
 		fs::{
 			copy::FileCopierJob, cut::FileCutterJob, delete::FileDeleterJob, erase::FileEraserJob,
 		},
-		preview::thumbnailer_job::ThumbnailerJob,
-		validation::validator_job::ObjectValidatorJob,
+		preview::{
+			thumbnail_validator_job::ThumbnailValidatorJob, thumbnailer_job::ThumbnailerJob,
+		},
+		validation::{coverage_job::ChecksumCoverageJob, validator_job::ObjectValidatorJob},
 	},
 	prisma::job,
 };
@@ -329,6 +331,8 @@ fn initialize_resumable_job(
 			IndexerJob,
 			FileIdentifierJob,
 			ObjectValidatorJob,
+			ChecksumCoverageJob,
+			ThumbnailValidatorJob,
 			FileCutterJob,
 			FileCopierJob,
 			FileDeleterJob,
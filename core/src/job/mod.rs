@@ -10,22 +10,29 @@ use std::{
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use tokio::sync::mpsc::UnboundedReceiver;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
 mod error;
 mod manager;
+mod progress;
 mod report;
 mod worker;
 
 pub use error::*;
 pub use manager::*;
+pub use progress::*;
 pub use report::*;
 pub use worker::*;
 
 pub type JobResult = Result<JobMetadata, JobError>;
 pub type JobMetadata = Option<serde_json::Value>;
 pub type JobRunErrors = Vec<String>;
+
+/// How often a waiting job checks if it can run its next step
+const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 /// `JobInitData` is a trait to represent the data being passed to initialize a `Job`
 pub trait JobInitData: Serialize + DeserializeOwned + Send + Sync + Hash {
 	type Job: StatefulJob;
@@ -66,6 +73,12 @@ pub trait StatefulJob: Send + Sync + Sized {
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError>;
 
+	/// Why the job can't run its next step right now, checked between steps. The job waits,
+	/// keeping its state in memory and listening for commands, until this returns `None`.
+	async fn wait_reason(&self, _ctx: &WorkerContext, _state: &JobState<Self>) -> Option<String> {
+		None
+	}
+
 	/// is called after all steps have been executed
 	async fn finalize(&mut self, ctx: &mut WorkerContext, state: &mut JobState<Self>) -> JobResult;
 }
@@ -190,14 +203,33 @@ where
 		stateful_job: SJob, // whichever type of job this should be is passed here
 		next_jobs: Option<VecDeque<Box<dyn DynJob>>>,
 	) -> Result<Box<dyn DynJob>, JobError> {
+		let data = report
+			.data
+			.take()
+			.ok_or_else(|| JobError::MissingJobDataState(report.id, report.name.clone()))?;
+
+		let state = match rmp_serde::from_slice(&data) {
+			Ok(state) => state,
+			Err(e) => {
+				// The state was probably serialized by an older version of this job, if we can still
+				// read its init data we restart it from scratch instead of losing it
+				let JobStateInit { init } = rmp_serde::from_slice(&data).map_err(|_| e)?;
+				warn!(
+					"Restarting job <id='{}', name='{}'> as its state couldn't be resumed",
+					report.id, report.name
+				);
+				JobState {
+					init,
+					data: None,
+					steps: VecDeque::new(),
+					step_number: 0,
+				}
+			}
+		};
+
 		Ok(Box::new(Self {
 			id: report.id,
-			state: rmp_serde::from_slice(
-				&report
-					.data
-					.take()
-					.ok_or_else(|| JobError::MissingJobDataState(report.id, report.name.clone()))?,
-			)?,
+			state,
 			report: Some(report),
 			stateful_job,
 			next_jobs: next_jobs.unwrap_or_default(),
@@ -234,6 +266,41 @@ pub struct JobState<Job: StatefulJob> {
 	pub step_number: usize,
 }
 
+/// Only the init data of a serialized [`JobState`], used to restart jobs that can't be resumed
+#[derive(Deserialize)]
+struct JobStateInit<Init> {
+	init: Init,
+}
+
+impl<SJob: StatefulJob> Job<SJob> {
+	/// Stops the job with its state, to be persisted, if it was told to shut down or cancel
+	fn check_command(
+		&self,
+		command_rx: &mut UnboundedReceiver<WorkerCommand>,
+	) -> Result<(), JobError> {
+		match command_rx.try_recv() {
+			Ok(WorkerCommand::Shutdown) => {
+				Err(JobError::Paused(rmp_serde::to_vec_named(&self.state)?))
+			}
+			Ok(WorkerCommand::Cancel) => {
+				Err(JobError::Canceled(rmp_serde::to_vec_named(&self.state)?))
+			}
+			Err(_) => Ok(()),
+		}
+	}
+
+	/// Why the job can't run its next step right now: background jobs back off while the
+	/// library's policy asks them to, and any job can be missing something to run it, see
+	/// [`StatefulJob::wait_reason`]
+	async fn wait_reason(&self, ctx: &WorkerContext) -> Option<String> {
+		if SJob::IS_BACKGROUND && ctx.should_yield() {
+			return Some("Waiting for the low power window to end".to_string());
+		}
+
+		self.stateful_job.wait_reason(ctx, &self.state).await
+	}
+}
+
 #[async_trait::async_trait]
 impl<SJob: StatefulJob> DynJob for Job<SJob> {
 	fn id(&self) -> Uuid {
@@ -293,15 +360,22 @@ impl<SJob: StatefulJob> DynJob for Job<SJob> {
 		// Run the job until it's done or we get a command
 		while job_should_run && !self.state.steps.is_empty() {
 			// Check for commands every iteration
-			if let Ok(command) = command_rx.try_recv() {
-				match command {
-					WorkerCommand::Shutdown => {
-						return Err(JobError::Paused(rmp_serde::to_vec_named(&self.state)?));
-					}
-					WorkerCommand::Cancel => {
-						return Err(JobError::Canceled(rmp_serde::to_vec_named(&self.state)?));
-					}
+			self.check_command(&mut command_rx)?;
+
+			// The job waits while it can't run its next step, keeping its state in memory and
+			// listening for commands, so a shutdown still persists it
+			let mut shown_reason = None;
+			while let Some(reason) = self.wait_reason(ctx).await {
+				if shown_reason.as_ref() != Some(&reason) {
+					ctx.progress(vec![JobReportUpdate::Message(reason.clone())]);
+					shown_reason = Some(reason);
 				}
+
+				self.check_command(&mut command_rx)?;
+				tokio::time::sleep(WAIT_POLL_INTERVAL).await;
+			}
+			if shown_reason.is_some() {
+				ctx.progress(vec![JobReportUpdate::Message(String::new())]);
 			}
 
 			let mut state_preserved = false;
@@ -0,0 +1,203 @@
+use crate::job::{JobReportUpdate, WorkerEvent};
+
+use std::{
+	sync::{Arc, Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Coalesces the progress updates of a job, which may come from concurrent tasks, into at most one
+/// event every `interval` so high throughput jobs don't flood the UI. Only the latest update of
+/// each kind is kept, and whatever is pending is always sent on [`ProgressAggregator::flush`]
+/// or once the last clone is dropped, so the final state isn't lost.
+#[derive(Clone)]
+pub struct ProgressAggregator(Arc<Shared>);
+
+struct Shared {
+	events_tx: UnboundedSender<WorkerEvent>,
+	interval: Duration,
+	pending: Mutex<Pending>,
+}
+
+#[derive(Default)]
+struct Pending {
+	task_count: Option<usize>,
+	completed_task_count: Option<usize>,
+	byte_progress: Option<(u64, u64)>,
+	message: Option<String>,
+	last_sent: Option<Instant>,
+}
+
+impl Pending {
+	fn record(&mut self, update: JobReportUpdate) {
+		match update {
+			JobReportUpdate::TaskCount(task_count) => self.task_count = Some(task_count),
+			JobReportUpdate::CompletedTaskCount(completed_task_count) => {
+				self.completed_task_count = Some(completed_task_count)
+			}
+			JobReportUpdate::ByteProgress { completed, total } => {
+				self.byte_progress = Some((completed, total))
+			}
+			JobReportUpdate::Message(message) => self.message = Some(message),
+		}
+	}
+
+	fn take(&mut self) -> Vec<JobReportUpdate> {
+		[
+			self.task_count.take().map(JobReportUpdate::TaskCount),
+			self.completed_task_count
+				.take()
+				.map(JobReportUpdate::CompletedTaskCount),
+			self.byte_progress
+				.take()
+				.map(|(completed, total)| JobReportUpdate::ByteProgress { completed, total }),
+			self.message.take().map(JobReportUpdate::Message),
+		]
+		.into_iter()
+		.flatten()
+		.collect()
+	}
+}
+
+impl ProgressAggregator {
+	pub const DEFAULT_INTERVAL: Duration = Duration::from_millis(100);
+
+	pub(super) fn new(events_tx: UnboundedSender<WorkerEvent>, interval: Duration) -> Self {
+		Self(Arc::new(Shared {
+			events_tx,
+			interval,
+			pending: Mutex::new(Pending::default()),
+		}))
+	}
+
+	/// Records `updates`, sending them along with the pending ones if the last event was sent
+	/// at least `interval` ago
+	pub fn progress(&self, updates: Vec<JobReportUpdate>) {
+		self.progress_at(updates, Instant::now());
+	}
+
+	fn progress_at(&self, updates: Vec<JobReportUpdate>, now: Instant) {
+		let mut pending = self.0.lock();
+		for update in updates {
+			pending.record(update);
+		}
+
+		if pending.last_sent.map_or(true, |last_sent| {
+			now.duration_since(last_sent) >= self.0.interval
+		}) {
+			pending.last_sent = Some(now);
+			self.0.send(pending.take());
+		}
+	}
+
+	/// Sends the pending updates right away, like at the end of a step
+	pub fn flush(&self) {
+		let mut pending = self.0.lock();
+		pending.last_sent = Some(Instant::now());
+		self.0.send(pending.take());
+	}
+}
+
+impl Shared {
+	fn lock(&self) -> std::sync::MutexGuard<'_, Pending> {
+		self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+
+	fn send(&self, updates: Vec<JobReportUpdate>) {
+		if !updates.is_empty() {
+			// the worker is gone once the job is done, there's no one left to tell
+			self.events_tx.send(WorkerEvent::Progressed(updates)).ok();
+		}
+	}
+}
+
+impl Drop for Shared {
+	fn drop(&mut self) {
+		let updates = self
+			.pending
+			.get_mut()
+			.unwrap_or_else(PoisonError::into_inner)
+			.take();
+		self.send(updates);
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+
+	fn sent(events_rx: &mut UnboundedReceiver<WorkerEvent>) -> Vec<Vec<JobReportUpdate>> {
+		let mut sent = vec![];
+		while let Ok(event) = events_rx.try_recv() {
+			match event {
+				WorkerEvent::Progressed(updates) => sent.push(updates),
+				event => panic!("unexpected event: {event:?}"),
+			}
+		}
+		sent
+	}
+
+	#[test]
+	fn test_progress_aggregator() {
+		let (events_tx, mut events_rx) = unbounded_channel();
+		let aggregator = ProgressAggregator::new(events_tx, Duration::from_millis(100));
+		let start = Instant::now();
+
+		// the first update goes out right away
+		aggregator.progress_at(vec![JobReportUpdate::TaskCount(10)], start);
+		assert!(matches!(
+			sent(&mut events_rx).as_slice(),
+			[updates] if matches!(updates.as_slice(), [JobReportUpdate::TaskCount(10)])
+		));
+
+		// the next ones within the interval are coalesced, the latest of each kind kept
+		for completed in 1..=3 {
+			aggregator.progress_at(
+				vec![
+					JobReportUpdate::CompletedTaskCount(completed),
+					JobReportUpdate::Message(format!("{completed} done")),
+				],
+				start + Duration::from_millis(10 * completed as u64),
+			);
+		}
+		assert!(sent(&mut events_rx).is_empty());
+
+		aggregator.progress_at(
+			vec![JobReportUpdate::ByteProgress {
+				completed: 5,
+				total: 10,
+			}],
+			start + Duration::from_millis(100),
+		);
+		let sent_updates = sent(&mut events_rx);
+		assert!(matches!(
+			sent_updates.as_slice(),
+			[updates] if matches!(
+				updates.as_slice(),
+				[
+					JobReportUpdate::CompletedTaskCount(3),
+					JobReportUpdate::ByteProgress { completed: 5, total: 10 },
+					JobReportUpdate::Message(message),
+				] if message == "3 done"
+			)
+		));
+
+		// the final state is sent once the last clone is dropped, even within the interval
+		let clone = aggregator.clone();
+		clone.progress_at(
+			vec![JobReportUpdate::CompletedTaskCount(10)],
+			start + Duration::from_millis(110),
+		);
+		drop(aggregator);
+		assert!(sent(&mut events_rx).is_empty());
+		drop(clone);
+		assert!(matches!(
+			sent(&mut events_rx).as_slice(),
+			[updates] if matches!(updates.as_slice(), [JobReportUpdate::CompletedTaskCount(10)])
+		));
+	}
+}
@@ -22,6 +22,12 @@ use super::JobError;
 pub enum JobReportUpdate {
 	TaskCount(usize),
 	CompletedTaskCount(usize),
+	/// Bytes processed out of the job's total, for jobs whose steps vary greatly in size so
+	/// progress and its estimate are weighted by them instead of the task counts
+	ByteProgress {
+		completed: u64,
+		total: u64,
+	},
 	Message(String),
 }
 
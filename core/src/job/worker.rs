@@ -1,9 +1,9 @@
 use super::JobReport;
 use crate::api::CoreEvent;
 use crate::invalidate_query;
-use crate::job::{DynJob, JobError, JobManager, JobReportUpdate, JobStatus};
+use crate::job::{DynJob, JobError, JobManager, JobReportUpdate, JobStatus, ProgressAggregator};
 use crate::library::Library;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
 use serde::Serialize;
 use specta::Type;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -20,6 +20,8 @@ pub struct JobProgressEvent {
 	pub id: Uuid,
 	pub task_count: i32,
 	pub completed_task_count: i32,
+	/// share of the job's bytes processed, for jobs reporting [`JobReportUpdate::ByteProgress`]
+	pub byte_progress: Option<f64>,
 	pub message: String,
 	pub estimated_completion: DateTime<Utc>,
 }
@@ -38,8 +40,28 @@ pub enum WorkerCommand {
 	Shutdown,
 }
 
+/// Policy set by the library to ask background jobs to back off, e.g. while the machine is on
+/// battery or busy with user activity. Jobs resume on their own once it's cleared.
+#[derive(Debug, Default)]
+pub struct BackgroundJobPolicy {
+	low_power: AtomicBool,
+}
+
+impl BackgroundJobPolicy {
+	pub fn set_low_power(&self, low_power: bool) {
+		self.low_power.store(low_power, Ordering::Relaxed);
+	}
+
+	pub fn should_yield(&self) -> bool {
+		self.low_power.load(Ordering::Relaxed)
+	}
+}
+
 pub struct WorkerContext {
 	pub library: Library,
+	pub job_id: Uuid,
+	/// when the job first started running, kept across pauses and resumes
+	pub started_at: DateTime<Utc>,
 	events_tx: UnboundedSender<WorkerEvent>,
 	pub command_rx: Arc<Mutex<UnboundedReceiver<WorkerCommand>>>,
 	pub paused: Arc<AtomicBool>,
@@ -51,6 +73,15 @@ impl WorkerContext {
 			.send(WorkerEvent::Progressed(updates))
 			.expect("critical error: failed to send worker worker progress event updates");
 	}
+	/// Progress reporting for updates sent often or from concurrent tasks, coalesced into at most
+	/// one event every `interval`, see [`ProgressAggregator`]
+	pub fn progress_aggregator(&self, interval: std::time::Duration) -> ProgressAggregator {
+		ProgressAggregator::new(self.events_tx.clone(), interval)
+	}
+	/// Background jobs should check this between steps and suspend while it's `true`
+	pub fn should_yield(&self) -> bool {
+		self.library.background_policy.should_yield()
+	}
 	pub fn preserve_state(&self, state: Vec<u8>) {
 		self.events_tx
 			.send(WorkerEvent::Paused(Some(state)))
@@ -68,6 +99,8 @@ pub struct Worker {
 	command_tx: Option<UnboundedSender<WorkerCommand>>,
 	// external_event_tx: UnboundedSender<JobManagerUpdate>,
 	start_time: Option<DateTime<Utc>>,
+	/// bytes completed and total, as last reported by the job
+	byte_progress: Option<(u64, u64)>,
 	paused: Arc<AtomicBool>,
 }
 
@@ -87,6 +120,7 @@ impl Worker {
 			command_tx: None,
 			// external_event_tx,
 			start_time: None,
+			byte_progress: None,
 			paused: Arc::new(AtomicBool::new(false)),
 		}
 	}
@@ -135,9 +169,7 @@ impl Worker {
 		let job_id = worker.report.id;
 
 		worker.report.status = JobStatus::Running;
-		if worker.report.started_at.is_none() {
-			worker.report.started_at = Some(Utc::now());
-		}
+		let started_at = *worker.report.started_at.get_or_insert_with(Utc::now);
 
 		worker.start_time = Some(Utc::now());
 
@@ -170,6 +202,8 @@ impl Worker {
 		tokio::spawn(async move {
 			let mut worker_ctx = WorkerContext {
 				library: library.clone(),
+				job_id,
+				started_at,
 				events_tx,
 				command_rx,
 				paused,
@@ -307,6 +341,9 @@ impl Worker {
 							JobReportUpdate::CompletedTaskCount(completed_task_count) => {
 								worker.report.completed_task_count = completed_task_count as i32;
 							}
+							JobReportUpdate::ByteProgress { completed, total } => {
+								worker.byte_progress = Some((completed, total));
+							}
 
 							JobReportUpdate::Message(message) => {
 								worker.report.message = message;
@@ -317,12 +354,15 @@ impl Worker {
 					if let Some(start_time) = worker.start_time {
 						let elapsed = Utc::now() - start_time;
 
-						// Calculate remaining time
-						let task_count = worker.report.task_count as usize;
-						let completed_task_count = worker.report.completed_task_count as usize;
-						let remaining_task_count = task_count.saturating_sub(completed_task_count);
-						let remaining_time_per_task = elapsed / (completed_task_count + 1) as i32; // Adding 1 to avoid division by zero
-						let remaining_time = remaining_time_per_task * remaining_task_count as i32;
+						// Calculate remaining time, by bytes when the job reports them
+						let (completed, total) = match worker.byte_progress {
+							Some((completed, total)) if total > 0 => (completed, total),
+							_ => (
+								worker.report.completed_task_count as u64,
+								worker.report.task_count as u64,
+							),
+						};
+						let remaining_time = remaining_time(elapsed, completed, total);
 
 						// Update the report with estimated remaining time
 						worker.report.estimated_completion = Utc::now()
@@ -335,6 +375,10 @@ impl Worker {
 							id: report.id,
 							task_count: report.task_count,
 							completed_task_count: report.completed_task_count,
+							byte_progress: worker
+								.byte_progress
+								.filter(|(_, total)| *total > 0)
+								.map(|(completed, total)| completed as f64 / total as f64),
 							estimated_completion: report.estimated_completion,
 							message: report.message,
 						}));
@@ -422,3 +466,12 @@ fn invalidate_queries(library: &Library) {
 	invalidate_query!(library, "jobs.isActive");
 	invalidate_query!(library, "jobs.reports");
 }
+
+/// Extrapolates the time the rest of the work takes from the time the completed part took
+fn remaining_time(elapsed: Duration, completed: u64, total: u64) -> Duration {
+	let remaining = total.saturating_sub(completed);
+	// Adding 1 to avoid division by zero
+	let remaining_ratio = remaining as f64 / (completed as f64 + 1.0);
+
+	Duration::milliseconds((elapsed.num_milliseconds() as f64 * remaining_ratio) as i64)
+}
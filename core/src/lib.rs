@@ -36,6 +36,14 @@ pub(crate) mod sync;
 pub(crate) mod util;
 pub(crate) mod volume;
 
+/// The checksums of the object validator, reachable from the benchmarks in `benches/` and the
+/// fuzz targets in `fuzz/`
+#[doc(hidden)]
+pub use object::validation::hash;
+/// The parsers of checksum files made by other tools, reachable from the fuzz targets in `fuzz/`
+#[doc(hidden)]
+pub use object::validation::{ExternalChecksumSource, SignedManifest};
+
 #[derive(Clone)]
 pub struct NodeContext {
 	pub config: Arc<NodeConfigManager>,
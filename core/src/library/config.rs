@@ -9,6 +9,7 @@ use specta::Type;
 use uuid::Uuid;
 
 use crate::{
+	object::validation::hash::ChecksumAlgorithm,
 	prisma::{file_path, indexer_rule, PrismaClient},
 	util::{
 		db::uuid_to_bytes,
@@ -27,6 +28,16 @@ pub struct LibraryConfig {
 	pub identity: Vec<u8>,
 	/// Id of the current node
 	pub node_id: Uuid,
+	/// Algorithm used for new integrity checksums, when a validation doesn't ask for one.
+	#[serde(default)]
+	pub default_checksum_algorithm: ChecksumAlgorithm,
+	/// Validate files in the background when they're opened or previewed.
+	#[serde(default)]
+	pub validate_on_access: bool,
+	/// Compute the checksums of files the location watcher sees created or modified, once they
+	/// settle.
+	#[serde(default)]
+	pub checksum_on_change: bool,
 	// /// is_encrypted is a flag that is set to true if the library is encrypted.
 	// #[serde(default)]
 	// pub is_encrypted: bool,
@@ -37,6 +48,9 @@ pub struct SanitisedLibraryConfig {
 	pub name: String,
 	pub description: Option<String>,
 	pub node_id: Uuid,
+	pub default_checksum_algorithm: ChecksumAlgorithm,
+	pub validate_on_access: bool,
+	pub checksum_on_change: bool,
 }
 
 impl From<LibraryConfig> for SanitisedLibraryConfig {
@@ -45,6 +59,9 @@ impl From<LibraryConfig> for SanitisedLibraryConfig {
 			name: config.name,
 			description: config.description,
 			node_id: config.node_id,
+			default_checksum_algorithm: config.default_checksum_algorithm,
+			validate_on_access: config.validate_on_access,
+			checksum_on_change: config.checksum_on_change,
 		}
 	}
 }
@@ -56,6 +73,9 @@ impl LibraryConfig {
 			description: None,
 			identity: Identity::new().to_bytes().to_vec(),
 			node_id,
+			default_checksum_algorithm: Default::default(),
+			validate_on_access: false,
+			checksum_on_change: false,
 		}
 	}
 }
@@ -1,12 +1,12 @@
 use crate::{
 	api::CoreEvent,
-	job::{IntoJob, JobInitData, JobManagerError, StatefulJob},
+	job::{BackgroundJobPolicy, IntoJob, JobInitData, JobManagerError, StatefulJob},
 	location::{
 		file_path_helper::{file_path_to_full_path, IsolatedFilePathData},
 		LocationManager,
 	},
 	node::NodeConfigManager,
-	object::{orphan_remover::OrphanRemoverActor, preview::get_thumbnail_path},
+	object::{orphan_remover::OrphanRemoverActor, preview::get_thumbnail_path, validation},
 	prisma::{file_path, location, PrismaClient},
 	sync::SyncManager,
 	util::{db::maybe_missing, error::FileIOError},
@@ -48,6 +48,8 @@ pub struct Library {
 	/// p2p identity
 	pub identity: Arc<Identity>,
 	pub orphan_remover: OrphanRemoverActor,
+	/// policy used to ask background jobs to back off, like when on battery
+	pub background_policy: Arc<BackgroundJobPolicy>,
 }
 
 impl Debug for Library {
@@ -103,6 +105,18 @@ impl Library {
 		}
 	}
 
+	/// Validates a file that was just opened or previewed in the background, if the library asks
+	/// for it
+	pub fn validate_on_access(&self, file_path_id: file_path::id::Type) {
+		validation::validate_on_access(self, file_path_id);
+	}
+
+	/// Computes the checksum of a file the watcher just created or updated in the background, once
+	/// it settles, if the library asks for it
+	pub fn checksum_on_change(&self, file_path_id: file_path::id::Type) {
+		validation::checksum_on_change(self, file_path_id);
+	}
+
 	/// Returns the full path of a file
 	pub async fn get_file_paths(
 		&self,
@@ -2,7 +2,7 @@ use crate::{
 	invalidate_query,
 	location::{indexer, LocationManagerError},
 	node::{NodeConfig, Platform},
-	object::{orphan_remover::OrphanRemoverActor, tag},
+	object::{orphan_remover::OrphanRemoverActor, tag, validation::hash::ChecksumAlgorithm},
 	prisma::{location, node},
 	sync::{SyncManager, SyncMessage},
 	util::{
@@ -285,6 +285,9 @@ impl LibraryManager {
 		id: Uuid,
 		name: Option<String>,
 		description: MaybeUndefined<String>,
+		default_checksum_algorithm: Option<ChecksumAlgorithm>,
+		validate_on_access: Option<bool>,
+		checksum_on_change: Option<bool>,
 	) -> Result<(), LibraryManagerError> {
 		// check library is valid
 		let mut libraries = self.libraries.write().await;
@@ -302,6 +305,15 @@ impl LibraryManager {
 			MaybeUndefined::Null => library.config.description = None,
 			MaybeUndefined::Value(description) => library.config.description = Some(description),
 		}
+		if let Some(default_checksum_algorithm) = default_checksum_algorithm {
+			library.config.default_checksum_algorithm = default_checksum_algorithm;
+		}
+		if let Some(validate_on_access) = validate_on_access {
+			library.config.validate_on_access = validate_on_access;
+		}
+		if let Some(checksum_on_change) = checksum_on_change {
+			library.config.checksum_on_change = checksum_on_change;
+		}
 
 		LibraryConfig::save(
 			&library.config,
@@ -478,6 +490,7 @@ impl LibraryManager {
 			node_local_id: node_data.id,
 			node_context,
 			identity,
+			background_policy: Default::default(),
 		};
 
 		indexer::rules::seed::new_or_existing_library(&library).await?;
@@ -14,7 +14,9 @@ use regex::RegexSet;
 use serde::{Deserialize, Serialize};
 
 use super::{
-	file_path_for_file_identifier, file_path_for_object_validator, file_path_for_thumbnailer,
+	file_path_for_acknowledgment, file_path_for_aggregate_checksum, file_path_for_cas_cross_check,
+	file_path_for_file_identifier, file_path_for_object_validator, file_path_for_peer_checksums,
+	file_path_for_stored_aggregate, file_path_for_thumbnailer, file_path_for_tree_checksum,
 	file_path_to_full_path, file_path_to_handle_custom_uri, file_path_to_isolate,
 	file_path_to_isolate_with_id, file_path_with_object, FilePathError,
 };
@@ -459,6 +461,12 @@ impl_from_db_without_location_id!(
 	file_path_to_full_path,
 	file_path_for_thumbnailer,
 	file_path_for_object_validator,
+	file_path_for_aggregate_checksum,
+	file_path_for_stored_aggregate,
+	file_path_for_cas_cross_check,
+	file_path_for_acknowledgment,
+	file_path_for_peer_checksums,
+	file_path_for_tree_checksum,
 	file_path_to_handle_custom_uri
 );
 
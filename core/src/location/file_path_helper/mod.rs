@@ -38,12 +38,101 @@ file_path::select!(file_path_for_file_identifier {
 	extension
 });
 file_path::select!(file_path_for_object_validator {
+	id
+	pub_id
+	materialized_path
+	is_dir
+	name
+	extension
+	integrity_checksum
+	integrity_checksum_algorithm
+	content_checksum
+	size_in_bytes_bytes
+	device
+	date_modified
+	object: select {
+		id
+		date_accessed
+	}
+});
+file_path::select!(file_path_for_duplicate_merge {
+	id
+	pub_id
+	location_id
+	object_id
+	integrity_checksum
+	integrity_checksum_algorithm
+	size_in_bytes_bytes
+});
+file_path::select!(file_path_for_aggregate_checksum {
+	id
+	materialized_path
+	is_dir
+	name
+	extension
+	integrity_checksum
+	integrity_checksum_algorithm
+});
+file_path::select!(file_path_for_stored_aggregate {
+	materialized_path
+	is_dir
+	name
+	extension
+	integrity_checksum
+	integrity_checksum_algorithm
+	exclude_from_validation
+	location: select {
+		id
+		pub_id
+		aggregate_checksum
+		aggregate_checksum_algorithm
+	}
+});
+file_path::select!(file_path_for_cas_cross_check {
+	id
+	materialized_path
+	is_dir
+	name
+	extension
+	cas_id
+	integrity_checksum
+});
+file_path::select!(file_path_for_acknowledgment {
+	id
+	materialized_path
+	is_dir
+	name
+	extension
+	acknowledged_checksum
+	acknowledged_by
+	date_acknowledged
+});
+file_path::select!(file_path_for_peer_checksums {
+	id
+	materialized_path
+	is_dir
+	name
+	extension
+	integrity_checksum
+	integrity_checksum_algorithm
+	object: select { pub_id }
+});
+file_path::select!(file_path_for_tree_checksum {
+	id
 	pub_id
 	materialized_path
 	is_dir
 	name
 	extension
 	integrity_checksum
+	integrity_checksum_algorithm
+	tree_checksum
+});
+file_path::select!(file_path_for_checksum_status {
+	id
+	integrity_checksum
+	date_checksummed
+	date_modified
 });
 file_path::select!(file_path_for_thumbnailer {
 	materialized_path
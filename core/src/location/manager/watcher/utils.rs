@@ -17,7 +17,11 @@ use crate::{
 	object::{
 		file_identifier::FileMetadata,
 		preview::{can_generate_thumbnail_for_image, generate_image_thumbnail, get_thumbnail_path},
-		validation::hash::file_checksum,
+		validation::{
+			hash::{file_checksum_with, ChecksumAlgorithm, ReadOptions},
+			media::media_content_checksum,
+			update_content_index, update_stored_aggregate,
+		},
 	},
 	prisma::{file_path, location, object},
 	sync,
@@ -39,7 +43,7 @@ use std::{
 
 use sd_file_ext::extensions::ImageExtension;
 
-use chrono::{DateTime, Local};
+use chrono::{DateTime, FixedOffset, Local};
 use notify::{Event, EventKind};
 use prisma_client_rust::{raw, PrismaValue};
 use serde_json::json;
@@ -239,6 +243,8 @@ pub(super) async fn create_file(
 		.exec()
 		.await?;
 
+	library.checksum_on_change(created_file.id);
+
 	if !extension.is_empty() {
 		// Running in a detached task as thumbnail generation can take a while and we don't want to block the watcher
 		let path = path.to_path_buf();
@@ -367,6 +373,39 @@ async fn inner_update_file(
 
 	if let Some(old_cas_id) = &file_path.cas_id {
 		if old_cas_id != &cas_id {
+			// TODO: Should this be a skip rather than a null-set?
+			let algorithm =
+				ChecksumAlgorithm::from_db(file_path.integrity_checksum_algorithm.as_deref())
+					.unwrap_or_default();
+			// when the library checksums changed files once they settle, it's done then instead
+			let recompute_checksum =
+				file_path.integrity_checksum.is_some() && !library.config.checksum_on_change;
+			let checksum = if recompute_checksum {
+				// If a checksum was already computed, we need to recompute it, with the same algorithm
+				Some(
+					file_checksum_with(
+						full_path,
+						ReadOptions {
+							algorithm,
+							..Default::default()
+						},
+					)
+					.await
+					.map_err(|e| FileIOError::from((full_path, e)))?,
+				)
+			} else {
+				None
+			};
+			let media_checksum = if file_path.content_checksum.is_some() {
+				Some(
+					media_content_checksum(full_path)
+						.await
+						.map_err(|e| FileIOError::from((full_path, e)))?,
+				)
+			} else {
+				None
+			};
+
 			let (sync_params, db_params): (Vec<_>, Vec<_>) = {
 				use file_path::*;
 
@@ -390,24 +429,37 @@ async fn inner_update_file(
 							date_modified::set(Some(date)),
 						)
 					},
+					(
+						(integrity_checksum::NAME, json!(checksum)),
+						integrity_checksum::set(checksum.clone()),
+					),
 					{
-						// TODO: Should this be a skip rather than a null-set?
-						let checksum = if file_path.integrity_checksum.is_some() {
-							// If a checksum was already computed, we need to recompute it
-							Some(
-								file_checksum(full_path)
-									.await
-									.map_err(|e| FileIOError::from((full_path, e)))?,
-							)
-						} else {
-							None
-						};
+						let algorithm_name =
+							checksum.is_some().then(|| algorithm.as_str().to_string());
 
 						(
-							(integrity_checksum::NAME, json!(checksum)),
-							integrity_checksum::set(checksum),
+							(integrity_checksum_algorithm::NAME, json!(algorithm_name)),
+							integrity_checksum_algorithm::set(algorithm_name),
 						)
 					},
+					{
+						let date: Option<DateTime<FixedOffset>> =
+							checksum.is_some().then(|| Local::now().into());
+
+						(
+							(date_checksummed::NAME, json!(date)),
+							date_checksummed::set(date),
+						)
+					},
+					// the checksum was just computed by us, even if it was imported before
+					(
+						(integrity_checksum_source::NAME, json!(None::<String>)),
+						integrity_checksum_source::set(None),
+					),
+					(
+						(content_checksum::NAME, json!(media_checksum)),
+						content_checksum::set(media_checksum),
+					),
 				]
 				.into_iter()
 				.unzip()
@@ -437,6 +489,18 @@ async fn inner_update_file(
 			)
 			.await?;
 
+			update_content_index(db, file_path.id, checksum.as_deref()).await?;
+			update_stored_aggregate(
+				library,
+				&file_path.pub_id,
+				(
+					file_path.integrity_checksum.as_deref(),
+					file_path.integrity_checksum_algorithm.as_deref(),
+				),
+			)
+			.await?;
+			library.checksum_on_change(file_path.id);
+
 			if let Some(ref object) = file_path.object {
 				// if this file had a thumbnail previously, we update it to match the new content
 				if library.thumbnail_exists(old_cas_id).await? {
@@ -6,6 +6,7 @@ use crate::{
 	object::{
 		file_identifier::{self, file_identifier_job::FileIdentifierJobInit},
 		preview::{shallow_thumbnailer, thumbnailer_job::ThumbnailerJobInit},
+		validation::{hash::ChecksumAlgorithm, validator_job::ObjectValidatorJobInit},
 	},
 	prisma::{file_path, indexer_rules_in_location, location, node, PrismaClient},
 	sync,
@@ -58,6 +59,34 @@ pub struct LocationCreateArgs {
 	pub path: PathBuf,
 	pub dry_run: bool,
 	pub indexer_rules_ids: Vec<i32>,
+	/// compute the checksums of the files once the new location is indexed, so it's
+	/// fingerprinted from the start instead of waiting for a validator run
+	#[serde(default)]
+	pub validate_after_indexing: Option<InitialValidation>,
+}
+
+/// How the files of a new location are validated right after its initial indexing
+#[derive(Type, Deserialize, Debug, Clone, Copy)]
+pub struct InitialValidation {
+	/// the library's default algorithm is used when not set
+	pub algorithm: Option<ChecksumAlgorithm>,
+	/// keep reads under this many bytes per second on average, so the rest of the node isn't
+	/// slowed down while a big location is hashed
+	pub max_read_rate: Option<u32>,
+}
+
+impl InitialValidation {
+	fn job_init(self, location: location::Data) -> ObjectValidatorJobInit {
+		let mut builder = ObjectValidatorJobInit::builder(location);
+		if let Some(algorithm) = self.algorithm {
+			builder = builder.algorithm(algorithm);
+		}
+		if let Some(max_read_rate) = self.max_read_rate {
+			builder = builder.max_read_rate(max_read_rate.into());
+		}
+
+		builder.build()
+	}
 }
 
 impl LocationCreateArgs {
@@ -372,9 +401,12 @@ async fn link_location_and_indexer_rules(
 	Ok(())
 }
 
+/// Indexes the whole location, identifying its files and generating their thumbnails after,
+/// and computing their checksums at the end when `validation` is given
 pub async fn scan_location(
 	library: &Library,
 	location: location_with_indexer_rules::Data,
+	validation: Option<InitialValidation>,
 ) -> Result<(), JobManagerError> {
 	if location.node_id != Some(library.node_local_id) {
 		return Ok(());
@@ -382,24 +414,27 @@ pub async fn scan_location(
 
 	let location_base_data = location::Data::from(&location);
 
+	let job = Job::new_with_action(
+		IndexerJobInit {
+			location,
+			sub_path: None,
+		},
+		"scan_location",
+	)
+	.queue_next(FileIdentifierJobInit {
+		location: location_base_data.clone(),
+		sub_path: None,
+	})
+	.queue_next(ThumbnailerJobInit {
+		location: location_base_data.clone(),
+		sub_path: None,
+	});
+
 	library
-		.spawn_job(
-			Job::new_with_action(
-				IndexerJobInit {
-					location,
-					sub_path: None,
-				},
-				"scan_location",
-			)
-			.queue_next(FileIdentifierJobInit {
-				location: location_base_data.clone(),
-				sub_path: None,
-			})
-			.queue_next(ThumbnailerJobInit {
-				location: location_base_data,
-				sub_path: None,
-			}),
-		)
+		.spawn_job(match validation {
+			Some(validation) => job.queue_next(validation.job_init(location_base_data)),
+			None => job,
+		})
 		.await
 }
 
@@ -718,6 +753,8 @@ impl From<location_with_indexer_rules::Data> for location::Data {
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
 			date_created: data.date_created,
+			aggregate_checksum: data.aggregate_checksum,
+			aggregate_checksum_algorithm: data.aggregate_checksum_algorithm,
 			node: None,
 			file_paths: None,
 			indexer_rules: None,
@@ -740,6 +777,8 @@ impl From<&location_with_indexer_rules::Data> for location::Data {
 			sync_preview_media: data.sync_preview_media,
 			hidden: data.hidden,
 			date_created: data.date_created,
+			aggregate_checksum: data.aggregate_checksum.clone(),
+			aggregate_checksum_algorithm: data.aggregate_checksum_algorithm.clone(),
 			node: None,
 			file_paths: None,
 			indexer_rules: None,
@@ -0,0 +1,50 @@
+use crate::{object::validation::hash::file_checksum, util::error::FileIOError};
+
+use std::path::{Path, PathBuf};
+
+use tokio::{fs, io};
+
+pub const THUMBNAIL_CHECKSUM_EXTENSION: &str = "checksum";
+
+/// Thumbnails have their checksum stored next to them, so a corrupted cache can be detected and
+/// only the bad thumbnails regenerated
+pub fn get_thumbnail_checksum_path(thumbnail_path: impl AsRef<Path>) -> PathBuf {
+	thumbnail_path
+		.as_ref()
+		.with_extension(THUMBNAIL_CHECKSUM_EXTENSION)
+}
+
+/// Computes and stores the checksum of a freshly generated thumbnail
+pub async fn save_thumbnail_checksum(thumbnail_path: impl AsRef<Path>) -> Result<(), FileIOError> {
+	let thumbnail_path = thumbnail_path.as_ref();
+
+	let checksum = file_checksum(thumbnail_path)
+		.await
+		.map_err(|e| FileIOError::from((thumbnail_path, e)))?;
+
+	write_thumbnail_checksum(thumbnail_path, &checksum).await
+}
+
+pub async fn write_thumbnail_checksum(
+	thumbnail_path: impl AsRef<Path>,
+	checksum: &str,
+) -> Result<(), FileIOError> {
+	let checksum_path = get_thumbnail_checksum_path(thumbnail_path);
+
+	fs::write(&checksum_path, checksum)
+		.await
+		.map_err(|e| FileIOError::from((checksum_path, e)))
+}
+
+/// Returns `None` for thumbnails generated before we stored their checksums
+pub async fn read_thumbnail_checksum(
+	thumbnail_path: impl AsRef<Path>,
+) -> Result<Option<String>, FileIOError> {
+	let checksum_path = get_thumbnail_checksum_path(thumbnail_path);
+
+	match fs::read_to_string(&checksum_path).await {
+		Ok(checksum) => Ok(Some(checksum.trim().to_string())),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+		Err(e) => Err(FileIOError::from((checksum_path, e))),
+	}
+}
@@ -29,11 +29,14 @@ use webp::Encoder;
 
 use self::thumbnailer_job::ThumbnailerJob;
 
+mod checksum;
 mod directory;
 mod shallow;
 mod shard;
+pub mod thumbnail_validator_job;
 pub mod thumbnailer_job;
 
+pub use checksum::*;
 pub use directory::*;
 pub use shallow::*;
 pub use shard::*;
@@ -172,7 +175,11 @@ pub async fn generate_image_thumbnail<P: AsRef<Path>>(
 		Ok(encoder.encode(THUMBNAIL_QUALITY).deref().to_owned())
 	})?;
 
-	fs::write(output_path, &webp).await.map_err(Into::into)
+	fs::write(&output_path, &webp).await?;
+
+	save_thumbnail_checksum(output_path).await?;
+
+	Ok(())
 }
 
 #[cfg(feature = "ffmpeg")]
@@ -182,7 +189,9 @@ pub async fn generate_video_thumbnail<P: AsRef<Path>>(
 ) -> Result<(), Box<dyn Error>> {
 	use sd_ffmpeg::to_thumbnail;
 
-	to_thumbnail(file_path, output_path, 256, THUMBNAIL_QUALITY).await?;
+	to_thumbnail(file_path, &output_path, 256, THUMBNAIL_QUALITY).await?;
+
+	save_thumbnail_checksum(output_path).await?;
 
 	Ok(())
 }
@@ -0,0 +1,337 @@
+use crate::{
+	extract_job_data, extract_job_data_mut, invalidate_query,
+	job::{
+		JobError, JobInitData, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	library::Library,
+	location::file_path_helper::file_path_for_thumbnailer,
+	object::{preview::thumbnail::directory::init_thumbnail_dir, validation::hash::file_checksum},
+	prisma::{file_path, location},
+	util::{db::maybe_missing, error::FileIOError},
+};
+
+use std::{
+	ffi::OsStr,
+	path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io};
+use tracing::{info, warn};
+
+use super::{
+	get_thumbnail_checksum_path, inner_process_step, read_thumbnail_checksum,
+	write_thumbnail_checksum, ThumbnailerError, ThumbnailerJobStep, ThumbnailerJobStepKind,
+	FILTERED_IMAGE_EXTENSIONS, THUMBNAIL_CHECKSUM_EXTENSION,
+};
+
+#[cfg(feature = "ffmpeg")]
+use super::FILTERED_VIDEO_EXTENSIONS;
+
+// The thumbnail validator checks every cached thumbnail against the checksum stored when it was
+// generated, deleting the corrupted ones and generating them again from a file of this node with
+// their cas_id.
+// Thumbnails from before checksums were stored get theirs computed.
+pub struct ThumbnailValidatorJob {}
+
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct ThumbnailValidatorJobInit {}
+
+impl JobInitData for ThumbnailValidatorJobInit {
+	type Job = ThumbnailValidatorJob;
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ThumbnailValidatorJobState {
+	thumbnail_dir: PathBuf,
+	report: ThumbnailValidatorJobReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ThumbnailValidatorJobReport {
+	thumbnails_checked: u32,
+	checksums_created: u32,
+	/// cas ids of the thumbnails deleted for being corrupted
+	corrupted_thumbnails: Vec<String>,
+	/// corrupted thumbnails generated again, the others have no file left to generate them from
+	thumbnails_regenerated: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ThumbnailValidation {
+	Valid,
+	ChecksumCreated,
+	Corrupted,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ThumbnailValidatorJob {
+	type Init = ThumbnailValidatorJobInit;
+	type Data = ThumbnailValidatorJobState;
+	/// A shard directory of the thumbnail cache
+	type Step = PathBuf;
+
+	const NAME: &'static str = "thumbnail_validator";
+	const IS_BACKGROUND: bool = true;
+
+	fn new() -> Self {
+		Self {}
+	}
+
+	async fn init(
+		&self,
+		ctx: &mut WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let thumbnail_dir = init_thumbnail_dir(ctx.library.config().data_directory()).await?;
+
+		let mut read_dir = fs::read_dir(&thumbnail_dir)
+			.await
+			.map_err(|e| ThumbnailerError::from(FileIOError::from((&thumbnail_dir, e))))?;
+
+		let mut shard_dirs = vec![];
+		while let Some(entry) = read_dir
+			.next_entry()
+			.await
+			.map_err(|e| ThumbnailerError::from(FileIOError::from((&thumbnail_dir, e))))?
+		{
+			let path = entry.path();
+			if path.is_dir() {
+				shard_dirs.push(path);
+			}
+		}
+		shard_dirs.sort();
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(shard_dirs.len())]);
+
+		state.data = Some(ThumbnailValidatorJobState {
+			thumbnail_dir,
+			report: Default::default(),
+		});
+		state.steps.extend(shard_dirs);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &mut WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let shard_dir = &state.steps[0];
+		let data = extract_job_data_mut!(state);
+
+		let mut errors = vec![];
+
+		let mut read_dir = fs::read_dir(shard_dir)
+			.await
+			.map_err(|e| ThumbnailerError::from(FileIOError::from((shard_dir, e))))?;
+
+		while let Some(entry) = read_dir
+			.next_entry()
+			.await
+			.map_err(|e| ThumbnailerError::from(FileIOError::from((shard_dir, e))))?
+		{
+			let path = entry.path();
+
+			match path.extension().and_then(OsStr::to_str) {
+				Some("webp") => match validate_thumbnail(&path).await {
+					Ok(validation) => {
+						data.report.thumbnails_checked += 1;
+
+						match validation {
+							ThumbnailValidation::Valid => {}
+							ThumbnailValidation::ChecksumCreated => {
+								data.report.checksums_created += 1;
+							}
+							ThumbnailValidation::Corrupted => {
+								warn!("Deleted corrupted thumbnail {}", path.display());
+								let cas_id = path
+									.file_stem()
+									.and_then(OsStr::to_str)
+									.unwrap_or_default()
+									.to_string();
+
+								match regenerate_thumbnail(
+									&ctx.library,
+									&data.thumbnail_dir,
+									&cas_id,
+								)
+								.await
+								{
+									Ok(true) => data.report.thumbnails_regenerated += 1,
+									Ok(false) => {
+										warn!("No file left to regenerate thumbnail {cas_id} from")
+									}
+									Err(e) => errors.push(e.to_string()),
+								}
+
+								data.report.corrupted_thumbnails.push(cas_id);
+							}
+						}
+					}
+					Err(e) => errors.push(e.to_string()),
+				},
+				Some(THUMBNAIL_CHECKSUM_EXTENSION) => {
+					// the thumbnail itself is gone, so its checksum is of no use
+					if matches!(
+						fs::metadata(path.with_extension("webp")).await,
+						Err(e) if e.kind() == io::ErrorKind::NotFound
+					) {
+						if let Err(e) = fs::remove_file(&path).await {
+							errors.push(FileIOError::from((&path, e)).to_string());
+						}
+					}
+				}
+				_ => {}
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(JobError::StepCompletedWithErrors(errors))
+		}
+	}
+
+	async fn finalize(&mut self, ctx: &mut WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = extract_job_data!(state);
+		info!(
+			"finalizing thumbnail validator job at {}: {} thumbnails checked, {} corrupted and {} \
+			of those regenerated",
+			data.thumbnail_dir.display(),
+			data.report.thumbnails_checked,
+			data.report.corrupted_thumbnails.len(),
+			data.report.thumbnails_regenerated
+		);
+
+		if !data.report.corrupted_thumbnails.is_empty() {
+			invalidate_query!(ctx.library, "search.paths");
+		}
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+/// Checks a thumbnail against its stored checksum, deleting it along with its checksum if they
+/// don't match
+async fn validate_thumbnail(path: &Path) -> Result<ThumbnailValidation, FileIOError> {
+	let checksum = file_checksum(path)
+		.await
+		.map_err(|e| FileIOError::from((path, e)))?;
+
+	match read_thumbnail_checksum(path).await? {
+		None => {
+			write_thumbnail_checksum(path, &checksum).await?;
+			Ok(ThumbnailValidation::ChecksumCreated)
+		}
+		Some(stored) if stored == checksum => Ok(ThumbnailValidation::Valid),
+		Some(_) => {
+			fs::remove_file(path)
+				.await
+				.map_err(|e| FileIOError::from((path, e)))?;
+
+			let checksum_path = get_thumbnail_checksum_path(path);
+			match fs::remove_file(&checksum_path).await {
+				Ok(()) => {}
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => return Err(FileIOError::from((checksum_path, e))),
+			}
+
+			Ok(ThumbnailValidation::Corrupted)
+		}
+	}
+}
+
+/// Generates the thumbnail of a cas_id again, from the first file of this node's locations with
+/// it that the thumbnailer handles. Returns `false` if there's no such file.
+async fn regenerate_thumbnail(
+	library: &Library,
+	thumbnail_dir: &Path,
+	cas_id: &str,
+) -> Result<bool, JobError> {
+	let kinds = [
+		(&*FILTERED_IMAGE_EXTENSIONS, ThumbnailerJobStepKind::Image),
+		#[cfg(feature = "ffmpeg")]
+		(&*FILTERED_VIDEO_EXTENSIONS, ThumbnailerJobStepKind::Video),
+	];
+
+	for (extensions, kind) in kinds {
+		let Some(file_path) = library
+			.db
+			.file_path()
+			.find_first(vec![
+				file_path::cas_id::equals(Some(cas_id.to_string())),
+				file_path::extension::in_vec(extensions.iter().map(ToString::to_string).collect()),
+				file_path::location::is(vec![location::node_id::equals(Some(
+					library.node_local_id,
+				))]),
+			])
+			.include(file_path::include!({ location }))
+			.exec()
+			.await?
+		else {
+			continue;
+		};
+
+		let location = maybe_missing(file_path.location, "file_path.location")?;
+		let location_path = maybe_missing(&location.path, "location.path")?;
+
+		let step = ThumbnailerJobStep {
+			file_path: file_path_for_thumbnailer::Data {
+				materialized_path: file_path.materialized_path,
+				is_dir: file_path.is_dir,
+				name: file_path.name,
+				extension: file_path.extension,
+				cas_id: file_path.cas_id,
+			},
+			kind,
+		};
+
+		return inner_process_step(&step, location_path, thumbnail_dir, &location, library).await;
+	}
+
+	Ok(false)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+	use tempfile::tempdir;
+
+	#[tokio::test]
+	async fn test_validate_thumbnail() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("abcdef.webp");
+		fs::write(&path, b"thumbnail").await.unwrap();
+
+		// thumbnails from before checksums were stored get one
+		assert_eq!(
+			validate_thumbnail(&path).await.unwrap(),
+			ThumbnailValidation::ChecksumCreated
+		);
+		assert_eq!(
+			read_thumbnail_checksum(&path).await.unwrap(),
+			Some(file_checksum(&path).await.unwrap())
+		);
+		assert_eq!(
+			validate_thumbnail(&path).await.unwrap(),
+			ThumbnailValidation::Valid
+		);
+
+		fs::write(&path, b"thumbnaik").await.unwrap();
+		assert_eq!(
+			validate_thumbnail(&path).await.unwrap(),
+			ThumbnailValidation::Corrupted
+		);
+		assert!(fs::metadata(&path).await.is_err());
+		assert!(fs::metadata(get_thumbnail_checksum_path(&path))
+			.await
+			.is_err());
+	}
+}
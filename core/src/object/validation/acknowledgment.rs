@@ -0,0 +1,268 @@
+//! Acknowledgments of files the user confirmed changed on purpose after they stopped matching
+//! their checksum, like a document edited since a manifest was signed. The file is checksummed
+//! again and the checksum stored, so the library treats it as current, and audits against a
+//! manifest older than the acknowledgment accept the file as long as it still has the acknowledged
+//! contents.
+
+use crate::{
+	library::Library,
+	location::file_path_helper::{
+		file_path_for_acknowledgment, file_path_for_object_validator, IsolatedFilePathData,
+	},
+	prisma::{file_path, location, PrismaClient},
+	sync,
+	util::{
+		db::{chain_optional_iter, maybe_missing},
+		error::FileIOError,
+	},
+};
+
+use std::{collections::HashMap, path::Path};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+use super::{
+	hash::{
+		checksums_match, decode_checksum, encode_checksum, file_checksum_with, ChecksumAlgorithm,
+		ReadOptions,
+	},
+	validator_job::extended_length_path,
+	ChecksumStore, LibraryChecksumStore, StoredChecksums, ValidatorError,
+};
+
+/// Contents of a file the user vouched for, see [`acknowledge_mismatch`]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Acknowledgment {
+	pub algorithm: ChecksumAlgorithm,
+	pub checksum: String,
+	pub at: DateTime<Utc>,
+	/// name of the node it was acknowledged from
+	pub by: Option<String>,
+}
+
+impl Acknowledgment {
+	/// The checksum as stored on the file path, `algorithm:checksum`
+	pub fn checksum_to_db(&self) -> String {
+		encode_checksum(self.algorithm, &self.checksum)
+	}
+
+	pub fn from_db(stored: &str, at: DateTime<FixedOffset>, by: Option<String>) -> Option<Self> {
+		let (algorithm, checksum) = decode_checksum(stored)?;
+
+		Some(Self {
+			algorithm,
+			checksum: checksum.to_string(),
+			at: at.into(),
+			by,
+		})
+	}
+}
+
+/// Accepts the contents of a file that no longer matches its checksum as a legitimate change:
+/// it's checksummed again with the library's default algorithm, the checksum is stored, and who
+/// acknowledged it and when is recorded along with it. `expected` is the checksum with that
+/// algorithm the user was shown, the call fails if the file changed again since.
+pub async fn acknowledge_mismatch(
+	library: &Library,
+	file_path_id: file_path::id::Type,
+	expected: &str,
+) -> Result<Acknowledgment, ValidatorError> {
+	let Library { db, sync, .. } = library;
+
+	let located = db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.select(file_path::select!({ location: select { id node_id path } }))
+		.exec()
+		.await?
+		.ok_or(ValidatorError::FilePathNotFound(file_path_id))?;
+	let location = maybe_missing(located.location, "file_path.location")?;
+	if location.node_id != Some(library.node_local_id) {
+		return Err(ValidatorError::LocationNotOnThisNode(location.id));
+	}
+	let location_path = maybe_missing(location.path, "location.path")?;
+
+	let file_path = db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.select(file_path_for_object_validator::select())
+		.exec()
+		.await?
+		.filter(|file_path| file_path.is_dir == Some(false))
+		.ok_or(ValidatorError::FilePathNotFound(file_path_id))?;
+
+	let algorithm = library.config.default_checksum_algorithm;
+	let full_path = extended_length_path(
+		Path::new(&location_path).join(IsolatedFilePathData::try_from((location.id, &file_path))?),
+	);
+	let checksum = file_checksum_with(
+		&full_path,
+		ReadOptions {
+			algorithm,
+			..Default::default()
+		},
+	)
+	.await
+	.map_err(|e| FileIOError::from((&full_path, e)))?;
+	if !checksums_match(expected, &checksum) {
+		return Err(ValidatorError::AcknowledgedChecksumChanged {
+			path: full_path.into_boxed_path(),
+			expected: expected.to_string(),
+			actual: checksum,
+		});
+	}
+
+	LibraryChecksumStore::new(library)
+		.put(
+			&file_path,
+			StoredChecksums {
+				checksum: Some(checksum.clone()),
+				algorithm,
+				..Default::default()
+			},
+		)
+		.await?;
+
+	let acknowledgment = Acknowledgment {
+		algorithm,
+		checksum,
+		at: Utc::now(),
+		by: Some(library.config().get().await.name),
+	};
+	let acknowledged_checksum = acknowledgment.checksum_to_db();
+	let date_acknowledged = DateTime::<FixedOffset>::from(acknowledgment.at);
+
+	let (sync_params, db_params): (Vec<_>, Vec<_>) = [
+		(
+			(
+				file_path::acknowledged_checksum::NAME,
+				json!(&acknowledged_checksum),
+			),
+			file_path::acknowledged_checksum::set(Some(acknowledged_checksum)),
+		),
+		(
+			(file_path::acknowledged_by::NAME, json!(&acknowledgment.by)),
+			file_path::acknowledged_by::set(acknowledgment.by.clone()),
+		),
+		(
+			(file_path::date_acknowledged::NAME, json!(date_acknowledged)),
+			file_path::date_acknowledged::set(Some(date_acknowledged)),
+		),
+	]
+	.into_iter()
+	.unzip();
+
+	sync.write_ops(
+		db,
+		(
+			sync_params
+				.into_iter()
+				.map(|(field, value)| {
+					sync.shared_update(
+						sync::file_path::SyncId {
+							pub_id: file_path.pub_id.clone(),
+						},
+						field,
+						value,
+					)
+				})
+				.collect(),
+			db.file_path().update(
+				file_path::pub_id::equals(file_path.pub_id.clone()),
+				db_params,
+			),
+		),
+	)
+	.await?;
+
+	info!(
+		"Acknowledged the contents of {} as a legitimate change",
+		full_path.display()
+	);
+
+	Ok(acknowledgment)
+}
+
+/// Acknowledgments of the location's files made after `since`, keyed by their path relative to
+/// the location
+pub async fn acknowledgments(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	since: Option<DateTime<Utc>>,
+) -> Result<HashMap<String, Acknowledgment>, ValidatorError> {
+	let file_paths = db
+		.file_path()
+		.find_many(chain_optional_iter(
+			[
+				file_path::location_id::equals(Some(location_id)),
+				file_path::acknowledged_checksum::not(None),
+				file_path::date_acknowledged::not(None),
+			],
+			[since.map(|since| file_path::date_acknowledged::gt(since.into()))],
+		))
+		.select(file_path_for_acknowledgment::select())
+		.exec()
+		.await?;
+
+	let mut acknowledgments = HashMap::with_capacity(file_paths.len());
+	for file_path in &file_paths {
+		let (Some(stored), Some(at)) = (
+			&file_path.acknowledged_checksum,
+			file_path.date_acknowledged,
+		) else {
+			continue;
+		};
+
+		if let Some(acknowledgment) =
+			Acknowledgment::from_db(stored, at, file_path.acknowledged_by.clone())
+		{
+			acknowledgments.insert(
+				IsolatedFilePathData::try_from((location_id, file_path))?.to_string(),
+				acknowledgment,
+			);
+		}
+	}
+
+	Ok(acknowledgments)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_acknowledgment_db_round_trip() {
+		let acknowledgment = Acknowledgment {
+			algorithm: ChecksumAlgorithm::Sha256,
+			checksum: "aaaa".to_string(),
+			at: DateTime::parse_from_rfc3339("2023-07-05T12:00:00Z")
+				.unwrap()
+				.into(),
+			by: Some("my node".to_string()),
+		};
+		let stored = acknowledgment.checksum_to_db();
+		assert_eq!(stored, "sha256:aaaa");
+
+		assert_eq!(
+			Acknowledgment::from_db(
+				&stored,
+				DateTime::parse_from_rfc3339("2023-07-05T12:00:00Z").unwrap(),
+				Some("my node".to_string()),
+			),
+			Some(acknowledgment)
+		);
+
+		assert_eq!(
+			Acknowledgment::from_db("aaaa", Utc::now().into(), None),
+			None
+		);
+		assert_eq!(
+			Acknowledgment::from_db("md4:aaaa", Utc::now().into(), None),
+			None
+		);
+	}
+}
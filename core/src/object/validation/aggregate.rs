@@ -0,0 +1,393 @@
+//! Aggregate checksum of a location, to tell if two locations hold the same files at a glance.
+//!
+//! Each file contributes a leaf, the blake3 hash of its path relative to the location and its
+//! `integrity_checksum`, and the aggregate is the sum of all the leaves modulo 2^256. Unlike a
+//! Merkle root over the sorted checksums, it doesn't depend on the order of the files, so it can
+//! be kept up to date without reading the others: adding a file adds its leaf, deleting a file
+//! subtracts its leaf, and a file whose contents changed has its old leaf subtracted and the new
+//! one added. Renaming a file changes its leaf too, as locations with the same contents under
+//! other paths aren't identical.
+//!
+//! The aggregate stored on a location is kept current that way whenever a file's checksum is
+//! stored, changed by an edit or the file pruned, see [`update_stored_aggregate`], so it doesn't
+//! wait for the next validation of the whole location. A file checksummed with another algorithm
+//! or left without a checksum clears it, as it can't vouch for that file anymore.
+
+use crate::{
+	library::Library,
+	location::file_path_helper::{
+		file_path_for_aggregate_checksum, file_path_for_stored_aggregate, IsolatedFilePathData,
+	},
+	prisma::{file_path, location, SortOrder},
+	sync,
+};
+
+use once_cell::sync::Lazy;
+use prisma_client_rust::QueryError;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use super::{hash::ChecksumAlgorithm, not_excluded, ValidatorError};
+
+const PAGE_SIZE: i64 = 1000;
+
+/// Stored aggregates are read, updated and written back, one file at a time so concurrent
+/// updates don't lose each other's
+static STORED_AGGREGATE_LOCK: Lazy<Mutex<()>> = Lazy::new(Mutex::default);
+
+/// 256 bits integer, as little endian limbs
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LocationAggregate([u64; 4]);
+
+impl LocationAggregate {
+	pub fn add(&mut self, relative_path: &str, checksum: &str) {
+		self.add_limbs(leaf(relative_path, checksum));
+	}
+
+	pub fn remove(&mut self, relative_path: &str, checksum: &str) {
+		// subtracting is adding the two's complement
+		self.add_limbs(leaf(relative_path, checksum).map(|limb| !limb));
+		self.add_limbs([1, 0, 0, 0]);
+	}
+
+	fn add_limbs(&mut self, other: [u64; 4]) {
+		let mut carry = false;
+		for (limb, other) in self.0.iter_mut().zip(other) {
+			let (sum, overflowed) = limb.overflowing_add(other);
+			let (sum, carried) = sum.overflowing_add(u64::from(carry));
+			*limb = sum;
+			carry = overflowed || carried;
+		}
+	}
+
+	pub fn to_hex(&self) -> String {
+		self.0
+			.iter()
+			.rev()
+			.map(|limb| format!("{limb:016x}"))
+			.collect()
+	}
+
+	pub fn from_hex(hex: &str) -> Option<Self> {
+		if hex.len() != 64 {
+			return None;
+		}
+
+		let mut limbs = [0; 4];
+		for (limb, digits) in limbs.iter_mut().rev().zip(hex.as_bytes().chunks(16)) {
+			*limb = u64::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+		}
+
+		Some(Self(limbs))
+	}
+}
+
+fn leaf(relative_path: &str, checksum: &str) -> [u64; 4] {
+	let mut hasher = blake3::Hasher::new();
+	hasher.update(relative_path.as_bytes());
+	// paths can't hold nul bytes, so a path and checksum pair can't be read as another one
+	hasher.update(&[0]);
+	hasher.update(checksum.as_bytes());
+	let hash = hasher.finalize();
+
+	std::array::from_fn(|index| {
+		let mut limb = [0; 8];
+		limb.copy_from_slice(&hash.as_bytes()[index * 8..(index + 1) * 8]);
+		u64::from_le_bytes(limb)
+	})
+}
+
+/// Computes the aggregate checksum of every file in the location, but those excluded from
+/// validation, and stores it on its row. It's cleared instead when any file lacks a checksum for
+/// `algorithm`, as it couldn't vouch for the contents of those files. Returns the stored aggregate.
+pub async fn update_aggregate_checksum(
+	library: &Library,
+	location: &location::Data,
+	algorithm: ChecksumAlgorithm,
+) -> Result<Option<String>, ValidatorError> {
+	let Library { db, .. } = library;
+
+	let _guard = STORED_AGGREGATE_LOCK.lock().await;
+
+	let mut aggregate = Some(LocationAggregate::default());
+	let mut last_id = None;
+
+	while aggregate.is_some() {
+		let file_paths = db
+			.file_path()
+			.find_many(
+				[
+					file_path::location_id::equals(Some(location.id)),
+					file_path::is_dir::equals(Some(false)),
+					not_excluded(),
+				]
+				.into_iter()
+				.chain(last_id.map(file_path::id::gt))
+				.collect(),
+			)
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(PAGE_SIZE)
+			.select(file_path_for_aggregate_checksum::select())
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		last_id = Some(last.id);
+
+		for file_path in &file_paths {
+			let relative_path = IsolatedFilePathData::try_from((location.id, file_path))?;
+			match (
+				&file_path.integrity_checksum,
+				ChecksumAlgorithm::from_db(file_path.integrity_checksum_algorithm.as_deref()),
+				&mut aggregate,
+			) {
+				(Some(checksum), Some(file_algorithm), Some(aggregate))
+					if file_algorithm == algorithm =>
+				{
+					aggregate.add(&relative_path.to_string(), checksum);
+				}
+				_ => aggregate = None,
+			}
+		}
+	}
+
+	let aggregate = aggregate.map(|aggregate| aggregate.to_hex());
+	store_aggregate(
+		library,
+		location.id,
+		&location.pub_id,
+		aggregate.clone(),
+		algorithm,
+	)
+	.await?;
+
+	Ok(aggregate)
+}
+
+/// Keeps the aggregate stored on the location of the file path with `pub_id` current once its
+/// checksum was changed from `previous`, a checksum and its algorithm as stored. Locations without
+/// a stored aggregate are left alone.
+pub async fn update_stored_aggregate(
+	library: &Library,
+	pub_id: &[u8],
+	(previous_checksum, previous_algorithm): (Option<&str>, Option<&str>),
+) -> Result<(), QueryError> {
+	update_stored_aggregate_with(
+		library,
+		pub_id,
+		|aggregate, algorithm, relative_path, file_path| {
+			if let Some(previous_checksum) = previous_checksum
+				.filter(|_| ChecksumAlgorithm::from_db(previous_algorithm) == Some(algorithm))
+			{
+				aggregate.remove(relative_path, previous_checksum);
+			}
+
+			match (
+				&file_path.integrity_checksum,
+				ChecksumAlgorithm::from_db(file_path.integrity_checksum_algorithm.as_deref()),
+			) {
+				(Some(checksum), Some(file_algorithm)) if file_algorithm == algorithm => {
+					aggregate.add(relative_path, checksum);
+					true
+				}
+				_ => false,
+			}
+		},
+	)
+	.await
+}
+
+/// Takes the file path with `pub_id` out of the aggregate stored on its location, before its row
+/// is deleted
+pub async fn remove_from_stored_aggregate(
+	library: &Library,
+	pub_id: &[u8],
+) -> Result<(), QueryError> {
+	update_stored_aggregate_with(
+		library,
+		pub_id,
+		|aggregate, algorithm, relative_path, file_path| {
+			if let (Some(checksum), Some(file_algorithm)) = (
+				&file_path.integrity_checksum,
+				ChecksumAlgorithm::from_db(file_path.integrity_checksum_algorithm.as_deref()),
+			) {
+				if file_algorithm == algorithm {
+					aggregate.remove(relative_path, checksum);
+				}
+			}
+
+			true
+		},
+	)
+	.await
+}
+
+/// Applies `update` to the aggregate stored on the location of the file path with `pub_id`, along
+/// with the aggregate's algorithm and the file's path relative to the location. The aggregate is
+/// cleared when `update` returns `false`.
+async fn update_stored_aggregate_with(
+	library: &Library,
+	pub_id: &[u8],
+	update: impl FnOnce(
+		&mut LocationAggregate,
+		ChecksumAlgorithm,
+		&str,
+		&file_path_for_stored_aggregate::Data,
+	) -> bool,
+) -> Result<(), QueryError> {
+	let _guard = STORED_AGGREGATE_LOCK.lock().await;
+
+	let Some(file_path) = library
+		.db
+		.file_path()
+		.find_unique(file_path::pub_id::equals(pub_id.to_vec()))
+		.select(file_path_for_stored_aggregate::select())
+		.exec()
+		.await?
+	else {
+		return Ok(());
+	};
+
+	// directories and excluded files aren't part of it
+	if file_path.is_dir != Some(false) || file_path.exclude_from_validation == Some(true) {
+		return Ok(());
+	}
+
+	let Some(location) = &file_path.location else {
+		return Ok(());
+	};
+	let (Some(mut aggregate), Some(algorithm)) = (
+		location
+			.aggregate_checksum
+			.as_deref()
+			.and_then(LocationAggregate::from_hex),
+		location
+			.aggregate_checksum_algorithm
+			.as_deref()
+			.and_then(|algorithm| ChecksumAlgorithm::from_db(Some(algorithm))),
+	) else {
+		return Ok(());
+	};
+
+	let updated = match IsolatedFilePathData::try_from((location.id, &file_path)) {
+		Ok(relative_path) => update(
+			&mut aggregate,
+			algorithm,
+			&relative_path.to_string(),
+			&file_path,
+		),
+		// its leaf can't be told, so the aggregate can't be kept right
+		Err(_) => false,
+	};
+
+	store_aggregate(
+		library,
+		location.id,
+		&location.pub_id,
+		updated.then(|| aggregate.to_hex()),
+		algorithm,
+	)
+	.await
+}
+
+/// Stores `aggregate` on the location, with the algorithm of the checksums it was computed from
+async fn store_aggregate(
+	library: &Library,
+	location_id: location::id::Type,
+	location_pub_id: &[u8],
+	aggregate: Option<String>,
+	algorithm: ChecksumAlgorithm,
+) -> Result<(), QueryError> {
+	let Library { db, sync, .. } = library;
+
+	let algorithm = aggregate.as_ref().map(|_| algorithm.as_str().to_string());
+
+	sync.write_ops(
+		db,
+		(
+			[
+				(location::aggregate_checksum::NAME, json!(aggregate)),
+				(
+					location::aggregate_checksum_algorithm::NAME,
+					json!(algorithm),
+				),
+			]
+			.into_iter()
+			.map(|(field, value)| {
+				sync.shared_update(
+					sync::location::SyncId {
+						pub_id: location_pub_id.to_vec(),
+					},
+					field,
+					value,
+				)
+			})
+			.collect(),
+			db.location().update(
+				location::id::equals(location_id),
+				vec![
+					location::aggregate_checksum::set(aggregate),
+					location::aggregate_checksum_algorithm::set(algorithm),
+				],
+			),
+		),
+	)
+	.await?;
+
+	Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_location_aggregate() {
+		let files = [("a.txt", "aaaa"), ("docs/b.txt", "bbbb"), ("c.txt", "cccc")];
+
+		let mut forward = LocationAggregate::default();
+		for (path, checksum) in files {
+			forward.add(path, checksum);
+		}
+		let mut backward = LocationAggregate::default();
+		for (path, checksum) in files.iter().rev() {
+			backward.add(path, checksum);
+		}
+		assert_eq!(forward, backward);
+		assert_ne!(forward, LocationAggregate::default());
+
+		// a deleted file leaves the aggregate as if it was never there
+		let mut deleted = forward;
+		deleted.remove("c.txt", "cccc");
+		let mut never_added = LocationAggregate::default();
+		never_added.add("a.txt", "aaaa");
+		never_added.add("docs/b.txt", "bbbb");
+		assert_eq!(deleted, never_added);
+
+		// moving a file changes the aggregate, even if the contents are the same
+		let mut moved = never_added;
+		moved.add("docs/c.txt", "cccc");
+		assert_ne!(moved, forward);
+
+		assert_eq!(
+			LocationAggregate::from_hex(&forward.to_hex()),
+			Some(forward)
+		);
+		assert_eq!(forward.to_hex().len(), 64);
+		assert_eq!(LocationAggregate::from_hex("not hex"), None);
+	}
+
+	#[test]
+	fn test_location_aggregate_wraps() {
+		let mut aggregate = LocationAggregate::default();
+		aggregate.remove("a.txt", "aaaa");
+		aggregate.add("b.txt", "bbbb");
+		aggregate.add("a.txt", "aaaa");
+		aggregate.remove("b.txt", "bbbb");
+		assert_eq!(aggregate, LocationAggregate::default());
+	}
+}
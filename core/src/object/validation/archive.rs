@@ -0,0 +1,661 @@
+use crate::{
+	library::Library,
+	location::file_path_helper::{file_path_for_object_validator, IsolatedFilePathData},
+	prisma::{file_path, location},
+	util::error::FileIOError,
+};
+
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	path::Path,
+};
+
+use serde::Serialize;
+use specta::Type;
+use tokio::{
+	fs::File,
+	io::{self, AsyncRead, AsyncReadExt, BufReader},
+};
+
+use super::{
+	external::normalize_path,
+	hash::{checksums_match, reader_checksum, ChecksumAlgorithm},
+	manifest::missing_from_location,
+	ValidatorError,
+};
+
+const TAR_BLOCK_LEN: u64 = 512;
+/// Longest content of the entries holding a path or the records of the next one, which are read
+/// into memory, as a corrupt size could otherwise be terabytes
+const MAX_EXTENSION_LEN: u64 = 1024 * 1024;
+
+/// How an entry of an archive compares with the file the library has at its path
+#[derive(Serialize, Type, Debug, Clone, PartialEq, Eq)]
+pub enum ArchiveEntryOutcome {
+	/// The entry has the checksum stored for its file
+	Matched,
+	/// The entry's checksum isn't the one stored for its file
+	Mismatched { expected: String, actual: String },
+	/// The library has no checksum for a file at the entry's path to compare it with
+	NoStoredChecksum,
+	/// A file with a stored checksum isn't in the archive
+	MissingFromArchive,
+	/// The entry is a hard link to `target`, a file archived before it, so it has the same
+	/// contents and they're only compared with the library's under `target`
+	HardLink { target: String },
+}
+
+/// Outcome of each entry, keyed by its path relative to the location
+#[derive(Serialize, Type, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ArchiveVerification {
+	pub entries: BTreeMap<String, ArchiveEntryOutcome>,
+}
+
+struct StoredChecksum {
+	checksum: String,
+	algorithm: ChecksumAlgorithm,
+}
+
+/// Checks a tar export of a location against the checksums stored for its files, hashing the
+/// entries as the archive is read so nothing has to be extracted. Entries are matched by their
+/// path relative to `root`, the archive's root when `None`, entries outside of it are ignored.
+/// Zip archives aren't supported, as their entries can only be told apart reliably from the
+/// central directory at their end.
+pub async fn verify_tar_archive(
+	library: &Library,
+	location_id: location::id::Type,
+	archive_path: &Path,
+	root: Option<&str>,
+) -> Result<ArchiveVerification, ValidatorError> {
+	let stored = stored_checksums(library, location_id).await?;
+
+	let root = root
+		.map(normalize_path)
+		.map(|root| format!("{}/", root.trim_end_matches('/')))
+		.filter(|root| root != "/");
+
+	let file = File::open(archive_path)
+		.await
+		.map_err(|e| FileIOError::from((archive_path, e)))?;
+	let mut archive = TarReader::new(BufReader::new(file));
+
+	let archive_error = |archive: &TarReader<_>, e: io::Error| match e.kind() {
+		io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+			ValidatorError::InvalidArchive {
+				path: archive_path.into(),
+				offset: archive.offset,
+			}
+		}
+		_ => FileIOError::from((archive_path, e)).into(),
+	};
+
+	let relative_to_root = |entry_path: String| match &root {
+		Some(root) => entry_path
+			.strip_prefix(root.as_str())
+			.map(ToString::to_string),
+		None => Some(entry_path),
+	};
+
+	let mut verification = ArchiveVerification::default();
+	let mut listed = HashSet::new();
+
+	loop {
+		let (entry_path, link_target) = match archive.next_entry().await {
+			Ok(Some(TarEntry::File(path))) => (normalize_path(&path), None),
+			Ok(Some(TarEntry::HardLink { path, target })) => {
+				(normalize_path(&path), Some(normalize_path(&target)))
+			}
+			Ok(None) => break,
+			Err(e) => return Err(archive_error(&archive, e)),
+		};
+		let Some(relative_path) = relative_to_root(entry_path) else {
+			continue;
+		};
+
+		let outcome = match (link_target, stored.get(&relative_path)) {
+			(Some(target), _) => ArchiveEntryOutcome::HardLink {
+				target: relative_to_root(target.clone()).unwrap_or(target),
+			},
+			(None, None) => ArchiveEntryOutcome::NoStoredChecksum,
+			(
+				None,
+				Some(StoredChecksum {
+					checksum,
+					algorithm,
+				}),
+			) => {
+				let actual = match archive.content_checksum(*algorithm).await {
+					Ok(actual) => actual,
+					Err(e) => return Err(archive_error(&archive, e)),
+				};
+
+				if checksums_match(checksum, &actual) {
+					ArchiveEntryOutcome::Matched
+				} else {
+					ArchiveEntryOutcome::Mismatched {
+						expected: checksum.clone(),
+						actual,
+					}
+				}
+			}
+		};
+
+		listed.insert(relative_path.clone());
+		verification.entries.insert(relative_path, outcome);
+	}
+
+	for relative_path in missing_from_location(&stored, &listed, None) {
+		verification.entries.insert(
+			relative_path.to_string(),
+			ArchiveEntryOutcome::MissingFromArchive,
+		);
+	}
+
+	Ok(verification)
+}
+
+/// Checksums of the location's files, keyed by their path relative to the location like
+/// manifests are
+async fn stored_checksums(
+	library: &Library,
+	location_id: location::id::Type,
+) -> Result<HashMap<String, StoredChecksum>, ValidatorError> {
+	library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::is_dir::equals(Some(false)),
+			file_path::integrity_checksum::not(None),
+		])
+		.select(file_path_for_object_validator::select())
+		.exec()
+		.await?
+		.into_iter()
+		.filter_map(|file_path| {
+			// checksums with an algorithm we don't know can't be compared with anything
+			let algorithm =
+				ChecksumAlgorithm::from_db(file_path.integrity_checksum_algorithm.as_deref())?;
+			let relative_path = match IsolatedFilePathData::try_from((location_id, &file_path)) {
+				Ok(iso_file_path) => iso_file_path.to_string(),
+				Err(e) => return Some(Err(e.into())),
+			};
+
+			file_path.integrity_checksum.map(|checksum| {
+				Ok((
+					relative_path,
+					StoredChecksum {
+						checksum,
+						algorithm,
+					},
+				))
+			})
+		})
+		.collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum TarEntry {
+	File(String),
+	/// archivers store the files with several links once, the other paths point at the first
+	HardLink {
+		path: String,
+		target: String,
+	},
+}
+
+/// Reads the regular files and hard links of a tar archive one after the other, in the ustar,
+/// GNU and pax formats, without seeking so the archive can be streamed
+struct TarReader<R> {
+	reader: R,
+	/// how far into the archive we are, to point at the damage when it's malformed
+	offset: u64,
+	/// content of the last entry left to read, with its padding
+	pending: u64,
+	/// length of the content of the last entry
+	content_len: u64,
+}
+
+impl<R: AsyncRead + Unpin> TarReader<R> {
+	fn new(reader: R) -> Self {
+		Self {
+			reader,
+			offset: 0,
+			pending: 0,
+			content_len: 0,
+		}
+	}
+
+	/// The next regular file or hard link, skipping directories, symbolic links and the rest of
+	/// the last file's content. `None` once the archive ends.
+	async fn next_entry(&mut self) -> Result<Option<TarEntry>, io::Error> {
+		self.skip(self.pending).await?;
+
+		// set by the extension entries preceding the ones they describe
+		let mut long_path = None;
+		let mut long_link = None;
+		let mut long_len = None;
+
+		loop {
+			let mut header = [0; TAR_BLOCK_LEN as usize];
+			let mut filled = 0;
+			while filled < header.len() {
+				match self.reader.read(&mut header[filled..]).await? {
+					0 => break,
+					read => filled += read,
+				}
+			}
+			self.offset += filled as u64;
+
+			match filled {
+				// some archivers leave out the end of archive blocks
+				0 if long_path.is_none() && long_link.is_none() && long_len.is_none() => {
+					return Ok(None)
+				}
+				filled if filled < header.len() => return Err(io::ErrorKind::UnexpectedEof.into()),
+				_ if header.iter().all(|byte| *byte == 0) => return Ok(None),
+				_ => {}
+			}
+			if !has_valid_checksum(&header) {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					"tar header checksum mismatch",
+				));
+			}
+
+			let len = parse_number(&header[124..136])
+				.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid entry size"))?;
+			let len = long_len.take().unwrap_or(len);
+
+			match header[156] {
+				// GNU long name, the entry's content is the path of the next one
+				b'L' => {
+					let content = self.read_content(len).await?;
+					long_path = Some(c_string(&content));
+				}
+				// GNU long link name, the target of the next entry
+				b'K' => {
+					let content = self.read_content(len).await?;
+					long_link = Some(c_string(&content));
+				}
+				// pax extended header, its records override fields of the next entry
+				b'x' => {
+					let content = self.read_content(len).await?;
+					for (key, value) in pax_records(&content) {
+						match key {
+							"path" => long_path = Some(value.to_string()),
+							"linkpath" => long_link = Some(value.to_string()),
+							"size" => long_len = value.parse().ok(),
+							_ => {}
+						}
+					}
+				}
+				b'0' | b'\0' | b'7' => {
+					self.content_len = len;
+					self.pending = padded(len)?;
+
+					return Ok(Some(TarEntry::File(
+						long_path.take().unwrap_or_else(|| ustar_path(&header)),
+					)));
+				}
+				b'1' => {
+					// the content is the target's, archivers don't store any with the link
+					self.content_len = 0;
+					self.pending = padded(len)?;
+
+					return Ok(Some(TarEntry::HardLink {
+						path: long_path.take().unwrap_or_else(|| ustar_path(&header)),
+						target: long_link
+							.take()
+							.unwrap_or_else(|| c_string(&header[157..257])),
+					}));
+				}
+				_ => {
+					self.skip(padded(len)?).await?;
+					long_path = None;
+					long_link = None;
+				}
+			}
+		}
+	}
+
+	/// Checksum of the content of the file [`Self::next_entry`] returned last
+	async fn content_checksum(
+		&mut self,
+		algorithm: ChecksumAlgorithm,
+	) -> Result<String, io::Error> {
+		let (checksum, len) =
+			reader_checksum((&mut self.reader).take(self.content_len), algorithm).await?;
+		self.offset += len;
+		self.pending -= len;
+
+		if len == self.content_len {
+			Ok(checksum)
+		} else {
+			Err(io::ErrorKind::UnexpectedEof.into())
+		}
+	}
+
+	async fn read_content(&mut self, len: u64) -> Result<Vec<u8>, io::Error> {
+		if len > MAX_EXTENSION_LEN {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidData,
+				"tar extension entry too long",
+			));
+		}
+
+		let mut content = vec![];
+		(&mut self.reader)
+			.take(len)
+			.read_to_end(&mut content)
+			.await?;
+		self.offset += content.len() as u64;
+		if content.len() as u64 != len {
+			return Err(io::ErrorKind::UnexpectedEof.into());
+		}
+
+		self.skip(padded(len)? - len).await?;
+
+		Ok(content)
+	}
+
+	async fn skip(&mut self, len: u64) -> Result<(), io::Error> {
+		let skipped = io::copy(&mut (&mut self.reader).take(len), &mut io::sink()).await?;
+		self.offset += skipped;
+		self.pending = 0;
+
+		if skipped == len {
+			Ok(())
+		} else {
+			Err(io::ErrorKind::UnexpectedEof.into())
+		}
+	}
+}
+
+/// Contents are padded to whole blocks
+fn padded(len: u64) -> Result<u64, io::Error> {
+	len.checked_add(TAR_BLOCK_LEN - 1)
+		.map(|len| len / TAR_BLOCK_LEN * TAR_BLOCK_LEN)
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid entry size"))
+}
+
+/// The checksum field holds the sum of the header's bytes, counting its own as spaces
+fn has_valid_checksum(header: &[u8; TAR_BLOCK_LEN as usize]) -> bool {
+	let sum = header
+		.iter()
+		.enumerate()
+		.map(|(i, byte)| {
+			if (148..156).contains(&i) {
+				u64::from(b' ')
+			} else {
+				u64::from(*byte)
+			}
+		})
+		.sum::<u64>();
+
+	parse_number(&header[148..156]) == Some(sum)
+}
+
+/// Numeric fields are octal, padded with spaces or NULs, or big endian binary when their first
+/// bit is set, as GNU tar does for files over 8GiB
+fn parse_number(field: &[u8]) -> Option<u64> {
+	if field.first()? & 0x80 != 0 {
+		return field[1..]
+			.iter()
+			.try_fold(u64::from(field[0] & 0x7f), |number, byte| {
+				number.checked_mul(256)?.checked_add(u64::from(*byte))
+			});
+	}
+
+	let digits = std::str::from_utf8(field)
+		.ok()?
+		.trim_matches(|c: char| c == ' ' || c == '\0');
+	if digits.is_empty() {
+		Some(0)
+	} else {
+		u64::from_str_radix(digits, 8).ok()
+	}
+}
+
+fn c_string(bytes: &[u8]) -> String {
+	let len = bytes
+		.iter()
+		.position(|byte| *byte == 0)
+		.unwrap_or(bytes.len());
+	String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Path of an entry from its header, ustar headers can split it in a prefix and a name
+fn ustar_path(header: &[u8; TAR_BLOCK_LEN as usize]) -> String {
+	let name = c_string(&header[0..100]);
+	let prefix = c_string(&header[345..500]);
+
+	if &header[257..262] == b"ustar" && !prefix.is_empty() {
+		format!("{prefix}/{name}")
+	} else {
+		name
+	}
+}
+
+/// Records of a pax extended header, each `<len> <key>=<value>\n` with `len` counting the whole
+/// record
+fn pax_records(content: &[u8]) -> Vec<(&str, &str)> {
+	let mut records = vec![];
+	let mut rest = content;
+
+	while let Some(space) = rest.iter().position(|byte| *byte == b' ') {
+		let Some(len) = std::str::from_utf8(&rest[..space])
+			.ok()
+			.and_then(|len| len.parse::<usize>().ok())
+			.filter(|len| *len > space && *len <= rest.len())
+		else {
+			break;
+		};
+
+		if let Some((key, value)) = std::str::from_utf8(&rest[space + 1..len])
+			.ok()
+			.and_then(|record| record.strip_suffix('\n'))
+			.and_then(|record| record.split_once('='))
+		{
+			records.push((key, value));
+		}
+		rest = &rest[len..];
+	}
+
+	records
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	fn header(path: &str, type_flag: u8, len: usize) -> Vec<u8> {
+		let mut header = vec![0; TAR_BLOCK_LEN as usize];
+		header[..path.len()].copy_from_slice(path.as_bytes());
+		header[124..135].copy_from_slice(format!("{len:011o}").as_bytes());
+		header[156] = type_flag;
+		header[257..263].copy_from_slice(b"ustar\0");
+
+		seal(&mut header);
+		header
+	}
+
+	fn seal(header: &mut [u8]) {
+		header[148..156].fill(b' ');
+		let sum = header.iter().map(|byte| u64::from(*byte)).sum::<u64>();
+		header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+	}
+
+	fn hard_link(path: &str, target: &str) -> Vec<u8> {
+		let mut header = header(path, b'1', 0);
+		header[157..157 + target.len()].copy_from_slice(target.as_bytes());
+		seal(&mut header);
+		header
+	}
+
+	fn entry(path: &str, type_flag: u8, content: &[u8]) -> Vec<u8> {
+		let mut entry = header(path, type_flag, content.len());
+		entry.extend_from_slice(content);
+		entry.resize(
+			TAR_BLOCK_LEN as usize + padded(content.len() as u64).unwrap() as usize,
+			0,
+		);
+		entry
+	}
+
+	fn pax_record(key: &str, value: &str) -> String {
+		let record = format!(" {key}={value}\n");
+		// the length counts its own digits
+		let mut len = record.len();
+		while len != len.to_string().len() + record.len() {
+			len = len.to_string().len() + record.len();
+		}
+		format!("{len}{record}")
+	}
+
+	#[test]
+	fn test_pax_records() {
+		let content = format!(
+			"{}{}",
+			pax_record("path", "docs/a very long name.txt"),
+			pax_record("mtime", "1700000000.5")
+		);
+
+		assert_eq!(
+			pax_records(content.as_bytes()),
+			vec![
+				("path", "docs/a very long name.txt"),
+				("mtime", "1700000000.5")
+			]
+		);
+		assert_eq!(pax_records(b"99 path=truncated\n"), vec![]);
+	}
+
+	#[test]
+	fn test_parse_number() {
+		assert_eq!(parse_number(b"00000000012\0"), Some(10));
+		assert_eq!(parse_number(b"     12 \0"), Some(10));
+		assert_eq!(parse_number(b"\0\0\0\0"), Some(0));
+		assert_eq!(
+			parse_number(&[0x80, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0]),
+			Some(8 << 30)
+		);
+		assert_eq!(parse_number(b"9"), None);
+	}
+
+	#[tokio::test]
+	async fn test_tar_reader() {
+		let long_path = format!("docs/{}.txt", "long".repeat(40));
+		let pax_path = "docs/pax name.txt";
+
+		let mut archive = vec![];
+		archive.extend(entry("docs/", b'5', b""));
+		archive.extend(entry("docs/a.txt", b'0', b"spacedrive"));
+		archive.extend(entry("docs/link", b'2', b""));
+		archive.extend(hard_link("docs/b.txt", "docs/a.txt"));
+		archive.extend(entry("././@LongLink", b'L', long_path.as_bytes()));
+		archive.extend(entry("docs/trunc", b'0', b"long name"));
+		archive.extend(entry(
+			"PaxHeaders/x",
+			b'x',
+			pax_record("path", pax_path).as_bytes(),
+		));
+		archive.extend(entry("docs/pax name", b'0', &[7; 600]));
+		archive.extend(entry("docs/unread.txt", b'0', b"skipped"));
+		archive.extend([0; 2 * TAR_BLOCK_LEN as usize]);
+
+		let mut reader = TarReader::new(archive.as_slice());
+
+		let mut files = vec![];
+		while let Some(entry) = reader.next_entry().await.unwrap() {
+			let checksum = match &entry {
+				TarEntry::File(path) if !path.ends_with("unread.txt") => Some(
+					reader
+						.content_checksum(ChecksumAlgorithm::Blake3)
+						.await
+						.unwrap(),
+				),
+				_ => None,
+			};
+			files.push((entry, checksum));
+		}
+
+		let checksum = |content: &[u8]| Some(blake3::hash(content).to_hex().to_string());
+		let expected = vec![
+			(
+				TarEntry::File("docs/a.txt".to_string()),
+				checksum(b"spacedrive"),
+			),
+			(
+				TarEntry::HardLink {
+					path: "docs/b.txt".to_string(),
+					target: "docs/a.txt".to_string(),
+				},
+				None,
+			),
+			(TarEntry::File(long_path), checksum(b"long name")),
+			(TarEntry::File(pax_path.to_string()), checksum(&[7; 600])),
+			(TarEntry::File("docs/unread.txt".to_string()), None),
+		];
+
+		assert_eq!(files, expected);
+		assert_eq!(reader.offset, archive.len() as u64 - TAR_BLOCK_LEN);
+	}
+
+	#[tokio::test]
+	async fn test_tar_reader_corruption() {
+		let mut archive = entry("a.txt", b'0', b"spacedrive");
+		archive[0] = b'b';
+		assert_eq!(
+			TarReader::new(archive.as_slice())
+				.next_entry()
+				.await
+				.unwrap_err()
+				.kind(),
+			io::ErrorKind::InvalidData
+		);
+
+		// cut off in the middle of the content
+		let archive = entry("a.txt", b'0', b"spacedrive");
+		let mut reader = TarReader::new(&archive[..TAR_BLOCK_LEN as usize + 4]);
+		assert_eq!(
+			reader.next_entry().await.unwrap(),
+			Some(TarEntry::File("a.txt".to_string()))
+		);
+		assert_eq!(
+			reader
+				.content_checksum(ChecksumAlgorithm::Blake3)
+				.await
+				.unwrap_err()
+				.kind(),
+			io::ErrorKind::UnexpectedEof
+		);
+
+		// sizes padding past the largest offset
+		let mut archive = header("a.txt", b'0', 0);
+		archive[124..136].copy_from_slice(&[
+			0x80, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+		]);
+		seal(&mut archive);
+		assert_eq!(
+			TarReader::new(archive.as_slice())
+				.next_entry()
+				.await
+				.unwrap_err()
+				.kind(),
+			io::ErrorKind::InvalidData
+		);
+
+		// a long name that would have to be read whole before the next entry
+		let archive = header("././@LongLink", b'L', 2 * MAX_EXTENSION_LEN as usize);
+		assert_eq!(
+			TarReader::new(archive.as_slice())
+				.next_entry()
+				.await
+				.unwrap_err()
+				.kind(),
+			io::ErrorKind::InvalidData
+		);
+	}
+}
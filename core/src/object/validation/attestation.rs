@@ -0,0 +1,275 @@
+//! Signed attestations of clean audits, evidence that as of a given time every file of a location
+//! matched a signed manifest. The attestation is signed with the library's key, the same one it's
+//! identified by over p2p, and its signature is detached so the attestation stays readable. What's
+//! signed starts with [`ATTESTATION_SIGNING_CONTEXT`], so the signature can't be passed off as one
+//! the key made for anything else, nor can signatures it made elsewhere be passed off as one of an
+//! attestation.
+
+use crate::{library::Library, prisma::location, util::error::FileIOError};
+
+use std::{
+	collections::HashMap,
+	ffi::OsStr,
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Keypair, Signer};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::fs;
+use uuid::Uuid;
+
+use super::{
+	hash::ChecksumAlgorithm, FileValidationOutcome, ObjectValidatorReport, SignedManifest,
+	ValidatorError,
+};
+
+pub const ATTESTATION_FILE_NAME: &str = "attestation.json";
+/// hex encoded ed25519 signature of the attestation's bytes, see [`signed_attestation`]
+pub const ATTESTATION_SIGNATURE_FILE_NAME: &str = "attestation.json.sig";
+/// Prefix of the attestation's bytes in what's signed, the nul byte can't be in the JSON
+pub const ATTESTATION_SIGNING_CONTEXT: &[u8] = b"spacedrive-attestation-v1\0";
+
+/// What's signed for the attestation with the bytes `content`
+pub fn signed_attestation(content: &[u8]) -> Vec<u8> {
+	[ATTESTATION_SIGNING_CONTEXT, content].concat()
+}
+
+/// What a clean audit asserts, written as JSON along with a copy of the manifest it was run against
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Attestation {
+	pub library_id: Uuid,
+	pub location_id: location::id::Type,
+	pub location_path: PathBuf,
+	pub sub_path: Option<PathBuf>,
+	pub attested_at: DateTime<Utc>,
+	/// files that matched the manifest, every file audited did
+	pub files: usize,
+	/// files the user excluded from validation, which weren't audited
+	pub excluded: usize,
+	/// empty files left out, when asked to
+	pub skipped_empty: usize,
+	pub algorithm: ChecksumAlgorithm,
+	/// hex encoded sha256 of the manifest's bytes, which the copy next to the attestation has
+	pub manifest_sha256: String,
+	/// hex encoded ed25519 public key of the library, to check the signature with
+	pub public_key: String,
+}
+
+impl Attestation {
+	/// Summary of the audit in `report`, `None` unless every file matched the manifest, as then
+	/// there's nothing to attest
+	pub fn of_audit(
+		library_id: Uuid,
+		location_path: impl Into<PathBuf>,
+		algorithm: ChecksumAlgorithm,
+		manifest_content: &[u8],
+		report: &ObjectValidatorReport,
+		keypair: &Keypair,
+	) -> Option<Self> {
+		if !report.files.values().all(|outcome| {
+			matches!(
+				outcome,
+				FileValidationOutcome::Verified | FileValidationOutcome::VerifiedRemotely
+			)
+		}) {
+			return None;
+		}
+
+		Some(Self {
+			library_id,
+			location_id: report.location_id,
+			location_path: location_path.into(),
+			sub_path: report.sub_path.clone(),
+			attested_at: Utc::now(),
+			files: report.files.len(),
+			excluded: report.excluded,
+			skipped_empty: report.skipped_empty,
+			algorithm,
+			manifest_sha256: hex::encode(Sha256::digest(manifest_content)),
+			public_key: hex::encode(keypair.public.as_bytes()),
+		})
+	}
+}
+
+/// Writes the manifest as `manifest_file_name`, the attestation and its detached signature to
+/// `dir`, creating it if needed
+pub async fn write_attestation(
+	dir: &Path,
+	manifest_file_name: &OsStr,
+	manifest_content: &[u8],
+	attestation: &Attestation,
+	keypair: &Keypair,
+) -> Result<(), ValidatorError> {
+	let content = serde_json::to_vec_pretty(attestation)?;
+	let signature = hex::encode(keypair.sign(&signed_attestation(&content)).to_bytes());
+
+	fs::create_dir_all(dir)
+		.await
+		.map_err(|e| FileIOError::from((dir, e)))?;
+
+	for (file_name, bytes) in [
+		(manifest_file_name, manifest_content),
+		(OsStr::new(ATTESTATION_FILE_NAME), content.as_slice()),
+		(
+			OsStr::new(ATTESTATION_SIGNATURE_FILE_NAME),
+			signature.as_bytes(),
+		),
+	] {
+		let path = dir.join(file_name);
+		fs::write(&path, bytes)
+			.await
+			.map_err(|e| FileIOError::from((&path, e)))?;
+	}
+
+	Ok(())
+}
+
+/// Attests the audit of a location against `manifest` in `report` to `dir`, if no file failed
+/// it. The manifest is read again so the copy is the one the files were audited against, the
+/// audit can't be attested if it changed since.
+pub async fn attest_audit(
+	library: &Library,
+	location_path: &Path,
+	manifest: &SignedManifest,
+	checksums: &HashMap<String, String>,
+	report: &ObjectValidatorReport,
+	dir: &Path,
+) -> Result<Option<Attestation>, ValidatorError> {
+	let content = manifest.read_verified().await?;
+	if manifest.parse(&content)? != *checksums {
+		return Err(ValidatorError::ManifestChanged(
+			manifest.path.clone().into_boxed_path(),
+		));
+	}
+
+	let keypair = Keypair::from_bytes(&library.identity.to_bytes())?;
+	let Some(attestation) = Attestation::of_audit(
+		library.id,
+		location_path,
+		manifest.algorithm,
+		&content,
+		report,
+		&keypair,
+	) else {
+		return Ok(None);
+	};
+
+	write_attestation(
+		dir,
+		manifest
+			.path
+			.file_name()
+			.unwrap_or_else(|| OsStr::new("manifest")),
+		&content,
+		&attestation,
+		&keypair,
+	)
+	.await?;
+
+	Ok(Some(attestation))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use crate::object::validation::manifest::verify_signature;
+
+	use ed25519_dalek::{PublicKey, SecretKey};
+	use tempfile::tempdir;
+
+	fn keypair() -> Keypair {
+		let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+		let public = PublicKey::from(&secret);
+		Keypair { secret, public }
+	}
+
+	#[tokio::test]
+	async fn test_write_attestation() {
+		let dir = tempdir().unwrap();
+		let keypair = keypair();
+		let manifest_content = b"aaaa  ./a.txt\n";
+		let mut report = ObjectValidatorReport {
+			location_id: 1,
+			files: [
+				("a.txt".to_string(), FileValidationOutcome::Verified),
+				("b.txt".to_string(), FileValidationOutcome::VerifiedRemotely),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+
+		let attestation = Attestation::of_audit(
+			Uuid::new_v4(),
+			"/location",
+			ChecksumAlgorithm::Blake3,
+			manifest_content,
+			&report,
+			&keypair,
+		)
+		.unwrap();
+		assert_eq!(attestation.files, 2);
+		assert_eq!(
+			attestation.manifest_sha256,
+			hex::encode(Sha256::digest(manifest_content))
+		);
+
+		let out = dir.path().join("attestations");
+		write_attestation(
+			&out,
+			OsStr::new("manifest.txt"),
+			manifest_content,
+			&attestation,
+			&keypair,
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(
+			fs::read(out.join("manifest.txt")).await.unwrap(),
+			manifest_content
+		);
+		let content = fs::read(out.join(ATTESTATION_FILE_NAME)).await.unwrap();
+		let signature = fs::read_to_string(out.join(ATTESTATION_SIGNATURE_FILE_NAME))
+			.await
+			.unwrap();
+		assert!(verify_signature(
+			&signed_attestation(&content),
+			&signature,
+			&attestation.public_key
+		)
+		.is_ok());
+		// it's not a signature of the bare attestation, as anything else signed with the key is
+		assert!(verify_signature(&content, &signature, &attestation.public_key).is_err());
+		assert_eq!(
+			serde_json::from_slice::<Attestation>(&content).unwrap(),
+			attestation
+		);
+
+		// a single file that didn't match and there's nothing to attest
+		for outcome in [
+			FileValidationOutcome::Pruned,
+			FileValidationOutcome::InFlux,
+			FileValidationOutcome::Failed {
+				reason: "listed in the manifest but missing from the location".to_string(),
+			},
+		] {
+			report.files.insert("c.txt".to_string(), outcome);
+			assert_eq!(
+				Attestation::of_audit(
+					Uuid::new_v4(),
+					"/location",
+					ChecksumAlgorithm::Blake3,
+					manifest_content,
+					&report,
+					&keypair,
+				),
+				None
+			);
+		}
+	}
+}
@@ -0,0 +1,130 @@
+use crate::location::file_path_helper::file_path_for_object_validator;
+
+use std::{error::Error, fmt, sync::Arc};
+
+use futures::future::BoxFuture;
+
+use super::FileValidationOutcome;
+
+pub type ValidationCallbackResult = Result<(), Box<dyn Error + Send + Sync>>;
+
+type CallbackFn = dyn for<'a> Fn(
+		&'a file_path_for_object_validator::Data,
+		&'a FileValidationOutcome,
+	) -> BoxFuture<'a, ValidationCallbackResult>
+	+ Send
+	+ Sync;
+
+/// Custom logic run after each file is validated and its checksums stored, like keeping an
+/// external search index up to date
+#[derive(Clone)]
+pub struct ValidationCallback {
+	callback: Arc<CallbackFn>,
+	fail_step_on_error: bool,
+}
+
+impl ValidationCallback {
+	/// Errors returned by `callback` are only logged, see [`Self::failing_step_on_error`]
+	pub fn new<F>(callback: F) -> Self
+	where
+		F: for<'a> Fn(
+				&'a file_path_for_object_validator::Data,
+				&'a FileValidationOutcome,
+			) -> BoxFuture<'a, ValidationCallbackResult>
+			+ Send
+			+ Sync
+			+ 'static,
+	{
+		Self {
+			callback: Arc::new(callback),
+			fail_step_on_error: false,
+		}
+	}
+
+	/// Reports the callback's errors as errors of the step, the job still carries on
+	pub fn failing_step_on_error(mut self) -> Self {
+		self.fail_step_on_error = true;
+		self
+	}
+
+	pub fn fails_step_on_error(&self) -> bool {
+		self.fail_step_on_error
+	}
+
+	pub async fn call(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+		outcome: &FileValidationOutcome,
+	) -> ValidationCallbackResult {
+		(self.callback)(file_path, outcome).await
+	}
+}
+
+impl fmt::Debug for ValidationCallback {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("ValidationCallback")
+			.field("fail_step_on_error", &self.fail_step_on_error)
+			.finish_non_exhaustive()
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use std::sync::Mutex;
+
+	use futures::FutureExt;
+
+	#[tokio::test]
+	async fn test_validation_callback() {
+		let seen = Arc::new(Mutex::new(vec![]));
+		let callback_seen = Arc::clone(&seen);
+		let callback = ValidationCallback::new(move |file_path, outcome| {
+			let seen = Arc::clone(&callback_seen);
+			async move {
+				if outcome.is_failure() {
+					return Err("failed file".into());
+				}
+				seen.lock().unwrap().push(file_path.name.clone().unwrap());
+				Ok(())
+			}
+			.boxed()
+		});
+		assert!(!callback.fails_step_on_error());
+
+		let file_path = file_path_for_object_validator::Data {
+			id: 0,
+			pub_id: vec![],
+			materialized_path: Some("/".to_string()),
+			is_dir: Some(false),
+			name: Some("file".to_string()),
+			extension: Some("txt".to_string()),
+			integrity_checksum: None,
+			integrity_checksum_algorithm: None,
+			content_checksum: None,
+			size_in_bytes_bytes: None,
+			device: None,
+			date_modified: None,
+			object: None,
+		};
+
+		callback
+			.call(&file_path, &FileValidationOutcome::Checksummed)
+			.await
+			.unwrap();
+		assert!(callback
+			.call(
+				&file_path,
+				&FileValidationOutcome::Failed {
+					reason: "unreadable".to_string()
+				}
+			)
+			.await
+			.is_err());
+
+		assert_eq!(*seen.lock().unwrap(), vec!["file".to_string()]);
+		assert!(callback.failing_step_on_error().fails_step_on_error());
+	}
+}
@@ -0,0 +1,179 @@
+//! Cross-check of the two ways files are identified: the `cas_id` the file identifier samples from
+//! a few parts of each file, and the `integrity_checksum` the validator computes over all of it.
+//!
+//! Both are derived from the contents alone, so they must agree on which files are the same.
+//! Files sharing a `cas_id` but not their checksum differ where the `cas_id` doesn't sample them,
+//! or one of them changed since its `cas_id` was computed. Files sharing their checksum but not
+//! their `cas_id` can only be explained by a stale `cas_id`.
+
+use crate::{
+	library::Library,
+	location::file_path_helper::{file_path_for_cas_cross_check, IsolatedFilePathData},
+	prisma::{file_path, location, SortOrder},
+};
+
+use std::collections::{BTreeMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use super::{hash::ChecksumAlgorithm, not_excluded, same_checksum_algorithm, ValidatorError};
+
+const PAGE_SIZE: i64 = 1000;
+
+/// Files whose `cas_id` and `integrity_checksum` disagree about them being the same
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum CasDisagreement {
+	/// The files share `cas_id` but not their contents, keyed by path with their checksums
+	SameCasId {
+		cas_id: String,
+		integrity_checksums: BTreeMap<String, String>,
+	},
+	/// The files have the same contents but not the same `cas_id`, keyed by path with their
+	/// `cas_id`s
+	SameChecksum {
+		integrity_checksum: String,
+		cas_ids: BTreeMap<String, String>,
+	},
+}
+
+/// Groups the `(relative_path, cas_id, integrity_checksum)` of files by each of their ids, keeping
+/// the groups the other id splits
+pub fn cas_disagreements<'a>(
+	files: impl IntoIterator<Item = (&'a str, &'a str, &'a str)>,
+) -> Vec<CasDisagreement> {
+	let mut by_cas_id = BTreeMap::<_, BTreeMap<_, _>>::new();
+	let mut by_checksum = BTreeMap::<_, BTreeMap<_, _>>::new();
+	for (relative_path, cas_id, checksum) in files {
+		by_cas_id
+			.entry(cas_id)
+			.or_default()
+			.insert(relative_path.to_string(), checksum.to_string());
+		by_checksum
+			.entry(checksum)
+			.or_default()
+			.insert(relative_path.to_string(), cas_id.to_string());
+	}
+
+	let disagree =
+		|group: &BTreeMap<String, String>| group.values().collect::<HashSet<_>>().len() > 1;
+
+	by_cas_id
+		.into_iter()
+		.filter(|(_, checksums)| disagree(checksums))
+		.map(|(cas_id, integrity_checksums)| CasDisagreement::SameCasId {
+			cas_id: cas_id.to_string(),
+			integrity_checksums,
+		})
+		.chain(
+			by_checksum
+				.into_iter()
+				.filter(|(_, cas_ids)| disagree(cas_ids))
+				.map(|(checksum, cas_ids)| CasDisagreement::SameChecksum {
+					integrity_checksum: checksum.to_string(),
+					cas_ids,
+				}),
+		)
+		.collect()
+}
+
+/// Cross-checks the files of the location with both a `cas_id` and a checksum computed with
+/// `algorithm`, but those excluded from validation
+pub async fn cross_check_cas(
+	library: &Library,
+	location_id: location::id::Type,
+	algorithm: ChecksumAlgorithm,
+) -> Result<Vec<CasDisagreement>, ValidatorError> {
+	let mut files = vec![];
+	let mut last_id = None;
+
+	loop {
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(
+				[
+					file_path::location_id::equals(Some(location_id)),
+					file_path::is_dir::equals(Some(false)),
+					file_path::cas_id::not(None),
+					file_path::integrity_checksum::not(None),
+					same_checksum_algorithm(algorithm, false),
+					not_excluded(),
+				]
+				.into_iter()
+				.chain(last_id.map(file_path::id::gt))
+				.collect(),
+			)
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(PAGE_SIZE)
+			.select(file_path_for_cas_cross_check::select())
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		last_id = Some(last.id);
+
+		for file_path in &file_paths {
+			if let (Some(cas_id), Some(checksum)) =
+				(&file_path.cas_id, &file_path.integrity_checksum)
+			{
+				files.push((
+					IsolatedFilePathData::try_from((location_id, file_path))?.to_string(),
+					cas_id.clone(),
+					checksum.clone(),
+				));
+			}
+		}
+	}
+
+	Ok(cas_disagreements(files.iter().map(
+		|(relative_path, cas_id, checksum)| {
+			(relative_path.as_str(), cas_id.as_str(), checksum.as_str())
+		},
+	)))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_cas_disagreements() {
+		assert_eq!(
+			cas_disagreements([
+				("a.txt", "cas1", "aaaa"),
+				("copy of a.txt", "cas1", "aaaa"),
+				// sampled alike, but not the same file
+				("big.iso", "cas2", "bbbb"),
+				("other big.iso", "cas2", "cccc"),
+				// the same file, one of them with a stale cas_id
+				("c.txt", "cas3", "dddd"),
+				("copy of c.txt", "cas4", "dddd"),
+			]),
+			vec![
+				CasDisagreement::SameCasId {
+					cas_id: "cas2".to_string(),
+					integrity_checksums: [
+						("big.iso".to_string(), "bbbb".to_string()),
+						("other big.iso".to_string(), "cccc".to_string()),
+					]
+					.into_iter()
+					.collect(),
+				},
+				CasDisagreement::SameChecksum {
+					integrity_checksum: "dddd".to_string(),
+					cas_ids: [
+						("c.txt".to_string(), "cas3".to_string()),
+						("copy of c.txt".to_string(), "cas4".to_string()),
+					]
+					.into_iter()
+					.collect(),
+				},
+			]
+		);
+
+		assert!(cas_disagreements([("a.txt", "cas1", "aaaa")]).is_empty());
+	}
+}
@@ -0,0 +1,215 @@
+use crate::{
+	library::Library,
+	prisma::{file_path, location, SortOrder},
+};
+
+use prisma_client_rust::{operator::or, QueryError};
+use serde::Serialize;
+use specta::Type;
+
+use super::hash::{is_valid_checksum, ChecksumAlgorithm};
+
+const PAGE_SIZE: i64 = 1000;
+
+/// What's wrong with a stored `integrity_checksum`
+#[derive(Serialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumProblem {
+	/// The algorithm tag isn't one we know, so the checksum can't be compared with anything
+	UnknownAlgorithm,
+	/// The checksum isn't a lowercase hex digest
+	InvalidEncoding,
+	/// The checksum is hex, but not as long as the digests of its algorithm
+	WrongLength,
+	/// There's an algorithm tag but no checksum
+	AlgorithmWithoutChecksum,
+}
+
+impl ChecksumProblem {
+	/// Legacy checksums without an algorithm tag are blake3 ones
+	pub fn of(checksum: Option<&str>, algorithm: Option<&str>) -> Option<Self> {
+		let Some(checksum) = checksum else {
+			return algorithm.map(|_| Self::AlgorithmWithoutChecksum);
+		};
+		let Some(algorithm) = ChecksumAlgorithm::from_db(algorithm) else {
+			return Some(Self::UnknownAlgorithm);
+		};
+
+		if is_valid_checksum(checksum, algorithm) {
+			None
+		} else if !checksum
+			.bytes()
+			.all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+		{
+			Some(Self::InvalidEncoding)
+		} else {
+			Some(Self::WrongLength)
+		}
+	}
+}
+
+#[derive(Serialize, Type, Debug, Clone, PartialEq, Eq)]
+pub struct MalformedChecksum {
+	pub file_path_id: file_path::id::Type,
+	pub checksum: Option<String>,
+	pub algorithm: Option<String>,
+	pub problem: ChecksumProblem,
+}
+
+#[derive(Serialize, Type, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumProblemCounts {
+	pub unknown_algorithm: u32,
+	pub invalid_encoding: u32,
+	pub wrong_length: u32,
+	pub algorithm_without_checksum: u32,
+}
+
+impl ChecksumProblemCounts {
+	pub fn new(malformed: &[MalformedChecksum]) -> Self {
+		let mut counts = Self::default();
+		for malformed in malformed {
+			*match malformed.problem {
+				ChecksumProblem::UnknownAlgorithm => &mut counts.unknown_algorithm,
+				ChecksumProblem::InvalidEncoding => &mut counts.invalid_encoding,
+				ChecksumProblem::WrongLength => &mut counts.wrong_length,
+				ChecksumProblem::AlgorithmWithoutChecksum => &mut counts.algorithm_without_checksum,
+			} += 1;
+		}
+
+		counts
+	}
+}
+
+/// Flags the stored checksums of the location's files that couldn't have been computed by us,
+/// left by past bugs or a corrupted database. Only the database is read, none of the files.
+pub async fn audit_stored_checksums(
+	library: &Library,
+	location_id: location::id::Type,
+) -> Result<Vec<MalformedChecksum>, QueryError> {
+	let mut malformed = vec![];
+	let mut last_id = None;
+
+	loop {
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(
+				[
+					file_path::location_id::equals(Some(location_id)),
+					or(vec![
+						file_path::integrity_checksum::not(None),
+						file_path::integrity_checksum_algorithm::not(None),
+					]),
+				]
+				.into_iter()
+				.chain(last_id.map(file_path::id::gt))
+				.collect(),
+			)
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(PAGE_SIZE)
+			.select(file_path::select!({
+				id
+				integrity_checksum
+				integrity_checksum_algorithm
+			}))
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		last_id = Some(last.id);
+
+		malformed.extend(file_paths.into_iter().filter_map(|file_path| {
+			ChecksumProblem::of(
+				file_path.integrity_checksum.as_deref(),
+				file_path.integrity_checksum_algorithm.as_deref(),
+			)
+			.map(|problem| MalformedChecksum {
+				file_path_id: file_path.id,
+				checksum: file_path.integrity_checksum,
+				algorithm: file_path.integrity_checksum_algorithm,
+				problem,
+			})
+		}));
+	}
+
+	Ok(malformed)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_checksum_problem() {
+		let blake3 = ChecksumAlgorithm::Blake3.empty_checksum();
+		let sha256 = ChecksumAlgorithm::Sha256.empty_checksum();
+
+		for (checksum, algorithm, expected) in [
+			(Some(blake3), None, None),
+			(Some(blake3), Some("blake3"), None),
+			(Some(sha256), Some("sha256"), None),
+			(None, None, None),
+			(
+				Some(blake3),
+				Some("md5"),
+				Some(ChecksumProblem::UnknownAlgorithm),
+			),
+			(
+				Some(&blake3[..40]),
+				Some("blake3"),
+				Some(ChecksumProblem::WrongLength),
+			),
+			(
+				Some("abcd"),
+				Some("sha256"),
+				Some(ChecksumProblem::WrongLength),
+			),
+			(
+				Some(blake3.to_uppercase().as_str()),
+				None,
+				Some(ChecksumProblem::InvalidEncoding),
+			),
+			(
+				Some("not a checksum"),
+				Some("blake3"),
+				Some(ChecksumProblem::InvalidEncoding),
+			),
+			(
+				None,
+				Some("sha256"),
+				Some(ChecksumProblem::AlgorithmWithoutChecksum),
+			),
+		] {
+			assert_eq!(
+				ChecksumProblem::of(checksum, algorithm),
+				expected,
+				"{checksum:?} {algorithm:?}"
+			);
+		}
+	}
+
+	#[test]
+	fn test_checksum_problem_counts() {
+		let malformed = |problem| MalformedChecksum {
+			file_path_id: 1,
+			checksum: None,
+			algorithm: None,
+			problem,
+		};
+
+		assert_eq!(
+			ChecksumProblemCounts::new(&[
+				malformed(ChecksumProblem::WrongLength),
+				malformed(ChecksumProblem::UnknownAlgorithm),
+				malformed(ChecksumProblem::WrongLength),
+			]),
+			ChecksumProblemCounts {
+				unknown_algorithm: 1,
+				wrong_length: 2,
+				..Default::default()
+			}
+		);
+	}
+}
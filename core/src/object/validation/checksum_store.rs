@@ -0,0 +1,586 @@
+use crate::{
+	library::Library,
+	location::file_path_helper::file_path_for_object_validator,
+	prisma::{file_path, PrismaClient},
+	sync,
+};
+
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde_json::json;
+use tracing::warn;
+
+use super::{
+	hash::ChecksumAlgorithm, update_content_index, update_stored_aggregate, RangeChecksum,
+	SyncPacer, ValidatorError,
+};
+
+/// Checksums of a file, fields left as `None` weren't computed
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StoredChecksums {
+	pub checksum: Option<String>,
+	pub algorithm: ChecksumAlgorithm,
+	/// the checksum also covers the file's other streams, see
+	/// [`ReadOptions::include_alt_streams`](super::hash::ReadOptions::include_alt_streams)
+	pub include_alt_streams: bool,
+	/// where the checksum was imported from, `None` when it was computed by us
+	pub checksum_source: Option<String>,
+	pub content_checksum: Option<String>,
+	pub content_type: Option<String>,
+	pub perceptual_hash: Option<String>,
+	pub range_checksum: Option<RangeChecksum>,
+}
+
+/// Where the validator persists the checksums it computes.
+/// The job uses [`LibraryChecksumStore`] unless another one is set on its init, like a separate
+/// key value store or extended attributes.
+#[async_trait::async_trait]
+pub trait ChecksumStore: Send + Sync {
+	async fn get(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+	) -> Result<Option<StoredChecksums>, ValidatorError>;
+
+	/// Fields left as `None` must keep their stored value
+	async fn put(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+		checksums: StoredChecksums,
+	) -> Result<(), ValidatorError>;
+
+	/// Like [`ChecksumStore::put`], but only if the stored checksum is still the one `file_path`
+	/// was read with, so one another writer stored meanwhile isn't replaced. Stores that can't
+	/// compare and set store them regardless.
+	async fn compare_and_put(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+		checksums: StoredChecksums,
+	) -> Result<ChecksumWrite, ValidatorError> {
+		self.put(file_path, checksums)
+			.await
+			.map(|()| ChecksumWrite::Stored)
+	}
+}
+
+/// How a [`ChecksumStore::compare_and_put`] went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChecksumWrite {
+	Stored,
+	/// Another writer changed the stored checksum since it was read, to `found`, which was kept
+	Conflict {
+		found: Option<String>,
+	},
+}
+
+impl fmt::Debug for dyn ChecksumStore {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ChecksumStore")
+	}
+}
+
+/// Stores checksums on the file path rows of the library database, synced to other nodes.
+/// When syncing them fails but the database can still be written, like with the sync layer down,
+/// the checksums are only stored on this node and the row is flagged to be synced later, see
+/// [`LibraryChecksumStore::sync_deferred`].
+pub struct LibraryChecksumStore<'a> {
+	library: &'a Library,
+	sync_pacer: Option<&'a SyncPacer>,
+}
+
+impl<'a> LibraryChecksumStore<'a> {
+	pub fn new(library: &'a Library) -> Self {
+		Self {
+			library,
+			sync_pacer: None,
+		}
+	}
+
+	/// Counts the sync operations of the checksums stored against `sync_pacer`, for the job to wait
+	/// between its steps when they go over its rate
+	pub fn with_sync_pacer(mut self, sync_pacer: Option<&'a SyncPacer>) -> Self {
+		self.sync_pacer = sync_pacer;
+		self
+	}
+
+	/// Syncs the checksums of every file path flagged as stored without being synced, by this job
+	/// or an earlier one. Their rows already hold them, so only their sync operations are written,
+	/// with the date they were computed at. The ones whose sync fails again stay flagged for the
+	/// next time, returns how many.
+	pub async fn sync_deferred(&self) -> Result<usize, ValidatorError> {
+		let Library { db, sync, .. } = self.library;
+
+		let file_paths = db
+			.file_path()
+			.find_many(vec![file_path::checksum_sync_deferred::equals(Some(true))])
+			.select(file_path_stored_checksums::select())
+			.exec()
+			.await?;
+
+		let mut deferred = 0;
+		for file_path in file_paths {
+			let (id, pub_id) = (file_path.id, file_path.pub_id.clone());
+			// only rows holding an integrity checksum have to have a date for it
+			let date_checksummed = file_path
+				.date_checksummed
+				.unwrap_or_else(|| Utc::now().into());
+			let Some(checksums) = row_checksums(file_path) else {
+				warn!("Couldn't read the stored checksums of file path <id='{id}'> to sync them");
+				deferred += 1;
+				continue;
+			};
+
+			let ops = checksum_params(&checksums, date_checksummed)
+				.into_iter()
+				.map(|((field, value), _)| {
+					sync.shared_update(
+						sync::file_path::SyncId {
+							pub_id: pub_id.clone(),
+						},
+						field,
+						value,
+					)
+				})
+				.collect::<Vec<_>>();
+			let ops_len = ops.len();
+
+			// the flag is only cleared along with the operations being written
+			match sync
+				.write_ops(
+					db,
+					(
+						ops,
+						db.file_path().update_many(
+							vec![file_path::pub_id::equals(pub_id.clone())],
+							vec![file_path::checksum_sync_deferred::set(None)],
+						),
+					),
+				)
+				.await
+			{
+				Ok(_) => {
+					if let Some(sync_pacer) = self.sync_pacer {
+						sync_pacer.record(ops_len);
+					}
+				}
+				Err(e) => {
+					warn!("Couldn't sync the checksums of file path <id='{id}'> again: {e}");
+					deferred += 1;
+				}
+			}
+		}
+
+		Ok(deferred)
+	}
+
+	/// The integrity checksum stored on the row of `file_path`, `None` if it's gone
+	async fn stored_checksum(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+	) -> Result<Option<Option<String>>, ValidatorError> {
+		Ok(self
+			.library
+			.db
+			.file_path()
+			.find_unique(file_path::pub_id::equals(file_path.pub_id.clone()))
+			.select(file_path::select!({ integrity_checksum }))
+			.exec()
+			.await?
+			.map(|stored| stored.integrity_checksum))
+	}
+
+	/// Stores `checksums` on the row of `file_path`, only while its integrity checksum is still
+	/// `expected` when given. Returns whether the row was updated.
+	async fn store(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+		checksums: &StoredChecksums,
+		expected: Option<&Option<String>>,
+	) -> Result<bool, ValidatorError> {
+		let Library { db, sync, .. } = self.library;
+
+		let date_checksummed = DateTime::<FixedOffset>::from(Utc::now());
+		let (sync_params, db_params): (Vec<_>, Vec<_>) =
+			checksum_params(checksums, date_checksummed)
+				.into_iter()
+				.unzip();
+
+		let mut updated = true;
+		if !db_params.is_empty() {
+			let pub_id = &file_path.pub_id;
+			let (count, sync_error) = db
+				._transaction()
+				.run(|tx| async move {
+					// the guard is checked in the same transaction the row and its sync operations
+					// are written in, so peers are only told about updates that go through
+					let matched = tx
+						.file_path()
+						.count(checksums_guard(pub_id, expected))
+						.exec()
+						.await?;
+					let sync_params = sync_params_to_emit(matched, sync_params);
+					if sync_params.is_empty() {
+						return Ok::<_, ValidatorError>((matched, None));
+					}
+
+					let ops = sync_params
+						.into_iter()
+						.map(|(field, value)| {
+							sync.shared_update(
+								sync::file_path::SyncId {
+									pub_id: pub_id.clone(),
+								},
+								field,
+								value,
+							)
+						})
+						.collect::<Vec<_>>();
+					let ops_len = ops.len();
+
+					match sync
+						.write_ops(
+							&tx,
+							(ops, update_checksums(&tx, pub_id, expected, db_params)),
+						)
+						.await
+					{
+						Ok(count) => {
+							if let Some(sync_pacer) = self.sync_pacer {
+								sync_pacer.record(ops_len);
+							}
+							Ok((count, None))
+						}
+						Err(e) => {
+							// the guard matching means it's syncing it that failed, so the
+							// checksums are kept on this node and synced later instead of failing
+							// the file. It already matched in this transaction, so it isn't
+							// checked again against a write that may have gone through.
+							let (_, db_params): (Vec<_>, Vec<_>) =
+								checksum_params(checksums, date_checksummed)
+									.into_iter()
+									.unzip();
+							let count = update_checksums(
+								&tx,
+								pub_id,
+								None,
+								db_params
+									.into_iter()
+									.chain([file_path::checksum_sync_deferred::set(Some(true))])
+									.collect(),
+							)
+							.exec()
+							.await?;
+							Ok((count, Some(e)))
+						}
+					}
+				})
+				.await?;
+
+			updated = count > 0;
+			if let Some(e) = sync_error {
+				warn!(
+					"Couldn't sync the checksums of file path <id='{}'>, deferring it: {e}",
+					file_path.id
+				);
+			}
+		}
+
+		if let Some(checksum) = checksums.checksum.as_ref().filter(|_| updated) {
+			update_content_index(db, file_path.id, Some(checksum)).await?;
+			update_stored_aggregate(
+				self.library,
+				&file_path.pub_id,
+				(
+					file_path.integrity_checksum.as_deref(),
+					file_path.integrity_checksum_algorithm.as_deref(),
+				),
+			)
+			.await?;
+		}
+
+		Ok(updated)
+	}
+}
+
+#[async_trait::async_trait]
+impl ChecksumStore for LibraryChecksumStore<'_> {
+	async fn get(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+	) -> Result<Option<StoredChecksums>, ValidatorError> {
+		Ok(self
+			.library
+			.db
+			.file_path()
+			.find_unique(file_path::pub_id::equals(file_path.pub_id.clone()))
+			.select(file_path_stored_checksums::select())
+			.exec()
+			.await?
+			.and_then(row_checksums))
+	}
+
+	async fn put(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+		checksums: StoredChecksums,
+	) -> Result<(), ValidatorError> {
+		if self.store(file_path, &checksums, None).await? {
+			Ok(())
+		} else {
+			Err(ValidatorError::FilePathNotFound(file_path.id))
+		}
+	}
+
+	async fn compare_and_put(
+		&self,
+		file_path: &file_path_for_object_validator::Data,
+		checksums: StoredChecksums,
+	) -> Result<ChecksumWrite, ValidatorError> {
+		let expected = &file_path.integrity_checksum;
+
+		if self.store(file_path, &checksums, Some(expected)).await? {
+			return Ok(ChecksumWrite::Stored);
+		}
+
+		// another writer changed it since it was read
+		self.stored_checksum(file_path)
+			.await?
+			.map(|found| ChecksumWrite::Conflict { found })
+			.ok_or(ValidatorError::FilePathNotFound(file_path.id))
+	}
+}
+
+file_path::select!(file_path_stored_checksums {
+	id
+	pub_id
+	integrity_checksum
+	integrity_checksum_algorithm
+	integrity_checksum_source
+	content_checksum
+	content_type
+	perceptual_hash
+	range_checksum
+	date_checksummed
+});
+
+/// The checksums stored on a row, `None` if they're of an algorithm we don't know
+fn row_checksums(stored: file_path_stored_checksums::Data) -> Option<StoredChecksums> {
+	let (algorithm, include_alt_streams) = ChecksumAlgorithm::from_db_with_alt_streams(
+		stored.integrity_checksum_algorithm.as_deref(),
+	)?;
+
+	Some(StoredChecksums {
+		algorithm,
+		include_alt_streams,
+		checksum: stored.integrity_checksum,
+		checksum_source: stored.integrity_checksum_source,
+		content_checksum: stored.content_checksum,
+		content_type: stored.content_type,
+		perceptual_hash: stored.perceptual_hash,
+		range_checksum: stored
+			.range_checksum
+			.as_deref()
+			.and_then(RangeChecksum::from_db),
+	})
+}
+
+/// The row of `pub_id`, only while its integrity checksum is still `expected` when given
+fn checksums_guard(pub_id: &[u8], expected: Option<&Option<String>>) -> Vec<file_path::WhereParam> {
+	[file_path::pub_id::equals(pub_id.to_vec())]
+		.into_iter()
+		.chain(expected.cloned().map(file_path::integrity_checksum::equals))
+		.collect()
+}
+
+/// The update of the row of `pub_id` to `db_params`, see [`checksums_guard`]
+fn update_checksums<'db>(
+	db: &'db PrismaClient,
+	pub_id: &[u8],
+	expected: Option<&Option<String>>,
+	db_params: Vec<file_path::SetParam>,
+) -> file_path::UpdateManyQuery<'db> {
+	db.file_path()
+		.update_many(checksums_guard(pub_id, expected), db_params)
+}
+
+/// The sync fields of an update whose guard matched `count` rows, none when it matched none, as
+/// peers must not be told about an update that didn't happen
+fn sync_params_to_emit<T>(count: i64, sync_params: Vec<T>) -> Vec<T> {
+	if count > 0 {
+		sync_params
+	} else {
+		vec![]
+	}
+}
+
+/// The sync fields and database updates storing `checksums`, leaving out the ones not computed
+fn checksum_params(
+	checksums: &StoredChecksums,
+	date_checksummed: DateTime<FixedOffset>,
+) -> Vec<((&'static str, serde_json::Value), file_path::SetParam)> {
+	let StoredChecksums {
+		checksum,
+		algorithm,
+		include_alt_streams,
+		checksum_source,
+		content_checksum,
+		content_type,
+		perceptual_hash,
+		range_checksum,
+	} = checksums;
+
+	[
+		checksum.as_ref().map(|checksum| {
+			(
+				(file_path::integrity_checksum::NAME, json!(checksum)),
+				file_path::integrity_checksum::set(Some(checksum.clone())),
+			)
+		}),
+		checksum.is_some().then(|| {
+			(
+				(
+					file_path::integrity_checksum_algorithm::NAME,
+					json!(algorithm.db_name(*include_alt_streams)),
+				),
+				file_path::integrity_checksum_algorithm::set(Some(
+					algorithm.db_name(*include_alt_streams),
+				)),
+			)
+		}),
+		// a checksum computed by us has no source, even if the one before was imported
+		checksum.is_some().then(|| {
+			(
+				(
+					file_path::integrity_checksum_source::NAME,
+					json!(checksum_source),
+				),
+				file_path::integrity_checksum_source::set(checksum_source.clone()),
+			)
+		}),
+		checksum.is_some().then(|| {
+			(
+				(file_path::date_checksummed::NAME, json!(date_checksummed)),
+				file_path::date_checksummed::set(Some(date_checksummed)),
+			)
+		}),
+		content_checksum.clone().map(|content_checksum| {
+			(
+				(file_path::content_checksum::NAME, json!(&content_checksum)),
+				file_path::content_checksum::set(Some(content_checksum)),
+			)
+		}),
+		content_type.clone().map(|content_type| {
+			(
+				(file_path::content_type::NAME, json!(&content_type)),
+				file_path::content_type::set(Some(content_type)),
+			)
+		}),
+		perceptual_hash.clone().map(|perceptual_hash| {
+			(
+				(file_path::perceptual_hash::NAME, json!(&perceptual_hash)),
+				file_path::perceptual_hash::set(Some(perceptual_hash)),
+			)
+		}),
+		range_checksum.as_ref().map(|range_checksum| {
+			let range_checksum = range_checksum.to_db();
+			(
+				(file_path::range_checksum::NAME, json!(&range_checksum)),
+				file_path::range_checksum::set(Some(range_checksum)),
+			)
+		}),
+	]
+	.into_iter()
+	.flatten()
+	.collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_checksum_params() {
+		let fields = |checksums: &StoredChecksums| {
+			checksum_params(checksums, Utc::now().into())
+				.into_iter()
+				.map(|((field, _), _)| field)
+				.collect::<Vec<_>>()
+		};
+
+		let checksums = StoredChecksums {
+			checksum: Some("aaaa".to_string()),
+			perceptual_hash: Some("bbbb".to_string()),
+			..Default::default()
+		};
+		assert_eq!(
+			fields(&checksums),
+			vec![
+				file_path::integrity_checksum::NAME,
+				file_path::integrity_checksum_algorithm::NAME,
+				file_path::integrity_checksum_source::NAME,
+				file_path::date_checksummed::NAME,
+				file_path::perceptual_hash::NAME,
+			]
+		);
+
+		// fields not computed keep their stored value
+		assert!(fields(&StoredChecksums::default()).is_empty());
+
+		let source = |checksums: &StoredChecksums| {
+			checksum_params(checksums, Utc::now().into())
+				.into_iter()
+				.find(|((field, _), _)| *field == file_path::integrity_checksum_source::NAME)
+				.map(|((_, value), _)| value)
+		};
+		assert_eq!(source(&checksums), Some(json!(None::<String>)));
+		assert_eq!(
+			source(&StoredChecksums {
+				checksum_source: Some("sumfile".to_string()),
+				..checksums
+			}),
+			Some(json!("sumfile"))
+		);
+	}
+
+	#[test]
+	fn test_row_checksums() {
+		let row = |algorithm: &str| file_path_stored_checksums::Data {
+			id: 1,
+			pub_id: vec![1],
+			integrity_checksum: Some("aaaa".to_string()),
+			integrity_checksum_algorithm: Some(algorithm.to_string()),
+			integrity_checksum_source: Some("sumfile".to_string()),
+			content_checksum: None,
+			content_type: None,
+			perceptual_hash: None,
+			range_checksum: None,
+			date_checksummed: Some(Utc::now().into()),
+		};
+
+		let checksums = row_checksums(row("sha256")).unwrap();
+		assert_eq!(checksums.algorithm, ChecksumAlgorithm::Sha256);
+		// synced again as they are, with where they were imported from
+		assert_eq!(checksums.checksum_source.as_deref(), Some("sumfile"));
+		assert!(row_checksums(row("md5")).is_none());
+	}
+
+	#[test]
+	fn test_no_sync_ops_when_guard_fails() {
+		let sync_params = checksum_params(
+			&StoredChecksums {
+				checksum: Some("aaaa".to_string()),
+				..Default::default()
+			},
+			Utc::now().into(),
+		)
+		.into_iter()
+		.map(|(sync_param, _)| sync_param)
+		.collect::<Vec<_>>();
+		assert!(!sync_params.is_empty());
+
+		// another writer changed the checksum, the guarded update matched no row
+		assert!(sync_params_to_emit(0, sync_params.clone()).is_empty());
+		assert_eq!(sync_params_to_emit(1, sync_params.clone()), sync_params);
+	}
+}
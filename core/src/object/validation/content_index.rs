@@ -0,0 +1,50 @@
+use crate::prisma::{content_index, file_path, PrismaClient};
+
+use prisma_client_rust::QueryError;
+
+/// Keeps the content index in sync with a file path's `integrity_checksum`, so it must be called
+/// whenever one is written. Rows of deleted file paths are removed by the database cascade.
+pub async fn update_content_index(
+	db: &PrismaClient,
+	file_path_id: file_path::id::Type,
+	checksum: Option<&str>,
+) -> Result<(), QueryError> {
+	match checksum {
+		Some(checksum) => db
+			.content_index()
+			.upsert(
+				content_index::file_path_id::equals(file_path_id),
+				content_index::create(
+					file_path::id::equals(file_path_id),
+					checksum.to_string(),
+					vec![],
+				),
+				vec![content_index::checksum::set(checksum.to_string())],
+			)
+			.exec()
+			.await
+			.map(|_| ()),
+		None => db
+			.content_index()
+			.delete_many(vec![content_index::file_path_id::equals(file_path_id)])
+			.exec()
+			.await
+			.map(|_| ()),
+	}
+}
+
+/// Answers "where do I have this content?" without scanning every file path
+pub async fn file_paths_with_checksum(
+	db: &PrismaClient,
+	checksum: &str,
+) -> Result<Vec<file_path::id::Type>, QueryError> {
+	Ok(db
+		.content_index()
+		.find_many(vec![content_index::checksum::equals(checksum.to_string())])
+		.select(content_index::select!({ file_path_id }))
+		.exec()
+		.await?
+		.into_iter()
+		.map(|entry| entry.file_path_id)
+		.collect())
+}
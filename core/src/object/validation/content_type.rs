@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+
+/// Other extensions of formats we recognize from their contents, mapped to the one [`infer`]
+/// knows them by
+const EXTENSION_ALIASES: &[(&str, &str)] = &[
+	("jpeg", "jpg"),
+	("jpe", "jpg"),
+	("tif", "tiff"),
+	("mpeg", "mpg"),
+];
+
+/// Formats stored as zip archives, which their first bytes may only tell to be a zip archive
+const ZIP_BASED: &[&str] = &[
+	"zip", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "xpi",
+];
+
+/// MIME type of a file from its first bytes, see [`super::hash::file_checksum_and_head`].
+/// `None` if they don't match any format we know, which is always the case for files too short to
+/// hold a signature, so empty files are never given a type.
+pub fn sniff_content_type(head: &[u8]) -> Option<&'static str> {
+	infer::get(head).map(|kind| kind.mime_type())
+}
+
+/// A file whose contents are of another type than its extension claims, like a `.jpg` holding
+/// a zip archive
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionMismatch {
+	/// the extension of the file, lowercased
+	pub claimed: String,
+	/// MIME type sniffed from its contents
+	pub detected: String,
+}
+
+/// How the first bytes of a file contradict its `extension`, if they do. Extensions of formats we
+/// can't recognize from their contents never are, and neither are contents we don't recognize.
+pub fn extension_mismatch(extension: &str, head: &[u8]) -> Option<ExtensionMismatch> {
+	let claimed = extension.to_lowercase();
+	let canonical = EXTENSION_ALIASES
+		.iter()
+		.find_map(|&(alias, canonical)| (alias == claimed).then_some(canonical))
+		.unwrap_or(&claimed);
+	if !infer::is_supported(canonical) {
+		return None;
+	}
+
+	let kind = infer::get(head)?;
+	let detected = kind.extension();
+	if detected == canonical || (ZIP_BASED.contains(&detected) && ZIP_BASED.contains(&canonical)) {
+		return None;
+	}
+
+	Some(ExtensionMismatch {
+		claimed,
+		detected: kind.mime_type().to_string(),
+	})
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	const PNG: &[u8] = &[0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00];
+	const ZIP: &[u8] = &[0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00];
+
+	#[test]
+	fn test_sniff_content_type() {
+		assert_eq!(sniff_content_type(PNG), Some("image/png"));
+		assert_eq!(sniff_content_type(b"%PDF-1.7\n"), Some("application/pdf"));
+
+		assert_eq!(sniff_content_type(b""), None);
+		// a truncated png signature
+		assert_eq!(sniff_content_type(&[0x89, 0x50]), None);
+		assert_eq!(sniff_content_type(b"just some text"), None);
+	}
+
+	#[test]
+	fn test_extension_mismatch() {
+		assert_eq!(
+			extension_mismatch("JPG", ZIP),
+			Some(ExtensionMismatch {
+				claimed: "jpg".to_string(),
+				detected: "application/zip".to_string(),
+			})
+		);
+		assert_eq!(
+			extension_mismatch("jpeg", PNG),
+			Some(ExtensionMismatch {
+				claimed: "jpeg".to_string(),
+				detected: "image/png".to_string(),
+			})
+		);
+
+		assert_eq!(extension_mismatch("png", PNG), None);
+		assert_eq!(extension_mismatch("PNG", PNG), None);
+		// office documents are zip archives
+		assert_eq!(extension_mismatch("docx", ZIP), None);
+		// we can't tell what text files should start with, nor what unknown contents are
+		assert_eq!(extension_mismatch("txt", PNG), None);
+		assert_eq!(extension_mismatch("png", b"just some text"), None);
+	}
+}
@@ -0,0 +1,89 @@
+//! Integrity coverage of a library: files the identifier gave a `cas_id`, which only samples a few
+//! chunks of big files, that never got a full `integrity_checksum` computed, so corruption in the
+//! parts their `cas_id` skips would go unnoticed. The coverage job checksums exactly those.
+
+use crate::{
+	library::Library,
+	prisma::{file_path, location, object, SortOrder},
+};
+
+use serde::Serialize;
+use specta::Type;
+
+use super::{not_excluded, ValidatorError};
+
+const PAGE_SIZE: i64 = 1000;
+
+/// A file of an object that has a `cas_id` but no integrity checksum
+#[derive(Serialize, Type, Debug, Clone, PartialEq, Eq)]
+pub struct CasWithoutChecksum {
+	pub file_path_id: file_path::id::Type,
+	pub location_id: location::id::Type,
+	pub object_id: object::id::Type,
+	pub cas_id: String,
+}
+
+/// Files of this node's locations identified by their `cas_id` that lack an integrity checksum,
+/// files excluded from validation left out
+fn cas_without_checksum(library: &Library) -> Vec<file_path::WhereParam> {
+	vec![
+		file_path::is_dir::equals(Some(false)),
+		file_path::cas_id::not(None),
+		file_path::object_id::not(None),
+		file_path::integrity_checksum::equals(None),
+		not_excluded(),
+		file_path::location::is(vec![location::node_id::equals(Some(library.node_local_id))]),
+	]
+}
+
+/// Every file of an object in this node's locations that was quick-identified with a `cas_id` but
+/// never fully checksummed, by id
+pub async fn find_cas_without_checksum(
+	library: &Library,
+) -> Result<Vec<CasWithoutChecksum>, ValidatorError> {
+	let mut found = vec![];
+	let mut last_id = None;
+
+	loop {
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(
+				cas_without_checksum(library)
+					.into_iter()
+					.chain(last_id.map(file_path::id::gt))
+					.collect(),
+			)
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(PAGE_SIZE)
+			.select(file_path::select!({ id location_id object_id cas_id }))
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		last_id = Some(last.id);
+
+		found.extend(file_paths.into_iter().filter_map(|file_path| {
+			Some(CasWithoutChecksum {
+				file_path_id: file_path.id,
+				location_id: file_path.location_id?,
+				object_id: file_path.object_id?,
+				cas_id: file_path.cas_id?,
+			})
+		}));
+	}
+
+	Ok(found)
+}
+
+/// How many files [`find_cas_without_checksum`] would find, without loading them
+pub async fn count_cas_without_checksum(library: &Library) -> Result<usize, ValidatorError> {
+	Ok(library
+		.db
+		.file_path()
+		.count(cas_without_checksum(library))
+		.exec()
+		.await? as usize)
+}
@@ -0,0 +1,143 @@
+use crate::{
+	extract_job_data_mut, invalidate_query,
+	job::{
+		JobError, JobInitData, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+	},
+	prisma::file_path,
+};
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use super::{
+	count_cas_without_checksum, find_cas_without_checksum, on_access::validate_accessed_file,
+	FileValidationOutcome,
+};
+
+const BATCH_SIZE: usize = 100;
+
+// The coverage job computes the integrity checksum of every file of an object that only has a
+// cas_id, found with `find_cas_without_checksum`, so the whole library is covered by full
+// checksums instead of the sampled cas_ids. Files already checksummed aren't read.
+pub struct ChecksumCoverageJob {}
+
+#[derive(Serialize, Deserialize, Debug, Hash)]
+pub struct ChecksumCoverageJobInit {}
+
+impl JobInitData for ChecksumCoverageJobInit {
+	type Job = ChecksumCoverageJob;
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChecksumCoverageJobState {
+	report: ChecksumCoverageJobReport,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ChecksumCoverageJobReport {
+	/// files with a cas_id but no checksum when the job started
+	gap_before: usize,
+	/// and when it was done, files identified meanwhile included
+	gap_after: usize,
+	checksummed: usize,
+	/// reasons the files that couldn't be checksummed weren't, by full path
+	failed: BTreeMap<String, String>,
+}
+
+#[async_trait::async_trait]
+impl StatefulJob for ChecksumCoverageJob {
+	type Init = ChecksumCoverageJobInit;
+	type Data = ChecksumCoverageJobState;
+	type Step = Vec<file_path::id::Type>;
+
+	const NAME: &'static str = "checksum_coverage";
+	const IS_BACKGROUND: bool = true;
+
+	fn new() -> Self {
+		Self {}
+	}
+
+	async fn init(
+		&self,
+		ctx: &mut WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let gap = find_cas_without_checksum(&ctx.library).await?;
+		info!("{} files have a cas_id but no checksum", gap.len());
+
+		let batches = gap
+			.chunks(BATCH_SIZE)
+			.map(|batch| batch.iter().map(|file| file.file_path_id).collect())
+			.collect::<Vec<_>>();
+
+		ctx.progress(vec![JobReportUpdate::TaskCount(batches.len())]);
+
+		state.data = Some(ChecksumCoverageJobState {
+			report: ChecksumCoverageJobReport {
+				gap_before: gap.len(),
+				..Default::default()
+			},
+		});
+		state.steps.extend(batches);
+
+		Ok(())
+	}
+
+	async fn execute_step(
+		&self,
+		ctx: &mut WorkerContext,
+		state: &mut JobState<Self>,
+	) -> Result<(), JobError> {
+		let batch = &state.steps[0];
+		let data = extract_job_data_mut!(state);
+
+		let mut errors = vec![];
+
+		for &file_path_id in batch {
+			match validate_accessed_file(&ctx.library, file_path_id, false).await {
+				Ok(Some((_, _, FileValidationOutcome::Checksummed))) => {
+					data.report.checksummed += 1;
+				}
+				Ok(Some((_, path, FileValidationOutcome::Failed { reason }))) => {
+					warn!("Couldn't checksum {}: {reason}", path.display());
+					data.report
+						.failed
+						.insert(path.to_string_lossy().to_string(), reason);
+				}
+				// files gone, in flux or checksummed since the job started
+				Ok(_) => {}
+				Err(e) => errors.push(e.to_string()),
+			}
+		}
+
+		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
+			state.step_number + 1,
+		)]);
+
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(JobError::StepCompletedWithErrors(errors))
+		}
+	}
+
+	async fn finalize(&mut self, ctx: &mut WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let gap_after = count_cas_without_checksum(&ctx.library).await?;
+		let data = extract_job_data_mut!(state);
+		data.report.gap_after = gap_after;
+
+		info!(
+			"finalizing checksum coverage job: {} files checksummed, {} without a checksum before \
+			and {} after",
+			data.report.checksummed, data.report.gap_before, data.report.gap_after
+		);
+
+		if data.report.checksummed > 0 {
+			invalidate_query!(ctx.library, "search.paths");
+		}
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
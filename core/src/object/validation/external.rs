@@ -0,0 +1,225 @@
+use crate::util::error::FileIOError;
+
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use super::{
+	hash::{is_valid_checksum, ChecksumAlgorithm},
+	ValidatorError,
+};
+
+/// Checksums already computed by another tool, used to seed `integrity_checksum` without hashing
+/// the files again. Paths in these files must be relative to the location root.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ExternalChecksumSource {
+	/// Output of `git annex find --format='${key} ${file}\n'`
+	GitAnnex { path: PathBuf },
+	/// Output of `rclone hashsum <hash> <path>`, `hash` being the name rclone uses for it
+	Rclone { path: PathBuf, hash: String },
+}
+
+impl ExternalChecksumSource {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Self::GitAnnex { .. } => "git-annex",
+			Self::Rclone { .. } => "rclone",
+		}
+	}
+
+	/// Reads the checksums, keyed by their path relative to the location
+	pub async fn load(&self) -> Result<HashMap<String, ExternalChecksum>, ValidatorError> {
+		let path = match self {
+			Self::GitAnnex { path } | Self::Rclone { path, .. } => path,
+		};
+
+		let content = fs::read_to_string(path)
+			.await
+			.map_err(|e| FileIOError::from((path, e)))?;
+
+		self.parse(&content)
+	}
+
+	/// The checksums listed in the contents of the file, see [`Self::load`]
+	pub fn parse(
+		&self,
+		content: &str,
+	) -> Result<HashMap<String, ExternalChecksum>, ValidatorError> {
+		let path = match self {
+			Self::GitAnnex { path } | Self::Rclone { path, .. } => path,
+		};
+
+		content
+			.lines()
+			.enumerate()
+			.filter(|(_, line)| !line.trim().is_empty())
+			.filter_map(|(line_number, line)| {
+				match self {
+					Self::GitAnnex { .. } => parse_git_annex_line(line),
+					Self::Rclone { hash, .. } => parse_rclone_line(line, hash).map(Some),
+				}
+				.ok_or_else(|| ValidatorError::InvalidExternalChecksum {
+					path: path.clone().into_boxed_path(),
+					line: line_number + 1,
+				})
+				.transpose()
+			})
+			.map(|entry| {
+				entry.map(|(relative_path, checksum)| (normalize_path(relative_path), checksum))
+			})
+			.collect()
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExternalChecksum {
+	/// Lowercase algorithm name, like `sha256` or `blake3`
+	pub algorithm: String,
+	pub hex: String,
+}
+
+impl ExternalChecksum {
+	/// Only digests made with `algorithm` are the same as the ones we would compute, others can't
+	/// be stored as our `integrity_checksum`
+	pub fn as_integrity_checksum(&self, algorithm: ChecksumAlgorithm) -> Option<&str> {
+		(self.algorithm == algorithm.as_str() && is_valid_checksum(&self.hex, algorithm))
+			.then_some(self.hex.as_str())
+	}
+}
+
+pub(super) fn normalize_path(path: &str) -> String {
+	path.trim_start_matches("./")
+		.trim_start_matches('/')
+		.to_string()
+}
+
+fn is_hex(s: &str) -> bool {
+	!s.is_empty() && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Keys look like `SHA256E-s1048576--<hex>.ext`, backends ending with `E` carry the file's
+/// extension after the digest. Returns `Some(None)` for keys without a checksum, like WORM or URL.
+fn parse_git_annex_line(line: &str) -> Option<Option<(&str, ExternalChecksum)>> {
+	let (key, file) = line.split_once(' ')?;
+	let (fields, name) = key.split_once("--")?;
+	let backend = fields.split('-').next()?;
+
+	if matches!(backend, "WORM" | "URL") {
+		return Some(None);
+	}
+
+	let (algorithm, hex) = match backend.strip_suffix('E') {
+		Some(algorithm) => (algorithm, name.split('.').next()?),
+		None => (backend, name),
+	};
+
+	is_hex(hex).then(|| {
+		Some((
+			file,
+			ExternalChecksum {
+				algorithm: algorithm.to_lowercase(),
+				hex: hex.to_lowercase(),
+			},
+		))
+	})
+}
+
+/// Lines look like `<hex>  <path>`, same as `sha256sum` and friends
+pub(super) fn parse_rclone_line<'line>(
+	line: &'line str,
+	hash: &str,
+) -> Option<(&'line str, ExternalChecksum)> {
+	let (hex, file) = line.split_once("  ")?;
+
+	is_hex(hex).then(|| {
+		(
+			file,
+			ExternalChecksum {
+				algorithm: hash.to_lowercase(),
+				hex: hex.to_lowercase(),
+			},
+		)
+	})
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+	use tempfile::tempdir;
+
+	#[tokio::test]
+	async fn test_load_external_checksums() {
+		let dir = tempdir().unwrap();
+
+		let annex_path = dir.path().join("annex.txt");
+		fs::write(
+			&annex_path,
+			"SHA256E-s5--ABCDEF.txt docs/a.txt\nSHA1-s3-m1600000000--0123 b.bin\n\
+			WORM-s3-m1600000000--c.txt c.txt\n\n",
+		)
+		.await
+		.unwrap();
+
+		let annex = ExternalChecksumSource::GitAnnex { path: annex_path }
+			.load()
+			.await
+			.unwrap();
+		assert_eq!(
+			annex["docs/a.txt"],
+			ExternalChecksum {
+				algorithm: "sha256".to_string(),
+				hex: "abcdef".to_string()
+			}
+		);
+		assert_eq!(annex["b.bin"].algorithm, "sha1");
+		assert!(!annex.contains_key("c.txt"));
+		assert_eq!(
+			annex["docs/a.txt"].as_integrity_checksum(ChecksumAlgorithm::Blake3),
+			None
+		);
+
+		let rclone_path = dir.path().join("rclone.txt");
+		let blake3_hex = "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262";
+		fs::write(
+			&rclone_path,
+			format!("{blake3_hex}  ./with spaces.txt\naf1349b9  truncated.txt\n"),
+		)
+		.await
+		.unwrap();
+
+		let rclone = ExternalChecksumSource::Rclone {
+			path: rclone_path.clone(),
+			hash: "BLAKE3".to_string(),
+		}
+		.load()
+		.await
+		.unwrap();
+		assert_eq!(
+			rclone["with spaces.txt"].as_integrity_checksum(ChecksumAlgorithm::Blake3),
+			Some(blake3_hex)
+		);
+		assert_eq!(
+			rclone["with spaces.txt"].as_integrity_checksum(ChecksumAlgorithm::Sha256),
+			None
+		);
+		assert_eq!(
+			rclone["truncated.txt"].as_integrity_checksum(ChecksumAlgorithm::Blake3),
+			None
+		);
+
+		fs::write(&rclone_path, "af1349b9  a.txt\nnot a checksum\n")
+			.await
+			.unwrap();
+		assert!(matches!(
+			ExternalChecksumSource::Rclone {
+				path: rclone_path,
+				hash: "blake3".to_string(),
+			}
+			.load()
+			.await,
+			Err(ValidatorError::InvalidExternalChecksum { line: 2, .. })
+		));
+	}
+}
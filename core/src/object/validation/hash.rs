@@ -1,24 +1,1906 @@
-use blake3::Hasher;
-use std::path::Path;
+use std::{
+	io::SeekFrom,
+	path::{Path, PathBuf},
+	time::{Duration, Instant},
+};
+
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use specta::Type;
 use tokio::{
-	fs::File,
-	io::{self, AsyncReadExt},
+	fs::{self, File},
+	io::{self, AsyncRead, AsyncReadExt, AsyncSeekExt},
+	time::{sleep, timeout},
 };
 
+use tracing::warn;
+
+#[cfg(any(target_os = "linux", target_os = "macos", windows))]
+use tracing::debug;
+
 const BLOCK_LEN: usize = 1048576;
 
+/// Smallest block reads fall back to when they fail for lack of memory, unless told otherwise
+pub const MIN_BLOCK_LEN: usize = 64 * 1024;
+
+// Network and FUSE mounts have high latency, so we read bigger blocks and retry reads that stall
+const REMOTE_BLOCK_LEN: usize = 8 * BLOCK_LEN;
+const REMOTE_READ_TIMEOUT: Duration = Duration::from_secs(30);
+const REMOTE_READ_RETRIES: u32 = 3;
+
+/// Algorithm used to compute a file's `integrity_checksum`
+#[derive(Serialize, Deserialize, Type, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+	#[default]
+	Blake3,
+	Sha256,
+}
+
+impl ChecksumAlgorithm {
+	/// Name stored in the database alongside checksums
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			Self::Blake3 => "blake3",
+			Self::Sha256 => "sha256",
+		}
+	}
+
+	/// Name stored in the database alongside checksums computed with `include_alt_streams`, see
+	/// [`ReadOptions::include_alt_streams`]. Those also covering the file's other streams get
+	/// [`ALT_STREAMS_SUFFIX`], as they differ from plain ones for files having any.
+	pub fn db_name(&self, include_alt_streams: bool) -> String {
+		if include_alt_streams {
+			format!("{}{ALT_STREAMS_SUFFIX}", self.as_str())
+		} else {
+			self.as_str().to_string()
+		}
+	}
+
+	/// Checksums stored before we kept track of the algorithm were all blake3. Those also
+	/// covering the file's other streams are `None`, so they're never compared with plain ones,
+	/// see [`ChecksumAlgorithm::from_db_in_mode`].
+	pub fn from_db(algorithm: Option<&str>) -> Option<Self> {
+		Self::from_db_in_mode(algorithm, false)
+	}
+
+	/// The algorithm of a checksum stored as `algorithm` if it was computed with
+	/// `include_alt_streams` too, `None` if it wasn't, checksums computed otherwise not being
+	/// comparable to the ones we'd compute
+	pub fn from_db_in_mode(algorithm: Option<&str>, include_alt_streams: bool) -> Option<Self> {
+		Self::from_db_with_alt_streams(algorithm)
+			.filter(|(_, alt_streams)| *alt_streams == include_alt_streams)
+			.map(|(algorithm, _)| algorithm)
+	}
+
+	/// The algorithm of a checksum stored as `algorithm` and if it also covers the file's other
+	/// streams
+	pub fn from_db_with_alt_streams(algorithm: Option<&str>) -> Option<(Self, bool)> {
+		// checksums without an algorithm predate the other streams being hashed
+		let Some(algorithm) = algorithm else {
+			return Some((Self::Blake3, false));
+		};
+		let (algorithm, alt_streams) = match algorithm.strip_suffix(ALT_STREAMS_SUFFIX) {
+			Some(algorithm) => (algorithm, true),
+			None => (algorithm, false),
+		};
+
+		match algorithm {
+			"blake3" => Some((Self::Blake3, alt_streams)),
+			"sha256" => Some((Self::Sha256, alt_streams)),
+			_ => None,
+		}
+	}
+
+	/// Length of the hex encoded digests
+	pub fn hex_len(&self) -> usize {
+		match self {
+			Self::Blake3 => blake3::OUT_LEN * 2,
+			Self::Sha256 => 64,
+		}
+	}
+
+	/// Checksum every empty file has
+	pub fn empty_checksum(&self) -> &'static str {
+		match self {
+			Self::Blake3 => "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+			Self::Sha256 => "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+		}
+	}
+}
+
+/// Suffix of the stored algorithm of checksums also covering the file's other streams, see
+/// [`ChecksumAlgorithm::db_name`]
+pub const ALT_STREAMS_SUFFIX: &str = "+streams";
+
+/// How many of a file's first bytes are enough to sniff the type of most formats
+pub const HEAD_LEN: usize = 8192;
+
+enum HashState {
+	Blake3(Box<blake3::Hasher>),
+	Sha256(Sha256),
+}
+
+struct Hasher {
+	algorithm: ChecksumAlgorithm,
+	state: HashState,
+	/// first bytes of the file, only kept when asked for
+	head: Option<Vec<u8>>,
+	head_len: usize,
+	/// how many bytes were hashed
+	len: u64,
+	/// hashes the same bytes with another algorithm, see [`file_checksums_migrating`]
+	previous: Option<Box<Hasher>>,
+}
+
+impl Hasher {
+	fn new(algorithm: ChecksumAlgorithm) -> Self {
+		Self {
+			algorithm,
+			state: match algorithm {
+				ChecksumAlgorithm::Blake3 => HashState::Blake3(Box::new(blake3::Hasher::new())),
+				ChecksumAlgorithm::Sha256 => HashState::Sha256(Sha256::new()),
+			},
+			head: None,
+			head_len: 0,
+			len: 0,
+			previous: None,
+		}
+	}
+
+	fn also_hashing(mut self, algorithm: ChecksumAlgorithm) -> Self {
+		self.previous = Some(Box::new(Self::new(algorithm)));
+		self
+	}
+
+	fn keeping_head(mut self, head_len: usize) -> Self {
+		// the file may be much shorter
+		self.head = Some(Vec::with_capacity(head_len.min(HEAD_LEN)));
+		self.head_len = head_len;
+		self
+	}
+
+	/// A hasher with the same settings that hasn't seen any data yet
+	#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+	fn fresh(&self) -> Self {
+		let mut hasher = Self::new(self.algorithm);
+		hasher.previous = self
+			.previous
+			.as_ref()
+			.map(|previous| Box::new(previous.fresh()));
+		if self.head.is_some() {
+			hasher.keeping_head(self.head_len)
+		} else {
+			hasher
+		}
+	}
+
+	fn update(&mut self, data: &[u8]) {
+		if let Some(head) = &mut self.head {
+			let missing = self.head_len - head.len();
+			head.extend_from_slice(&data[..missing.min(data.len())]);
+		}
+
+		if let Some(previous) = &mut self.previous {
+			previous.update(data);
+		}
+
+		self.len += data.len() as u64;
+		match &mut self.state {
+			HashState::Blake3(hasher) => {
+				hasher.update(data);
+			}
+			HashState::Sha256(hasher) => hasher.update(data),
+		}
+	}
+
+	fn finalize_hex(self) -> String {
+		self.finalize_with_head().0
+	}
+
+	/// Along with the checksum of [`Self::also_hashing`], if it was asked for
+	fn finalize_with_previous(mut self) -> (String, Option<String>) {
+		let previous = self.previous.take().map(|previous| previous.finalize_hex());
+		(self.finalize_hex(), previous)
+	}
+
+	fn finalize_with_head(self) -> (String, Vec<u8>) {
+		let checksum = match self.state {
+			HashState::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+			HashState::Sha256(hasher) => format!("{:x}", hasher.finalize()),
+		};
+
+		(checksum, self.head.unwrap_or_default())
+	}
+}
+
+// O_DIRECT requires buffers, offsets and lengths aligned to the device's logical block size,
+// 4KiB covers the common cases
+#[cfg(target_os = "linux")]
+const DIRECT_IO_ALIGNMENT: usize = 4096;
+
 pub async fn file_checksum(path: impl AsRef<Path>) -> Result<String, io::Error> {
+	buffered_hash(
+		path,
+		Hasher::new(ChecksumAlgorithm::Blake3),
+		None,
+		BLOCK_LEN,
+	)
+	.await
+	.map(Hasher::finalize_hex)
+}
+
+async fn buffered_hash(
+	path: impl AsRef<Path>,
+	context: Hasher,
+	read_timeout: Option<Duration>,
+	block_len: usize,
+) -> Result<Hasher, io::Error> {
+	hash_reader(File::open(path).await?, context, read_timeout, block_len).await
+}
+
+async fn hash_reader(
+	mut reader: impl AsyncRead + Unpin,
+	mut context: Hasher,
+	read_timeout: Option<Duration>,
+	block_len: usize,
+) -> Result<Hasher, io::Error> {
+	let mut buffer = allocate_buffer(block_len)?;
+	loop {
+		// reads can be shorter than asked before the end of the file
+		let read_count = timed_read(&mut reader, &mut buffer, read_timeout).await?;
+		if read_count == 0 {
+			break;
+		}
+		context.update(&buffer[..read_count]);
+	}
+
+	Ok(context)
+}
+
+/// The file yielded another number of bytes than its size while being checksummed, like when a
+/// flaky device ends reads early, so the checksum isn't of its contents
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("read {got} bytes of a file of {expected} bytes")]
+pub struct ShortRead {
+	pub expected: u64,
+	pub got: u64,
+}
+
+/// The [`ShortRead`] the read failed with, if that's why it did
+pub fn short_read(e: &io::Error) -> Option<&ShortRead> {
+	e.get_ref()?.downcast_ref()
+}
+
+/// The file's size changed while it was being checksummed, like a download in progress or a log
+/// being appended to, so the checksum is of none of its versions
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+#[error("file changed from {before} to {after} bytes while being read")]
+pub struct ChangedDuringRead {
+	pub before: u64,
+	pub after: u64,
+}
+
+/// The [`ChangedDuringRead`] the read failed with, if that's why it did
+pub fn changed_during_read(e: &io::Error) -> Option<&ChangedDuringRead> {
+	e.get_ref()?.downcast_ref()
+}
+
+/// Fails with a [`ChangedDuringRead`] unless the file still has the size it had before it was read
+async fn ensure_unchanged(path: &Path, before: u64) -> Result<(), io::Error> {
+	let after = fs::metadata(path).await?.len();
+	if after == before {
+		Ok(())
+	} else {
+		Err(io::Error::new(
+			io::ErrorKind::Other,
+			ChangedDuringRead { before, after },
+		))
+	}
+}
+
+/// Fails with a [`ShortRead`] unless `context` hashed `expected` bytes
+fn ensure_read_whole(context: Hasher, expected: u64) -> Result<Hasher, io::Error> {
+	if context.len == expected {
+		Ok(context)
+	} else {
+		Err(io::Error::new(
+			io::ErrorKind::UnexpectedEof,
+			ShortRead {
+				expected,
+				got: context.len,
+			},
+		))
+	}
+}
+
+/// Fails with [`io::ErrorKind::OutOfMemory`] instead of aborting when there's no memory left for
+/// the buffer
+fn allocate_buffer(len: usize) -> Result<Box<[u8]>, io::Error> {
+	let mut buffer = Vec::new();
+	buffer
+		.try_reserve_exact(len)
+		.map_err(|_| io::Error::from(io::ErrorKind::OutOfMemory))?;
+	buffer.resize(len, 0);
+
+	Ok(buffer.into_boxed_slice())
+}
+
+/// The read failed for lack of memory, in our process or the kernel's, so it may work with a
+/// smaller buffer
+pub fn is_memory_pressure(e: &io::Error) -> bool {
+	e.kind() == io::ErrorKind::OutOfMemory
+}
+
+/// Reads into `buffer`, failing with [`io::ErrorKind::TimedOut`] if it takes longer than
+/// `read_timeout`
+async fn timed_read(
+	reader: &mut (impl AsyncRead + Unpin),
+	buffer: &mut [u8],
+	read_timeout: Option<Duration>,
+) -> Result<usize, io::Error> {
+	match read_timeout {
+		Some(read_timeout) => timeout(read_timeout, reader.read(buffer))
+			.await
+			.unwrap_or_else(|_| Err(io::Error::from(io::ErrorKind::TimedOut))),
+		None => reader.read(buffer).await,
+	}
+}
+
+/// How a file's checksum should be computed
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReadOptions {
+	pub algorithm: ChecksumAlgorithm,
+	/// see [`file_checksum_bypassing_page_cache`]
+	pub bypass_page_cache: bool,
+	/// the file is on a network or FUSE mount, see [`remote_file_checksum`]
+	pub remote: bool,
+	/// give up on the file if a single read takes longer than this, so failing media can't hang
+	/// us. Direct IO reads can't be interrupted, so the page cache isn't bypassed when it's set.
+	pub read_timeout: Option<Duration>,
+	/// length of each read, a default tuned for the kind of mount when `None`
+	pub block_len: Option<usize>,
+	/// also hash the file's other streams, see [`with_alt_streams`]. The checksums are stored
+	/// under their own [`ChecksumAlgorithm::db_name`], so they're only compared with each other.
+	pub include_alt_streams: bool,
+}
+
+impl ReadOptions {
+	fn block_len(&self) -> usize {
+		self.block_len.unwrap_or(if self.remote {
+			REMOTE_BLOCK_LEN
+		} else {
+			BLOCK_LEN
+		})
+	}
+
+	/// The same options with reads half as long, `None` once they're at `min_block_len`
+	pub fn with_smaller_blocks(self, min_block_len: usize) -> Option<Self> {
+		let block_len = self.block_len();
+		let smaller = (block_len / 2).max(min_block_len).max(1);
+
+		(smaller < block_len).then_some(Self {
+			block_len: Some(smaller),
+			..self
+		})
+	}
+}
+
+pub async fn file_checksum_with(
+	path: impl AsRef<Path>,
+	options: ReadOptions,
+) -> Result<String, io::Error> {
+	let checksum = hash_with(path.as_ref(), options, Hasher::new(options.algorithm))
+		.await?
+		.finalize_hex();
+
+	with_alt_streams(path.as_ref(), options, checksum).await
+}
+
+/// Same as [`file_checksum_with`], also handing back the first `head_len` bytes of the file, or
+/// the whole file if it's shorter, so it doesn't need to be read again to sniff its type or
+/// decode it
+pub async fn file_checksum_and_head(
+	path: impl AsRef<Path>,
+	options: ReadOptions,
+	head_len: usize,
+) -> Result<(String, Vec<u8>), io::Error> {
+	let (checksum, head) = hash_with(
+		path.as_ref(),
+		options,
+		Hasher::new(options.algorithm).keeping_head(head_len),
+	)
+	.await?
+	.finalize_with_head();
+
+	Ok((
+		with_alt_streams(path.as_ref(), options, checksum).await?,
+		head,
+	))
+}
+
+/// Same as [`file_checksum_with`], also computing the checksum with `previous` from the same
+/// reads, so files migrated to another algorithm can be checked against the checksum they had
+/// without reading them twice. Returns the checksums with `options.algorithm` and `previous`.
+pub async fn file_checksums_migrating(
+	path: impl AsRef<Path>,
+	options: ReadOptions,
+	previous: ChecksumAlgorithm,
+) -> Result<(String, String), io::Error> {
+	let (checksum, previous_checksum) = hash_with(
+		path.as_ref(),
+		options,
+		Hasher::new(options.algorithm).also_hashing(previous),
+	)
+	.await?
+	.finalize_with_previous();
+
+	let (checksum, previous_checksum) = with_alt_streams_and_previous(
+		path.as_ref(),
+		options,
+		checksum,
+		previous_checksum.map(|previous_checksum| (previous, previous_checksum)),
+	)
+	.await?;
+
+	// it's always computed once asked for
+	Ok((checksum, previous_checksum.unwrap_or_default()))
+}
+
+/// The file's streams beyond its main one, the alternate data streams of NTFS and the resource
+/// fork on macOS, by name along with the path they can be read at, sorted by name. There are
+/// none on other platforms, nor on file systems without them.
+#[cfg_attr(not(any(windows, target_os = "macos")), allow(unused_variables))]
+async fn alt_streams(path: &Path) -> Result<Vec<(String, PathBuf)>, io::Error> {
+	#[cfg(windows)]
+	let mut streams = {
+		let path = path.to_path_buf();
+		tokio::task::spawn_blocking(move || windows_alt_streams(&path))
+			.await
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))??
+	};
+
+	#[cfg(target_os = "macos")]
+	let mut streams = {
+		let fork_path = path.join("..namedfork/rsrc");
+		match fs::metadata(&fork_path).await {
+			// every file has an empty resource fork on file systems supporting them
+			Ok(metadata) if metadata.len() > 0 => vec![("rsrc".to_string(), fork_path)],
+			Ok(_) => vec![],
+			Err(e) => {
+				debug!(
+					"No resource fork for {}, hashing its data fork only: {e}",
+					path.display()
+				);
+				vec![]
+			}
+		}
+	};
+
+	#[cfg(not(any(windows, target_os = "macos")))]
+	let mut streams = Vec::<(String, PathBuf)>::new();
+
+	streams.sort_by(|(a, _), (b, _)| a.cmp(b));
+	Ok(streams)
+}
+
+#[cfg(windows)]
+fn windows_alt_streams(path: &Path) -> Result<Vec<(String, PathBuf)>, io::Error> {
+	use std::{
+		ffi::OsString,
+		mem,
+		os::windows::ffi::{OsStrExt, OsStringExt},
+	};
+
+	use windows_sys::Win32::{
+		Foundation::{ERROR_HANDLE_EOF, INVALID_HANDLE_VALUE},
+		Storage::FileSystem::{
+			FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+			WIN32_FIND_STREAM_DATA,
+		},
+	};
+
+	let wide_path = path
+		.as_os_str()
+		.encode_wide()
+		.chain(Some(0))
+		.collect::<Vec<_>>();
+	// SAFETY: it's plain data, all zeros is a valid value
+	let mut data = unsafe { mem::zeroed::<WIN32_FIND_STREAM_DATA>() };
+
+	// SAFETY: the path is nul terminated and `data` is what `FindStreamInfoStandard` fills
+	let handle = unsafe {
+		FindFirstStreamW(
+			wide_path.as_ptr(),
+			FindStreamInfoStandard,
+			(&mut data as *mut WIN32_FIND_STREAM_DATA).cast(),
+			0,
+		)
+	};
+	if handle == INVALID_HANDLE_VALUE {
+		// like on FAT or network shares
+		debug!(
+			"Couldn't list the streams of {}, hashing its main stream only: {}",
+			path.display(),
+			io::Error::last_os_error()
+		);
+		return Ok(vec![]);
+	}
+
+	let mut streams = vec![];
+	let result = loop {
+		let name_len = data
+			.cStreamName
+			.iter()
+			.position(|&c| c == 0)
+			.unwrap_or(data.cStreamName.len());
+		let name = OsString::from_wide(&data.cStreamName[..name_len])
+			.to_string_lossy()
+			.into_owned();
+
+		// the main stream is listed too, unnamed
+		if name != "::$DATA" {
+			let mut stream_path = path.as_os_str().to_owned();
+			stream_path.push(&name);
+			streams.push((name, PathBuf::from(stream_path)));
+		}
+
+		// SAFETY: the handle is open until closed below
+		if unsafe { FindNextStreamW(handle, (&mut data as *mut WIN32_FIND_STREAM_DATA).cast()) }
+			== 0
+		{
+			let e = io::Error::last_os_error();
+			break if e.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+				Ok(streams)
+			} else {
+				Err(e)
+			};
+		}
+	};
+
+	// SAFETY: the handle isn't used after this
+	unsafe { FindClose(handle) };
+
+	result
+}
+
+/// Combines `checksum`, of the file's main stream, with the checksums of its other streams when
+/// asked to, as two files with the same main contents can differ there. Files without other
+/// streams keep the checksum of their main stream.
+async fn with_alt_streams(
+	path: &Path,
+	options: ReadOptions,
+	checksum: String,
+) -> Result<String, io::Error> {
+	with_alt_streams_and_previous(path, options, checksum, None)
+		.await
+		.map(|(checksum, _)| checksum)
+}
+
+/// Same as [`with_alt_streams`], also combining `previous`, the checksum of the main stream with
+/// another algorithm, with the checksums of the other streams computed with it from the same reads
+async fn with_alt_streams_and_previous(
+	path: &Path,
+	options: ReadOptions,
+	checksum: String,
+	previous: Option<(ChecksumAlgorithm, String)>,
+) -> Result<(String, Option<String>), io::Error> {
+	if !options.include_alt_streams {
+		return Ok((checksum, previous.map(|(_, checksum)| checksum)));
+	}
+
+	let streams = alt_streams(path).await?;
+	if streams.is_empty() {
+		return Ok((checksum, previous.map(|(_, checksum)| checksum)));
+	}
+
+	let mut content = stream_listing_start(checksum);
+	let mut previous =
+		previous.map(|(algorithm, checksum)| (algorithm, stream_listing_start(checksum)));
+	for (name, stream_path) in streams {
+		let mut hasher = Hasher::new(options.algorithm);
+		if let Some((algorithm, _)) = &previous {
+			hasher = hasher.also_hashing(*algorithm);
+		}
+		let (stream_checksum, previous_stream_checksum) = hash_with(&stream_path, options, hasher)
+			.await?
+			.finalize_with_previous();
+
+		list_stream(&mut content, &name, &stream_checksum);
+		if let (Some((_, content)), Some(stream_checksum)) =
+			(&mut previous, previous_stream_checksum)
+		{
+			list_stream(content, &name, &stream_checksum);
+		}
+	}
+
+	Ok((
+		bytes_checksum(&content, options.algorithm),
+		previous.map(|(algorithm, content)| bytes_checksum(&content, algorithm)),
+	))
+}
+
+fn stream_listing_start(checksum: String) -> Vec<u8> {
+	let mut content = checksum.into_bytes();
+	content.push(b'\n');
+	content
+}
+
+fn list_stream(content: &mut Vec<u8>, name: &str, stream_checksum: &str) {
+	// names can't hold nul bytes nor checksums newlines, so streams can't be read as others
+	content.extend_from_slice(name.as_bytes());
+	content.push(0);
+	content.extend_from_slice(stream_checksum.as_bytes());
+	content.push(b'\n');
+}
+
+/// How often [`checksum_stream`] reports its progress at most
+pub const HASH_PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How much of a file [`checksum_stream`] read so far
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashProgress {
+	pub bytes_done: u64,
+	pub total: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashEvent {
+	Progress(HashProgress),
+	/// The checksum of the whole file, always the last event
+	Done(String),
+}
+
+/// Checksums a single file as a stream of its progress ending with its checksum, for interactive
+/// checks like a "verifying this file" dialog. The file is only read while the stream is polled,
+/// so a slow consumer slows the read down instead of events piling up, and dropping the stream
+/// stops the read before the next block. Progress is reported at most every
+/// [`HASH_PROGRESS_INTERVAL`], and reads always go through the page cache.
+pub fn checksum_stream(
+	path: impl AsRef<Path>,
+	options: ReadOptions,
+) -> impl Stream<Item = Result<HashEvent, io::Error>> {
+	let path = path.as_ref().to_path_buf();
+
+	async_stream::try_stream! {
+		let total = fs::metadata(&path).await?.len();
+		let mut reader = File::open(&path).await?;
+		let mut buffer = allocate_buffer(options.block_len())?;
+		let mut context = Hasher::new(options.algorithm);
+
+		yield HashEvent::Progress(HashProgress { bytes_done: 0, total });
+		let mut last_reported = Instant::now();
+
+		loop {
+			let read_count = timed_read(&mut reader, &mut buffer, options.read_timeout).await?;
+			if read_count == 0 {
+				break;
+			}
+			context.update(&buffer[..read_count]);
+
+			if last_reported.elapsed() >= HASH_PROGRESS_INTERVAL {
+				last_reported = Instant::now();
+				yield HashEvent::Progress(HashProgress { bytes_done: context.len, total });
+			}
+		}
+
+		ensure_unchanged(&path, total).await?;
+		let checksum = ensure_read_whole(context, total)?.finalize_hex();
+		let checksum = with_alt_streams(&path, options, checksum).await?;
+
+		yield HashEvent::Progress(HashProgress { bytes_done: total, total });
+		yield HashEvent::Done(checksum);
+	}
+}
+
+/// Checksums of the `(offset, len)` byte ranges of a file, in order. Ranges going past the end
+/// of the file only hash the bytes it has, so they don't match if it was truncated.
+pub async fn range_checksums(
+	path: impl AsRef<Path>,
+	options: ReadOptions,
+	ranges: &[(u64, u64)],
+) -> Result<Vec<String>, io::Error> {
 	let mut reader = File::open(path).await?;
-	let mut context = Hasher::new();
-	let mut buffer = vec![0; BLOCK_LEN].into_boxed_slice();
+	let mut buffer = allocate_buffer(options.block_len())?;
+
+	let mut checksums = Vec::with_capacity(ranges.len());
+	for &(offset, len) in ranges {
+		reader.seek(SeekFrom::Start(offset)).await?;
+
+		let mut context = Hasher::new(options.algorithm);
+		let mut remaining = len;
+		while remaining > 0 {
+			let to_read = remaining.min(buffer.len() as u64) as usize;
+			let read_count =
+				timed_read(&mut reader, &mut buffer[..to_read], options.read_timeout).await?;
+			if read_count == 0 {
+				break;
+			}
+			context.update(&buffer[..read_count]);
+			remaining -= read_count as u64;
+		}
+
+		checksums.push(context.finalize_hex());
+	}
+
+	Ok(checksums)
+}
+
+/// Checksum of everything `reader` yields, along with how many bytes that was
+pub async fn reader_checksum(
+	mut reader: impl AsyncRead + Unpin,
+	algorithm: ChecksumAlgorithm,
+) -> Result<(String, u64), io::Error> {
+	let mut context = Hasher::new(algorithm);
+	let mut buffer = allocate_buffer(BLOCK_LEN)?;
+
+	let mut len = 0;
 	loop {
 		let read_count = reader.read(&mut buffer).await?;
+		if read_count == 0 {
+			break;
+		}
 		context.update(&buffer[..read_count]);
-		if read_count != BLOCK_LEN {
+		len += read_count as u64;
+	}
+
+	Ok((context.finalize_hex(), len))
+}
+
+/// Fails with a [`ChangedDuringRead`] if the file's size changed while it was read, and with a
+/// [`ShortRead`] if the file doesn't yield as many bytes as its size
+async fn hash_with(
+	path: impl AsRef<Path>,
+	options: ReadOptions,
+	context: Hasher,
+) -> Result<Hasher, io::Error> {
+	let expected = fs::metadata(path.as_ref()).await?.len();
+
+	let context = if options.remote {
+		// Direct IO is rarely supported by remote file systems, so we don't even try
+		remote_hash(
+			path.as_ref(),
+			context,
+			options.read_timeout.unwrap_or(REMOTE_READ_TIMEOUT),
+			options.block_len(),
+		)
+		.await
+	} else if options.bypass_page_cache && options.read_timeout.is_none() {
+		hash_bypassing_page_cache(path.as_ref(), context, options.block_len()).await
+	} else {
+		buffered_hash(
+			path.as_ref(),
+			context,
+			options.read_timeout,
+			options.block_len(),
+		)
+		.await
+	}?;
+
+	// a file growing while it's read also yields more bytes than its size
+	ensure_unchanged(path.as_ref(), expected).await?;
+	ensure_read_whole(context, expected)
+}
+
+/// Same as [`file_checksum`] but tuned for network and FUSE mounts: reads bigger blocks, gives up
+/// on reads that stall and retries them from where they stopped, reopening the file.
+pub async fn remote_file_checksum(
+	path: impl AsRef<Path>,
+	algorithm: ChecksumAlgorithm,
+	read_timeout: Duration,
+) -> Result<String, io::Error> {
+	remote_hash(
+		path.as_ref(),
+		Hasher::new(algorithm),
+		read_timeout,
+		REMOTE_BLOCK_LEN,
+	)
+	.await
+	.map(Hasher::finalize_hex)
+}
+
+async fn remote_hash(
+	path: &Path,
+	mut context: Hasher,
+	read_timeout: Duration,
+	block_len: usize,
+) -> Result<Hasher, io::Error> {
+	let mut reader = File::open(path).await?;
+	let mut buffer = allocate_buffer(block_len)?;
+	let mut offset = 0;
+	let mut retries = 0;
+
+	loop {
+		match timed_read(&mut reader, &mut buffer, Some(read_timeout)).await {
+			Ok(0) => break,
+			Ok(read_count) => {
+				context.update(&buffer[..read_count]);
+				offset += read_count as u64;
+				retries = 0;
+			}
+			Err(e) if retries < REMOTE_READ_RETRIES => {
+				retries += 1;
+				warn!(
+					"Read of {} failed at offset {offset}, retrying ({retries}/{REMOTE_READ_RETRIES}): {e}",
+					path.display()
+				);
+				sleep(Duration::from_secs(retries.into())).await;
+
+				reader = File::open(path).await?;
+				reader.seek(SeekFrom::Start(offset)).await?;
+			}
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(context)
+}
+
+/// Same as [`file_checksum`] but avoids filling the page cache with the file's contents, so large
+/// scrubs don't evict data that the rest of the system is using. Only Linux supports it (with
+/// `O_DIRECT`), other platforms and filesystems refusing direct IO fall back to regular reads.
+pub async fn file_checksum_bypassing_page_cache(
+	path: impl AsRef<Path>,
+	algorithm: ChecksumAlgorithm,
+) -> Result<String, io::Error> {
+	hash_bypassing_page_cache(path.as_ref(), Hasher::new(algorithm), BLOCK_LEN)
+		.await
+		.map(Hasher::finalize_hex)
+}
+
+async fn hash_bypassing_page_cache(
+	path: &Path,
+	context: Hasher,
+	block_len: usize,
+) -> Result<Hasher, io::Error> {
+	#[cfg(target_os = "linux")]
+	let context = {
+		// direct IO can fail after some reads, so the fallback starts over
+		let fallback_context = context.fresh();
+		let direct_path = path.to_path_buf();
+		match tokio::task::spawn_blocking(move || direct_io_hash(&direct_path, context, block_len))
+			.await
+		{
+			Ok(Ok(context)) => return Ok(context),
+			// the buffered reads would need as much memory
+			Ok(Err(e)) if is_memory_pressure(&e) => return Err(e),
+			Ok(Err(e)) => {
+				debug!(
+					"Direct IO unavailable for {}, falling back to buffered reads: {e}",
+					path.display()
+				);
+				fallback_context
+			}
+			Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+		}
+	};
+
+	buffered_hash(path, context, None, block_len).await
+}
+
+#[cfg(target_os = "linux")]
+fn direct_io_hash(path: &Path, mut context: Hasher, block_len: usize) -> Result<Hasher, io::Error> {
+	use std::{fs::OpenOptions, io::Read, os::unix::fs::OpenOptionsExt};
+
+	let mut reader = OpenOptions::new()
+		.read(true)
+		.custom_flags(libc::O_DIRECT)
+		.open(path)?;
+
+	let block_len = (block_len / DIRECT_IO_ALIGNMENT).max(1) * DIRECT_IO_ALIGNMENT;
+
+	// Over allocating so we can pick an aligned window inside the buffer
+	let mut raw_buffer = allocate_buffer(block_len + DIRECT_IO_ALIGNMENT)?;
+	let offset = raw_buffer.as_ptr().align_offset(DIRECT_IO_ALIGNMENT);
+	let buffer = &mut raw_buffer[offset..offset + block_len];
+
+	loop {
+		// With O_DIRECT only the last read of the file can be short, so we read until EOF
+		let read_count = reader.read(buffer)?;
+		if read_count == 0 {
 			break;
 		}
+		context.update(&buffer[..read_count]);
+	}
+
+	Ok(context)
+}
+
+/// Checksum of bytes already in memory, hex encoded like the ones of files
+pub fn bytes_checksum(data: &[u8], algorithm: ChecksumAlgorithm) -> String {
+	let mut hasher = Hasher::new(algorithm);
+	hasher.update(data);
+	hasher.finalize_hex()
+}
+
+/// Checks that a checksum looks like one produced by [`file_checksum_with`] for `algorithm`,
+/// a lowercase hex digest of the right length, or one [`truncate_checksum`]ed from it
+pub fn is_valid_checksum(checksum: &str, algorithm: ChecksumAlgorithm) -> bool {
+	let (hex, hex_len) = match checksum.split_once('/') {
+		// full length ones are never stored with their length
+		Some((hex, digest_bits)) => match digest_bits.parse::<usize>() {
+			Ok(bits)
+				if is_valid_digest_bits(bits, algorithm)
+					&& bits < algorithm.hex_len() * 4
+					&& bits.to_string() == digest_bits =>
+			{
+				(hex, bits / 4)
+			}
+			_ => return false,
+		},
+		None => (checksum, algorithm.hex_len()),
+	};
+
+	hex.len() == hex_len && hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Shortest checksums can be truncated to, collisions among shorter ones are to be expected in
+/// big libraries
+pub const MIN_DIGEST_BITS: usize = 64;
+
+/// Checks that checksums of `algorithm` can be truncated to `digest_bits`, a whole number of bytes
+/// from [`MIN_DIGEST_BITS`] up to their full length
+pub fn is_valid_digest_bits(digest_bits: usize, algorithm: ChecksumAlgorithm) -> bool {
+	digest_bits % 8 == 0 && (MIN_DIGEST_BITS..=algorithm.hex_len() * 4).contains(&digest_bits)
+}
+
+/// `checksum` cut down to its first `digest_bits` bits, to take less space in huge libraries.
+/// The length is kept along with it, as `hex/bits`, so it's only ever compared with checksums cut to
+/// the same length, see [`checksums_match`]. Checksums already as short are kept as they are.
+///
+/// Telling a file changed stays as reliable, a modified file only goes unnoticed with a chance of
+/// 1 in 2^bits. Two different files sharing a checksum gets more likely though, with about
+/// n^2 / 2^(bits + 1) chance among n files: for a billion files it's 1 in 10^21 with 128 bits, but
+/// already 1 in 40 with 64 bits, which matters for finding duplicates by checksum.
+pub fn truncate_checksum(checksum: &str, digest_bits: Option<usize>) -> String {
+	let hex = checksum.split_once('/').map_or(checksum, |(hex, _)| hex);
+
+	match digest_bits.and_then(|digest_bits| hex.get(..digest_bits / 4)) {
+		Some(truncated) if truncated.len() < hex.len() => {
+			format!("{truncated}/{}", truncated.len() * 4)
+		}
+		_ => checksum.to_string(),
+	}
+}
+
+/// Length in bits a checksum was [`truncate_checksum`]ed to, `None` for full length ones
+pub fn checksum_digest_bits(checksum: &str) -> Option<usize> {
+	checksum.split_once('/')?.1.parse().ok()
+}
+
+/// Checks if the `stored` checksum of a file is the one of its contents, `computed` at full length,
+/// cutting it to the length `stored` was truncated to if it was
+pub fn checksums_match(stored: &str, computed: &str) -> bool {
+	match checksum_digest_bits(stored) {
+		Some(digest_bits) => truncate_checksum(computed, Some(digest_bits)) == stored,
+		None => stored == computed,
 	}
-	let hex = context.finalize().to_hex();
+}
+
+/// A checksum along with the algorithm it was computed with, as `algorithm:checksum`, for the
+/// columns holding checksums of any algorithm
+pub fn encode_checksum(algorithm: ChecksumAlgorithm, checksum: &str) -> String {
+	format!("{}:{checksum}", algorithm.as_str())
+}
+
+/// The algorithm and checksum of an [`encode_checksum`]ed one, `None` if the algorithm isn't one
+/// we know. It's parsed from synced or imported data, so the checksum isn't trusted to be a valid
+/// one, see [`is_valid_checksum`].
+pub fn decode_checksum(encoded: &str) -> Option<(ChecksumAlgorithm, &str)> {
+	let (algorithm, checksum) = encoded.split_once(':')?;
+
+	Some((ChecksumAlgorithm::from_db(Some(algorithm))?, checksum))
+}
+
+/// Checks if two arbitrary files have the same content, by their checksums with `algorithm`, they
+/// don't need to be in a location. Files with different sizes are reported as different without
+/// reading any of their bytes.
+pub async fn compare_files(
+	a: impl AsRef<Path>,
+	b: impl AsRef<Path>,
+	algorithm: ChecksumAlgorithm,
+) -> Result<bool, io::Error> {
+	let (a, b) = (a.as_ref(), b.as_ref());
+
+	if fs::metadata(a).await?.len() != fs::metadata(b).await?.len() {
+		return Ok(false);
+	}
+
+	let options = ReadOptions {
+		algorithm,
+		..Default::default()
+	};
+
+	Ok(file_checksum_with(a, options).await? == file_checksum_with(b, options).await?)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileComparison {
+	Equal,
+	/// `first_diff_offset` is the length of the shorter file if it's a prefix of the other one
+	NotEqual {
+		first_diff_offset: u64,
+	},
+}
+
+/// Compares two arbitrary files byte by byte, reading both in lockstep and stopping at the first
+/// difference. Way faster than [`compare_files`] to tell apart files that differ early, when
+/// neither of their checksums is known.
+pub async fn compare_streaming(
+	a: impl AsRef<Path>,
+	b: impl AsRef<Path>,
+) -> Result<FileComparison, io::Error> {
+	let mut a = File::open(a).await?;
+	let mut b = File::open(b).await?;
+
+	let mut a_buffer = allocate_buffer(BLOCK_LEN)?;
+	let mut b_buffer = allocate_buffer(BLOCK_LEN)?;
+	let mut offset = 0;
+
+	loop {
+		let a_read = read_block(&mut a, &mut a_buffer).await?;
+		let b_read = read_block(&mut b, &mut b_buffer).await?;
+
+		if let Some(position) = a_buffer[..a_read]
+			.iter()
+			.zip(&b_buffer[..b_read])
+			.position(|(a, b)| a != b)
+		{
+			return Ok(FileComparison::NotEqual {
+				first_diff_offset: offset + position as u64,
+			});
+		}
+
+		if a_read != b_read {
+			return Ok(FileComparison::NotEqual {
+				first_diff_offset: offset + a_read.min(b_read) as u64,
+			});
+		}
+
+		if a_read < BLOCK_LEN {
+			return Ok(FileComparison::Equal);
+		}
+
+		offset += BLOCK_LEN as u64;
+	}
+}
+
+/// Fills `buffer` unless the end of the file is reached first, as reads can be shorter than asked
+async fn read_block(reader: &mut File, buffer: &mut [u8]) -> Result<usize, io::Error> {
+	let mut filled = 0;
+	while filled < buffer.len() {
+		match reader.read(&mut buffer[filled..]).await? {
+			0 => break,
+			read_count => filled += read_count,
+		}
+	}
+
+	Ok(filled)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+	use tempfile::tempdir;
+	use tokio::io::AsyncWriteExt;
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn test_file_changed_during_read() {
+		use std::sync::{
+			atomic::{AtomicBool, Ordering},
+			Arc,
+		};
+
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("download.part");
+		fs::write(&path, vec![7; 4 * 1024 * 1024]).await.unwrap();
+
+		// appends to the file until it's done being read, like a download in progress
+		let done = Arc::new(AtomicBool::new(false));
+		let writer = tokio::spawn({
+			let path = path.clone();
+			let done = Arc::clone(&done);
+			async move {
+				let mut file = fs::OpenOptions::new()
+					.append(true)
+					.open(&path)
+					.await
+					.unwrap();
+				while !done.load(Ordering::Relaxed) {
+					file.write_all(b"x").await.unwrap();
+					file.flush().await.unwrap();
+					tokio::time::sleep(Duration::from_millis(1)).await;
+				}
+			}
+		});
+
+		let result = file_checksum_with(
+			&path,
+			ReadOptions {
+				block_len: Some(4096),
+				..Default::default()
+			},
+		)
+		.await;
+		done.store(true, Ordering::Relaxed);
+		writer.await.unwrap();
+
+		let e = result.unwrap_err();
+		let changed = changed_during_read(&e).unwrap();
+		assert!(changed.after > changed.before);
+		assert!(short_read(&e).is_none());
+
+		// once it's done changing its checksum can be computed
+		assert!(file_checksum_with(&path, ReadOptions::default())
+			.await
+			.is_ok());
+	}
+
+	#[tokio::test]
+	async fn test_compare_files() {
+		let dir = tempdir().unwrap();
+		let original = dir.path().join("original.txt");
+		let copy = dir.path().join("copy.txt");
+		let same_size = dir.path().join("same_size.txt");
+		let bigger = dir.path().join("bigger.txt");
+
+		fs::write(&original, b"spacedrive").await.unwrap();
+		fs::write(&copy, b"spacedrive").await.unwrap();
+		fs::write(&same_size, b"spacedrivf").await.unwrap();
+		fs::write(&bigger, b"spacedrive!").await.unwrap();
+
+		for algorithm in [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256] {
+			assert!(compare_files(&original, &copy, algorithm).await.unwrap());
+			assert!(!compare_files(&original, &same_size, algorithm)
+				.await
+				.unwrap());
+			assert!(!compare_files(&original, &bigger, algorithm).await.unwrap());
+		}
+		assert!(compare_files(
+			&original,
+			dir.path().join("missing.txt"),
+			ChecksumAlgorithm::Blake3
+		)
+		.await
+		.is_err());
+	}
+
+	#[tokio::test]
+	async fn test_compare_streaming() {
+		let dir = tempdir().unwrap();
+		let path = |name: &str| dir.path().join(name);
+
+		let content = (0..BLOCK_LEN + 1234)
+			.map(|i| (i % 251) as u8)
+			.collect::<Vec<_>>();
+		let mut late_diff = content.clone();
+		late_diff[BLOCK_LEN + 10] ^= 1;
+
+		fs::write(path("original"), &content).await.unwrap();
+		fs::write(path("copy"), &content).await.unwrap();
+		fs::write(path("late_diff"), &late_diff).await.unwrap();
+		fs::write(path("prefix"), &content[..BLOCK_LEN])
+			.await
+			.unwrap();
+		fs::write(path("empty"), b"").await.unwrap();
+
+		for (other, expected) in [
+			("copy", FileComparison::Equal),
+			(
+				"late_diff",
+				FileComparison::NotEqual {
+					first_diff_offset: BLOCK_LEN as u64 + 10,
+				},
+			),
+			(
+				"prefix",
+				FileComparison::NotEqual {
+					first_diff_offset: BLOCK_LEN as u64,
+				},
+			),
+			(
+				"empty",
+				FileComparison::NotEqual {
+					first_diff_offset: 0,
+				},
+			),
+		] {
+			assert_eq!(
+				compare_streaming(path("original"), path(other))
+					.await
+					.unwrap(),
+				expected,
+				"{other}"
+			);
+		}
+
+		assert_eq!(
+			compare_streaming(path("empty"), path("empty"))
+				.await
+				.unwrap(),
+			FileComparison::Equal
+		);
+	}
+
+	#[tokio::test]
+	async fn test_is_valid_checksum() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file.txt");
+		fs::write(&path, b"spacedrive").await.unwrap();
+
+		let checksum = file_checksum(&path).await.unwrap();
+		let blake3 = ChecksumAlgorithm::Blake3;
+		assert!(is_valid_checksum(&checksum, blake3));
+		assert!(!is_valid_checksum(
+			&checksum[..blake3.hex_len() - 1],
+			blake3
+		));
+		assert!(!is_valid_checksum(&checksum.to_uppercase(), blake3));
+		assert!(!is_valid_checksum(&format!("{}g", &checksum[1..]), blake3));
+	}
+
+	#[test]
+	fn test_truncate_checksum() {
+		let blake3 = ChecksumAlgorithm::Blake3;
+		let checksum = blake3.empty_checksum();
+
+		let truncated = truncate_checksum(checksum, Some(128));
+		assert_eq!(truncated, format!("{}/128", &checksum[..32]));
+		assert_eq!(checksum_digest_bits(&truncated), Some(128));
+		assert!(is_valid_checksum(&truncated, blake3));
+		assert!(checksums_match(&truncated, checksum));
+		assert!(checksums_match(checksum, checksum));
+		assert!(!checksums_match(
+			&truncated,
+			ChecksumAlgorithm::Sha256.empty_checksum()
+		));
+		// compared at the length they were truncated to, not the one currently configured
+		assert!(checksums_match(
+			&truncate_checksum(checksum, Some(64)),
+			checksum
+		));
+		assert_eq!(truncate_checksum(&truncated, Some(128)), truncated);
+
+		// full length checksums are kept as they are
+		assert_eq!(truncate_checksum(checksum, None), checksum);
+		assert_eq!(truncate_checksum(checksum, Some(256)), checksum);
+		assert_eq!(checksum_digest_bits(checksum), None);
+
+		assert!(is_valid_digest_bits(MIN_DIGEST_BITS, blake3));
+		assert!(!is_valid_digest_bits(MIN_DIGEST_BITS - 8, blake3));
+		assert!(!is_valid_digest_bits(100, blake3));
+		assert!(!is_valid_digest_bits(512, blake3));
 
-	Ok(hex.to_string())
+		// the stored length must be the one of the digest
+		assert!(!is_valid_checksum(
+			&format!("{}/64", &checksum[..32]),
+			blake3
+		));
+		assert!(!is_valid_checksum(
+			&format!("{}/0128", &checksum[..32]),
+			blake3
+		));
+		assert!(!is_valid_checksum(
+			&format!("{}/32", &checksum[..8]),
+			blake3
+		));
+		assert!(!is_valid_checksum(&format!("{checksum}/256"), blake3));
+	}
+
+	#[test]
+	fn test_checksum_encoding() {
+		for algorithm in [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256] {
+			let encoded = encode_checksum(algorithm, algorithm.empty_checksum());
+			assert_eq!(
+				decode_checksum(&encoded),
+				Some((algorithm, algorithm.empty_checksum()))
+			);
+		}
+
+		// whatever decodes encodes back to the same string
+		for encoded in ["blake3:", "sha256:a:b", "blake3:not hex"] {
+			let (algorithm, checksum) = decode_checksum(encoded).unwrap();
+			assert_eq!(encode_checksum(algorithm, checksum), encoded);
+		}
+
+		for encoded in ["", "aaaa", ":aaaa", "md4:aaaa", "BLAKE3:aaaa"] {
+			assert_eq!(decode_checksum(encoded), None, "{encoded}");
+		}
+	}
+
+	#[tokio::test]
+	async fn test_sha256_checksum() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file.txt");
+		fs::write(&path, b"spacedrive").await.unwrap();
+
+		let options = ReadOptions {
+			algorithm: ChecksumAlgorithm::Sha256,
+			..Default::default()
+		};
+		let checksum = file_checksum_with(&path, options).await.unwrap();
+
+		assert_ne!(checksum, file_checksum(&path).await.unwrap());
+		assert!(is_valid_checksum(&checksum, ChecksumAlgorithm::Sha256));
+		assert_eq!(
+			checksum,
+			file_checksum_bypassing_page_cache(&path, ChecksumAlgorithm::Sha256)
+				.await
+				.unwrap()
+		);
+	}
+
+	#[tokio::test]
+	async fn test_file_checksums_migrating() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file.txt");
+		fs::write(&path, b"spacedrive").await.unwrap();
+
+		// direct IO falls back to buffered reads on file systems refusing it, starting over
+		for (bypass_page_cache, include_alt_streams) in
+			[(false, false), (true, false), (false, true)]
+		{
+			let options = ReadOptions {
+				algorithm: ChecksumAlgorithm::Sha256,
+				bypass_page_cache,
+				include_alt_streams,
+				..Default::default()
+			};
+			let (checksum, previous) =
+				file_checksums_migrating(&path, options, ChecksumAlgorithm::Blake3)
+					.await
+					.unwrap();
+
+			assert_eq!(checksum, file_checksum_with(&path, options).await.unwrap());
+			assert_eq!(
+				previous,
+				file_checksum_with(
+					&path,
+					ReadOptions {
+						algorithm: ChecksumAlgorithm::Blake3,
+						..options
+					}
+				)
+				.await
+				.unwrap()
+			);
+		}
+	}
+
+	#[tokio::test]
+	async fn test_empty_checksum() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("empty");
+		fs::write(&path, b"").await.unwrap();
+
+		for algorithm in [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256] {
+			let options = ReadOptions {
+				algorithm,
+				..Default::default()
+			};
+			assert_eq!(
+				file_checksum_with(&path, options).await.unwrap(),
+				algorithm.empty_checksum()
+			);
+		}
+	}
+
+	#[tokio::test]
+	async fn test_remote_checksum_matches_buffered_checksum() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("big.bin");
+
+		let content = (0..REMOTE_BLOCK_LEN + 1234)
+			.map(|i| (i % 251) as u8)
+			.collect::<Vec<_>>();
+		fs::write(&path, &content).await.unwrap();
+
+		assert_eq!(
+			remote_file_checksum(&path, ChecksumAlgorithm::Blake3, REMOTE_READ_TIMEOUT)
+				.await
+				.unwrap(),
+			file_checksum(&path).await.unwrap()
+		);
+	}
+
+	#[tokio::test]
+	async fn test_timed_read() {
+		// nothing is ever written to the other end, so reads hang
+		let (mut reader, _writer) = io::duplex(64);
+		let mut buffer = [0; 16];
+
+		assert_eq!(
+			timed_read(&mut reader, &mut buffer, Some(Duration::from_millis(10)))
+				.await
+				.unwrap_err()
+				.kind(),
+			io::ErrorKind::TimedOut
+		);
+
+		let (mut reader, mut writer) = io::duplex(64);
+		writer.write_all(b"spacedrive").await.unwrap();
+		assert_eq!(
+			timed_read(&mut reader, &mut buffer, Some(Duration::from_secs(10)))
+				.await
+				.unwrap(),
+			10
+		);
+	}
+
+	#[tokio::test]
+	async fn test_short_reads() {
+		let content = (0..100_000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+		let (expected, _) = reader_checksum(&content[..], ChecksumAlgorithm::Blake3)
+			.await
+			.unwrap();
+
+		// pipes hand their bytes over in reads way shorter than asked
+		let (reader, mut writer) = io::duplex(1000);
+		let written = content.clone();
+		tokio::spawn(async move { writer.write_all(&written).await.unwrap() });
+		let context = hash_reader(
+			reader,
+			Hasher::new(ChecksumAlgorithm::Blake3),
+			None,
+			BLOCK_LEN,
+		)
+		.await
+		.unwrap();
+		let Ok(context) = ensure_read_whole(context, content.len() as u64) else {
+			panic!("short reads ended the file early");
+		};
+		assert_eq!(context.finalize_hex(), expected);
+
+		// like a flaky device ending the file early
+		let (reader, mut writer) = io::duplex(1000);
+		let written = content[..1500].to_vec();
+		tokio::spawn(async move { writer.write_all(&written).await.unwrap() });
+		let context = hash_reader(
+			reader,
+			Hasher::new(ChecksumAlgorithm::Blake3),
+			None,
+			BLOCK_LEN,
+		)
+		.await
+		.unwrap();
+		let Err(e) = ensure_read_whole(context, content.len() as u64) else {
+			panic!("the checksum of part of the file was computed");
+		};
+		assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+		assert_eq!(
+			short_read(&e),
+			Some(&ShortRead {
+				expected: 100_000,
+				got: 1500
+			})
+		);
+		assert_eq!(short_read(&io::Error::from(io::ErrorKind::Other)), None);
+	}
+
+	#[tokio::test]
+	async fn test_file_checksum_and_head() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file.bin");
+		let content = (0..HEAD_LEN * 2).map(|i| i as u8).collect::<Vec<_>>();
+		fs::write(&path, &content).await.unwrap();
+
+		for bypass_page_cache in [false, true] {
+			let (checksum, head) = file_checksum_and_head(
+				&path,
+				ReadOptions {
+					bypass_page_cache,
+					..Default::default()
+				},
+				HEAD_LEN,
+			)
+			.await
+			.unwrap();
+
+			assert_eq!(checksum, file_checksum(&path).await.unwrap());
+			assert_eq!(head, &content[..HEAD_LEN]);
+		}
+
+		// heads can be longer, up to the whole file
+		for head_len in [HEAD_LEN + 1000, content.len(), content.len() * 2] {
+			let (_, head) = file_checksum_and_head(&path, ReadOptions::default(), head_len)
+				.await
+				.unwrap();
+			assert_eq!(head, &content[..head_len.min(content.len())]);
+		}
+
+		// files shorter than the head are handed back whole
+		fs::write(&path, b"tiny").await.unwrap();
+		let (_, head) = file_checksum_and_head(&path, ReadOptions::default(), HEAD_LEN)
+			.await
+			.unwrap();
+		assert_eq!(head, b"tiny");
+	}
+
+	#[test]
+	fn test_db_name() {
+		for algorithm in [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256] {
+			for include_alt_streams in [false, true] {
+				let db_name = algorithm.db_name(include_alt_streams);
+				assert_eq!(
+					ChecksumAlgorithm::from_db_with_alt_streams(Some(&db_name)),
+					Some((algorithm, include_alt_streams))
+				);
+				assert_eq!(
+					ChecksumAlgorithm::from_db_in_mode(Some(&db_name), include_alt_streams),
+					Some(algorithm)
+				);
+				// checksums computed in the other mode are never compared with ours
+				assert_eq!(
+					ChecksumAlgorithm::from_db_in_mode(Some(&db_name), !include_alt_streams),
+					None
+				);
+			}
+		}
+
+		assert_eq!(
+			ChecksumAlgorithm::from_db(None),
+			Some(ChecksumAlgorithm::Blake3)
+		);
+		assert_eq!(ChecksumAlgorithm::from_db(Some("blake3+streams")), None);
+		assert_eq!(
+			ChecksumAlgorithm::from_db_with_alt_streams(Some("md5+streams")),
+			None
+		);
+	}
+
+	#[tokio::test]
+	async fn test_alt_streams() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file.txt");
+		fs::write(&path, b"main contents").await.unwrap();
+
+		let options = ReadOptions {
+			include_alt_streams: true,
+			..Default::default()
+		};
+		let main_only = file_checksum(&path).await.unwrap();
+
+		// files without other streams keep the checksum of their main one
+		assert_eq!(file_checksum_with(&path, options).await.unwrap(), main_only);
+
+		#[cfg(any(windows, target_os = "macos"))]
+		{
+			#[cfg(windows)]
+			let stream_path = {
+				let mut stream_path = path.as_os_str().to_owned();
+				stream_path.push(":extra");
+				PathBuf::from(stream_path)
+			};
+			#[cfg(target_os = "macos")]
+			let stream_path = path.join("..namedfork/rsrc");
+
+			// the test may run on a file system without streams
+			if fs::write(&stream_path, b"hidden contents").await.is_ok()
+				&& !alt_streams(&path).await.unwrap().is_empty()
+			{
+				let with_streams = file_checksum_with(&path, options).await.unwrap();
+				assert_ne!(with_streams, main_only);
+				assert_eq!(file_checksum(&path).await.unwrap(), main_only);
+
+				fs::write(&stream_path, b"other hidden contents")
+					.await
+					.unwrap();
+				assert_ne!(
+					file_checksum_with(&path, options).await.unwrap(),
+					with_streams
+				);
+			}
+		}
+	}
+
+	#[tokio::test]
+	async fn test_checksum_stream() {
+		use futures::StreamExt;
+
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file.bin");
+		let content = (0..MIN_BLOCK_LEN * 4).map(|i| i as u8).collect::<Vec<_>>();
+		fs::write(&path, &content).await.unwrap();
+
+		let options = ReadOptions {
+			algorithm: ChecksumAlgorithm::Sha256,
+			block_len: Some(MIN_BLOCK_LEN),
+			..Default::default()
+		};
+		let events = checksum_stream(&path, options)
+			.collect::<Vec<_>>()
+			.await
+			.into_iter()
+			.collect::<Result<Vec<_>, _>>()
+			.unwrap();
+
+		let total = content.len() as u64;
+		assert_eq!(
+			events.first(),
+			Some(&HashEvent::Progress(HashProgress {
+				bytes_done: 0,
+				total
+			}))
+		);
+		assert_eq!(
+			events.last(),
+			Some(&HashEvent::Done(
+				file_checksum_with(&path, options).await.unwrap()
+			))
+		);
+		assert_eq!(
+			events[events.len() - 2],
+			HashEvent::Progress(HashProgress {
+				bytes_done: total,
+				total
+			})
+		);
+
+		// the file is only read as the stream is polled, it can be dropped halfway
+		let mut stream = Box::pin(checksum_stream(&path, options));
+		assert!(matches!(
+			stream.next().await,
+			Some(Ok(HashEvent::Progress(HashProgress { bytes_done: 0, .. })))
+		));
+		drop(stream);
+
+		fs::remove_file(&path).await.unwrap();
+		assert!(checksum_stream(&path, options)
+			.collect::<Vec<_>>()
+			.await
+			.into_iter()
+			.any(|event| event.is_err()));
+	}
+
+	#[tokio::test]
+	async fn test_bypassing_page_cache_matches_buffered_checksum() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("big.bin");
+
+		// Not a multiple of the block size or of the direct IO alignment
+		let content = (0..BLOCK_LEN * 2 + 1234)
+			.map(|i| (i % 251) as u8)
+			.collect::<Vec<_>>();
+		fs::write(&path, &content).await.unwrap();
+
+		assert_eq!(
+			file_checksum_bypassing_page_cache(&path, ChecksumAlgorithm::Blake3)
+				.await
+				.unwrap(),
+			file_checksum(&path).await.unwrap()
+		);
+	}
+
+	#[tokio::test]
+	async fn test_smaller_blocks() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("big.bin");
+		let content = (0..BLOCK_LEN + 1234)
+			.map(|i| (i % 251) as u8)
+			.collect::<Vec<_>>();
+		fs::write(&path, &content).await.unwrap();
+
+		let mut options = ReadOptions::default();
+		let mut block_lens = vec![];
+		while let Some(smaller) = options.with_smaller_blocks(BLOCK_LEN / 8) {
+			block_lens.push(smaller.block_len.unwrap());
+			options = smaller;
+
+			for bypass_page_cache in [false, true] {
+				assert_eq!(
+					file_checksum_with(
+						&path,
+						ReadOptions {
+							bypass_page_cache,
+							..options
+						}
+					)
+					.await
+					.unwrap(),
+					file_checksum(&path).await.unwrap()
+				);
+			}
+		}
+		assert_eq!(
+			block_lens,
+			vec![BLOCK_LEN / 2, BLOCK_LEN / 4, BLOCK_LEN / 8]
+		);
+
+		// the floor is reached even if halving would go past it
+		assert_eq!(
+			ReadOptions {
+				block_len: Some(100),
+				..Default::default()
+			}
+			.with_smaller_blocks(60)
+			.and_then(|options| options.block_len),
+			Some(60)
+		);
+	}
+
+	#[tokio::test]
+	async fn test_range_checksums() {
+		let dir = tempdir().unwrap();
+		let whole = dir.path().join("whole.txt");
+		let part = dir.path().join("part.txt");
+		fs::write(&whole, b"hello spacedrive").await.unwrap();
+		fs::write(&part, b"spacedrive").await.unwrap();
+
+		let options = ReadOptions {
+			block_len: Some(3),
+			..Default::default()
+		};
+		let checksums = range_checksums(&whole, options, &[(6, 10), (0, 5), (6, 100)])
+			.await
+			.unwrap();
+
+		let part_checksum = file_checksum(&part).await.unwrap();
+		assert_eq!(checksums[0], part_checksum);
+		assert_ne!(checksums[1], part_checksum);
+		// the range goes past the end of the file
+		assert_eq!(checksums[2], part_checksum);
+	}
+
+	/// Content and its blake3 and sha256 digests, from the reference implementations. Fixtures
+	/// are generated rather than checked in, so git can't convert their line endings.
+	fn portable_fixtures() -> Vec<(&'static str, Vec<u8>, &'static str, &'static str)> {
+		let pattern = |len: usize| (0..len).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+		vec![
+			(
+				"empty",
+				vec![],
+				"af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+				"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+			),
+			(
+				"text",
+				b"spacedrive".to_vec(),
+				"d07b0cc2cf782e5122c03666fef1add9e7bbdb108d0ecc2f9cf2805b57a18561",
+				"67b2114ca7aa6bbfe3664f2fee87367843cbdf4d4fd668ec81e9363c9874024c",
+			),
+			(
+				"nuls",
+				b"\0space\0\0drive\0".to_vec(),
+				"c4a3ce5157f35c5d18124d74eb04e3ae6f307e64e49085d927fe2c992a8db5ed",
+				"d4b5272202270295182cfdd503a169d9be152586e1544a876e5dc85b78d72c1d",
+			),
+			(
+				"line_endings",
+				b"one\r\ntwo\nthree\r".to_vec(),
+				"2f9bd29ca9f3b3d6145244b0ac58d4e963cc4590b356435a3548c02c1b36e5fe",
+				"c487d23b88fa7291e67805566cdcfb2bfe10aec8755f494562421dd66eb97ad6",
+			),
+			(
+				"all_bytes",
+				(0..=255).collect(),
+				"4a495ba42461748eca8fdad618f976aa726cc2903de9fcb40735a786ac1c196b",
+				"40aff2e9d2d8922e47afd4648e6967497158785fbd1da870e7110266bf944880",
+			),
+			(
+				// several blake3 chunks, not a whole number of them
+				"chunks",
+				pattern(10_000),
+				"5f81f9e4ab67627b6b036d5d4e3bc40d9d3daa6fcc2b6dd07ab2bbf0a877da54",
+				"0cd0bf930677960951dda8588edcb6b293c0c3b26ef3ba72cddff4ddfc6822c7",
+			),
+			(
+				"past_block",
+				pattern(BLOCK_LEN + 1),
+				"2f053cd7472cf0cd2f9adaf45c1180255b91b9a865404a63671a0ee5f792ed33",
+				"5769f52bc3eef28afa39c6fc68cadb7d0bd69812ae3a3d71452f519ec3c7aa56",
+			),
+		]
+	}
+
+	/// Names the fixtures are also written under, the checksum must only depend on the content
+	fn portable_names() -> Vec<std::ffi::OsString> {
+		#[allow(unused_mut)]
+		let mut names = vec![
+			"plain".into(),
+			"with space.txt".into(),
+			"ünïcödé 文件".into(),
+		];
+
+		// macOS and most Windows file systems reject names that aren't valid Unicode
+		#[cfg(target_os = "linux")]
+		{
+			use std::os::unix::ffi::OsStrExt;
+			names.push(std::ffi::OsStr::from_bytes(b"latin1-\xe9t\xe9").to_os_string());
+		}
+
+		names
+	}
+
+	#[tokio::test]
+	async fn test_checksums_are_portable() {
+		let dir = tempdir().unwrap();
+
+		for (name, content, blake3, sha256) in portable_fixtures() {
+			for file_name in portable_names() {
+				let mut path_name = std::ffi::OsString::from(format!("{name}-"));
+				path_name.push(&file_name);
+				let path = dir.path().join(path_name);
+				fs::write(&path, &content).await.unwrap();
+
+				for (algorithm, expected) in [
+					(ChecksumAlgorithm::Blake3, blake3),
+					(ChecksumAlgorithm::Sha256, sha256),
+				] {
+					for options in [
+						ReadOptions::default(),
+						ReadOptions {
+							bypass_page_cache: true,
+							..Default::default()
+						},
+						ReadOptions {
+							remote: true,
+							..Default::default()
+						},
+						ReadOptions {
+							block_len: Some(1000),
+							..Default::default()
+						},
+					] {
+						let options = ReadOptions {
+							algorithm,
+							..options
+						};
+						assert_eq!(
+							file_checksum_with(&path, options).await.unwrap(),
+							expected,
+							"{name} at {} with {options:?}",
+							path.display()
+						);
+					}
+				}
+			}
+		}
+	}
+
+	/// Hashes a sparse file past 4GiB, where 32 bit offsets or lengths would wrap. It's slow, so
+	/// run it with `cargo test -- --ignored`.
+	#[tokio::test]
+	#[ignore]
+	async fn test_checksums_past_4gib() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("huge.bin");
+
+		let len = (4 << 30) + 4097;
+		let tail = b"end of a huge file";
+		{
+			let mut file = File::create(&path).await.unwrap();
+			file.set_len(len).await.unwrap();
+			file.seek(SeekFrom::Start(len - tail.len() as u64))
+				.await
+				.unwrap();
+			file.write_all(tail).await.unwrap();
+		}
+
+		for algorithm in [ChecksumAlgorithm::Blake3, ChecksumAlgorithm::Sha256] {
+			let mut expected = Hasher::new(algorithm);
+			let zeros = vec![0; BLOCK_LEN];
+			let mut remaining = len - tail.len() as u64;
+			while remaining > 0 {
+				let zeros_len = remaining.min(BLOCK_LEN as u64) as usize;
+				expected.update(&zeros[..zeros_len]);
+				remaining -= zeros_len as u64;
+			}
+			expected.update(tail);
+
+			assert_eq!(
+				file_checksum_with(
+					&path,
+					ReadOptions {
+						algorithm,
+						..Default::default()
+					}
+				)
+				.await
+				.unwrap(),
+				expected.finalize_hex()
+			);
+		}
+	}
 }
@@ -0,0 +1,473 @@
+use crate::util::error::FileIOError;
+
+use std::{
+	collections::{HashMap, HashSet},
+	path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{PublicKey, Signature, Verifier};
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tokio::fs;
+
+use super::{
+	external::{normalize_path, parse_rclone_line},
+	hash::{is_valid_checksum, ChecksumAlgorithm},
+	ValidatorError,
+};
+
+/// Checksums published by a trusted authority, to audit files against them instead of the
+/// checksums stored in the library, which could have been altered along with the files.
+/// The manifest is in the `sha256sum` format, with paths relative to the location root. Lines
+/// starting with `#` are comments, the authority tells when it signed the manifest in one, see
+/// [`Self::date_signed`].
+#[derive(Serialize, Deserialize, Type, Debug, Clone)]
+pub struct SignedManifest {
+	pub path: PathBuf,
+	/// hex encoded ed25519 signature of the manifest's bytes
+	pub signature_path: PathBuf,
+	/// hex encoded ed25519 public key of the authority
+	pub public_key: String,
+	pub algorithm: ChecksumAlgorithm,
+	/// checksums of the content-defined chunks of the listed files, one `<checksum> <offset>
+	/// <len> <path>` line per chunk, to tell which byte ranges of a corrupted file differ. It
+	/// isn't signed, as it only locates damage in files the manifest already failed.
+	#[serde(default)]
+	pub chunks_path: Option<PathBuf>,
+}
+
+/// Start of the comment of a manifest holding when it was signed, followed by an RFC 3339 date
+const SIGNED_AT_PREFIX: &str = "# signed ";
+
+/// A byte range of a file and its checksum, as listed in a chunk manifest
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+	pub offset: u64,
+	pub len: u64,
+	pub checksum: String,
+}
+
+impl SignedManifest {
+	/// Reads the checksums, keyed by their path relative to the location, only once the manifest
+	/// is known to have been signed by the authority
+	pub async fn load(&self) -> Result<HashMap<String, String>, ValidatorError> {
+		self.parse(&self.read_verified().await?)
+	}
+
+	/// The bytes of the manifest, once its signature was checked
+	pub async fn read_verified(&self) -> Result<Vec<u8>, ValidatorError> {
+		let content = fs::read(&self.path)
+			.await
+			.map_err(|e| FileIOError::from((&self.path, e)))?;
+		let signature = fs::read_to_string(&self.signature_path)
+			.await
+			.map_err(|e| FileIOError::from((&self.signature_path, e)))?;
+
+		verify_signature(&content, &signature, &self.public_key)
+			.map_err(|()| ValidatorError::InvalidManifestSignature(self.path.clone().into()))?;
+
+		Ok(content)
+	}
+
+	/// The checksums listed in the bytes of the manifest, see [`Self::read_verified`]
+	pub fn parse(&self, content: &[u8]) -> Result<HashMap<String, String>, ValidatorError> {
+		String::from_utf8_lossy(content)
+			.lines()
+			.enumerate()
+			.filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+			.map(|(line_number, line)| {
+				parse_rclone_line(line, self.algorithm.as_str())
+					.and_then(|(relative_path, checksum)| {
+						checksum
+							.as_integrity_checksum(self.algorithm)
+							.map(|hex| (normalize_path(relative_path), hex.to_string()))
+					})
+					.ok_or_else(|| ValidatorError::InvalidExternalChecksum {
+						path: self.path.clone().into_boxed_path(),
+						line: line_number + 1,
+					})
+			})
+			.collect()
+	}
+
+	/// Reads the chunks of each file of the chunk manifest, if there's one, keyed by their path
+	/// relative to the location and ordered by offset
+	pub async fn load_chunks(&self) -> Result<Option<HashMap<String, Vec<Chunk>>>, ValidatorError> {
+		let Some(chunks_path) = &self.chunks_path else {
+			return Ok(None);
+		};
+
+		let content = fs::read_to_string(chunks_path)
+			.await
+			.map_err(|e| FileIOError::from((chunks_path, e)))?;
+
+		self.parse_chunks(chunks_path, &content).map(Some)
+	}
+
+	/// The chunks listed in the contents of the chunk manifest at `chunks_path`, see
+	/// [`Self::load_chunks`]
+	pub fn parse_chunks(
+		&self,
+		chunks_path: &Path,
+		content: &str,
+	) -> Result<HashMap<String, Vec<Chunk>>, ValidatorError> {
+		let mut chunks = HashMap::<_, Vec<_>>::new();
+		for (line_number, line) in content
+			.lines()
+			.enumerate()
+			.filter(|(_, line)| !line.trim().is_empty())
+		{
+			let (relative_path, chunk) =
+				parse_chunk_line(line, self.algorithm).ok_or_else(|| {
+					ValidatorError::InvalidExternalChecksum {
+						path: chunks_path.into(),
+						line: line_number + 1,
+					}
+				})?;
+			chunks.entry(relative_path).or_default().push(chunk);
+		}
+
+		for file_chunks in chunks.values_mut() {
+			file_chunks.sort_unstable_by_key(|chunk| chunk.offset);
+		}
+
+		Ok(chunks)
+	}
+
+	/// When the manifest was signed, from its `# signed <RFC 3339 date>` comment, so it's covered
+	/// by the signature, unlike the dates of the files the manifest is copied to. `None` if the
+	/// authority didn't write one. Takes the bytes of the manifest, see [`Self::read_verified`].
+	pub fn date_signed(&self, content: &[u8]) -> Result<Option<DateTime<Utc>>, ValidatorError> {
+		String::from_utf8_lossy(content)
+			.lines()
+			.enumerate()
+			.find_map(|(line_number, line)| {
+				line.strip_prefix(SIGNED_AT_PREFIX)
+					.map(|date| (line_number, date))
+			})
+			.map(|(line_number, date)| {
+				DateTime::parse_from_rfc3339(date.trim())
+					.map(Into::into)
+					.map_err(|_| ValidatorError::InvalidExternalChecksum {
+						path: self.path.clone().into_boxed_path(),
+						line: line_number + 1,
+					})
+			})
+			.transpose()
+	}
+}
+
+fn parse_chunk_line(line: &str, algorithm: ChecksumAlgorithm) -> Option<(String, Chunk)> {
+	let (checksum, rest) = line.trim_start().split_once(char::is_whitespace)?;
+	let (offset, rest) = rest.trim_start().split_once(char::is_whitespace)?;
+	let (len, relative_path) = rest.trim_start().split_once(char::is_whitespace)?;
+	let relative_path = relative_path.trim_start();
+
+	let checksum = checksum.to_ascii_lowercase();
+	if !is_valid_checksum(&checksum, algorithm) || relative_path.is_empty() {
+		return None;
+	}
+
+	Some((
+		normalize_path(relative_path),
+		Chunk {
+			offset: offset.parse().ok()?,
+			len: len.parse().ok()?,
+			checksum,
+		},
+	))
+}
+
+/// The `(offset, len)` ranges of the chunks whose checksum in `checksums` isn't the listed one,
+/// adjacent ones merged into a single range
+pub(super) fn corrupt_ranges(chunks: &[Chunk], checksums: &[String]) -> Vec<(u64, u64)> {
+	let mut ranges = Vec::<(u64, u64)>::new();
+
+	for (chunk, checksum) in chunks.iter().zip(checksums) {
+		if &chunk.checksum == checksum {
+			continue;
+		}
+
+		match ranges.last_mut() {
+			Some((offset, len)) if *offset + *len == chunk.offset => *len += chunk.len,
+			_ => ranges.push((chunk.offset, chunk.len)),
+		}
+	}
+
+	ranges
+}
+
+pub(super) fn verify_signature(
+	content: &[u8],
+	signature: &str,
+	public_key: &str,
+) -> Result<(), ()> {
+	let public_key = hex::decode(public_key.trim())
+		.ok()
+		.and_then(|bytes| PublicKey::from_bytes(&bytes).ok())
+		.ok_or(())?;
+	let signature = hex::decode(signature.trim())
+		.ok()
+		.and_then(|bytes| Signature::try_from(bytes.as_slice()).ok())
+		.ok_or(())?;
+
+	public_key.verify(content, &signature).map_err(|_| ())
+}
+
+/// Files the manifest lists under the `sub_path` directory, relative to the location, that the
+/// location doesn't have
+pub(super) fn missing_from_location<'manifest, V>(
+	manifest: &'manifest HashMap<String, V>,
+	listed: &HashSet<String>,
+	sub_path: Option<&str>,
+) -> Vec<&'manifest str> {
+	let prefix = sub_path
+		.map(normalize_path)
+		.filter(|prefix| !prefix.is_empty())
+		.map(|prefix| format!("{}/", prefix.trim_end_matches('/')));
+
+	let mut missing = manifest
+		.keys()
+		.filter(|relative_path| {
+			prefix
+				.as_ref()
+				.map_or(true, |prefix| relative_path.starts_with(prefix))
+		})
+		.filter(|relative_path| !listed.contains(*relative_path))
+		.map(String::as_str)
+		.collect::<Vec<_>>();
+	missing.sort_unstable();
+
+	missing
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use ed25519_dalek::{Keypair, SecretKey, Signer};
+	use tempfile::tempdir;
+
+	const BLAKE3_HEX: &str = "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262";
+
+	fn keypair() -> Keypair {
+		let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+		let public = PublicKey::from(&secret);
+		Keypair { secret, public }
+	}
+
+	#[tokio::test]
+	async fn test_load_signed_manifest() {
+		let dir = tempdir().unwrap();
+		let keypair = keypair();
+
+		let manifest = SignedManifest {
+			path: dir.path().join("manifest.txt"),
+			signature_path: dir.path().join("manifest.txt.sig"),
+			public_key: hex::encode(keypair.public.as_bytes()),
+			algorithm: ChecksumAlgorithm::Blake3,
+			chunks_path: None,
+		};
+		let content = format!("{BLAKE3_HEX}  ./docs/a.txt\n");
+		fs::write(&manifest.path, &content).await.unwrap();
+		fs::write(
+			&manifest.signature_path,
+			hex::encode(keypair.sign(content.as_bytes()).to_bytes()),
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(
+			manifest.load().await.unwrap(),
+			[("docs/a.txt".to_string(), BLAKE3_HEX.to_string())]
+				.into_iter()
+				.collect()
+		);
+
+		// someone altered the manifest after it was signed
+		fs::write(&manifest.path, format!("{BLAKE3_HEX}  ./docs/b.txt\n"))
+			.await
+			.unwrap();
+		assert!(matches!(
+			manifest.load().await,
+			Err(ValidatorError::InvalidManifestSignature(_))
+		));
+
+		let other_authority = SignedManifest {
+			public_key: hex::encode([1; 32]),
+			..manifest.clone()
+		};
+		fs::write(&manifest.path, &content).await.unwrap();
+		assert!(matches!(
+			other_authority.load().await,
+			Err(ValidatorError::InvalidManifestSignature(_))
+		));
+	}
+
+	#[test]
+	fn test_date_signed() {
+		let manifest = SignedManifest {
+			path: PathBuf::from("manifest.txt"),
+			signature_path: PathBuf::from("manifest.txt.sig"),
+			public_key: String::new(),
+			algorithm: ChecksumAlgorithm::Blake3,
+			chunks_path: None,
+		};
+
+		let content = format!("# signed 2023-06-01T12:00:00+02:00\n{BLAKE3_HEX}  a.txt\n");
+		assert_eq!(
+			manifest.date_signed(content.as_bytes()).unwrap(),
+			Some("2023-06-01T10:00:00Z".parse().unwrap())
+		);
+		// it's a comment, not a file
+		assert_eq!(
+			manifest.parse(content.as_bytes()).unwrap(),
+			[("a.txt".to_string(), BLAKE3_HEX.to_string())]
+				.into_iter()
+				.collect()
+		);
+
+		assert_eq!(
+			manifest
+				.date_signed(format!("{BLAKE3_HEX}  a.txt\n").as_bytes())
+				.unwrap(),
+			None
+		);
+		assert!(matches!(
+			manifest.date_signed(format!("{BLAKE3_HEX}  a.txt\n# signed yesterday\n").as_bytes()),
+			Err(ValidatorError::InvalidExternalChecksum { line: 2, .. })
+		));
+	}
+
+	#[test]
+	fn test_missing_from_location() {
+		let manifest = ["a.txt", "docs/b.txt", "docs/c.txt", "docsx/d.txt"]
+			.into_iter()
+			.map(|path| (path.to_string(), BLAKE3_HEX.to_string()))
+			.collect();
+		let listed = ["docs/b.txt".to_string()].into_iter().collect();
+
+		assert_eq!(
+			missing_from_location(&manifest, &listed, None),
+			vec!["a.txt", "docs/c.txt", "docsx/d.txt"]
+		);
+		assert_eq!(
+			missing_from_location(&manifest, &listed, Some("docs/")),
+			vec!["docs/c.txt"]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_load_chunks() {
+		let dir = tempdir().unwrap();
+		let chunk = |offset, len| Chunk {
+			offset,
+			len,
+			checksum: BLAKE3_HEX.to_string(),
+		};
+
+		let mut manifest = SignedManifest {
+			path: dir.path().join("manifest.txt"),
+			signature_path: dir.path().join("manifest.txt.sig"),
+			public_key: String::new(),
+			algorithm: ChecksumAlgorithm::Blake3,
+			chunks_path: None,
+		};
+		assert_eq!(manifest.load_chunks().await.unwrap(), None);
+
+		let chunks_path = dir.path().join("chunks.txt");
+		fs::write(
+			&chunks_path,
+			format!(
+				"{BLAKE3_HEX}  4096  1024  ./docs/my file.txt\n\n{}  0  4096  docs/my file.txt\n",
+				BLAKE3_HEX.to_uppercase()
+			),
+		)
+		.await
+		.unwrap();
+		manifest.chunks_path = Some(chunks_path.clone());
+		assert_eq!(
+			manifest.load_chunks().await.unwrap(),
+			Some(
+				[(
+					"docs/my file.txt".to_string(),
+					vec![chunk(0, 4096), chunk(4096, 1024)]
+				)]
+				.into_iter()
+				.collect()
+			)
+		);
+
+		fs::write(&chunks_path, format!("{BLAKE3_HEX}  0  docs/a.txt\n"))
+			.await
+			.unwrap();
+		assert!(matches!(
+			manifest.load_chunks().await,
+			Err(ValidatorError::InvalidExternalChecksum { line: 1, .. })
+		));
+	}
+
+	#[test]
+	fn test_parse_malformed_manifest() {
+		let manifest = SignedManifest {
+			path: PathBuf::from("manifest.txt"),
+			signature_path: PathBuf::from("manifest.txt.sig"),
+			public_key: String::new(),
+			algorithm: ChecksumAlgorithm::Blake3,
+			chunks_path: None,
+		};
+
+		// the manifest comes from elsewhere, anything it holds fails to parse instead of panicking
+		for content in [
+			&b"\xff\xfe\x00"[..],
+			format!("{BLAKE3_HEX}  a.txt\r\n{BLAKE3_HEX}").as_bytes(),
+			format!("{}  a.txt", &BLAKE3_HEX[1..]).as_bytes(),
+			"\u{e9}\u{e9}  \u{1f600}".as_bytes(),
+		] {
+			assert!(manifest.parse(content).is_err(), "{content:?}");
+		}
+		for content in [
+			"1  2  3",
+			"\u{e9}  0  0  a.txt",
+			&format!("{BLAKE3_HEX}  18446744073709551616  1  a.txt"),
+			&format!("{BLAKE3_HEX}  0  1  "),
+		] {
+			assert!(
+				manifest
+					.parse_chunks(Path::new("chunks.txt"), content)
+					.is_err(),
+				"{content:?}"
+			);
+		}
+
+		// blank lines are skipped, so blank manifests list nothing
+		for content in [&b"\n\n"[..], b"  ", b"\r\n\t\r\n"] {
+			assert!(manifest.parse(content).unwrap().is_empty());
+		}
+	}
+
+	#[test]
+	fn test_corrupt_ranges() {
+		let chunk = |offset, len, checksum: &str| Chunk {
+			offset,
+			len,
+			checksum: checksum.to_string(),
+		};
+		let chunks = [
+			chunk(0, 10, "a"),
+			chunk(10, 5, "b"),
+			chunk(15, 20, "c"),
+			chunk(35, 5, "d"),
+			chunk(40, 8, "e"),
+		];
+		let checksums = ["a", "x", "x", "d", "x"].map(str::to_string);
+
+		assert_eq!(corrupt_ranges(&chunks, &checksums), vec![(10, 25), (40, 8)]);
+		assert_eq!(
+			corrupt_ranges(&chunks, &["a", "b", "c", "d", "e"].map(str::to_string)),
+			vec![]
+		);
+	}
+}
@@ -0,0 +1,431 @@
+use std::path::Path;
+
+use blake3::Hasher;
+use tokio::{
+	fs::File,
+	io::{self, AsyncBufReadExt, AsyncReadExt, BufReader},
+};
+use tracing::debug;
+
+const BLOCK_LEN: usize = 1048576;
+
+/// Checksums only the media payload of JPEG, PNG, MP3 and FLAC files, so copies that only differ
+/// in their embedded tags have the same checksum. Other files, or files we fail to parse, get a
+/// regular full byte checksum, computed along the way so the file is only read once.
+pub async fn media_content_checksum(path: impl AsRef<Path>) -> Result<String, io::Error> {
+	let path = path.as_ref();
+
+	let mut reader = MediaReader {
+		reader: BufReader::with_capacity(BLOCK_LEN, File::open(path).await?),
+		full: Hasher::new(),
+		payload: Hasher::new(),
+	};
+
+	let Some(format) = MediaFormat::from_header(reader.reader.fill_buf().await?) else {
+		reader.rest(false).await?;
+		return Ok(reader.full.finalize().to_hex().to_string());
+	};
+
+	if !format.hash_payload(&mut reader).await? {
+		debug!(
+			"Couldn't parse {} as {format:?}, falling back to a full byte checksum",
+			path.display()
+		);
+		reader.rest(false).await?;
+		return Ok(reader.full.finalize().to_hex().to_string());
+	}
+
+	Ok(reader.payload.finalize().to_hex().to_string())
+}
+
+/// Reads a file front to back, every byte going into the full byte checksum and the ones it's
+/// told to keep into the payload one
+struct MediaReader {
+	reader: BufReader<File>,
+	full: Hasher,
+	payload: Hasher,
+}
+
+impl MediaReader {
+	/// Fills `bytes` with the next bytes of the file, returning how many there were, fewer at its
+	/// end. They only go into the payload once kept with [`Self::keep`].
+	async fn read(&mut self, bytes: &mut [u8]) -> Result<usize, io::Error> {
+		let mut read = 0;
+		while read < bytes.len() {
+			match self.reader.read(&mut bytes[read..]).await? {
+				0 => break,
+				n => read += n,
+			}
+		}
+		self.full.update(&bytes[..read]);
+
+		Ok(read)
+	}
+
+	fn keep(&mut self, bytes: &[u8]) {
+		self.payload.update(bytes);
+	}
+
+	/// Hashes the next `len` bytes, into the payload too if `keep`, returning whether the file
+	/// had that many
+	async fn copy(&mut self, mut len: u64, keep: bool) -> Result<bool, io::Error> {
+		let Self {
+			reader,
+			full,
+			payload,
+		} = self;
+
+		while len > 0 {
+			let buffer = reader.fill_buf().await?;
+			if buffer.is_empty() {
+				return Ok(false);
+			}
+
+			let count = buffer.len().min(usize::try_from(len).unwrap_or(usize::MAX));
+			full.update(&buffer[..count]);
+			if keep {
+				payload.update(&buffer[..count]);
+			}
+			reader.consume(count);
+			len -= count as u64;
+		}
+
+		Ok(true)
+	}
+
+	/// Hashes the rest of the file, into the payload too if `keep`
+	async fn rest(&mut self, keep: bool) -> Result<(), io::Error> {
+		self.copy(u64::MAX, keep).await.map(|_| ())
+	}
+
+	/// Hashes the rest of the file into the payload, `read` having been read already, but for a
+	/// trailing ID3v1 tag. The last bytes are held back until the file ends to tell.
+	async fn rest_without_id3v1(&mut self, read: &[u8]) -> Result<(), io::Error> {
+		const ID3V1_LEN: usize = 128;
+
+		let Self {
+			reader,
+			full,
+			payload,
+		} = self;
+
+		let mut tail = read.to_vec();
+		loop {
+			let buffer = reader.fill_buf().await?;
+			if buffer.is_empty() {
+				break;
+			}
+
+			full.update(buffer);
+			tail.extend_from_slice(buffer);
+			let count = buffer.len();
+			reader.consume(count);
+
+			if tail.len() > ID3V1_LEN {
+				let payload_len = tail.len() - ID3V1_LEN;
+				payload.update(&tail[..payload_len]);
+				tail.drain(..payload_len);
+			}
+		}
+
+		if !(tail.len() == ID3V1_LEN && tail.starts_with(b"TAG")) {
+			payload.update(&tail);
+		}
+
+		Ok(())
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MediaFormat {
+	Jpeg,
+	Png,
+	Mp3,
+	Flac,
+}
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+impl MediaFormat {
+	fn from_header(header: &[u8]) -> Option<Self> {
+		match header {
+			[0xFF, 0xD8, 0xFF, ..] => Some(Self::Jpeg),
+			_ if header.starts_with(PNG_SIGNATURE) => Some(Self::Png),
+			_ if header.starts_with(b"fLaC") => Some(Self::Flac),
+			_ if header.starts_with(b"ID3") => Some(Self::Mp3),
+			// MPEG audio frame sync
+			[0xFF, second, ..] if second & 0xE0 == 0xE0 => Some(Self::Mp3),
+			_ => None,
+		}
+	}
+
+	/// Hashes the media payload of the file `reader` is at the start of, returning whether it was
+	/// well formed
+	async fn hash_payload(self, reader: &mut MediaReader) -> Result<bool, io::Error> {
+		match self {
+			Self::Jpeg => hash_jpeg_payload(reader).await,
+			Self::Png => hash_png_payload(reader).await,
+			Self::Mp3 => hash_mp3_payload(reader).await,
+			Self::Flac => hash_flac_payload(reader).await,
+		}
+	}
+}
+
+/// Keeps every segment except APPn (EXIF, XMP, ICC...) and comments, up to the end of the image
+async fn hash_jpeg_payload(reader: &mut MediaReader) -> Result<bool, io::Error> {
+	let mut start_of_image = [0; 2];
+	reader.read(&mut start_of_image).await?;
+	reader.keep(&start_of_image);
+
+	loop {
+		let mut marker = [0; 1];
+		if reader.read(&mut marker).await? == 0 || marker[0] != 0xFF {
+			return Ok(false);
+		}
+		// fill bytes before a marker
+		while marker[0] == 0xFF {
+			if reader.read(&mut marker).await? == 0 {
+				return Ok(false);
+			}
+		}
+
+		match marker[0] {
+			// end of image without a scan
+			0xD9 => {
+				reader.keep(&[0xFF, 0xD9]);
+				return Ok(true);
+			}
+			// start of scan, the entropy coded data runs until the end of the image
+			0xDA => {
+				reader.keep(&[0xFF, 0xDA]);
+				reader.rest(true).await?;
+				return Ok(true);
+			}
+			marker => {
+				let mut length = [0; 2];
+				if reader.read(&mut length).await? < length.len() {
+					return Ok(false);
+				}
+				// the length counts its own two bytes
+				let Some(data_len) = u16::from_be_bytes(length).checked_sub(2) else {
+					return Ok(false);
+				};
+
+				let keep = !matches!(marker, 0xE0..=0xEF | 0xFE);
+				if keep {
+					reader.keep(&[0xFF, marker, length[0], length[1]]);
+				}
+				if !reader.copy(data_len.into(), keep).await? {
+					return Ok(false);
+				}
+			}
+		}
+	}
+}
+
+/// Keeps only critical chunks (IHDR, PLTE, IDAT, IEND), ancillary ones hold text, timestamps, EXIF
+async fn hash_png_payload(reader: &mut MediaReader) -> Result<bool, io::Error> {
+	let mut signature = [0; PNG_SIGNATURE.len()];
+	reader.read(&mut signature).await?;
+	reader.keep(&signature);
+
+	loop {
+		// length and type
+		let mut header = [0; 8];
+		if reader.read(&mut header).await? < header.len() {
+			return Ok(false);
+		}
+		let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+		let chunk_type = &header[4..];
+
+		let critical = chunk_type[0].is_ascii_uppercase();
+		if critical {
+			reader.keep(&header);
+		}
+		// data and crc
+		if !reader.copy(u64::from(length) + 4, critical).await? {
+			return Ok(false);
+		}
+		if chunk_type == b"IEND" {
+			return Ok(true);
+		}
+	}
+}
+
+/// Strips ID3v2 tags from the start and ID3v1 tags from the end
+async fn hash_mp3_payload(reader: &mut MediaReader) -> Result<bool, io::Error> {
+	loop {
+		let mut header = [0; 10];
+		let read = reader.read(&mut header).await?;
+		if !header[..read].starts_with(b"ID3") {
+			reader.rest_without_id3v1(&header[..read]).await?;
+			return Ok(true);
+		}
+		if read < header.len() {
+			return Ok(false);
+		}
+
+		// tag sizes are "syncsafe" integers, with only 7 bits used per byte
+		let size = header[6..10]
+			.iter()
+			.fold(0, |size, byte| (size << 7) | (*byte as u64 & 0x7F));
+		let has_footer = header[5] & 0x10 != 0;
+		if !reader
+			.copy(size + if has_footer { 10 } else { 0 }, false)
+			.await?
+		{
+			return Ok(false);
+		}
+	}
+}
+
+/// Keeps STREAMINFO and the audio frames, dropping every other metadata block
+async fn hash_flac_payload(reader: &mut MediaReader) -> Result<bool, io::Error> {
+	const STREAMINFO: u8 = 0;
+
+	let mut magic = [0; 4];
+	reader.read(&mut magic).await?;
+
+	loop {
+		let mut header = [0; 4];
+		if reader.read(&mut header).await? < header.len() {
+			return Ok(false);
+		}
+		let is_last = header[0] & 0x80 != 0;
+		let length = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+
+		let is_streaminfo = header[0] & 0x7F == STREAMINFO;
+		if is_streaminfo {
+			reader.keep(&header[1..]);
+		}
+		if !reader.copy(length.into(), is_streaminfo).await? {
+			return Ok(false);
+		}
+
+		if is_last {
+			reader.rest(true).await?;
+			return Ok(true);
+		}
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use crate::object::validation::hash::file_checksum;
+
+	use tempfile::tempdir;
+	use tokio::fs;
+
+	fn png_chunk(chunk_type: &[u8], data: &[u8]) -> Vec<u8> {
+		let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+		chunk.extend_from_slice(chunk_type);
+		chunk.extend_from_slice(data);
+		// the crc isn't checked
+		chunk.extend_from_slice(&[0; 4]);
+		chunk
+	}
+
+	fn png(with_text: bool) -> Vec<u8> {
+		let mut png = PNG_SIGNATURE.to_vec();
+		png.extend(png_chunk(b"IHDR", &[1; 13]));
+		if with_text {
+			png.extend(png_chunk(b"tEXt", b"Author\0someone"));
+		}
+		png.extend(png_chunk(b"IDAT", &[2; 32]));
+		png.extend(png_chunk(b"IEND", &[]));
+		png
+	}
+
+	fn jpeg(exif: &[u8]) -> Vec<u8> {
+		let mut jpeg = vec![0xFF, 0xD8];
+		jpeg.extend_from_slice(&[0xFF, 0xE1]);
+		jpeg.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+		jpeg.extend_from_slice(exif);
+		// quantization table
+		jpeg.extend_from_slice(&[0xFF, 0xDB, 0x00, 0x04, 0x00, 0x01]);
+		// start of scan and the image data
+		jpeg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 3, 4, 5, 0xFF, 0xD9]);
+		jpeg
+	}
+
+	fn mp3(id3v2: &[u8], id3v1: bool) -> Vec<u8> {
+		let mut mp3 = b"ID3\x04\x00\x00".to_vec();
+		mp3.extend_from_slice(&[0, 0, 0, id3v2.len() as u8]);
+		mp3.extend_from_slice(id3v2);
+		mp3.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00, 6, 7, 8]);
+		if id3v1 {
+			let mut tag = b"TAG".to_vec();
+			tag.resize(128, b'x');
+			mp3.extend(tag);
+		}
+		mp3
+	}
+
+	fn flac(comment: &[u8]) -> Vec<u8> {
+		let mut flac = b"fLaC".to_vec();
+		// STREAMINFO
+		flac.extend_from_slice(&[0x00, 0, 0, 4, 9, 9, 9, 9]);
+		// VORBIS_COMMENT, the last metadata block
+		flac.extend_from_slice(&[0x84, 0, 0, comment.len() as u8]);
+		flac.extend_from_slice(comment);
+		flac.extend_from_slice(&[0xFF, 0xF8, 1, 2, 3]);
+		flac
+	}
+
+	async fn checksum_of(dir: &Path, name: &str, content: &[u8]) -> String {
+		let path = dir.join(name);
+		fs::write(&path, content).await.unwrap();
+		media_content_checksum(&path).await.unwrap()
+	}
+
+	#[tokio::test]
+	async fn test_media_content_checksum_ignores_metadata() {
+		let dir = tempdir().unwrap();
+		let dir = dir.path();
+
+		for (tagged, untagged, other_payload) in [
+			(png(true), png(false), {
+				let mut png = png(false);
+				png[PNG_SIGNATURE.len() + 8] = 9;
+				png
+			}),
+			(jpeg(b"Exif\0\0camera a"), jpeg(b"Exif\0\0b"), {
+				let mut jpeg = jpeg(b"Exif\0\0b");
+				let len = jpeg.len();
+				jpeg[len - 3] = 9;
+				jpeg
+			}),
+			(mp3(b"TIT2 a title", true), mp3(b"TIT2", false), {
+				let mut mp3 = mp3(b"TIT2", false);
+				*mp3.last_mut().unwrap() = 9;
+				mp3
+			}),
+			(flac(b"ARTIST=someone"), flac(b""), {
+				let mut flac = flac(b"");
+				*flac.last_mut().unwrap() = 9;
+				flac
+			}),
+		] {
+			let tagged = checksum_of(dir, "tagged", &tagged).await;
+			assert_eq!(tagged, checksum_of(dir, "untagged", &untagged).await);
+			assert_ne!(tagged, checksum_of(dir, "other", &other_payload).await);
+		}
+	}
+
+	#[tokio::test]
+	async fn test_media_content_checksum_falls_back_to_bytes() {
+		let dir = tempdir().unwrap();
+		let path = dir.path().join("file.txt");
+
+		for content in [&b"not media"[..], &PNG_SIGNATURE[..]] {
+			fs::write(&path, content).await.unwrap();
+			assert_eq!(
+				media_content_checksum(&path).await.unwrap(),
+				file_checksum(&path).await.unwrap()
+			);
+		}
+	}
+}
@@ -0,0 +1,738 @@
+use crate::{
+	library::Library,
+	location::file_path_helper::file_path_for_duplicate_merge,
+	prisma::{
+		file_path, label_on_object, location, object, object_in_space, tag_on_object, PrismaClient,
+		SortOrder,
+	},
+	sync,
+	util::db::chain_optional_iter,
+};
+
+use std::{
+	collections::{HashMap, HashSet},
+	mem,
+};
+
+use chrono::{DateTime, FixedOffset};
+use prisma_client_rust::operator::{and, or};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+use super::{
+	hash::{checksum_digest_bits, ChecksumAlgorithm},
+	ValidatorError,
+};
+
+/// Checksums of the location looked up at once in the library when merging in memory
+const IN_MEMORY_PAGE_SIZE: i64 = 512;
+
+/// Files are read in pages of this many when streaming, along with the files sharing their
+/// checksum with the last one of the page
+const STREAMING_PAGE_SIZE: i64 = 1000;
+
+/// Groups waiting to be merged while streaming, past which the finder waits for the merges
+const STREAMING_CHANNEL_CAPACITY: usize = 64;
+
+/// Checksums truncated to fewer bits than this are too likely to be shared by different files
+/// for merges, which can't be undone, to rest on them, see
+/// [`truncate_checksum`](super::hash::truncate_checksum)
+const MIN_MERGE_DIGEST_BITS: usize = 128;
+
+/// How [`merge_confirmed_duplicates`] finds the files sharing a checksum
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateStrategy {
+	/// Gathers the checksums of the location's files, then every file with one of them, in memory
+	#[default]
+	InMemory,
+	/// Walks the files of the library ordered by checksum, only holding a page and the current run
+	/// of equal checksums in memory, for libraries with tens of millions of files
+	Streaming,
+}
+
+object::select!(object_to_merge {
+	id
+	pub_id
+	hidden
+	favorite
+	important
+	note
+	date_created
+	date_accessed
+});
+
+/// Objects whose file paths were confirmed byte identical, to be merged into `canonical`
+#[derive(Debug, PartialEq, Eq)]
+struct DuplicateGroup {
+	canonical: object::id::Type,
+	merged: Vec<object::id::Type>,
+	/// file paths of the merged objects, to be pointed at `canonical`
+	file_paths: Vec<file_path::pub_id::Type>,
+}
+
+/// What the user set on any of the merged objects, so none of it is lost
+#[derive(Debug, Default, PartialEq, Eq)]
+struct MergedMetadata {
+	hidden: Option<bool>,
+	favorite: Option<bool>,
+	important: Option<bool>,
+	note: Option<String>,
+	date_created: Option<DateTime<FixedOffset>>,
+	date_accessed: Option<DateTime<FixedOffset>>,
+}
+
+/// Merges the objects of files in the location confirmed byte identical, by checksum and size,
+/// to files of other objects anywhere in the library. Files whose checksum was truncated below
+/// [`MIN_MERGE_DIGEST_BITS`] are never merged. The object created first is kept, with the
+/// tags, labels, spaces and metadata of all the others, and the file paths of the others are
+/// pointed to it. Objects left without file paths are deleted, so running it again is a no-op.
+/// Returns how many objects were merged away.
+pub async fn merge_confirmed_duplicates(
+	library: &Library,
+	location_id: location::id::Type,
+	strategy: DuplicateStrategy,
+) -> Result<usize, ValidatorError> {
+	match strategy {
+		DuplicateStrategy::InMemory => merge_in_memory(library, location_id).await,
+		DuplicateStrategy::Streaming => merge_streaming(library, location_id).await,
+	}
+}
+
+async fn merge_in_memory(
+	library: &Library,
+	location_id: location::id::Type,
+) -> Result<usize, ValidatorError> {
+	let mut merged_count = 0;
+	let mut last_checksum = None;
+
+	// the location's checksums are read a page at a time, in order so each is only read once
+	loop {
+		let mut checksums = library
+			.db
+			.file_path()
+			.find_many(chain_optional_iter(
+				[
+					file_path::location_id::equals(Some(location_id)),
+					file_path::integrity_checksum::not(None),
+					file_path::object_id::not(None),
+				],
+				[last_checksum.take().map(file_path::integrity_checksum::gt)],
+			))
+			.order_by(file_path::integrity_checksum::order(SortOrder::Asc))
+			.take(IN_MEMORY_PAGE_SIZE)
+			.select(file_path::select!({ integrity_checksum }))
+			.exec()
+			.await?
+			.into_iter()
+			.filter_map(|file_path| file_path.integrity_checksum)
+			.collect::<Vec<_>>();
+		checksums.dedup();
+
+		let Some(last) = checksums.last() else {
+			break;
+		};
+		last_checksum = Some(last.clone());
+
+		// every file with one of the checksums is fetched at once, so groups never span pages
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(vec![
+				file_path::integrity_checksum::in_vec(checksums),
+				file_path::object_id::not(None),
+			])
+			.select(file_path_for_duplicate_merge::select())
+			.exec()
+			.await?;
+
+		for group in group_confirmed_duplicates(file_paths) {
+			merged_count += merge_objects(library, group).await?;
+		}
+	}
+
+	Ok(merged_count)
+}
+
+async fn merge_streaming(
+	library: &Library,
+	location_id: location::id::Type,
+) -> Result<usize, ValidatorError> {
+	let (tx, mut rx) = mpsc::channel(STREAMING_CHANNEL_CAPACITY);
+
+	let merge = async {
+		let mut merged_count = 0;
+		while let Some(group) = rx.recv().await {
+			merged_count += merge_objects(library, group).await?;
+		}
+
+		Ok::<_, ValidatorError>(merged_count)
+	};
+
+	let ((), merged_count) =
+		tokio::try_join!(stream_duplicate_groups(&library.db, location_id, tx), merge)?;
+
+	Ok(merged_count)
+}
+
+/// Sends the groups of confirmed duplicates with files in the location as soon as each run of
+/// equal checksums ends, reading the library's files ordered by checksum. Merging only points
+/// file paths of runs already read to other objects, so it can go on while the files are read.
+/// Stops early once the receiver is dropped.
+async fn stream_duplicate_groups(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	tx: mpsc::Sender<DuplicateGroup>,
+) -> Result<(), ValidatorError> {
+	let mut runs = ChecksumRuns::default();
+	let mut cursor = None::<(String, file_path::id::Type)>;
+
+	loop {
+		let file_paths = db
+			.file_path()
+			.find_many(chain_optional_iter(
+				[
+					file_path::integrity_checksum::not(None),
+					file_path::object_id::not(None),
+				],
+				[cursor.take().map(|(checksum, id)| {
+					or(vec![
+						file_path::integrity_checksum::gt(checksum.clone()),
+						and(vec![
+							file_path::integrity_checksum::equals(Some(checksum)),
+							file_path::id::gt(id),
+						]),
+					])
+				})],
+			))
+			.order_by(file_path::integrity_checksum::order(SortOrder::Asc))
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(STREAMING_PAGE_SIZE)
+			.select(file_path_for_duplicate_merge::select())
+			.exec()
+			.await?;
+
+		let read_all = file_paths.len() < STREAMING_PAGE_SIZE as usize;
+		cursor = file_paths.last().and_then(|last| {
+			last.integrity_checksum
+				.clone()
+				.map(|checksum| (checksum, last.id))
+		});
+
+		// each run is sent as soon as it ends, so at most the page and the current run are held
+		let mut file_paths = file_paths.into_iter();
+		loop {
+			let ended_run = match file_paths.next() {
+				Some(file_path) => match runs.push(file_path) {
+					Some(run) => run,
+					None => continue,
+				},
+				None if read_all => match runs.finish() {
+					Some(run) => run,
+					None => break,
+				},
+				None => break,
+			};
+
+			if !send_duplicate_groups(location_id, ended_run, &tx).await {
+				return Ok(());
+			}
+		}
+
+		if read_all || cursor.is_none() {
+			return Ok(());
+		}
+	}
+}
+
+/// Sends the groups of confirmed duplicates of a run of files with the same checksum if any of
+/// them is in the location. Returns `false` once the receiver is dropped.
+async fn send_duplicate_groups(
+	location_id: location::id::Type,
+	run: Vec<file_path_for_duplicate_merge::Data>,
+	tx: &mpsc::Sender<DuplicateGroup>,
+) -> bool {
+	if !run
+		.iter()
+		.any(|file_path| file_path.location_id == Some(location_id))
+	{
+		return true;
+	}
+
+	for group in group_confirmed_duplicates(run) {
+		if tx.send(group).await.is_err() {
+			return false;
+		}
+	}
+
+	true
+}
+
+/// Splits files ordered by checksum into runs of files with the same checksum
+#[derive(Default)]
+struct ChecksumRuns {
+	run: Vec<file_path_for_duplicate_merge::Data>,
+}
+
+impl ChecksumRuns {
+	/// Returns the previous run if `file_path` starts another one
+	fn push(
+		&mut self,
+		file_path: file_path_for_duplicate_merge::Data,
+	) -> Option<Vec<file_path_for_duplicate_merge::Data>> {
+		let ended = self
+			.run
+			.last()
+			.filter(|last| last.integrity_checksum != file_path.integrity_checksum)
+			.is_some()
+			.then(|| mem::take(&mut self.run));
+
+		self.run.push(file_path);
+
+		ended
+	}
+
+	fn finish(&mut self) -> Option<Vec<file_path_for_duplicate_merge::Data>> {
+		(!self.run.is_empty()).then(|| mem::take(&mut self.run))
+	}
+}
+
+fn group_confirmed_duplicates(
+	file_paths: Vec<file_path_for_duplicate_merge::Data>,
+) -> Vec<DuplicateGroup> {
+	let mut by_content = HashMap::<_, Vec<_>>::new();
+	for file_path in file_paths {
+		// files without a size or with a checksum we can't compare aren't confirmed duplicates
+		let (Some(object_id), Some(checksum), Some(size), Some(algorithm)) = (
+			file_path.object_id,
+			file_path.integrity_checksum,
+			file_path.size_in_bytes_bytes,
+			ChecksumAlgorithm::from_db(file_path.integrity_checksum_algorithm.as_deref()),
+		) else {
+			continue;
+		};
+		// empty files all have the same checksum without being copies of each other
+		if size.iter().all(|byte| *byte == 0) {
+			continue;
+		}
+		if checksum_digest_bits(&checksum).map_or(false, |bits| bits < MIN_MERGE_DIGEST_BITS) {
+			continue;
+		}
+
+		by_content
+			.entry((checksum, algorithm.as_str(), size))
+			.or_default()
+			.push((object_id, file_path.pub_id));
+	}
+
+	let mut groups = by_content
+		.into_values()
+		.filter_map(|file_paths| {
+			let canonical = file_paths.iter().map(|(object_id, _)| *object_id).min()?;
+
+			let (mut merged, file_paths): (Vec<_>, Vec<_>) = file_paths
+				.into_iter()
+				.filter(|(object_id, _)| *object_id != canonical)
+				.unzip();
+			merged.sort_unstable();
+			merged.dedup();
+
+			(!merged.is_empty()).then_some(DuplicateGroup {
+				canonical,
+				merged,
+				file_paths,
+			})
+		})
+		.collect::<Vec<_>>();
+	groups.sort_by_key(|group| group.canonical);
+
+	groups
+}
+
+impl MergedMetadata {
+	/// `objects` must be sorted by id, so the notes of older objects come first
+	fn from_objects(objects: &[object_to_merge::Data]) -> Self {
+		let any = |flag: fn(&object_to_merge::Data) -> Option<bool>| {
+			objects.iter().filter_map(flag).reduce(|a, b| a || b)
+		};
+
+		let mut notes = vec![];
+		for note in objects.iter().filter_map(|object| object.note.as_deref()) {
+			if !note.is_empty() && !notes.contains(&note) {
+				notes.push(note);
+			}
+		}
+
+		Self {
+			hidden: any(|object| object.hidden),
+			favorite: any(|object| object.favorite),
+			important: any(|object| object.important),
+			note: (!notes.is_empty()).then(|| notes.join("\n\n")),
+			date_created: objects
+				.iter()
+				.filter_map(|object| object.date_created)
+				.min(),
+			date_accessed: objects
+				.iter()
+				.filter_map(|object| object.date_accessed)
+				.max(),
+		}
+	}
+
+	fn into_params(self) -> Vec<((&'static str, Value), object::SetParam)> {
+		vec![
+			(
+				(object::hidden::NAME, json!(self.hidden)),
+				object::hidden::set(self.hidden),
+			),
+			(
+				(object::favorite::NAME, json!(self.favorite)),
+				object::favorite::set(self.favorite),
+			),
+			(
+				(object::important::NAME, json!(self.important)),
+				object::important::set(self.important),
+			),
+			(
+				(object::note::NAME, json!(&self.note)),
+				object::note::set(self.note),
+			),
+			(
+				(object::date_created::NAME, json!(self.date_created)),
+				object::date_created::set(self.date_created),
+			),
+			(
+				(object::date_accessed::NAME, json!(self.date_accessed)),
+				object::date_accessed::set(self.date_accessed),
+			),
+		]
+	}
+}
+
+async fn merge_objects(library: &Library, group: DuplicateGroup) -> Result<usize, ValidatorError> {
+	let Library { db, sync, .. } = library;
+
+	let mut objects = db
+		.object()
+		.find_many(vec![object::id::in_vec(
+			[group.canonical]
+				.into_iter()
+				.chain(group.merged.iter().copied())
+				.collect(),
+		)])
+		.select(object_to_merge::select())
+		.exec()
+		.await?;
+	objects.sort_by_key(|object| object.id);
+
+	let Some(canonical_pub_id) = objects
+		.iter()
+		.find(|object| object.id == group.canonical)
+		.map(|object| object.pub_id.clone())
+	else {
+		return Ok(0);
+	};
+
+	let tags = db
+		.tag_on_object()
+		.find_many(vec![tag_on_object::object_id::in_vec(group.merged.clone())])
+		.select(tag_on_object::select!({ tag_id object_id tag: select { pub_id } }))
+		.exec()
+		.await?;
+	let labels = db
+		.label_on_object()
+		.find_many(vec![label_on_object::object_id::in_vec(
+			group.merged.clone(),
+		)])
+		.exec()
+		.await?;
+	let spaces = db
+		.object_in_space()
+		.find_many(vec![object_in_space::object_id::in_vec(
+			group.merged.clone(),
+		)])
+		.exec()
+		.await?;
+
+	// objects can have files with other contents too, those are kept as they are, the others are
+	// left without files once theirs point at the canonical object
+	let orphaned = db
+		.object()
+		.find_many(vec![
+			object::id::in_vec(group.merged.clone()),
+			object::file_paths::none(vec![file_path::pub_id::not_in_vec(
+				group.file_paths.clone(),
+			)]),
+		])
+		.select(object::select!({ id pub_id }))
+		.exec()
+		.await?;
+	let orphaned_ids = orphaned.iter().map(|object| object.id).collect::<Vec<_>>();
+
+	// tag assignments are synced as relations: the ones of the merged objects are copied to the
+	// canonical object, and those of the deleted objects go with them
+	let mut tag_ops = vec![];
+	if let Ok(canonical_uuid) = Uuid::from_slice(&canonical_pub_id) {
+		let mut copied = HashSet::new();
+		for tag in &tags {
+			let Ok(tag_uuid) = Uuid::from_slice(&tag.tag.pub_id) else {
+				continue;
+			};
+
+			if copied.insert(tag.tag_id) {
+				tag_ops.push(sync.relation_create(tag_on_object::NAME, tag_uuid, canonical_uuid));
+			}
+			if let Some(object_uuid) = orphaned
+				.iter()
+				.find(|object| object.id == tag.object_id)
+				.and_then(|object| Uuid::from_slice(&object.pub_id).ok())
+			{
+				tag_ops.push(sync.relation_delete(tag_on_object::NAME, tag_uuid, object_uuid));
+			}
+		}
+	}
+
+	let (sync_params, db_params): (Vec<_>, Vec<_>) = MergedMetadata::from_objects(&objects)
+		.into_params()
+		.into_iter()
+		.unzip();
+
+	let (file_path_ops, file_path_updates): (Vec<_>, Vec<_>) = group
+		.file_paths
+		.into_iter()
+		.map(|pub_id| {
+			(
+				sync.shared_update(
+					sync::file_path::SyncId {
+						pub_id: pub_id.clone(),
+					},
+					file_path::object::NAME,
+					json!(sync::object::SyncId {
+						pub_id: canonical_pub_id.clone()
+					}),
+				),
+				db.file_path().update(
+					file_path::pub_id::equals(pub_id),
+					vec![file_path::object::connect(object::id::equals(
+						group.canonical,
+					))],
+				),
+			)
+		})
+		.unzip();
+
+	let ops = sync_params
+		.into_iter()
+		.map(|(field, value)| {
+			sync.shared_update(
+				sync::object::SyncId {
+					pub_id: canonical_pub_id.clone(),
+				},
+				field,
+				value,
+			)
+		})
+		.chain(file_path_ops)
+		.chain(tag_ops)
+		.chain(orphaned.into_iter().map(|object| {
+			sync.shared_delete(sync::object::SyncId {
+				pub_id: object.pub_id,
+			})
+		}))
+		.collect::<Vec<_>>();
+
+	// labels and spaces moved to the canonical object have no sync ops of their own yet.
+	// Everything is written in the same batch so a failure doesn't leave the objects half merged.
+	let orphaned = || object::file_paths::none(vec![]);
+	let (_, _, (.., deleted)) = sync
+		.write_ops(
+			db,
+			(
+				ops,
+				(
+					(
+						db.object()
+							.update(object::id::equals(group.canonical), db_params),
+						db.tag_on_object()
+							.create_many(
+								tags.into_iter()
+									.map(|tag| tag_on_object::CreateUnchecked {
+										tag_id: tag.tag_id,
+										object_id: group.canonical,
+										_params: vec![],
+									})
+									.collect(),
+							)
+							.skip_duplicates(),
+						db.label_on_object()
+							.create_many(
+								labels
+									.into_iter()
+									.map(|label| label_on_object::CreateUnchecked {
+										label_id: label.label_id,
+										object_id: group.canonical,
+										_params: vec![label_on_object::date_created::set(
+											label.date_created,
+										)],
+									})
+									.collect(),
+							)
+							.skip_duplicates(),
+						db.object_in_space()
+							.create_many(
+								spaces
+									.into_iter()
+									.map(|space| object_in_space::CreateUnchecked {
+										space_id: space.space_id,
+										object_id: group.canonical,
+										_params: vec![],
+									})
+									.collect(),
+							)
+							.skip_duplicates(),
+					),
+					file_path_updates,
+					(
+						db.tag_on_object().delete_many(vec![
+							tag_on_object::object_id::in_vec(orphaned_ids.clone()),
+							tag_on_object::object::is(vec![orphaned()]),
+						]),
+						db.label_on_object().delete_many(vec![
+							label_on_object::object_id::in_vec(orphaned_ids.clone()),
+							label_on_object::object::is(vec![orphaned()]),
+						]),
+						db.object_in_space().delete_many(vec![
+							object_in_space::object_id::in_vec(orphaned_ids.clone()),
+							object_in_space::object::is(vec![orphaned()]),
+						]),
+						db.object()
+							.delete_many(vec![object::id::in_vec(orphaned_ids), orphaned()]),
+					),
+				),
+			),
+		)
+		.await?;
+
+	Ok(deleted as usize)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	fn fake_file_path(
+		pub_id: u8,
+		object_id: object::id::Type,
+		checksum: &str,
+		size: u64,
+	) -> file_path_for_duplicate_merge::Data {
+		file_path_for_duplicate_merge::Data {
+			id: pub_id.into(),
+			pub_id: vec![pub_id],
+			location_id: Some(1),
+			object_id: Some(object_id),
+			integrity_checksum: Some(checksum.to_string()),
+			integrity_checksum_algorithm: None,
+			size_in_bytes_bytes: Some(size.to_be_bytes().to_vec()),
+		}
+	}
+
+	#[test]
+	fn test_group_confirmed_duplicates() {
+		let groups = group_confirmed_duplicates(vec![
+			fake_file_path(1, 3, "abc", 10),
+			fake_file_path(2, 1, "abc", 10),
+			fake_file_path(3, 2, "abc", 10),
+			fake_file_path(4, 3, "abc", 10),
+			// already merged
+			fake_file_path(5, 4, "def", 10),
+			fake_file_path(6, 4, "def", 10),
+			// same checksum with another size isn't confirmed
+			fake_file_path(7, 5, "ghi", 10),
+			fake_file_path(8, 6, "ghi", 20),
+			// same digest computed with another algorithm isn't the same content
+			file_path_for_duplicate_merge::Data {
+				integrity_checksum_algorithm: Some("sha256".to_string()),
+				..fake_file_path(9, 7, "jkl", 10)
+			},
+			fake_file_path(10, 8, "jkl", 10),
+			// empty files aren't duplicates of each other
+			fake_file_path(11, 9, "mno", 0),
+			fake_file_path(12, 10, "mno", 0),
+			// nor are files sharing a checksum truncated too short to be trusted
+			fake_file_path(13, 11, "pqr/64", 10),
+			fake_file_path(14, 12, "pqr/64", 10),
+		]);
+
+		assert_eq!(
+			groups,
+			vec![DuplicateGroup {
+				canonical: 1,
+				merged: vec![2, 3],
+				file_paths: vec![vec![1], vec![3], vec![4]],
+			}]
+		);
+	}
+
+	#[test]
+	fn test_checksum_runs() {
+		let ids = |run: Vec<file_path_for_duplicate_merge::Data>| {
+			run.into_iter()
+				.map(|file_path| file_path.id)
+				.collect::<Vec<_>>()
+		};
+
+		let mut runs = ChecksumRuns::default();
+		let mut ended = vec![];
+		for file_path in [
+			fake_file_path(1, 1, "abc", 10),
+			fake_file_path(2, 2, "abc", 10),
+			fake_file_path(3, 3, "def", 10),
+			fake_file_path(4, 4, "ghi", 10),
+			fake_file_path(5, 5, "ghi", 10),
+		] {
+			ended.extend(runs.push(file_path).map(ids));
+		}
+		ended.extend(runs.finish().map(ids));
+
+		assert_eq!(ended, vec![vec![1, 2], vec![3], vec![4, 5]]);
+		assert!(runs.finish().is_none());
+	}
+
+	#[test]
+	fn test_merged_metadata() {
+		let date = |day: u32| {
+			DateTime::parse_from_rfc3339(&format!("2023-06-{day:02}T00:00:00Z")).unwrap()
+		};
+		let object = |id, favorite, note: Option<&str>, created, accessed| object_to_merge::Data {
+			id,
+			pub_id: vec![],
+			hidden: None,
+			favorite,
+			important: None,
+			note: note.map(str::to_string),
+			date_created: created.map(date),
+			date_accessed: accessed.map(date),
+		};
+
+		assert_eq!(
+			MergedMetadata::from_objects(&[
+				object(1, Some(false), Some("first"), Some(10), Some(11)),
+				object(2, Some(true), None, Some(5), None),
+				object(3, None, Some("second"), None, Some(20)),
+				object(4, None, Some(""), None, None),
+				object(5, None, Some("first"), None, None),
+			]),
+			MergedMetadata {
+				hidden: None,
+				favorite: Some(true),
+				important: None,
+				note: Some("first\n\nsecond".to_string()),
+				date_created: Some(date(5)),
+				date_accessed: Some(date(20)),
+			}
+		);
+	}
+}
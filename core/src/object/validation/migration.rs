@@ -0,0 +1,85 @@
+use crate::{
+	job::JobManagerError,
+	library::Library,
+	prisma::{file_path, location},
+};
+
+use tracing::{info, warn};
+
+use super::{
+	hash::ChecksumAlgorithm, not_excluded, same_checksum_algorithm,
+	validator_job::ObjectValidatorJobInit,
+};
+
+/// Algorithm migrations keep their reads under this many bytes per second
+pub const MIGRATION_READ_RATE: u64 = 32 * 1024 * 1024;
+
+/// Re-hashes the files of this node's locations checksummed with `from` with `to` instead, so
+/// the library converges on a single algorithm after its default changed. A validator job is
+/// spawned in the background for each location with such files, resumable and reporting its
+/// progress like any other. Each file is checked against its checksum with `from` while it's
+/// read for `to`, files that no longer match it fail instead of being migrated. Locations already
+/// being validated are skipped, another migration picks them up once they're done. Returns how
+/// many jobs were spawned.
+pub async fn migrate_algorithm(
+	library: &Library,
+	from: ChecksumAlgorithm,
+	to: ChecksumAlgorithm,
+) -> Result<usize, JobManagerError> {
+	if from == to {
+		return Ok(0);
+	}
+
+	let locations = library
+		.db
+		.location()
+		.find_many(vec![location::node_id::equals(Some(library.node_local_id))])
+		.exec()
+		.await?;
+
+	let mut spawned = 0;
+	for location in locations {
+		let location_id = location.id;
+
+		let to_migrate = library
+			.db
+			.file_path()
+			.count(vec![
+				file_path::location_id::equals(Some(location_id)),
+				file_path::integrity_checksum::not(None),
+				same_checksum_algorithm(from, false),
+				not_excluded(),
+			])
+			.exec()
+			.await?;
+		if to_migrate == 0 {
+			continue;
+		}
+
+		match library
+			.spawn_job(
+				ObjectValidatorJobInit::builder(location)
+					.algorithm(to)
+					.migrate_from(from)
+					.max_read_rate(MIGRATION_READ_RATE)
+					.build(),
+			)
+			.await
+		{
+			Ok(()) => {
+				info!(
+					"Migrating {to_migrate} checksums of location {location_id} from {} to {}",
+					from.as_str(),
+					to.as_str()
+				);
+				spawned += 1;
+			}
+			Err(JobManagerError::AlreadyRunningJob { .. }) => {
+				warn!("Location {location_id} is already being validated, not migrating it");
+			}
+			Err(e) => return Err(e),
+		}
+	}
+
+	Ok(spawned)
+}
@@ -0,0 +1,96 @@
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+/// How the copy of a file under the mirror root disagrees with the one in the location, for
+/// mirrored storage whose sides got out of sync
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum MirrorDivergence {
+	/// Both copies were read and their contents differ
+	Differs {
+		primary: String,
+		mirror: String,
+	},
+	MissingOnMirror,
+	/// The file is only on the mirror, it's gone from the location since it was indexed
+	MissingOnPrimary,
+	/// The mirror's copy is there but couldn't be read
+	MirrorUnreadable {
+		reason: String,
+	},
+}
+
+/// How a file compares with its copy on the mirror, from its `checksum` in the location, `None`
+/// if it couldn't be read, and the result of hashing the mirror's copy the same way. `Ok` when
+/// they agree, `None` when there's nothing to compare, as even the location's copy couldn't be
+/// read, which its outcome already tells.
+pub fn mirror_comparison(
+	checksum: Option<&str>,
+	mirror_checksum: Result<String, io::Error>,
+	missing_on_primary: bool,
+) -> Option<Result<(), MirrorDivergence>> {
+	Some(match (checksum, mirror_checksum) {
+		(Some(primary), Ok(mirror)) if primary == mirror => Ok(()),
+		(Some(primary), Ok(mirror)) => Err(MirrorDivergence::Differs {
+			primary: primary.to_string(),
+			mirror,
+		}),
+		(Some(_), Err(e)) if e.kind() == io::ErrorKind::NotFound => {
+			Err(MirrorDivergence::MissingOnMirror)
+		}
+		(Some(_), Err(e)) => Err(MirrorDivergence::MirrorUnreadable {
+			reason: e.to_string(),
+		}),
+		(None, Ok(_)) if missing_on_primary => Err(MirrorDivergence::MissingOnPrimary),
+		(None, _) => return None,
+	})
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_mirror_comparison() {
+		assert_eq!(
+			mirror_comparison(Some("aaaa"), Ok("aaaa".to_string()), false),
+			Some(Ok(()))
+		);
+		assert_eq!(
+			mirror_comparison(Some("aaaa"), Ok("bbbb".to_string()), false),
+			Some(Err(MirrorDivergence::Differs {
+				primary: "aaaa".to_string(),
+				mirror: "bbbb".to_string(),
+			}))
+		);
+		assert_eq!(
+			mirror_comparison(
+				Some("aaaa"),
+				Err(io::Error::from(io::ErrorKind::NotFound)),
+				false
+			),
+			Some(Err(MirrorDivergence::MissingOnMirror))
+		);
+		assert!(matches!(
+			mirror_comparison(
+				Some("aaaa"),
+				Err(io::Error::from(io::ErrorKind::PermissionDenied)),
+				false
+			),
+			Some(Err(MirrorDivergence::MirrorUnreadable { .. }))
+		));
+		assert_eq!(
+			mirror_comparison(None, Ok("bbbb".to_string()), true),
+			Some(Err(MirrorDivergence::MissingOnPrimary))
+		);
+
+		// the location's copy failing to be read for any other reason is its own failure
+		assert_eq!(mirror_comparison(None, Ok("bbbb".to_string()), false), None);
+		assert_eq!(
+			mirror_comparison(None, Err(io::Error::from(io::ErrorKind::NotFound)), true),
+			None
+		);
+	}
+}
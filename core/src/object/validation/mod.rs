@@ -1,16 +1,154 @@
-use crate::{location::file_path_helper::FilePathError, util::error::FileIOError};
+use crate::{
+	location::file_path_helper::FilePathError,
+	prisma::{file_path, location, tag},
+	util::{db::MissingFieldError, error::FileIOError},
+};
 
 use std::path::Path;
 
 use thiserror::Error;
 
+use hash::ChecksumAlgorithm;
+
+mod acknowledgment;
+mod aggregate;
+mod archive;
+mod attestation;
+mod callback;
+mod cas_check;
+mod checksum_audit;
+mod checksum_store;
+mod content_index;
+mod content_type;
+mod coverage;
+pub mod coverage_job;
+mod external;
 pub mod hash;
+mod manifest;
+pub mod media;
+mod merge;
+mod migration;
+mod mirror;
+mod on_access;
+mod on_change;
+mod outcome_tags;
+mod par2;
+mod peer_checksums;
+mod perceptual;
+mod range;
+mod reflink;
+mod remote_checksums;
+mod report;
+mod status;
+mod step_source;
+mod sync_rate;
+pub mod telemetry;
+mod tree_checksum;
+mod unvalidated;
 pub mod validator_job;
+mod verification;
+mod webhook;
+
+pub use acknowledgment::*;
+pub use aggregate::*;
+pub use archive::*;
+pub use attestation::*;
+pub use callback::*;
+pub use cas_check::*;
+pub use checksum_audit::*;
+pub use checksum_store::*;
+pub use content_index::*;
+pub use content_type::*;
+pub use coverage::*;
+pub use external::*;
+pub use manifest::*;
+pub use merge::*;
+pub use migration::*;
+pub use mirror::*;
+pub use on_access::*;
+pub use on_change::*;
+pub use outcome_tags::*;
+pub use par2::*;
+pub use peer_checksums::*;
+pub use perceptual::*;
+pub use range::*;
+pub use reflink::*;
+pub use remote_checksums::*;
+pub use report::*;
+pub use status::*;
+pub use step_source::*;
+pub use sync_rate::*;
+pub use tree_checksum::*;
+pub use unvalidated::*;
+pub use verification::*;
+pub use webhook::*;
 
 #[derive(Error, Debug)]
 pub enum ValidatorError {
 	#[error("sub path not found: <path='{}'>", .0.display())]
 	SubPathNotFound(Box<Path>),
+	#[error("invalid external checksum: <path='{}', line={line}>", .path.display())]
+	InvalidExternalChecksum { path: Box<Path>, line: usize },
+	#[error("invalid checksum: <path='{}', checksum='{checksum}'>", .path.display())]
+	InvalidChecksum { path: Box<Path>, checksum: String },
+	#[error("location path override is not a directory: <path='{}'>", .0.display())]
+	LocationPathOverrideNotDirectory(Box<Path>),
+	#[error("location path override doesn't hold the location's files: <path='{}'>", .0.display())]
+	LocationPathOverrideMismatch(Box<Path>),
+	#[error("mirror root is not a directory: <path='{}'>", .0.display())]
+	MirrorRootNotDirectory(Box<Path>),
+	#[error("location root is empty or missing, refusing to prune its files: <path='{}'>", .0.display())]
+	PruneLocationRootMissing(Box<Path>),
+	#[error("checksums can't be truncated to that length: <digest_bits={digest_bits}, algorithm={}>", .algorithm.as_str())]
+	InvalidDigestBits {
+		digest_bits: usize,
+		algorithm: ChecksumAlgorithm,
+	},
+	#[error("timed out reading file: <path='{}'>", .0.display())]
+	ReadTimeout(Box<Path>),
+	#[error("file changed while being read: <path='{}', before={before}, after={after}>", .path.display())]
+	FileChangedDuringRead {
+		path: Box<Path>,
+		before: u64,
+		after: u64,
+	},
+	#[error("file wasn't read whole: <path='{}', expected={expected}, got={got}>", .path.display())]
+	ShortRead {
+		path: Box<Path>,
+		expected: u64,
+		got: u64,
+	},
+	#[error("manifest signature doesn't match its public key: <path='{}'>", .0.display())]
+	InvalidManifestSignature(Box<Path>),
+	#[error("manifest changed during the audit: <path='{}'>", .0.display())]
+	ManifestChanged(Box<Path>),
+	#[error("attestations need an audit of every file against a signed manifest")]
+	AttestationNeedsFullAudit,
+	#[error("manifests only hold checksums of the files' main stream, they can't be audited with their other streams")]
+	AltStreamsAgainstManifest,
+	#[error("the checksum store the job was started with is lost on restart, start it again with the store")]
+	ChecksumStoreLost,
+	#[error("tag not found: <id={0}>")]
+	TagNotFound(tag::id::Type),
+	#[error("invalid tar archive: <path='{}', offset={offset}>", .path.display())]
+	InvalidArchive { path: Box<Path>, offset: u64 },
+	#[error("empty byte range to validate: <start={start}, end={end}>")]
+	EmptyRange { start: u64, end: u64 },
+	#[error("file path not found: <id={0}>")]
+	FilePathNotFound(file_path::id::Type),
+	#[error("location isn't on this node: <id={0}>")]
+	LocationNotOnThisNode(location::id::Type),
+	#[error("files to validate not in the location: <location_id={location_id}, count={count}>")]
+	FilePathsNotInLocation {
+		location_id: location::id::Type,
+		count: usize,
+	},
+	#[error("file changed since its checksum was shown: <path='{}', expected='{expected}', actual='{actual}'>", .path.display())]
+	AcknowledgedChecksumChanged {
+		path: Box<Path>,
+		expected: String,
+		actual: String,
+	},
 
 	// Internal errors
 	#[error("database error: {0}")]
@@ -19,4 +157,33 @@ pub enum ValidatorError {
 	FilePath(#[from] FilePathError),
 	#[error(transparent)]
 	FileIO(#[from] FileIOError),
+	#[error("missing-field: {0}")]
+	MissingField(#[from] MissingFieldError),
+	#[error("failed to serialize: {0}")]
+	Serialization(#[from] serde_json::Error),
+	#[error("invalid library key: {0}")]
+	LibraryKey(#[from] ed25519_dalek::SignatureError),
+}
+
+impl From<ValidatorError> for rspc::Error {
+	fn from(err: ValidatorError) -> Self {
+		match err {
+			ValidatorError::InvalidArchive { .. }
+			| ValidatorError::FilePathsNotInLocation { .. }
+			| ValidatorError::LocationNotOnThisNode(_)
+			| ValidatorError::AttestationNeedsFullAudit
+			| ValidatorError::AltStreamsAgainstManifest => {
+				rspc::Error::with_cause(rspc::ErrorCode::BadRequest, err.to_string(), err)
+			}
+			ValidatorError::FilePathNotFound(_) => {
+				rspc::Error::with_cause(rspc::ErrorCode::NotFound, err.to_string(), err)
+			}
+			ValidatorError::AcknowledgedChecksumChanged { .. } => {
+				rspc::Error::with_cause(rspc::ErrorCode::Conflict, err.to_string(), err)
+			}
+			_ => {
+				rspc::Error::with_cause(rspc::ErrorCode::InternalServerError, err.to_string(), err)
+			}
+		}
+	}
 }
@@ -0,0 +1,330 @@
+use crate::{
+	api::CoreEvent,
+	invalidate_query,
+	job::JobError,
+	library::Library,
+	location::file_path_helper::{file_path_for_object_validator, IsolatedFilePathData},
+	prisma::{file_path, location},
+	util::db::maybe_missing,
+	volume::is_remote_path,
+};
+
+use std::{
+	collections::HashSet,
+	path::{Path, PathBuf},
+	sync::{Mutex, PoisonError},
+};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use specta::Type;
+use tokio::sync::Semaphore;
+use tracing::{debug, error, warn};
+use uuid::Uuid;
+
+use super::{
+	hash::{checksums_match, ChecksumAlgorithm, ReadOptions, MIN_BLOCK_LEN},
+	validator_job::{
+		extended_length_path, file_checksum_with_fallback, has_checksum_for, validate_file,
+		ValidationOptions,
+	},
+	ChecksumStatus, ChecksumStore, ChecksumWrite, FileValidationOutcome, LibraryChecksumStore,
+	LibraryStepSource, StoredChecksums,
+};
+
+/// How many files opened are validated at once, across libraries
+const MAX_CONCURRENT_READS: usize = 2;
+
+/// Files being validated on access, by library, so a file opened again before its validation is
+/// done isn't read twice
+static IN_FLIGHT: Lazy<Mutex<HashSet<(Uuid, file_path::id::Type)>>> = Lazy::new(Default::default);
+
+/// Permits to read a file opened, see [`MAX_CONCURRENT_READS`]
+static READS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(MAX_CONCURRENT_READS));
+
+/// Sent on the event bus when a file validated on access doesn't match its stored checksum, or
+/// couldn't be read
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct FileCorruptedEvent {
+	pub library_id: Uuid,
+	pub file_path_id: file_path::id::Type,
+	pub location_id: location::id::Type,
+	pub path: PathBuf,
+	pub reason: String,
+}
+
+/// Removes its file from [`IN_FLIGHT`] once its validation is done, even if it panicked
+struct InFlight((Uuid, file_path::id::Type));
+
+impl InFlight {
+	fn start(library_id: Uuid, file_path_id: file_path::id::Type) -> Option<Self> {
+		IN_FLIGHT
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.insert((library_id, file_path_id))
+			.then_some(Self((library_id, file_path_id)))
+	}
+}
+
+impl Drop for InFlight {
+	fn drop(&mut self) {
+		IN_FLIGHT
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.remove(&self.0);
+	}
+}
+
+/// Validates a file in the background when it's opened or previewed, if the library asks for it,
+/// spreading integrity checks across normal usage instead of a big job. Returns right away, and
+/// does nothing if the file is already being validated. Only a few files are read at once, the
+/// others waiting for a read permit, so browsing a folder of previews doesn't start a read per
+/// file.
+pub fn validate_on_access(library: &Library, file_path_id: file_path::id::Type) {
+	if !library.config.validate_on_access {
+		return;
+	}
+
+	let Some(in_flight) = InFlight::start(library.id, file_path_id) else {
+		debug!("File path {file_path_id} is already being validated on access");
+		return;
+	};
+
+	let library = library.clone();
+	tokio::spawn(async move {
+		let _in_flight = in_flight;
+		let Ok(_permit) = READS.acquire().await else {
+			return;
+		};
+
+		match validate_accessed_file(&library, file_path_id, false).await {
+			Ok(Some((location_id, path, FileValidationOutcome::Failed { reason }))) => {
+				error!(
+					"File {} failed its validation on access: {reason}",
+					path.display()
+				);
+				library.emit(CoreEvent::FileCorrupted(FileCorruptedEvent {
+					library_id: library.id,
+					file_path_id,
+					location_id,
+					path,
+					reason,
+				}));
+			}
+			Ok(Some((_, path, outcome))) => {
+				debug!("Validated {} on access: {outcome:?}", path.display());
+				if outcome == FileValidationOutcome::Checksummed {
+					invalidate_query!(library, "search.paths");
+				}
+			}
+			Ok(None) => {}
+			Err(e) => error!("Failed to validate file path {file_path_id} on access: {e:#?}"),
+		}
+	});
+}
+
+/// Validates a single file the user just accessed, the way the job would: its checksum is compared
+/// with the stored one while that's current, and computed and stored otherwise, or right away when
+/// the file is known to have `changed` since. Returns the file's location and full path along with
+/// the outcome, `None` if it isn't a file of a location of this node, or if another writer stored
+/// its checksum while it was read.
+pub(super) async fn validate_accessed_file(
+	library: &Library,
+	file_path_id: file_path::id::Type,
+	changed: bool,
+) -> Result<Option<(location::id::Type, PathBuf, FileValidationOutcome)>, JobError> {
+	let Library { db, .. } = library;
+
+	let Some(located) = db
+		.file_path()
+		.find_unique(file_path::id::equals(file_path_id))
+		.select(file_path::select!({
+			id
+			pub_id
+			materialized_path
+			is_dir
+			name
+			extension
+			integrity_checksum
+			integrity_checksum_algorithm
+			content_checksum
+			size_in_bytes_bytes
+			device
+			date_modified
+			date_checksummed
+			object: select { id date_accessed }
+			location: select { id node_id path }
+		}))
+		.exec()
+		.await?
+		.filter(|located| located.is_dir == Some(false))
+	else {
+		return Ok(None);
+	};
+	let location = maybe_missing(located.location, "file_path.location")?;
+	if location.node_id != Some(library.node_local_id) {
+		return Ok(None);
+	}
+	let location_path = maybe_missing(location.path, "location.path")?;
+
+	let file_path = file_path_for_object_validator::Data {
+		id: located.id,
+		pub_id: located.pub_id,
+		materialized_path: located.materialized_path,
+		is_dir: located.is_dir,
+		name: located.name,
+		extension: located.extension,
+		integrity_checksum: located.integrity_checksum,
+		integrity_checksum_algorithm: located.integrity_checksum_algorithm,
+		content_checksum: located.content_checksum,
+		size_in_bytes_bytes: located.size_in_bytes_bytes,
+		device: located.device,
+		date_modified: located.date_modified,
+		object: located
+			.object
+			.map(|object| file_path_for_object_validator::object::Data {
+				id: object.id,
+				date_accessed: object.date_accessed,
+			}),
+	};
+
+	let algorithm = library.config.default_checksum_algorithm;
+	let mut options = ValidationOptions {
+		read: ReadOptions {
+			algorithm,
+			remote: is_remote_path(&location_path).await,
+			..Default::default()
+		},
+		min_block_len: MIN_BLOCK_LEN,
+		..Default::default()
+	};
+	let source = LibraryStepSource(db);
+	let full_path = extended_length_path(
+		Path::new(&location_path).join(IsolatedFilePathData::try_from((location.id, &file_path))?),
+	);
+
+	// a checksum computed before the file was last modified isn't trusted anymore
+	let current = !changed
+		&& ChecksumStatus::new(
+			file_path.integrity_checksum.as_deref(),
+			located.date_checksummed,
+			file_path.date_modified,
+		) == ChecksumStatus::Current;
+	let stored = file_path
+		.integrity_checksum
+		.as_deref()
+		.filter(|_| has_checksum_for(&file_path, algorithm, false));
+	let outcome = match stored {
+		Some(stored) if current => {
+			let (checksum, _) =
+				file_checksum_with_fallback(&source, &full_path, options, None).await;
+			match checksum {
+				Ok((checksum, _)) if checksums_match(stored, &checksum) => {
+					FileValidationOutcome::Verified
+				}
+				Ok((checksum, _)) => {
+					error!(
+						"Checksum of {} doesn't match the stored one",
+						full_path.display()
+					);
+					FileValidationOutcome::Failed {
+						reason: format!(
+							"checksum {checksum} doesn't match {stored} from the library"
+						),
+					}
+				}
+				Err(e) => FileValidationOutcome::Failed {
+					reason: e.to_string(),
+				},
+			}
+		}
+		// files modified since their checksum was computed aren't corrupted for not matching it,
+		// they're given a new one
+		_ => {
+			// a current checksum in another algorithm is checked from the same reads before it's
+			// replaced, like the job's migrations do, so corruption isn't blessed with a new one
+			if current {
+				let Some(from) = ChecksumAlgorithm::from_db_in_mode(
+					file_path.integrity_checksum_algorithm.as_deref(),
+					false,
+				) else {
+					// it also covers the file's other streams, which this read doesn't check
+					return Ok(None);
+				};
+				options.migrate_from = Some(from);
+			}
+
+			let Some(validated) = validate_file(
+				&source,
+				location.id,
+				&location_path,
+				&file_path_for_object_validator::Data {
+					integrity_checksum: file_path
+						.integrity_checksum
+						.clone()
+						.filter(|_| options.migrate_from.is_some()),
+					..file_path.clone()
+				},
+				options,
+			)
+			.await?
+			else {
+				return Ok(None);
+			};
+
+			if let (FileValidationOutcome::Checksummed, Some(_)) =
+				(&validated.outcome, &validated.checksum)
+			{
+				// only replaces the checksum it was read with
+				let stored = LibraryChecksumStore::new(library)
+					.compare_and_put(
+						&file_path,
+						StoredChecksums {
+							checksum: validated.checksum,
+							algorithm,
+							include_alt_streams: false,
+							checksum_source: None,
+							content_checksum: validated.content_checksum,
+							content_type: validated.content_type.map(str::to_string),
+							perceptual_hash: validated.perceptual_hash,
+							range_checksum: None,
+						},
+					)
+					.await?;
+				if let ChecksumWrite::Conflict { found } = stored {
+					warn!(
+						"The checksum of {} was changed to {} while it was read, keeping it",
+						full_path.display(),
+						found.as_deref().unwrap_or("none")
+					);
+					return Ok(None);
+				}
+			}
+
+			validated.outcome
+		}
+	};
+
+	Ok(Some((location.id, full_path, outcome)))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_in_flight_dedupes_requests() {
+		let library_id = Uuid::new_v4();
+
+		let first = InFlight::start(library_id, 1).unwrap();
+		assert!(InFlight::start(library_id, 1).is_none());
+		// other files, and the same file in other libraries, are validated alongside
+		let other = InFlight::start(library_id, 2).unwrap();
+		let other_library = InFlight::start(Uuid::new_v4(), 1).unwrap();
+
+		drop(first);
+		assert!(InFlight::start(library_id, 1).is_some());
+		drop((other, other_library));
+	}
+}
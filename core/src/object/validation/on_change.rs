@@ -0,0 +1,219 @@
+//! Checksums of the files the location watcher sees created or modified, computed in the
+//! background once they settle instead of waiting for a validation job, so they stay current as
+//! files change. A file changing over and over, like a download being written, is only read once
+//! it's done, and only a few settled files are read at once, so a bulk copy doesn't start
+//! thousands of reads when it's done.
+
+use crate::{invalidate_query, library::Library, prisma::file_path};
+
+use std::{
+	collections::HashMap,
+	sync::{Mutex, PoisonError},
+	time::Duration,
+};
+
+use once_cell::sync::Lazy;
+use tokio::{
+	sync::Semaphore,
+	time::{sleep, Instant},
+};
+use tracing::{debug, trace, warn};
+use uuid::Uuid;
+
+use super::{on_access::validate_accessed_file, FileValidationOutcome};
+
+/// How long a file has to go without changing before its checksum is computed
+pub const MIN_AGE: Duration = Duration::from_secs(10);
+
+/// How many settled files are read at once, across libraries
+const MAX_CONCURRENT_READS: usize = 2;
+
+type PendingFiles = HashMap<(Uuid, file_path::id::Type), Instant>;
+
+/// When the files waiting to settle last changed, by library
+static PENDING: Lazy<Mutex<PendingFiles>> = Lazy::new(Default::default);
+
+/// Permits to read a settled file, see [`MAX_CONCURRENT_READS`]
+static READS: Lazy<Semaphore> = Lazy::new(|| Semaphore::new(MAX_CONCURRENT_READS));
+
+/// Records a change of the file `at`, returning whether it wasn't waiting already, in which case
+/// someone has to wait for it to settle
+fn record_change(
+	pending: &mut PendingFiles,
+	file: (Uuid, file_path::id::Type),
+	at: Instant,
+) -> bool {
+	pending.insert(file, at).is_none()
+}
+
+/// How much longer the file has to wait at `now` for its last change to be [`MIN_AGE`] old,
+/// `None` once it settled. It's still waiting, further changes coalescing with it, until
+/// [`time_to_settle`] takes it.
+fn remaining_age(
+	pending: &PendingFiles,
+	file: (Uuid, file_path::id::Type),
+	now: Instant,
+) -> Option<Duration> {
+	pending
+		.get(&file)
+		.filter(|&&changed_at| changed_at + MIN_AGE > now)
+		.map(|&changed_at| changed_at + MIN_AGE - now)
+}
+
+/// Like [`remaining_age`], but the file is no longer waiting once it settled, changes from then
+/// on getting a checksum of their own
+fn time_to_settle(
+	pending: &mut PendingFiles,
+	file: (Uuid, file_path::id::Type),
+	now: Instant,
+) -> Option<Duration> {
+	let remaining = remaining_age(pending, file, now);
+	if remaining.is_none() {
+		pending.remove(&file);
+	}
+
+	remaining
+}
+
+/// Computes the checksum of a file the watcher just created or updated in the background, once
+/// it goes [`MIN_AGE`] without changing again and a read permit is free, if the library asks for
+/// it. Returns right away, further changes before the file is read only pushing its checksum
+/// back.
+pub fn checksum_on_change(library: &Library, file_path_id: file_path::id::Type) {
+	if !library.config.checksum_on_change {
+		return;
+	}
+
+	let file = (library.id, file_path_id);
+	if !record_change(
+		&mut PENDING.lock().unwrap_or_else(PoisonError::into_inner),
+		file,
+		Instant::now(),
+	) {
+		trace!("File path {file_path_id} changed again before it settled");
+		return;
+	}
+
+	let library = library.clone();
+	tokio::spawn(async move {
+		let _permit = loop {
+			let mut wait = Some(MIN_AGE);
+			while let Some(remaining) = wait {
+				sleep(remaining).await;
+				wait = remaining_age(
+					&PENDING.lock().unwrap_or_else(PoisonError::into_inner),
+					file,
+					Instant::now(),
+				);
+			}
+
+			let Ok(permit) = READS.acquire().await else {
+				return;
+			};
+			// a change while it waited for the permit pushes it back again, without a read of
+			// its own
+			if time_to_settle(
+				&mut PENDING.lock().unwrap_or_else(PoisonError::into_inner),
+				file,
+				Instant::now(),
+			)
+			.is_none()
+			{
+				break permit;
+			}
+		};
+
+		match validate_accessed_file(&library, file_path_id, true).await {
+			Ok(Some((_, path, FileValidationOutcome::Checksummed))) => {
+				debug!("Checksummed {} after it changed", path.display());
+				invalidate_query!(library, "search.paths");
+			}
+			// it was just written, not corrupted, if it can't be read it's likely still in use
+			Ok(Some((_, path, FileValidationOutcome::Failed { reason }))) => warn!(
+				"Couldn't checksum {} after it changed: {reason}",
+				path.display()
+			),
+			Ok(Some((_, path, outcome))) => {
+				debug!(
+					"Didn't checksum {} after it changed: {outcome:?}",
+					path.display()
+				);
+			}
+			Ok(None) => {}
+			Err(e) => warn!("Failed to checksum file path {file_path_id} after it changed: {e:#?}"),
+		}
+	});
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_changes_coalesce_until_read() {
+		let mut pending = PendingFiles::new();
+		let file = (Uuid::new_v4(), 1);
+		let start = Instant::now();
+
+		assert!(record_change(&mut pending, file, start));
+		assert_eq!(remaining_age(&pending, file, start + MIN_AGE), None);
+
+		// settled but still waiting for a read permit, so the change doesn't start another wait
+		assert!(!record_change(
+			&mut pending,
+			file,
+			start + MIN_AGE + Duration::from_secs(1)
+		));
+		assert_eq!(
+			remaining_age(&pending, file, start + MIN_AGE + Duration::from_secs(2)),
+			Some(MIN_AGE - Duration::from_secs(1))
+		);
+		assert_eq!(
+			time_to_settle(
+				&mut pending,
+				file,
+				start + MIN_AGE * 2 + Duration::from_secs(1)
+			),
+			None
+		);
+		assert!(record_change(
+			&mut pending,
+			file,
+			start + MIN_AGE * 2 + Duration::from_secs(2)
+		));
+	}
+
+	#[test]
+	fn test_changes_coalesce() {
+		let mut pending = PendingFiles::new();
+		let file = (Uuid::new_v4(), 1);
+		let start = Instant::now();
+
+		assert!(record_change(&mut pending, file, start));
+		assert!(!record_change(
+			&mut pending,
+			file,
+			start + Duration::from_secs(4)
+		));
+		// the same file in other libraries waits on its own
+		assert!(record_change(&mut pending, (Uuid::new_v4(), 1), start));
+
+		// each change pushes the checksum back
+		assert_eq!(
+			time_to_settle(&mut pending, file, start + MIN_AGE),
+			Some(Duration::from_secs(4))
+		);
+		assert_eq!(
+			time_to_settle(&mut pending, file, start + MIN_AGE + Duration::from_secs(4)),
+			None
+		);
+
+		// changes once it settled get a checksum of their own
+		assert!(record_change(
+			&mut pending,
+			file,
+			start + MIN_AGE + Duration::from_secs(5)
+		));
+	}
+}
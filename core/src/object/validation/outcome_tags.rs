@@ -0,0 +1,190 @@
+use crate::{
+	library::Library,
+	prisma::{object, tag, tag_on_object, PrismaClient},
+	sync::SyncManager,
+};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use sd_sync::CRDTOperation;
+use uuid::Uuid;
+
+use super::ValidatorError;
+
+/// Tags given to the objects of validated files, so they can be filtered by integrity status
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutcomeTags {
+	pub success: Option<tag::id::Type>,
+	pub failure: Option<tag::id::Type>,
+}
+
+impl OutcomeTags {
+	pub fn is_empty(&self) -> bool {
+		self.success.is_none() && self.failure.is_none()
+	}
+
+	/// Fails if any of the tags was deleted, before anything gets validated
+	pub async fn ensure_exist(&self, db: &PrismaClient) -> Result<(), ValidatorError> {
+		for tag_id in [self.success, self.failure].into_iter().flatten() {
+			if db.tag().count(vec![tag::id::equals(tag_id)]).exec().await? == 0 {
+				return Err(ValidatorError::TagNotFound(tag_id));
+			}
+		}
+
+		Ok(())
+	}
+
+	/// Tags the objects of healthy files with the success tag and those of corrupted ones with
+	/// the failure tag, taking the other one off as an object's status is the one of its last
+	/// validation. Tagging an object again is a no-op.
+	pub async fn apply(
+		&self,
+		library: &Library,
+		healthy: impl IntoIterator<Item = object::id::Type>,
+		failed: impl IntoIterator<Item = object::id::Type>,
+	) -> Result<(), ValidatorError> {
+		let Library { db, sync, .. } = library;
+
+		let (healthy, failed) = by_status(healthy, failed);
+		if healthy.is_empty() && failed.is_empty() {
+			return Ok(());
+		}
+
+		let tag_pub_ids = pub_ids(
+			db.tag()
+				.find_many(vec![tag::id::in_vec(
+					[self.success, self.failure].into_iter().flatten().collect(),
+				)])
+				.select(tag::select!({ id pub_id }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|tag| (tag.id, tag.pub_id)),
+		);
+		let object_pub_ids = pub_ids(
+			db.object()
+				.find_many(vec![object::id::in_vec(
+					healthy.iter().chain(&failed).copied().collect(),
+				)])
+				.select(object::select!({ id pub_id }))
+				.exec()
+				.await?
+				.into_iter()
+				.map(|object| (object.id, object.pub_id)),
+		);
+		// the sync ops of the tag's assignments to these objects
+		let relation_ops =
+			|tag_id: tag::id::Type,
+			 object_ids: &[object::id::Type],
+			 op: fn(&SyncManager, &str, Uuid, Uuid) -> CRDTOperation| {
+				object_ids
+					.iter()
+					.filter_map(|object_id| {
+						Some(op(
+							sync,
+							tag_on_object::NAME,
+							*tag_pub_ids.get(&tag_id)?,
+							*object_pub_ids.get(object_id)?,
+						))
+					})
+					.collect::<Vec<_>>()
+			};
+
+		let assignments = [
+			(self.success, self.failure, healthy),
+			(self.failure, self.success, failed),
+		];
+
+		let mut ops = vec![];
+		let (untag, tag): (Vec<_>, Vec<_>) = assignments
+			.into_iter()
+			.filter(|(_, _, object_ids)| !object_ids.is_empty())
+			.map(|(tag_id, other_tag_id, object_ids)| {
+				(
+					other_tag_id.map(|other_tag_id| {
+						ops.extend(relation_ops(
+							other_tag_id,
+							&object_ids,
+							SyncManager::relation_delete,
+						));
+
+						db.tag_on_object().delete_many(vec![
+							tag_on_object::tag_id::equals(other_tag_id),
+							tag_on_object::object_id::in_vec(object_ids.clone()),
+						])
+					}),
+					tag_id.map(|tag_id| {
+						ops.extend(relation_ops(
+							tag_id,
+							&object_ids,
+							SyncManager::relation_create,
+						));
+
+						db.tag_on_object()
+							.create_many(
+								object_ids
+									.into_iter()
+									.map(|object_id| tag_on_object::CreateUnchecked {
+										tag_id,
+										object_id,
+										_params: vec![],
+									})
+									.collect(),
+							)
+							.skip_duplicates()
+					}),
+				)
+			})
+			.unzip();
+
+		sync.write_ops(
+			db,
+			(
+				ops,
+				(
+					untag.into_iter().flatten().collect::<Vec<_>>(),
+					tag.into_iter().flatten().collect::<Vec<_>>(),
+				),
+			),
+		)
+		.await?;
+
+		Ok(())
+	}
+}
+
+/// Relations are synced by the pub ids of both sides, as uuids
+fn pub_ids<Id: Ord>(rows: impl Iterator<Item = (Id, Vec<u8>)>) -> BTreeMap<Id, Uuid> {
+	rows.filter_map(|(id, pub_id)| Some((id, Uuid::from_slice(&pub_id).ok()?)))
+		.collect()
+}
+
+/// Deduplicates the objects of each status, an object with both a healthy and a corrupted file
+/// is corrupted
+pub(super) fn by_status(
+	healthy: impl IntoIterator<Item = object::id::Type>,
+	failed: impl IntoIterator<Item = object::id::Type>,
+) -> (Vec<object::id::Type>, Vec<object::id::Type>) {
+	let failed = failed.into_iter().collect::<BTreeSet<_>>();
+	let healthy = healthy
+		.into_iter()
+		.filter(|object_id| !failed.contains(object_id))
+		.collect::<BTreeSet<_>>();
+
+	(healthy.into_iter().collect(), failed.into_iter().collect())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_by_status() {
+		assert_eq!(
+			by_status([3, 1, 2, 1, 4], [4, 5, 5]),
+			(vec![1, 2, 3], vec![4, 5])
+		);
+		assert_eq!(by_status([], []), (vec![], vec![]));
+	}
+}
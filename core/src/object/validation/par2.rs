@@ -0,0 +1,535 @@
+//! PAR2 recovery sets, the `.par2` files archival tools keep along with files so damage to them
+//! can be repaired. The files of a set are split in slices of the same size, each with its own
+//! checksum, and any damaged slice can be rebuilt from any of the set's recovery slices, so a set
+//! can repair as many damaged slices as it has recovery slices, whichever of its files they're in.
+
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap},
+	fs::{self, File},
+	io::{self, BufReader, Read, Seek, SeekFrom},
+	path::{Component, Path, PathBuf},
+};
+
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+use tokio::task::spawn_blocking;
+use tracing::warn;
+
+const PACKET_MAGIC: &[u8; 8] = b"PAR2\0PKT";
+const HEADER_LEN: u64 = 64;
+/// packets are kept whole but for recovery slices, longer ones can only be damaged
+const MAX_PACKET_LEN: u64 = 64 * 1024 * 1024;
+const READ_LEN: usize = 64 * 1024;
+
+const MAIN_PACKET: &[u8; 16] = b"PAR 2.0\0Main\0\0\0\0";
+const FILE_DESCRIPTION_PACKET: &[u8; 16] = b"PAR 2.0\0FileDesc";
+const SLICE_CHECKSUMS_PACKET: &[u8; 16] = b"PAR 2.0\0IFSC\0\0\0\0";
+const RECOVERY_SLICE_PACKET: &[u8; 16] = b"PAR 2.0\0RecvSlic";
+
+type Par2Id = [u8; 16];
+
+/// What the packets of the `.par2` files of a set tell about it, damaged ones left out
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Par2Set {
+	/// 0 until its main packet is found
+	pub slice_size: u64,
+	/// ids of the files the recovery slices are for, from the main packet
+	pub protected: Vec<Par2Id>,
+	pub files: HashMap<Par2Id, Par2File>,
+	/// exponents of the recovery slices, copies of the same one only counting once
+	pub recovery_slices: BTreeSet<u32>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Par2File {
+	/// path relative to the directory of the `.par2` files
+	pub name: String,
+	pub len: u64,
+	/// MD5 of each slice, the last one padded with zeros
+	pub slice_md5s: Vec<[u8; 16]>,
+}
+
+/// Whether the PAR2 set a corrupted file belongs to can repair it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Par2Recoverability {
+	/// first `.par2` file of the set, relative to the location
+	pub set: String,
+	/// slices of all the files of the set that are damaged, as they're repaired together
+	pub damaged_slices: u64,
+	pub recovery_slices: u64,
+	pub recoverable: bool,
+}
+
+/// Adds the packets read from a `.par2` file to the `sets` they're of, by recovery set id.
+/// Damaged packets are skipped, the next one being looked for past them.
+pub fn read_par2_packets(
+	reader: impl Read + Seek,
+	sets: &mut HashMap<Par2Id, Par2Set>,
+) -> io::Result<()> {
+	let mut reader = BufReader::new(reader);
+	let mut header = [0; HEADER_LEN as usize];
+	let mut buffer = vec![0; READ_LEN];
+	let mut offset = 0;
+
+	while let Some(start) = find_packet(&mut reader, offset)? {
+		reader.seek(SeekFrom::Start(start))?;
+		// where the next one is looked for if this one is damaged
+		offset = start + 4;
+		if !read_full(&mut reader, &mut header)? {
+			break;
+		}
+
+		let len = u64::from_le_bytes(header[8..16].try_into().unwrap_or_default());
+		let packet_type = &header[48..64];
+		let is_recovery_slice = packet_type == RECOVERY_SLICE_PACKET;
+		if len < HEADER_LEN || len % 4 != 0 || (!is_recovery_slice && len > MAX_PACKET_LEN) {
+			continue;
+		}
+
+		// the packet's MD5 is of all of it from the recovery set id on
+		let mut hasher = Md5::new();
+		hasher.update(&header[32..]);
+		let mut body = vec![];
+		let mut remaining = len - HEADER_LEN;
+		let mut complete = true;
+		while remaining > 0 {
+			let chunk = &mut buffer[..remaining.min(READ_LEN as u64) as usize];
+			if !read_full(&mut reader, chunk)? {
+				complete = false;
+				break;
+			}
+			hasher.update(&*chunk);
+			// only the exponent of recovery slices is of use
+			if !is_recovery_slice || body.is_empty() {
+				body.extend_from_slice(chunk);
+			}
+			remaining -= chunk.len() as u64;
+		}
+		if !complete || hasher.finalize()[..] != header[16..32] {
+			continue;
+		}
+
+		let set_id = header[32..48].try_into().unwrap_or_default();
+		if record_packet(sets.entry(set_id).or_default(), packet_type, &body).is_none() {
+			warn!("Skipping a PAR2 packet too short for its type");
+		}
+		offset = start + len;
+	}
+
+	Ok(())
+}
+
+/// Offset of the first packet from `from` on, packets all starting at multiples of 4
+fn find_packet(reader: &mut (impl Read + Seek), from: u64) -> io::Result<Option<u64>> {
+	reader.seek(SeekFrom::Start(from))?;
+
+	let mut window = Vec::with_capacity(READ_LEN * 2);
+	let mut window_start = from;
+	let mut buffer = vec![0; READ_LEN];
+	loop {
+		let read = reader.read(&mut buffer)?;
+		if read == 0 {
+			return Ok(None);
+		}
+		window.extend_from_slice(&buffer[..read]);
+
+		let searched = window.len().saturating_sub(PACKET_MAGIC.len() - 1);
+		if let Some(position) = (0..searched)
+			.step_by(4)
+			.find(|&position| window[position..].starts_with(PACKET_MAGIC))
+		{
+			return Ok(Some(window_start + position as u64));
+		}
+
+		// a packet may start in what's left, split with the next read
+		let searched = searched - searched % 4;
+		window.drain(..searched);
+		window_start += searched as u64;
+	}
+}
+
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<bool> {
+	match reader.read_exact(buffer) {
+		Ok(()) => Ok(true),
+		Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+		Err(e) => Err(e),
+	}
+}
+
+/// Adds a packet to its set, `None` if its body is too short for its type
+fn record_packet(set: &mut Par2Set, packet_type: &[u8], body: &[u8]) -> Option<()> {
+	let id = |bytes: &[u8]| -> Option<Par2Id> { bytes.try_into().ok() };
+	let u64_at = |at: usize| Some(u64::from_le_bytes(body.get(at..at + 8)?.try_into().ok()?));
+
+	if packet_type == MAIN_PACKET {
+		let slice_size = u64_at(0)?;
+		let count = u32::from_le_bytes(body.get(8..12)?.try_into().ok()?) as usize;
+		set.protected = body
+			.get(12..count.checked_mul(16)?.checked_add(12)?)?
+			.chunks_exact(16)
+			.filter_map(id)
+			.collect();
+		set.slice_size = slice_size;
+	} else if packet_type == FILE_DESCRIPTION_PACKET {
+		let file_id = id(body.get(..16)?)?;
+		let len = u64_at(48)?;
+		let name = body.get(56..)?;
+		let name = &name[..name.iter().rposition(|&b| b != 0).map_or(0, |end| end + 1)];
+
+		let file = set.files.entry(file_id).or_default();
+		file.name = String::from_utf8_lossy(name).into_owned();
+		file.len = len;
+	} else if packet_type == SLICE_CHECKSUMS_PACKET {
+		let file_id = id(body.get(..16)?)?;
+		set.files.entry(file_id).or_default().slice_md5s = body[16..]
+			.chunks_exact(20)
+			.filter_map(|entry| id(&entry[..16]))
+			.collect();
+	} else if packet_type == RECOVERY_SLICE_PACKET {
+		set.recovery_slices
+			.insert(u32::from_le_bytes(body.get(..4)?.try_into().ok()?));
+	}
+
+	Some(())
+}
+
+/// How many slices of the set's files under `dir` don't match their checksums, missing and
+/// unreadable ones included. `None` if the set's packets don't tell what its files are, like
+/// when the `.par2` files holding them are damaged too.
+pub fn damaged_slices(dir: &Path, set: &Par2Set) -> Option<u64> {
+	if set.slice_size == 0 || set.protected.is_empty() {
+		return None;
+	}
+
+	let mut buffer = vec![0; READ_LEN];
+	let mut damaged = 0;
+	for id in &set.protected {
+		let file = set.files.get(id)?;
+		// the names come from the set, they mustn't point out of its directory
+		if file.name.is_empty()
+			|| !Path::new(&file.name)
+				.components()
+				.all(|component| matches!(component, Component::Normal(_)))
+			|| file.slice_md5s.len() as u64
+				!= file.len / set.slice_size + u64::from(file.len % set.slice_size != 0)
+		{
+			return None;
+		}
+
+		let Ok(mut reader) = File::open(dir.join(&file.name)) else {
+			damaged += file.slice_md5s.len() as u64;
+			continue;
+		};
+		for (i, expected) in file.slice_md5s.iter().enumerate() {
+			match slice_md5(&mut reader, set.slice_size, &mut buffer) {
+				Ok(md5) if md5 == *expected => {}
+				Ok(_) => damaged += 1,
+				Err(_) => {
+					// bad sectors only take their slices with them
+					damaged += 1;
+					if reader
+						.seek(SeekFrom::Start((i as u64 + 1) * set.slice_size))
+						.is_err()
+					{
+						damaged += (file.slice_md5s.len() - i - 1) as u64;
+						break;
+					}
+				}
+			}
+		}
+	}
+
+	Some(damaged)
+}
+
+/// MD5 of the next `slice_size` bytes of `reader`, padded with zeros past its end
+fn slice_md5(reader: &mut impl Read, slice_size: u64, buffer: &mut [u8]) -> io::Result<[u8; 16]> {
+	let mut hasher = Md5::new();
+	let mut remaining = slice_size;
+	let mut at_end = false;
+
+	while remaining > 0 {
+		let chunk = &mut buffer[..remaining.min(buffer.len() as u64) as usize];
+		let read = if at_end {
+			0
+		} else {
+			match reader.read(chunk) {
+				Ok(read) => read,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => return Err(e),
+			}
+		};
+
+		if read == 0 {
+			at_end = true;
+			chunk.fill(0);
+			hasher.update(&*chunk);
+			remaining -= chunk.len() as u64;
+		} else {
+			hasher.update(&chunk[..read]);
+			remaining -= read as u64;
+		}
+	}
+
+	Ok(hasher.finalize().into())
+}
+
+/// The sets of the `.par2` files in `dir`, relative to the location, each along with the first of
+/// its files
+fn read_par2_sets(location_path: &Path, dir: &Path) -> Vec<(String, Par2Set)> {
+	let Ok(entries) = fs::read_dir(location_path.join(dir)) else {
+		return vec![];
+	};
+	let mut par2_files = entries
+		.filter_map(Result::ok)
+		.map(|entry| entry.path())
+		.filter(|path| {
+			path.extension()
+				.map_or(false, |extension| extension.eq_ignore_ascii_case("par2"))
+		})
+		.collect::<Vec<_>>();
+	// an index file comes before the volumes of its set, `set.par2` and `set.vol00+01.par2`
+	par2_files.sort();
+
+	let mut sets = HashMap::new();
+	let mut first_files = HashMap::new();
+	for par2_file in par2_files {
+		let name = with_slashes(&dir.join(par2_file.file_name().unwrap_or_default()));
+		if let Err(e) = File::open(&par2_file).and_then(|file| read_par2_packets(file, &mut sets)) {
+			warn!("Couldn't read PAR2 file {}: {e}", par2_file.display());
+		}
+		for set_id in sets.keys() {
+			first_files.entry(*set_id).or_insert_with(|| name.clone());
+		}
+	}
+
+	let mut sets = sets
+		.into_iter()
+		.filter_map(|(set_id, set)| Some((first_files.remove(&set_id)?, set)))
+		.collect::<Vec<_>>();
+	sets.sort_by(|(a, _), (b, _)| a.cmp(b));
+	sets
+}
+
+fn with_slashes(path: &Path) -> String {
+	path.components()
+		.map(|component| component.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+/// Whether the PAR2 sets the `failed` files, by path relative to the location, belong to can
+/// repair them, for the ones in a set. Sets are looked for in the `.par2` files of the directory
+/// of each file and the ones above it, up to the location's root.
+pub async fn par2_recoverability(
+	location_path: PathBuf,
+	failed: Vec<String>,
+) -> BTreeMap<String, Par2Recoverability> {
+	spawn_blocking(move || {
+		let mut sets_by_dir = HashMap::new();
+		let mut damaged_by_set = HashMap::new();
+		let mut recoverability = BTreeMap::new();
+
+		for relative_path in failed {
+			let path = Path::new(&relative_path);
+			for dir in path.ancestors().skip(1) {
+				let sets = sets_by_dir
+					.entry(dir.to_path_buf())
+					.or_insert_with(|| read_par2_sets(&location_path, dir));
+				let name = with_slashes(path.strip_prefix(dir).unwrap_or(path));
+				let Some((set_file, set)) = sets
+					.iter()
+					.find(|(_, set)| set.files.values().any(|file| file.name == name))
+				else {
+					continue;
+				};
+
+				let damaged = *damaged_by_set
+					.entry(set_file.clone())
+					.or_insert_with(|| damaged_slices(&location_path.join(dir), set));
+				match damaged {
+					Some(damaged_slices) => {
+						let recovery_slices = set.recovery_slices.len() as u64;
+						recoverability.insert(
+							relative_path.clone(),
+							Par2Recoverability {
+								set: set_file.clone(),
+								damaged_slices,
+								recovery_slices,
+								recoverable: damaged_slices <= recovery_slices,
+							},
+						);
+					}
+					None => warn!(
+						"PAR2 set {set_file} is missing packets, can't tell if {relative_path} can be repaired"
+					),
+				}
+				break;
+			}
+		}
+
+		recoverability
+	})
+	.await
+	.unwrap_or_else(|e| {
+		warn!("Failed to check the PAR2 sets of corrupted files: {e}");
+		BTreeMap::new()
+	})
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use std::io::Cursor;
+
+	use tempfile::tempdir;
+
+	const SET_ID: Par2Id = [7; 16];
+
+	fn packet(packet_type: &[u8; 16], body: &[u8]) -> Vec<u8> {
+		let mut hashed = SET_ID.to_vec();
+		hashed.extend_from_slice(packet_type);
+		hashed.extend_from_slice(body);
+
+		let mut packet = PACKET_MAGIC.to_vec();
+		packet.extend_from_slice(&(HEADER_LEN + body.len() as u64).to_le_bytes());
+		packet.extend_from_slice(&Md5::digest(&hashed));
+		packet.extend_from_slice(&hashed);
+		packet
+	}
+
+	/// A set of `files` in slices of 4 bytes, with `recovery_slices` of them
+	fn par2_file(files: &[(&str, &[u8])], recovery_slices: u32) -> Vec<u8> {
+		let ids = (0..files.len()).map(|i| [i as u8; 16]).collect::<Vec<_>>();
+
+		let mut main = 4u64.to_le_bytes().to_vec();
+		main.extend_from_slice(&(files.len() as u32).to_le_bytes());
+		main.extend(ids.iter().flatten());
+		let mut par2 = packet(MAIN_PACKET, &main);
+
+		for (id, (name, contents)) in ids.iter().zip(files) {
+			let mut description = id.to_vec();
+			description.extend_from_slice(&[0; 32]);
+			description.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+			description.extend_from_slice(name.as_bytes());
+			while description.len() % 4 != 0 {
+				description.push(0);
+			}
+			par2.extend(packet(FILE_DESCRIPTION_PACKET, &description));
+
+			let mut checksums = id.to_vec();
+			for slice in contents.chunks(4) {
+				let mut slice = slice.to_vec();
+				slice.resize(4, 0);
+				checksums.extend_from_slice(&Md5::digest(&slice));
+				checksums.extend_from_slice(&[0; 4]);
+			}
+			par2.extend(packet(SLICE_CHECKSUMS_PACKET, &checksums));
+		}
+
+		for exponent in 0..recovery_slices {
+			let mut recovery = exponent.to_le_bytes().to_vec();
+			recovery.extend_from_slice(&[0; 4]);
+			par2.extend(packet(RECOVERY_SLICE_PACKET, &recovery));
+		}
+
+		par2
+	}
+
+	#[test]
+	fn test_read_par2_packets() {
+		let files: &[(&str, &[u8])] = &[("a.txt", b"spacedrive"), ("docs/b.txt", b"")];
+		let par2 = par2_file(files, 2);
+
+		let mut sets = HashMap::new();
+		read_par2_packets(Cursor::new(&par2), &mut sets).unwrap();
+		let set = &sets[&SET_ID];
+		assert_eq!(set.slice_size, 4);
+		assert_eq!(set.protected.len(), 2);
+		assert_eq!(set.files[&[0; 16]].name, "a.txt");
+		assert_eq!(set.files[&[0; 16]].slice_md5s.len(), 3);
+		assert_eq!(set.files[&[1; 16]].name, "docs/b.txt");
+		assert_eq!(set.recovery_slices.len(), 2);
+
+		// damaged packets are skipped, the ones after them still read
+		let mut damaged = par2.clone();
+		damaged[HEADER_LEN as usize] ^= 1;
+		let mut prefixed = vec![0xff; 8];
+		prefixed.extend(&damaged);
+		prefixed.resize(prefixed.len() + 3, 0);
+		let mut sets = HashMap::new();
+		read_par2_packets(Cursor::new(prefixed), &mut sets).unwrap();
+		let set = &sets[&SET_ID];
+		assert_eq!(set.slice_size, 0);
+		assert_eq!(set.files.len(), 2);
+		assert_eq!(set.recovery_slices.len(), 2);
+	}
+
+	#[test]
+	fn test_damaged_slices() {
+		let dir = tempdir().unwrap();
+		let files: &[(&str, &[u8])] = &[("a.txt", b"spacedrive"), ("b.txt", b"abcd")];
+		for (name, contents) in files {
+			fs::write(dir.path().join(name), contents).unwrap();
+		}
+
+		let mut sets = HashMap::new();
+		read_par2_packets(Cursor::new(par2_file(files, 1)), &mut sets).unwrap();
+		let set = &sets[&SET_ID];
+		assert_eq!(damaged_slices(dir.path(), set), Some(0));
+
+		fs::write(dir.path().join("a.txt"), b"spaceDrive").unwrap();
+		assert_eq!(damaged_slices(dir.path(), set), Some(1));
+
+		// a missing file has all of its slices damaged
+		fs::remove_file(dir.path().join("b.txt")).unwrap();
+		assert_eq!(damaged_slices(dir.path(), set), Some(2));
+
+		let mut unsafe_name = set.clone();
+		unsafe_name.files.get_mut(&[0; 16]).unwrap().name = "../a.txt".to_string();
+		assert_eq!(damaged_slices(dir.path(), &unsafe_name), None);
+	}
+
+	#[tokio::test]
+	async fn test_par2_recoverability() {
+		let dir = tempdir().unwrap();
+		fs::create_dir(dir.path().join("archive")).unwrap();
+		let files: &[(&str, &[u8])] = &[("a.txt", b"spacedrive"), ("docs/b.txt", b"abcd")];
+		fs::create_dir(dir.path().join("archive/docs")).unwrap();
+		for (name, contents) in files {
+			fs::write(dir.path().join("archive").join(name), contents).unwrap();
+		}
+		fs::write(dir.path().join("archive/set.par2"), par2_file(files, 1)).unwrap();
+
+		fs::write(dir.path().join("archive/docs/b.txt"), b"abcD").unwrap();
+		let recoverability = par2_recoverability(
+			dir.path().to_path_buf(),
+			vec!["archive/docs/b.txt".to_string(), "other.txt".to_string()],
+		)
+		.await;
+		assert_eq!(
+			recoverability,
+			BTreeMap::from([(
+				"archive/docs/b.txt".to_string(),
+				Par2Recoverability {
+					set: "archive/set.par2".to_string(),
+					damaged_slices: 1,
+					recovery_slices: 1,
+					recoverable: true,
+				}
+			)])
+		);
+
+		// damage in the other files of the set takes recovery slices too
+		fs::write(dir.path().join("archive/a.txt"), b"Spacedrive").unwrap();
+		let recoverability = par2_recoverability(
+			dir.path().to_path_buf(),
+			vec!["archive/docs/b.txt".to_string()],
+		)
+		.await;
+		assert!(!recoverability["archive/docs/b.txt"].recoverable);
+		assert_eq!(recoverability["archive/docs/b.txt"].damaged_slices, 2);
+	}
+}
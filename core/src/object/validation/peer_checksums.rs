@@ -0,0 +1,329 @@
+//! Cross-device integrity audit: the checksums this node has for the files of a location compared
+//! with the ones a peer stored for the same objects, over p2p. Nothing is hashed again, so it's
+//! cheap enough to run often, and disagreements reveal sync bugs or one of the devices corrupting
+//! its copy.
+
+use crate::{
+	library::Library,
+	location::file_path_helper::{file_path_for_peer_checksums, IsolatedFilePathData},
+	p2p::P2PManager,
+	prisma::{file_path, location, object, SortOrder},
+};
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use sd_p2p::PeerId;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::warn;
+
+use super::{
+	hash::{checksums_match, ChecksumAlgorithm},
+	not_excluded, ValidatorError,
+};
+
+const PAGE_SIZE: i64 = 1000;
+
+/// A checksum a node stores for one of its copies of an object, as sent to peers asking for it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PeerChecksum {
+	pub object_pub_id: Vec<u8>,
+	pub algorithm: ChecksumAlgorithm,
+	pub checksum: String,
+}
+
+/// How the peer's checksums of a file's object disagree with the local one
+#[derive(Serialize, Deserialize, Type, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum PeerDivergence {
+	/// The peer computed its checksum with the same algorithm and got another one
+	Differs { local: String, remote: String },
+	/// The peer only has checksums computed with other algorithms, comparing them would mean
+	/// hashing the file again
+	OtherAlgorithm {
+		local: ChecksumAlgorithm,
+		remote: ChecksumAlgorithm,
+	},
+	/// The peer has no checksum of the object, either it doesn't have it or never validated it
+	MissingOnPeer,
+}
+
+/// Comparison of a location's checksums with a peer's.
+/// Counts are `u32` as rspc doesn't support bigints.
+#[derive(Serialize, Deserialize, Type, Debug, Default, Clone, PartialEq, Eq)]
+pub struct PeerChecksumReport {
+	pub location_id: location::id::Type,
+	/// files with a local checksum the peer was asked about
+	pub compared: u32,
+	pub matched: u32,
+	/// keyed by path relative to the location
+	pub divergences: BTreeMap<String, PeerDivergence>,
+	/// why the peer couldn't be asked about every file, like it being offline, the files it
+	/// wasn't asked about are left out of the report
+	pub unavailable: Option<String>,
+}
+
+impl PeerChecksumReport {
+	fn record(&mut self, relative_path: String, divergence: Option<PeerDivergence>) {
+		self.compared += 1;
+		match divergence {
+			Some(divergence) => {
+				self.divergences.insert(relative_path, divergence);
+			}
+			None => self.matched += 1,
+		}
+	}
+}
+
+/// How the peer's checksums of an object diverge from `checksum`, `None` when one of them matches.
+/// The peer may have several copies of the object, any of them matching is enough.
+pub fn peer_divergence<'a>(
+	algorithm: ChecksumAlgorithm,
+	checksum: &str,
+	remote: impl IntoIterator<Item = &'a PeerChecksum>,
+) -> Option<PeerDivergence> {
+	let mut same_algorithm = None;
+	let mut other_algorithm = None;
+
+	// peers may send their hex in another case, and either side may have truncated its checksum
+	let local = checksum.to_ascii_lowercase();
+	let matches = |remote: &str| {
+		let remote = remote.to_ascii_lowercase();
+		checksums_match(&remote, &local) || checksums_match(&local, &remote)
+	};
+
+	for remote in remote {
+		if remote.algorithm != algorithm {
+			other_algorithm.get_or_insert(remote.algorithm);
+		} else if matches(&remote.checksum) {
+			return None;
+		} else {
+			same_algorithm.get_or_insert(&remote.checksum);
+		}
+	}
+
+	Some(match (same_algorithm, other_algorithm) {
+		(Some(remote), _) => PeerDivergence::Differs {
+			local: checksum.to_string(),
+			remote: remote.clone(),
+		},
+		(None, Some(remote)) => PeerDivergence::OtherAlgorithm {
+			local: algorithm,
+			remote,
+		},
+		(None, None) => PeerDivergence::MissingOnPeer,
+	})
+}
+
+/// Compares the checksums of the location's files with the ones `peer_id` stored for their
+/// objects, a page of files at a time. A peer that's offline, doesn't have the library or stops
+/// answering doesn't fail the comparison, what was compared until then is reported along with why
+/// it stopped.
+pub async fn compare_checksums_with_peer(
+	library: &Library,
+	p2p: &P2PManager,
+	peer_id: PeerId,
+	location_id: location::id::Type,
+) -> Result<PeerChecksumReport, ValidatorError> {
+	let mut report = PeerChecksumReport {
+		location_id,
+		..Default::default()
+	};
+	let mut last_id = None;
+
+	loop {
+		let file_paths = library
+			.db
+			.file_path()
+			.find_many(
+				[
+					file_path::location_id::equals(Some(location_id)),
+					file_path::is_dir::equals(Some(false)),
+					file_path::object_id::not(None),
+					file_path::integrity_checksum::not(None),
+					not_excluded(),
+				]
+				.into_iter()
+				.chain(last_id.map(file_path::id::gt))
+				.collect(),
+			)
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(PAGE_SIZE)
+			.select(file_path_for_peer_checksums::select())
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		last_id = Some(last.id);
+
+		let object_pub_ids = file_paths
+			.iter()
+			.filter_map(|file_path| file_path.object.as_ref())
+			.map(|object| object.pub_id.clone())
+			.collect::<HashSet<_>>()
+			.into_iter()
+			.collect();
+
+		let remote = match p2p
+			.request_checksums(peer_id, library.id, object_pub_ids)
+			.await
+		{
+			Ok(remote) => remote,
+			Err(e) => {
+				warn!("Couldn't get the checksums of peer '{peer_id}': {e}");
+				report.unavailable = Some(e.to_string());
+				break;
+			}
+		};
+
+		let mut remote_by_object = HashMap::<_, Vec<_>>::new();
+		for checksum in &remote {
+			remote_by_object
+				.entry(checksum.object_pub_id.as_slice())
+				.or_default()
+				.push(checksum);
+		}
+
+		for file_path in &file_paths {
+			let (Some(object), Some(checksum)) = (&file_path.object, &file_path.integrity_checksum)
+			else {
+				continue;
+			};
+			let Some(algorithm) =
+				ChecksumAlgorithm::from_db(file_path.integrity_checksum_algorithm.as_deref())
+			else {
+				continue;
+			};
+
+			report.record(
+				IsolatedFilePathData::try_from((location_id, file_path))?.to_string(),
+				peer_divergence(
+					algorithm,
+					checksum,
+					remote_by_object
+						.get(object.pub_id.as_slice())
+						.into_iter()
+						.flatten()
+						.copied(),
+				),
+			);
+		}
+	}
+
+	Ok(report)
+}
+
+/// The checksums this node stored for its own copies of the objects, to answer a peer comparing
+/// its checksums with ours. Copies on other nodes are left out, as the library only has what was
+/// synced of them.
+pub async fn local_checksums_of_objects(
+	library: &Library,
+	object_pub_ids: Vec<Vec<u8>>,
+) -> Result<Vec<PeerChecksum>, ValidatorError> {
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(vec![
+			file_path::object::is(vec![object::pub_id::in_vec(object_pub_ids)]),
+			file_path::location::is(vec![location::node_id::equals(Some(library.node_local_id))]),
+			file_path::integrity_checksum::not(None),
+		])
+		.select(file_path::select!({
+			integrity_checksum
+			integrity_checksum_algorithm
+			object: select { pub_id }
+		}))
+		.exec()
+		.await?;
+
+	Ok(file_paths
+		.into_iter()
+		.filter_map(|file_path| {
+			Some(PeerChecksum {
+				object_pub_id: file_path.object?.pub_id,
+				algorithm: ChecksumAlgorithm::from_db(
+					file_path.integrity_checksum_algorithm.as_deref(),
+				)?,
+				checksum: file_path.integrity_checksum?,
+			})
+		})
+		.collect())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_peer_divergence() {
+		let remote = |object: u8, algorithm, checksum: &str| PeerChecksum {
+			object_pub_id: vec![object],
+			algorithm,
+			checksum: checksum.to_string(),
+		};
+		let blake3 = ChecksumAlgorithm::Blake3;
+
+		assert_eq!(
+			peer_divergence(blake3, "aaaa", &[remote(1, blake3, "AAAA")]),
+			None
+		);
+		assert_eq!(
+			peer_divergence(blake3, "aaaa", &[remote(1, blake3, "aa/8")]),
+			None
+		);
+		assert_eq!(
+			peer_divergence(blake3, "aa/8", &[remote(1, blake3, "aaaa")]),
+			None
+		);
+		// any of the peer's copies matching is enough
+		assert_eq!(
+			peer_divergence(
+				blake3,
+				"aaaa",
+				&[remote(1, blake3, "bbbb"), remote(1, blake3, "aaaa")]
+			),
+			None
+		);
+		assert_eq!(
+			peer_divergence(
+				blake3,
+				"aaaa",
+				&[
+					remote(1, ChecksumAlgorithm::Sha256, "cccc"),
+					remote(1, blake3, "bbbb")
+				]
+			),
+			Some(PeerDivergence::Differs {
+				local: "aaaa".to_string(),
+				remote: "bbbb".to_string(),
+			})
+		);
+		assert_eq!(
+			peer_divergence(
+				blake3,
+				"aaaa",
+				&[remote(1, ChecksumAlgorithm::Sha256, "cccc")]
+			),
+			Some(PeerDivergence::OtherAlgorithm {
+				local: blake3,
+				remote: ChecksumAlgorithm::Sha256,
+			})
+		);
+		assert_eq!(
+			peer_divergence(blake3, "aaaa", &[]),
+			Some(PeerDivergence::MissingOnPeer)
+		);
+
+		let mut report = PeerChecksumReport::default();
+		report.record("a.txt".to_string(), None);
+		report.record("b.txt".to_string(), Some(PeerDivergence::MissingOnPeer));
+		assert_eq!((report.compared, report.matched), (2, 1));
+		assert_eq!(
+			report.divergences.get("b.txt"),
+			Some(&PeerDivergence::MissingOnPeer)
+		);
+	}
+}
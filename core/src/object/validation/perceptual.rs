@@ -0,0 +1,111 @@
+//! Perceptual hashes of images, to find the ones that look the same without being identical, like
+//! a photo and its re-encoded or resized copies.
+//!
+//! It's a difference hash: the image is shrunk to 9x8 grey pixels, and each of the 64 bits tells
+//! whether a pixel is brighter than the one at its right. Re-encoding or resizing barely moves
+//! them, so similar images have hashes a few bits apart, see [`perceptual_distance`].
+
+use std::path::Path;
+
+use image::{imageops::FilterType, ImageFormat};
+
+/// Images bigger than this aren't kept in memory to be hashed, only their checksum is computed
+pub const MAX_PERCEPTUAL_LEN: u64 = 64 * 1024 * 1024;
+
+const WIDTH: u32 = 9;
+const HEIGHT: u32 = 8;
+
+/// Whether the file is an image we can decode, from its extension
+pub fn is_perceptually_hashable(path: impl AsRef<Path>) -> bool {
+	ImageFormat::from_path(path).is_ok()
+}
+
+/// The difference hash of an encoded image, as 16 hex digits, `None` if it can't be decoded
+pub fn perceptual_hash(contents: &[u8]) -> Option<String> {
+	let image = image::load_from_memory(contents).ok()?;
+	let pixels = image
+		.resize_exact(WIDTH, HEIGHT, FilterType::Triangle)
+		.into_luma8();
+
+	let mut hash = 0u64;
+	for y in 0..HEIGHT {
+		for x in 0..WIDTH - 1 {
+			hash <<= 1;
+			hash |= u64::from(pixels.get_pixel(x, y)[0] < pixels.get_pixel(x + 1, y)[0]);
+		}
+	}
+
+	Some(format!("{hash:016x}"))
+}
+
+/// How many bits two perceptual hashes differ by, images a handful of bits apart look alike.
+/// `None` if either isn't a perceptual hash.
+pub fn perceptual_distance(a: &str, b: &str) -> Option<u32> {
+	let a = u64::from_str_radix(a, 16).ok()?;
+	let b = u64::from_str_radix(b, 16).ok()?;
+
+	Some((a ^ b).count_ones())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use std::io::Cursor;
+
+	use image::{DynamicImage, ImageOutputFormat, Rgb, RgbImage};
+
+	fn gradient(width: u32, height: u32) -> DynamicImage {
+		DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+			let value = (x * 191 / width + y * 64 / height) as u8;
+			Rgb([value, value / 2, 255 - value])
+		}))
+	}
+
+	fn encode(image: &DynamicImage, format: ImageOutputFormat) -> Vec<u8> {
+		let mut contents = Cursor::new(vec![]);
+		image.write_to(&mut contents, format).unwrap();
+		contents.into_inner()
+	}
+
+	#[test]
+	fn test_perceptual_hash() {
+		let png = encode(&gradient(640, 480), ImageOutputFormat::Png);
+		let hash = perceptual_hash(&png).unwrap();
+		assert_eq!(hash.len(), 16);
+
+		// a smaller lossy copy looks the same
+		let jpeg = encode(&gradient(320, 240), ImageOutputFormat::Jpeg(60));
+		assert_ne!(png, jpeg);
+		assert!(perceptual_distance(&hash, &perceptual_hash(&jpeg).unwrap()).unwrap() <= 4);
+
+		// the same image flipped doesn't
+		let flipped = encode(&gradient(640, 480).fliph(), ImageOutputFormat::Png);
+		assert!(perceptual_distance(&hash, &perceptual_hash(&flipped).unwrap()).unwrap() > 16);
+
+		assert_eq!(perceptual_hash(b"not an image"), None);
+		assert_eq!(perceptual_hash(&png[..png.len() / 2]), None);
+	}
+
+	#[test]
+	fn test_perceptual_distance() {
+		assert_eq!(
+			perceptual_distance("0000000000000000", "000000000000000f"),
+			Some(4)
+		);
+		assert_eq!(
+			perceptual_distance("ffffffffffffffff", "0000000000000000"),
+			Some(64)
+		);
+		assert_eq!(perceptual_distance("not hex", "0000000000000000"), None);
+	}
+
+	#[test]
+	fn test_is_perceptually_hashable() {
+		assert!(is_perceptually_hashable("photo.JPG"));
+		assert!(is_perceptually_hashable("dir/image.png"));
+		assert!(!is_perceptually_hashable("notes.txt"));
+		assert!(!is_perceptually_hashable("no_extension"));
+	}
+}
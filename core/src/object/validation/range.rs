@@ -0,0 +1,74 @@
+use std::ops::Range;
+
+use super::hash::ChecksumAlgorithm;
+
+/// The checksum of only a byte range of a file, like the header of a disk image, for quick
+/// structural checks of big containers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeChecksum {
+	pub range: Range<u64>,
+	pub algorithm: ChecksumAlgorithm,
+	pub checksum: String,
+}
+
+impl RangeChecksum {
+	/// As stored in the library, `algorithm:start-end:checksum`
+	pub fn to_db(&self) -> String {
+		format!(
+			"{}:{}-{}:{}",
+			self.algorithm.as_str(),
+			self.range.start,
+			self.range.end,
+			self.checksum
+		)
+	}
+
+	pub fn from_db(stored: &str) -> Option<Self> {
+		let mut parts = stored.splitn(3, ':');
+		let algorithm = ChecksumAlgorithm::from_db(Some(parts.next()?))?;
+		let (start, end) = parts.next()?.split_once('-')?;
+		let checksum = parts.next()?;
+
+		Some(Self {
+			range: start.parse().ok()?..end.parse().ok()?,
+			algorithm,
+			checksum: checksum.to_string(),
+		})
+	}
+}
+
+/// Key of the checksum of a byte range of the file at `relative_path` in manifests, the path
+/// followed by `#start-end`
+pub fn range_key(relative_path: &str, range: &Range<u64>) -> String {
+	format!("{relative_path}#{}-{}", range.start, range.end)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_range_checksum_db_round_trip() {
+		let range_checksum = RangeChecksum {
+			range: 512..4096,
+			algorithm: ChecksumAlgorithm::Sha256,
+			checksum: "abcd".to_string(),
+		};
+		let stored = range_checksum.to_db();
+		assert_eq!(stored, "sha256:512-4096:abcd");
+		assert_eq!(RangeChecksum::from_db(&stored), Some(range_checksum));
+
+		assert_eq!(RangeChecksum::from_db("sha256:512:abcd"), None);
+		assert_eq!(RangeChecksum::from_db("md5:0-512:abcd"), None);
+		assert_eq!(RangeChecksum::from_db("abcd"), None);
+	}
+
+	#[test]
+	fn test_range_key() {
+		assert_eq!(
+			range_key("disk/image.iso", &(0..512)),
+			"disk/image.iso#0-512"
+		);
+	}
+}
@@ -0,0 +1,132 @@
+//! On copy-on-write file systems, files sharing all of their extents, like the copies made by
+//! `cp --reflink`, have the same bytes, so only one of them needs to be hashed.
+
+use std::{collections::HashMap, path::Path};
+
+/// Physical layout of a file's contents, files with the same layout have the same contents
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtentLayout {
+	device: u64,
+	size: u64,
+	/// logical offset, physical offset and length of each extent
+	extents: Vec<(u64, u64, u64)>,
+}
+
+/// The layout of a file sharing all of its extents with other files, `None` when it doesn't or
+/// the file system can't tell us, those files must be hashed on their own. Only supported on
+/// Linux, through FIEMAP.
+#[cfg_attr(not(target_os = "linux"), allow(unused_variables))]
+pub async fn shared_extent_layout(path: &Path) -> Option<ExtentLayout> {
+	#[cfg(target_os = "linux")]
+	{
+		let path = path.to_path_buf();
+		tokio::task::spawn_blocking(move || linux::shared_extent_layout(&path))
+			.await
+			.ok()
+			.flatten()
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	{
+		None
+	}
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+	use super::ExtentLayout;
+
+	use std::{fs, os::unix::fs::MetadataExt, path::Path};
+
+	use fiemap::FiemapExtentFlags;
+
+	/// Heavily fragmented files are quicker to hash than to compare
+	const MAX_EXTENTS: usize = 4096;
+
+	pub(super) fn shared_extent_layout(path: &Path) -> Option<ExtentLayout> {
+		let metadata = fs::metadata(path).ok()?;
+
+		// the contents of these extents aren't at a known physical location, or aren't theirs alone
+		let unreliable = FiemapExtentFlags::UNKNOWN
+			| FiemapExtentFlags::DELALLOC
+			| FiemapExtentFlags::ENCODED
+			| FiemapExtentFlags::DATA_ENCRYPTED
+			| FiemapExtentFlags::NOT_ALIGNED
+			| FiemapExtentFlags::DATA_INLINE
+			| FiemapExtentFlags::DATA_TAIL;
+
+		let mut extents = vec![];
+		for extent in fiemap::fiemap(path).ok()? {
+			let extent = extent.ok()?;
+			if !extent.fe_flags.contains(FiemapExtentFlags::SHARED)
+				|| extent.fe_flags.intersects(unreliable)
+				|| extents.len() == MAX_EXTENTS
+			{
+				return None;
+			}
+
+			extents.push((extent.fe_logical, extent.fe_physical, extent.fe_length));
+		}
+
+		(!extents.is_empty()).then_some(ExtentLayout {
+			device: metadata.dev(),
+			size: metadata.len(),
+			extents,
+		})
+	}
+}
+
+/// Groups items with the same layout, the first of each group is kept and the others are its
+/// copies. Items without a layout are kept on their own.
+pub(super) fn group_reflinks<T>(items: Vec<(T, Option<ExtentLayout>)>) -> Vec<(T, Vec<T>)> {
+	let mut groups = Vec::<(T, Vec<T>)>::with_capacity(items.len());
+	let mut by_layout = HashMap::new();
+
+	for (item, layout) in items {
+		match layout {
+			Some(layout) => match by_layout.get(&layout) {
+				Some(&index) => groups[index].1.push(item),
+				None => {
+					by_layout.insert(layout, groups.len());
+					groups.push((item, vec![]));
+				}
+			},
+			None => groups.push((item, vec![])),
+		}
+	}
+
+	groups
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_group_reflinks() {
+		let layout = |device, physical| ExtentLayout {
+			device,
+			size: 10,
+			extents: vec![(0, physical, 4096)],
+		};
+
+		assert_eq!(
+			group_reflinks(vec![
+				("a", Some(layout(1, 4096))),
+				("b", None),
+				("c", Some(layout(1, 4096))),
+				// same physical offset on another device is another extent
+				("d", Some(layout(2, 4096))),
+				("e", Some(layout(1, 8192))),
+				("f", Some(layout(1, 4096))),
+			]),
+			vec![
+				("a", vec!["c", "f"]),
+				("b", vec![]),
+				("d", vec![]),
+				("e", vec![]),
+			]
+		);
+	}
+}
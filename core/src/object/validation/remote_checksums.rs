@@ -0,0 +1,149 @@
+use std::{fmt, time::Duration};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::StatusCode;
+use tokio::io;
+use url::Url;
+
+use super::hash::ChecksumAlgorithm;
+
+/// A remote that doesn't answer quickly is slower than reading the file would be
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Checksums an object store keeps for the files of a location mounted from it, like the SHA-256
+/// additional checksums of S3 behind an rclone mount, so audits can compare them with a manifest
+/// instead of downloading the files.
+#[async_trait::async_trait]
+pub trait RemoteChecksums: Send + Sync {
+	/// The checksum of the file at `relative_path` in the location, `None` when the remote has
+	/// none computed with `algorithm`. ETags aren't, as they're MD5 digests or composites of the
+	/// parts of multipart uploads.
+	async fn checksum(
+		&self,
+		relative_path: &str,
+		algorithm: ChecksumAlgorithm,
+	) -> Result<Option<String>, io::Error>;
+}
+
+impl fmt::Debug for dyn RemoteChecksums {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("RemoteChecksums")
+	}
+}
+
+/// Asks S3, or a store speaking its API, for the SHA-256 checksum of each object with a `HEAD`
+/// request to the object's path under `base_url`, the bucket the location is mounted from along
+/// with the prefix of the location. Buckets that need signed requests can be reached through a
+/// signing proxy.
+#[derive(Debug, Clone)]
+pub struct S3RemoteChecksums {
+	base_url: Url,
+	client: reqwest::Client,
+}
+
+impl S3RemoteChecksums {
+	pub fn new(base_url: Url) -> Self {
+		Self {
+			base_url,
+			client: reqwest::Client::new(),
+		}
+	}
+
+	fn object_url(&self, relative_path: &str) -> Result<Url, io::Error> {
+		let mut url = self.base_url.clone();
+		url.path_segments_mut()
+			.map_err(|()| {
+				io::Error::new(
+					io::ErrorKind::InvalidInput,
+					format!("{} can't have objects under it", self.base_url),
+				)
+			})?
+			.pop_if_empty()
+			.extend(relative_path.split('/'));
+
+		Ok(url)
+	}
+}
+
+#[async_trait::async_trait]
+impl RemoteChecksums for S3RemoteChecksums {
+	async fn checksum(
+		&self,
+		relative_path: &str,
+		algorithm: ChecksumAlgorithm,
+	) -> Result<Option<String>, io::Error> {
+		// S3 doesn't compute blake3
+		if algorithm != ChecksumAlgorithm::Sha256 {
+			return Ok(None);
+		}
+
+		let response = self
+			.client
+			.head(self.object_url(relative_path)?)
+			.header("x-amz-checksum-mode", "ENABLED")
+			.timeout(REQUEST_TIMEOUT)
+			.send()
+			.await
+			.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+
+		match response.status() {
+			status if status.is_success() => Ok(response
+				.headers()
+				.get("x-amz-checksum-sha256")
+				.and_then(|checksum| checksum.to_str().ok())
+				.and_then(sha256_from_header)),
+			StatusCode::NOT_FOUND => Ok(None),
+			status => Err(io::Error::new(
+				io::ErrorKind::Other,
+				format!("{relative_path} answered with {status}"),
+			)),
+		}
+	}
+}
+
+/// S3 sends checksums base64 encoded, the ones of multipart uploads are a checksum of the parts'
+/// with their count appended, like `<base64>-3`, which isn't the checksum of the file
+fn sha256_from_header(checksum: &str) -> Option<String> {
+	STANDARD
+		.decode(checksum)
+		.ok()
+		.filter(|digest| digest.len() == 32)
+		.map(hex::encode)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sha256_from_header() {
+		// sha256 of "hello world"
+		assert_eq!(
+			sha256_from_header("uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=").as_deref(),
+			Some("b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9")
+		);
+		assert_eq!(
+			sha256_from_header("uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=-3"),
+			None
+		);
+		assert_eq!(sha256_from_header("not base64"), None);
+		assert_eq!(sha256_from_header("aGVsbG8="), None);
+	}
+
+	#[test]
+	fn test_object_url() {
+		let remote =
+			S3RemoteChecksums::new(Url::parse("https://bucket.s3.amazonaws.com/photos/").unwrap());
+		assert_eq!(
+			remote.object_url("2023/trip #1.jpg").unwrap().as_str(),
+			"https://bucket.s3.amazonaws.com/photos/2023/trip%20%231.jpg"
+		);
+
+		let remote = S3RemoteChecksums::new(Url::parse("https://bucket.s3.amazonaws.com").unwrap());
+		assert_eq!(
+			remote.object_url("a.txt").unwrap().as_str(),
+			"https://bucket.s3.amazonaws.com/a.txt"
+		);
+	}
+}
@@ -0,0 +1,501 @@
+use crate::prisma::location;
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	ops::Range,
+	path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
+use sd_file_ext::kind::ObjectKind;
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use uuid::Uuid;
+
+use super::{CasDisagreement, ExtensionMismatch, MirrorDivergence, Par2Recoverability};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum FileValidationOutcome {
+	/// A checksum was computed and stored for the file
+	Checksummed,
+	/// The checksum was imported from another tool instead of being computed
+	Seeded { source: String },
+	/// The file couldn't be validated
+	Failed { reason: String },
+	/// The path only differs in case from `with`, and both are the same file on this case
+	/// insensitive file system, so only `with` was validated
+	CaseCollision { with: String },
+	/// The file was gone from disk, so its row was removed from the library
+	Pruned,
+	/// The file's size changed while it was read, so nothing was stored for it
+	InFlux,
+	/// The file matches the checksum of a signed manifest, nothing was stored for it
+	Verified,
+	/// The checksum the remote the file is mounted from keeps for it matches the manifest, so it
+	/// wasn't downloaded
+	VerifiedRemotely,
+	/// The file doesn't match the manifest, but it still has the contents the user acknowledged
+	/// as a legitimate change after the manifest was signed
+	Acknowledged {
+		at: DateTime<Utc>,
+		by: Option<String>,
+	},
+	/// The file shares all of its extents with `of`, so it was given the checksum of `of` without
+	/// being read
+	ReflinkCopy { of: String },
+	/// The file is also reachable through the overlapping location `location_id`, at `path`, so it
+	/// was given the checksum already computed there without being read again
+	CrossLocationCopy {
+		location_id: location::id::Type,
+		path: String,
+	},
+}
+
+impl FileValidationOutcome {
+	pub fn is_failure(&self) -> bool {
+		matches!(self, Self::Failed { .. } | Self::CaseCollision { .. })
+	}
+}
+
+/// Outcome of a validator run, keyed by each file's path relative to the location
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ObjectValidatorReport {
+	pub location_id: location::id::Type,
+	pub sub_path: Option<PathBuf>,
+	pub files: BTreeMap<String, FileValidationOutcome>,
+	/// Set when only a random sample of the files was validated
+	#[serde(default)]
+	pub sample: Option<ReportSample>,
+	/// Set when only files last modified within this window were validated
+	#[serde(default)]
+	pub modified_within: Option<Range<DateTime<Utc>>>,
+	/// Set when only files of objects of these kinds were validated
+	#[serde(default)]
+	pub object_kinds: Option<Vec<ObjectKind>>,
+	/// Set when only files of favorite objects were validated, to how many of them there are in
+	/// the scope, checksummed already or not
+	#[serde(default)]
+	pub favorites: Option<usize>,
+	/// Objects merged into others after their files were confirmed duplicates
+	#[serde(default)]
+	pub merged_objects: usize,
+	/// Empty files left out, they all have the same checksum
+	#[serde(default)]
+	pub skipped_empty: usize,
+	/// Files left out as the user excluded them from validation
+	#[serde(default)]
+	pub excluded: usize,
+	/// Files given the checksum of the same file in an overlapping location instead of being hashed
+	#[serde(default)]
+	pub cross_location_duplicates: usize,
+	/// Files whose reads had to be shortened to this many bytes for lack of memory, a sign the
+	/// device is short on resources
+	#[serde(default)]
+	pub memory_fallbacks: BTreeMap<String, usize>,
+	/// The `(offset, len)` byte ranges found corrupted in files that don't match the manifest,
+	/// for the ones its chunk manifest lists
+	#[serde(default)]
+	pub corrupt_ranges: BTreeMap<String, Vec<(u64, u64)>>,
+	/// Whether the PAR2 set of each failed file that's in one can repair it, when looked for
+	#[serde(default)]
+	pub par2_recoverability: BTreeMap<String, Par2Recoverability>,
+	/// Files whose copy under the mirror root has the same contents, when there's one
+	#[serde(default)]
+	pub mirror_matched: usize,
+	/// Files whose copy under the mirror root disagrees with the location's
+	#[serde(default)]
+	pub mirror_divergences: BTreeMap<String, MirrorDivergence>,
+	/// Files whose checksums are only stored on this node, as syncing them failed even when
+	/// retried at the end of the job. They stay flagged on their rows and are retried at the end
+	/// of the next validation, see
+	/// [`LibraryChecksumStore::sync_deferred`](super::LibraryChecksumStore::sync_deferred)
+	#[serde(default)]
+	pub sync_deferred: usize,
+	/// How many times each file could be read over the job, when failed reads were retried
+	#[serde(default)]
+	pub max_file_attempts: Option<u32>,
+	/// Files that still couldn't be read after all of their attempts
+	#[serde(default)]
+	pub attempts_exhausted: BTreeSet<String>,
+	/// Files whose size kept changing while they were read, left for a later run
+	#[serde(default)]
+	pub in_flux: BTreeSet<String>,
+	/// Files whose size on disk isn't the one the indexer recorded, the one on disk being the one
+	/// used for the validation
+	#[serde(default)]
+	pub size_mismatches: BTreeMap<String, SizeMismatch>,
+	/// Files whose checksum another writer changed while they were validated
+	#[serde(default)]
+	pub checksum_conflicts: BTreeMap<String, ChecksumConflict>,
+	/// Files whose contents are of another type than their extension claims, when looked for
+	#[serde(default)]
+	pub extension_mismatches: BTreeMap<String, ExtensionMismatch>,
+	/// Files whose `cas_id` and checksum disagree about them being the same, when cross-checked
+	#[serde(default)]
+	pub cas_disagreements: Vec<CasDisagreement>,
+	/// Directory the signed attestation of the audit was written to, when every file passed it
+	#[serde(default)]
+	pub attested_to: Option<PathBuf>,
+	/// Aggregate checksum of the whole location once done, if all of its files had a checksum
+	#[serde(default)]
+	pub aggregate_checksum: Option<String>,
+	/// Tree checksums of the topmost directories that got one, when computed, by path relative
+	/// to the location, `""` being the location itself
+	#[serde(default)]
+	pub tree_checksum_roots: BTreeMap<String, String>,
+	/// Failures grouped by the directories holding them, to spot a whole directory going bad,
+	/// see [`ObjectValidatorReport::group_failures_by_directory`]
+	#[serde(default)]
+	pub failures_by_directory: BTreeMap<String, DirectoryFailures>,
+}
+
+/// Sizes of a file that disagree, see [`size_mismatch`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeMismatch {
+	/// the size the indexer recorded
+	pub stored: u64,
+	/// the size on disk
+	pub actual: u64,
+}
+
+/// A checksum another writer, like the location watcher, stored for a file between the validator
+/// reading the file and storing its own
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumConflict {
+	pub ours: Option<String>,
+	pub theirs: Option<String>,
+	/// whether ours replaced theirs, as the job's policy asked for
+	pub overwritten: bool,
+}
+
+/// How the size recorded for a file disagrees with the one it has on disk, `None` when they agree
+/// or either is unknown
+pub fn size_mismatch(stored: Option<u64>, actual: Option<u64>) -> Option<SizeMismatch> {
+	match (stored, actual) {
+		(Some(stored), Some(actual)) if stored != actual => Some(SizeMismatch { stored, actual }),
+		_ => None,
+	}
+}
+
+/// Failures among the files within a directory, the ones in its subdirectories included
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DirectoryFailures {
+	pub failed: usize,
+	/// Files checked, so directories where every one of them failed stand out
+	pub files: usize,
+}
+
+/// Running counts of a validator job over all of its sessions, kept in its state so a job resumed
+/// after a restart keeps adding to them instead of starting over
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ValidationTotals {
+	/// Files found healthy, checksummed or verified
+	pub validated: u64,
+	pub failed: u64,
+	/// Files left out as empty, gone from disk or changing while they were read
+	pub skipped: u64,
+	/// Bytes of the files read
+	pub bytes: u64,
+}
+
+impl ValidationTotals {
+	pub fn record(&mut self, outcome: &FileValidationOutcome) {
+		match outcome {
+			outcome if outcome.is_failure() => self.failed += 1,
+			FileValidationOutcome::Pruned | FileValidationOutcome::InFlux => self.skipped += 1,
+			_ => self.validated += 1,
+		}
+	}
+
+	/// Adds the counts of a step once all of its files are done
+	pub fn add(&mut self, step: Self) {
+		self.validated += step.validated;
+		self.failed += step.failed;
+		self.skipped += step.skipped;
+		self.bytes += step.bytes;
+	}
+
+	/// As shown along the job's progress
+	pub fn summary(&self) -> String {
+		format!(
+			"{} validated, {} failed, {} skipped, {} bytes read",
+			self.validated, self.failed, self.skipped, self.bytes
+		)
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ReportSample {
+	/// How many files could have been validated
+	pub population: usize,
+	/// Failure rate of the sample, extrapolated to the whole population
+	pub estimated_failures: f64,
+}
+
+impl ObjectValidatorReport {
+	pub fn failures_count(&self) -> usize {
+		self.files
+			.values()
+			.filter(|outcome| outcome.is_failure())
+			.count()
+	}
+
+	/// Groups the files by their directory, their `materialized_path`, counting them in every
+	/// directory above it too, by path relative to the location, `""` being the location itself.
+	/// Only directories with failures are kept.
+	pub fn group_failures_by_directory(&self) -> BTreeMap<String, DirectoryFailures> {
+		let mut directories = BTreeMap::<_, DirectoryFailures>::new();
+		for (relative_path, outcome) in &self.files {
+			let failed = outcome.is_failure();
+			let ancestors = relative_path
+				.match_indices('/')
+				.map(|(index, _)| &relative_path[..index]);
+
+			for directory in [""].into_iter().chain(ancestors) {
+				let failures = directories.entry(directory).or_default();
+				failures.files += 1;
+				failures.failed += usize::from(failed);
+			}
+		}
+
+		directories
+			.into_iter()
+			.filter(|(_, failures)| failures.failed > 0)
+			.map(|(directory, failures)| (directory.to_string(), failures))
+			.collect()
+	}
+
+	pub fn pruned_count(&self) -> usize {
+		self.files
+			.values()
+			.filter(|outcome| matches!(outcome, FileValidationOutcome::Pruned))
+			.count()
+	}
+
+	pub fn failure_rate(&self) -> f64 {
+		if self.files.is_empty() {
+			0.0
+		} else {
+			self.failures_count() as f64 / self.files.len() as f64
+		}
+	}
+
+	pub fn extrapolate_sample(&mut self) {
+		let failure_rate = self.failure_rate();
+		if let Some(sample) = &mut self.sample {
+			sample.estimated_failures = failure_rate * sample.population as f64;
+		}
+	}
+}
+
+/// Summary sent on the event bus once a validator job finishes.
+/// Counts are `u32` as rspc doesn't support bigints.
+#[derive(Debug, Clone, Serialize, Type)]
+pub struct ValidationCompletedEvent {
+	pub job_id: Uuid,
+	pub library_id: Uuid,
+	pub location_id: location::id::Type,
+	pub sub_path: Option<PathBuf>,
+	pub files_checked: u32,
+	pub failures: u32,
+	pub pruned: u32,
+	pub duration_ms: u32,
+}
+
+impl ValidationCompletedEvent {
+	pub fn new(
+		job_id: Uuid,
+		library_id: Uuid,
+		report: &ObjectValidatorReport,
+		duration: chrono::Duration,
+	) -> Self {
+		Self {
+			job_id,
+			library_id,
+			location_id: report.location_id,
+			sub_path: report.sub_path.clone(),
+			files_checked: report.files.len() as u32,
+			failures: report.failures_count() as u32,
+			pruned: report.pruned_count() as u32,
+			duration_ms: duration.num_milliseconds().clamp(0, u32::MAX as i64) as u32,
+		}
+	}
+}
+
+/// Changes between two validator runs over the same location
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq)]
+pub struct ReportDiff {
+	/// Files failing now that didn't fail on the previous run, including new files that failed
+	pub newly_failed: Vec<String>,
+	/// Files that failed on the previous run and are fine now
+	pub recovered: Vec<String>,
+	/// Files that weren't checked on the previous run
+	pub appeared: Vec<String>,
+	/// Files that were checked on the previous run but not on the current one
+	pub disappeared: Vec<String>,
+}
+
+pub fn diff_reports(prev: &ObjectValidatorReport, current: &ObjectValidatorReport) -> ReportDiff {
+	let mut diff = ReportDiff::default();
+
+	for (path, outcome) in &current.files {
+		match prev.files.get(path) {
+			Some(prev_outcome) => match (prev_outcome.is_failure(), outcome.is_failure()) {
+				(false, true) => diff.newly_failed.push(path.clone()),
+				(true, false) => diff.recovered.push(path.clone()),
+				_ => {}
+			},
+			None => {
+				if outcome.is_failure() {
+					diff.newly_failed.push(path.clone());
+				}
+				diff.appeared.push(path.clone());
+			}
+		}
+	}
+
+	diff.disappeared.extend(
+		prev.files
+			.keys()
+			.filter(|path| !current.files.contains_key(*path))
+			.cloned(),
+	);
+
+	diff
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	fn report(files: &[(&str, bool)]) -> ObjectValidatorReport {
+		ObjectValidatorReport {
+			files: files
+				.iter()
+				.map(|(path, failed)| {
+					(
+						path.to_string(),
+						if *failed {
+							FileValidationOutcome::Failed {
+								reason: "boom".to_string(),
+							}
+						} else {
+							FileValidationOutcome::Checksummed
+						},
+					)
+				})
+				.collect(),
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn test_diff_reports() {
+		let prev = report(&[
+			("a.txt", false),
+			("b.txt", true),
+			("c.txt", false),
+			("gone.txt", true),
+		]);
+		let current = report(&[
+			("a.txt", true),
+			("b.txt", false),
+			("c.txt", false),
+			("new.txt", false),
+			("new_broken.txt", true),
+		]);
+
+		assert_eq!(
+			diff_reports(&prev, &current),
+			ReportDiff {
+				newly_failed: vec!["a.txt".to_string(), "new_broken.txt".to_string()],
+				recovered: vec!["b.txt".to_string()],
+				appeared: vec!["new.txt".to_string(), "new_broken.txt".to_string()],
+				disappeared: vec!["gone.txt".to_string()],
+			}
+		);
+	}
+
+	#[test]
+	fn test_record_totals() {
+		let mut totals = ValidationTotals::default();
+		for outcome in [
+			FileValidationOutcome::Checksummed,
+			FileValidationOutcome::Verified,
+			FileValidationOutcome::ReflinkCopy {
+				of: "a.txt".to_string(),
+			},
+			FileValidationOutcome::Failed {
+				reason: "boom".to_string(),
+			},
+			FileValidationOutcome::CaseCollision {
+				with: "A.txt".to_string(),
+			},
+			FileValidationOutcome::Pruned,
+			FileValidationOutcome::InFlux,
+		] {
+			totals.record(&outcome);
+		}
+
+		assert_eq!(
+			totals,
+			ValidationTotals {
+				validated: 3,
+				failed: 2,
+				skipped: 2,
+				bytes: 0,
+			}
+		);
+	}
+
+	#[test]
+	fn test_add_totals() {
+		let mut totals = ValidationTotals {
+			validated: 1,
+			failed: 1,
+			skipped: 0,
+			bytes: 1024,
+		};
+		totals.add(ValidationTotals {
+			validated: 2,
+			skipped: 1,
+			bytes: 512,
+			..Default::default()
+		});
+
+		assert_eq!(
+			totals.summary(),
+			"3 validated, 1 failed, 1 skipped, 1536 bytes read"
+		);
+	}
+
+	#[test]
+	fn test_group_failures_by_directory() {
+		let grouped = report(&[
+			("a.txt", false),
+			("old-backup/b.txt", true),
+			("old-backup/photos/c.jpg", true),
+			("old-backup/photos/d.jpg", true),
+			("photos/e.jpg", false),
+			("photos/f.jpg", true),
+			("videos/g.mp4", false),
+		])
+		.group_failures_by_directory();
+
+		assert_eq!(
+			grouped,
+			[
+				("", (4, 7)),
+				("old-backup", (3, 3)),
+				("old-backup/photos", (2, 2)),
+				("photos", (1, 2)),
+			]
+			.into_iter()
+			.map(|(directory, (failed, files))| {
+				(directory.to_string(), DirectoryFailures { failed, files })
+			})
+			.collect()
+		);
+	}
+}
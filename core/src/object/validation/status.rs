@@ -0,0 +1,91 @@
+use crate::{
+	location::file_path_helper::file_path_for_checksum_status,
+	prisma::{file_path, PrismaClient},
+};
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, FixedOffset};
+use prisma_client_rust::QueryError;
+use serde::Serialize;
+use specta::Type;
+
+/// Whether a file's checksum can still be trusted, for badges in the UI
+#[derive(Serialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumStatus {
+	/// No checksum was computed for the file yet
+	Missing,
+	/// The file was modified after its checksum was computed
+	Stale,
+	Current,
+}
+
+impl ChecksumStatus {
+	/// Checksums written before we started tracking when, are considered current as the watcher
+	/// keeps them updated when files change
+	pub fn new(
+		integrity_checksum: Option<&str>,
+		date_checksummed: Option<DateTime<FixedOffset>>,
+		date_modified: Option<DateTime<FixedOffset>>,
+	) -> Self {
+		match (integrity_checksum, date_checksummed, date_modified) {
+			(None, _, _) => Self::Missing,
+			(Some(_), Some(checksummed), Some(modified)) if modified > checksummed => Self::Stale,
+			(Some(_), _, _) => Self::Current,
+		}
+	}
+}
+
+pub async fn checksum_statuses(
+	db: &PrismaClient,
+	file_path_ids: Vec<file_path::id::Type>,
+) -> Result<HashMap<file_path::id::Type, ChecksumStatus>, QueryError> {
+	Ok(db
+		.file_path()
+		.find_many(vec![file_path::id::in_vec(file_path_ids)])
+		.select(file_path_for_checksum_status::select())
+		.exec()
+		.await?
+		.into_iter()
+		.map(|file_path| {
+			(
+				file_path.id,
+				ChecksumStatus::new(
+					file_path.integrity_checksum.as_deref(),
+					file_path.date_checksummed,
+					file_path.date_modified,
+				),
+			)
+		})
+		.collect())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_checksum_status() {
+		let date = |hour: u32| {
+			Some(DateTime::parse_from_rfc3339(&format!("2023-06-28T{hour:02}:00:00Z")).unwrap())
+		};
+
+		assert_eq!(
+			ChecksumStatus::new(None, None, date(1)),
+			ChecksumStatus::Missing
+		);
+		assert_eq!(
+			ChecksumStatus::new(Some("abc"), date(1), date(2)),
+			ChecksumStatus::Stale
+		);
+		assert_eq!(
+			ChecksumStatus::new(Some("abc"), date(2), date(1)),
+			ChecksumStatus::Current
+		);
+		assert_eq!(
+			ChecksumStatus::new(Some("abc"), None, date(1)),
+			ChecksumStatus::Current
+		);
+	}
+}
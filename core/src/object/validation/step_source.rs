@@ -0,0 +1,340 @@
+use crate::{
+	location::file_path_helper::{file_path_for_object_validator, IsolatedFilePathData},
+	prisma::{file_path, location, object, PrismaClient},
+	util::db::chain_optional_iter,
+};
+
+use std::{ops::Range, path::Path};
+
+use chrono::{DateTime, Utc};
+use prisma_client_rust::operator::{and, or};
+use sd_file_ext::kind::ObjectKind;
+use tokio::{fs, io};
+
+use super::{
+	hash::{
+		file_checksum_and_head, file_checksum_with, file_checksums_migrating, range_checksums,
+		ChecksumAlgorithm, ReadOptions,
+	},
+	media::media_content_checksum,
+	ValidatorError,
+};
+
+/// Which of a location's files are listed, all of them when left to the defaults
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileScope<'a> {
+	pub sub_path: Option<&'a IsolatedFilePathData<'a>>,
+	/// only the files with these pub ids
+	pub pub_ids: Option<&'a [Vec<u8>]>,
+	/// files without a modification date can't be told to be in the window
+	pub modified_within: Option<&'a Range<DateTime<Utc>>>,
+	/// files not identified yet have no object, so no kind
+	pub object_kinds: Option<&'a [ObjectKind]>,
+	/// only files whose object the user marked as a favorite
+	pub favorites_only: bool,
+	/// only files with a checksum computed with this algorithm
+	pub checksummed_with: Option<ChecksumAlgorithm>,
+	/// the checksums are the ones also covering the files' other streams, see
+	/// [`ReadOptions::include_alt_streams`]
+	pub include_alt_streams: bool,
+	/// list the files the user excluded from validation instead of the others
+	pub excluded: bool,
+}
+
+/// Where the validator gets the files to validate and their checksums from.
+/// The job uses [`LibraryStepSource`], tests can provide a synthetic file set instead.
+#[async_trait::async_trait]
+pub trait StepSource: Send + Sync {
+	async fn file_paths(
+		&self,
+		location_id: location::id::Type,
+		scope: FileScope<'_>,
+		algorithm: ChecksumAlgorithm,
+		include_missing_content_checksum: bool,
+	) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError>;
+
+	/// Every file, checksummed or not, to audit them against a manifest
+	async fn all_file_paths(
+		&self,
+		location_id: location::id::Type,
+		scope: FileScope<'_>,
+	) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError>;
+
+	async fn file_checksum(&self, path: &Path, options: ReadOptions) -> Result<String, io::Error>;
+
+	/// The checksum along with the file's first `head_len` bytes, see [`file_checksum_and_head`]
+	async fn file_checksum_and_head(
+		&self,
+		path: &Path,
+		options: ReadOptions,
+		head_len: usize,
+	) -> Result<(String, Vec<u8>), io::Error>;
+
+	/// The checksum along with the one with `previous`, see [`file_checksums_migrating`]
+	async fn file_checksums_migrating(
+		&self,
+		path: &Path,
+		options: ReadOptions,
+		previous: ChecksumAlgorithm,
+	) -> Result<(String, String), io::Error>;
+
+	async fn content_checksum(&self, path: &Path) -> Result<String, io::Error>;
+
+	/// Size of the file on disk, which may not be the one the indexer recorded
+	async fn file_len(&self, path: &Path) -> Result<u64, io::Error>;
+
+	/// Checksums of the `(offset, len)` byte ranges of a file, see [`range_checksums`]
+	async fn range_checksums(
+		&self,
+		path: &Path,
+		options: ReadOptions,
+		ranges: &[(u64, u64)],
+	) -> Result<Vec<String>, io::Error>;
+}
+
+/// Fetches file paths missing a checksum for the given algorithm from the library database and
+/// hashes them from disk
+pub struct LibraryStepSource<'a>(pub &'a PrismaClient);
+
+#[async_trait::async_trait]
+impl StepSource for LibraryStepSource<'_> {
+	async fn file_paths(
+		&self,
+		location_id: location::id::Type,
+		scope: FileScope<'_>,
+		algorithm: ChecksumAlgorithm,
+		include_missing_content_checksum: bool,
+	) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError> {
+		self.find_file_paths(
+			location_id,
+			scope,
+			Some(missing_checksum(
+				algorithm,
+				scope.include_alt_streams,
+				include_missing_content_checksum,
+			)),
+		)
+		.await
+	}
+
+	async fn all_file_paths(
+		&self,
+		location_id: location::id::Type,
+		scope: FileScope<'_>,
+	) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError> {
+		self.find_file_paths(location_id, scope, None).await
+	}
+
+	async fn file_checksum(&self, path: &Path, options: ReadOptions) -> Result<String, io::Error> {
+		file_checksum_with(path, options).await
+	}
+
+	async fn file_checksum_and_head(
+		&self,
+		path: &Path,
+		options: ReadOptions,
+		head_len: usize,
+	) -> Result<(String, Vec<u8>), io::Error> {
+		file_checksum_and_head(path, options, head_len).await
+	}
+
+	async fn file_checksums_migrating(
+		&self,
+		path: &Path,
+		options: ReadOptions,
+		previous: ChecksumAlgorithm,
+	) -> Result<(String, String), io::Error> {
+		file_checksums_migrating(path, options, previous).await
+	}
+
+	async fn content_checksum(&self, path: &Path) -> Result<String, io::Error> {
+		media_content_checksum(path).await
+	}
+
+	async fn file_len(&self, path: &Path) -> Result<u64, io::Error> {
+		fs::metadata(path).await.map(|metadata| metadata.len())
+	}
+
+	async fn range_checksums(
+		&self,
+		path: &Path,
+		options: ReadOptions,
+		ranges: &[(u64, u64)],
+	) -> Result<Vec<String>, io::Error> {
+		range_checksums(path, options, ranges).await
+	}
+}
+
+impl LibraryStepSource<'_> {
+	/// How many files in `scope` the user excluded from validation
+	pub async fn count_excluded(
+		&self,
+		location_id: location::id::Type,
+		scope: FileScope<'_>,
+	) -> Result<usize, ValidatorError> {
+		Ok(self
+			.0
+			.file_path()
+			.count(scope_filters(
+				location_id,
+				FileScope {
+					excluded: true,
+					..scope
+				},
+				None,
+			))
+			.exec()
+			.await? as usize)
+	}
+
+	/// The files in `scope` the user excluded from validation
+	pub async fn excluded_file_paths(
+		&self,
+		location_id: location::id::Type,
+		scope: FileScope<'_>,
+	) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError> {
+		self.find_file_paths(
+			location_id,
+			FileScope {
+				excluded: true,
+				..scope
+			},
+			None,
+		)
+		.await
+	}
+
+	async fn find_file_paths(
+		&self,
+		location_id: location::id::Type,
+		scope: FileScope<'_>,
+		filter: Option<file_path::WhereParam>,
+	) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError> {
+		self.0
+			.file_path()
+			.find_many(scope_filters(location_id, scope, filter))
+			.select(file_path_for_object_validator::select())
+			.exec()
+			.await
+			.map_err(Into::into)
+	}
+}
+
+/// Matches the files of the location in `scope` that also match `filter`
+pub(super) fn scope_filters(
+	location_id: location::id::Type,
+	scope: FileScope<'_>,
+	filter: Option<file_path::WhereParam>,
+) -> Vec<file_path::WhereParam> {
+	let FileScope {
+		sub_path,
+		pub_ids,
+		modified_within,
+		object_kinds,
+		favorites_only,
+		checksummed_with,
+		include_alt_streams,
+		excluded,
+	} = scope;
+
+	chain_optional_iter(
+		[
+			file_path::location_id::equals(Some(location_id)),
+			file_path::is_dir::equals(Some(false)),
+			if excluded {
+				file_path::exclude_from_validation::equals(Some(true))
+			} else {
+				not_excluded()
+			},
+		],
+		[
+			filter,
+			sub_path.and_then(|iso_sub_path| {
+				iso_sub_path
+					.materialized_path_for_children()
+					.map(file_path::materialized_path::starts_with)
+			}),
+			pub_ids.map(|pub_ids| file_path::pub_id::in_vec(pub_ids.to_vec())),
+			modified_within.map(|window| file_path::date_modified::gte(window.start.into())),
+			modified_within.map(|window| file_path::date_modified::lt(window.end.into())),
+			object_kinds.map(|kinds| {
+				file_path::object::is(vec![object::kind::in_vec(
+					kinds.iter().map(|kind| *kind as i32).collect(),
+				)])
+			}),
+			favorites_only
+				.then(|| file_path::object::is(vec![object::favorite::equals(Some(true))])),
+			checksummed_with.map(|algorithm| {
+				and(vec![
+					file_path::integrity_checksum::not(None),
+					same_checksum_algorithm(algorithm, include_alt_streams),
+				])
+			}),
+		],
+	)
+}
+
+/// Matches the files the user didn't exclude from validation
+pub(super) fn not_excluded() -> file_path::WhereParam {
+	or(vec![
+		file_path::exclude_from_validation::equals(None),
+		file_path::exclude_from_validation::equals(Some(false)),
+	])
+}
+
+/// Matches the files the validator has to compute a checksum for with `algorithm`, those with
+/// one computed with `include_alt_streams` set otherwise included
+pub(super) fn missing_checksum(
+	algorithm: ChecksumAlgorithm,
+	include_alt_streams: bool,
+	include_missing_content_checksum: bool,
+) -> file_path::WhereParam {
+	let mut missing_checksum = vec![
+		file_path::integrity_checksum::equals(None),
+		other_checksum_algorithm(algorithm, include_alt_streams),
+	];
+	if include_missing_content_checksum {
+		missing_checksum.push(file_path::content_checksum::equals(None));
+	}
+
+	or(missing_checksum)
+}
+
+/// Matches checksums computed with an algorithm other than `algorithm`, or with
+/// `include_alt_streams` set otherwise
+fn other_checksum_algorithm(
+	algorithm: ChecksumAlgorithm,
+	include_alt_streams: bool,
+) -> file_path::WhereParam {
+	let db_name = algorithm.db_name(include_alt_streams);
+
+	match (algorithm, include_alt_streams) {
+		// checksums without an algorithm were all computed with blake3
+		(ChecksumAlgorithm::Blake3, false) => and(vec![
+			file_path::integrity_checksum_algorithm::not(None),
+			file_path::integrity_checksum_algorithm::not(Some(db_name)),
+		]),
+		_ => or(vec![
+			file_path::integrity_checksum_algorithm::equals(None),
+			file_path::integrity_checksum_algorithm::not(Some(db_name)),
+		]),
+	}
+}
+
+/// Matches checksums computed with `algorithm` and `include_alt_streams` as set
+pub(super) fn same_checksum_algorithm(
+	algorithm: ChecksumAlgorithm,
+	include_alt_streams: bool,
+) -> file_path::WhereParam {
+	let tagged = file_path::integrity_checksum_algorithm::equals(Some(
+		algorithm.db_name(include_alt_streams),
+	));
+
+	match (algorithm, include_alt_streams) {
+		(ChecksumAlgorithm::Blake3, false) => or(vec![
+			file_path::integrity_checksum_algorithm::equals(None),
+			tagged,
+		]),
+		_ => tagged,
+	}
+}
@@ -0,0 +1,124 @@
+//! Pacing of the sync operations the validator emits, so a big run in a synced library doesn't
+//! flood peers with an update for every file it checksums. The operations of a step go out as
+//! they're stored, and the job waits before its next step until they're paid for, so it keeps
+//! listening for pause and cancel meanwhile. Operations are never dropped nor merged, peers still
+//! get every one of them.
+
+use std::{
+	sync::{Mutex, PoisonError},
+	time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// A rate of events, like sync operations
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventsPerSecond(pub u32);
+
+/// Spaces out sync operations to average at most a rate, the first ones going out right away
+#[derive(Debug)]
+pub struct SyncPacer {
+	rate: EventsPerSecond,
+	/// when the operations emitted so far are all paid for
+	next_at: Mutex<Option<Instant>>,
+}
+
+impl SyncPacer {
+	pub fn new(rate: EventsPerSecond) -> Self {
+		Self {
+			rate,
+			next_at: Mutex::new(None),
+		}
+	}
+
+	pub fn rate(&self) -> EventsPerSecond {
+		self.rate
+	}
+
+	/// Counts `count` operations just emitted against the rate, without waiting
+	pub fn record(&self, count: usize) {
+		self.reserve(count, Instant::now());
+	}
+
+	/// How long to wait before emitting more operations, `None` once the ones recorded are paid
+	/// for
+	pub fn delay(&self) -> Option<Duration> {
+		self.delay_at(Instant::now())
+	}
+
+	fn delay_at(&self, now: Instant) -> Option<Duration> {
+		let next_at = *self.next_at.lock().unwrap_or_else(PoisonError::into_inner);
+
+		next_at
+			.map(|next_at| next_at.saturating_duration_since(now))
+			.filter(|delay| !delay.is_zero())
+	}
+
+	/// How long to wait at `now` before emitting `count` operations, the ones of concurrent callers
+	/// being queued after them. A rate of 0 is no limit.
+	fn reserve(&self, count: usize, now: Instant) -> Option<Duration> {
+		if self.rate.0 == 0 || count == 0 {
+			return None;
+		}
+
+		let mut next_at = self.next_at.lock().unwrap_or_else(PoisonError::into_inner);
+		let start = next_at.map_or(now, |next_at| next_at.max(now));
+		*next_at = Some(start + Duration::from_secs_f64(count as f64 / self.rate.0 as f64));
+
+		Some(start - now).filter(|delay| !delay.is_zero())
+	}
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_sync_pacer() {
+		let pacer = SyncPacer::new(EventsPerSecond(10));
+		let start = Instant::now();
+
+		assert_eq!(pacer.reserve(5, start), None);
+		// the 5 before take half a second
+		assert_eq!(
+			pacer.reserve(10, start + Duration::from_millis(100)),
+			Some(Duration::from_millis(400))
+		);
+		assert_eq!(
+			pacer.reserve(1, start + Duration::from_millis(200)),
+			Some(Duration::from_millis(1300))
+		);
+
+		// time spent idle isn't saved up for a burst later on
+		assert_eq!(pacer.reserve(1, start + Duration::from_secs(10)), None);
+		assert_eq!(
+			pacer.reserve(1, start + Duration::from_secs(10)),
+			Some(Duration::from_millis(100))
+		);
+
+		assert_eq!(
+			SyncPacer::new(EventsPerSecond(0)).reserve(1000, start),
+			None
+		);
+	}
+
+	#[test]
+	fn test_sync_pacer_delay() {
+		let pacer = SyncPacer::new(EventsPerSecond(10));
+		let start = Instant::now();
+		assert_eq!(pacer.delay_at(start), None);
+
+		pacer.reserve(20, start);
+		assert_eq!(
+			pacer.delay_at(start + Duration::from_millis(500)),
+			Some(Duration::from_millis(1500))
+		);
+		assert_eq!(pacer.delay_at(start + Duration::from_secs(2)), None);
+		assert_eq!(pacer.delay_at(start + Duration::from_secs(3)), None);
+
+		let unlimited = SyncPacer::new(EventsPerSecond(0));
+		unlimited.reserve(1000, start);
+		assert_eq!(unlimited.delay_at(start), None);
+	}
+}
@@ -0,0 +1,46 @@
+//! Counters and histograms of the validator, recorded through the `metrics` crate facade when the
+//! `metrics` feature is enabled, so the app can export them with the recorder of its choice.
+//! They're only labeled by algorithm, never by file or location, to keep cardinality bounded.
+
+use std::time::Duration;
+
+use super::hash::ChecksumAlgorithm;
+
+#[cfg(feature = "metrics")]
+use metrics::{counter, histogram, increment_counter};
+
+pub const FILES_VALIDATED: &str = "sd_validator_files_validated_total";
+pub const BYTES_HASHED: &str = "sd_validator_bytes_hashed_total";
+pub const FAILURES: &str = "sd_validator_failures_total";
+pub const HASHING_DURATION: &str = "sd_validator_hashing_duration_seconds";
+pub const THROUGHPUT: &str = "sd_validator_throughput_bytes_per_second";
+
+/// `size` is `None` for files the indexer didn't record the size of, they're still counted but
+/// left out of the bytes and throughput
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub fn record_file_hashed(algorithm: ChecksumAlgorithm, size: Option<u64>, duration: Duration) {
+	#[cfg(feature = "metrics")]
+	{
+		let algorithm = algorithm.as_str();
+
+		increment_counter!(FILES_VALIDATED, "algorithm" => algorithm);
+		histogram!(HASHING_DURATION, duration.as_secs_f64(), "algorithm" => algorithm);
+
+		if let Some(size) = size {
+			counter!(BYTES_HASHED, size, "algorithm" => algorithm);
+			if !duration.is_zero() {
+				histogram!(
+					THROUGHPUT,
+					size as f64 / duration.as_secs_f64(),
+					"algorithm" => algorithm
+				);
+			}
+		}
+	}
+}
+
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub fn record_failure(algorithm: ChecksumAlgorithm) {
+	#[cfg(feature = "metrics")]
+	increment_counter!(FAILURES, "algorithm" => algorithm.as_str());
+}
@@ -0,0 +1,343 @@
+//! Tree checksums of directories, a checksum over the names and checksums of everything within a
+//! directory, the tree checksums of its subdirectories included, Merkle style. Any file below a
+//! directory being added, removed, renamed or modified changes its tree checksum, so telling if a
+//! folder changed, or if folders on two devices hold the same files, takes a single comparison.
+//!
+//! They're computed from the checksums the validator stored, nothing is read, so a directory only
+//! gets one once every file below it was validated with the same algorithm. Files excluded from
+//! validation are left out of them.
+
+use crate::{
+	library::Library,
+	location::file_path_helper::{file_path_for_tree_checksum, IsolatedFilePathData},
+	prisma::{file_path, location, PrismaClient, SortOrder},
+	sync,
+};
+
+use std::{
+	cmp::Reverse,
+	collections::{BTreeMap, HashMap},
+};
+
+use prisma_client_rust::operator::or;
+use serde_json::json;
+
+use super::{
+	hash::{bytes_checksum, encode_checksum, ChecksumAlgorithm},
+	not_excluded, ValidatorError,
+};
+
+const PAGE_SIZE: i64 = 1000;
+
+/// An entry of a directory, as its tree checksum sees it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TreeEntry {
+	/// A file and its checksum, `None` if it has none computed with the algorithm of the tree
+	File(Option<String>),
+	Directory,
+}
+
+/// Tree checksums of every directory holding the `(materialized_path, full_name, entry)` entries,
+/// keyed by the materialized path of their children, `/` being the location itself. Directories
+/// with a file lacking a checksum below them have none.
+pub fn tree_checksums(
+	entries: impl IntoIterator<Item = (String, String, TreeEntry)>,
+	algorithm: ChecksumAlgorithm,
+) -> HashMap<String, Option<String>> {
+	let mut children = HashMap::<_, Vec<_>>::from([("/".to_string(), vec![])]);
+	for (materialized_path, name, entry) in entries {
+		if entry == TreeEntry::Directory {
+			children
+				.entry(format!("{materialized_path}{name}/"))
+				.or_default();
+		}
+		children
+			.entry(materialized_path)
+			.or_default()
+			.push((name, entry));
+	}
+
+	// deepest first, so subdirectories are done before their parents
+	let mut directories = children.into_iter().collect::<Vec<_>>();
+	directories.sort_by_key(|(path, _)| Reverse(path.matches('/').count()));
+
+	let mut checksums = HashMap::with_capacity(directories.len());
+	for (path, entries) in directories {
+		let checksum = directory_checksum(&path, entries, &checksums, algorithm);
+		checksums.insert(path, checksum);
+	}
+
+	checksums
+}
+
+/// Tree checksum of the directory whose children have the `path` materialized path, from the
+/// `(full_name, entry)` entries in it and the tree checksums of its subdirectories. `None` if one
+/// of them has none.
+fn directory_checksum(
+	path: &str,
+	mut entries: Vec<(String, TreeEntry)>,
+	checksums: &HashMap<String, Option<String>>,
+	algorithm: ChecksumAlgorithm,
+) -> Option<String> {
+	entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+	let mut content = vec![];
+	for (name, entry) in &entries {
+		let (kind, checksum) = match entry {
+			TreeEntry::File(checksum) => (b'f', checksum.as_ref()),
+			TreeEntry::Directory => (
+				b'd',
+				checksums
+					.get(&format!("{path}{name}/"))
+					.and_then(Option::as_ref),
+			),
+		};
+
+		// names can't hold nul bytes nor checksums newlines, so entries can't be read as others
+		content.push(kind);
+		content.extend_from_slice(name.as_bytes());
+		content.push(0);
+		content.extend_from_slice(checksum?.as_bytes());
+		content.push(b'\n');
+	}
+
+	Some(bytes_checksum(&content, algorithm))
+}
+
+/// The topmost directories with a tree checksum, those whose parent has none, by path relative to
+/// the location, `""` being the location itself
+pub fn tree_checksum_roots(
+	checksums: &HashMap<String, Option<String>>,
+) -> BTreeMap<String, String> {
+	checksums
+		.iter()
+		.filter_map(|(path, checksum)| {
+			let checksum = checksum.as_ref()?;
+			let parent = path
+				.trim_end_matches('/')
+				.rsplit_once('/')
+				.map(|(parent, _)| format!("{parent}/"));
+
+			match parent {
+				Some(parent) if checksums.get(&parent).map_or(false, Option::is_some) => None,
+				_ => Some((path.trim_matches('/').to_string(), checksum.clone())),
+			}
+		})
+		.collect()
+}
+
+/// Computes the tree checksum of every directory of the location with `algorithm` and stores the
+/// ones that changed on their rows, as `algorithm:checksum`. Returns the topmost ones computed,
+/// see [`tree_checksum_roots`], the location's own included, which has no row to be stored on.
+///
+/// Directories are done one at a time, deepest first, so only their rows and the entries of the
+/// directory at hand are loaded, not every file of the location.
+pub async fn update_tree_checksums(
+	library: &Library,
+	location_id: location::id::Type,
+	algorithm: ChecksumAlgorithm,
+) -> Result<BTreeMap<String, String>, ValidatorError> {
+	let Library { db, sync, .. } = library;
+
+	// the pub_id of each directory and its stored tree checksum, by the path of its children,
+	// the location itself having no row unless the root was indexed
+	let mut directories = HashMap::from([("/".to_string(), None)]);
+	for file_path in
+		find_tree_entries(db, location_id, || file_path::is_dir::equals(Some(true))).await?
+	{
+		let iso_file_path = IsolatedFilePathData::try_from((location_id, &file_path))?;
+		if let Some(children_path) = iso_file_path.materialized_path_for_children() {
+			directories.insert(
+				children_path,
+				Some((file_path.pub_id, file_path.tree_checksum)),
+			);
+		}
+	}
+
+	// deepest first, so subdirectories are done before their parents
+	let mut directories = directories.into_iter().collect::<Vec<_>>();
+	directories.sort_by_key(|(path, _)| Reverse(path.matches('/').count()));
+
+	let mut checksums = HashMap::with_capacity(directories.len());
+	for (children_path, row) in directories {
+		let mut entries = vec![];
+		for file_path in find_tree_entries(db, location_id, || {
+			file_path::materialized_path::equals(Some(children_path.clone()))
+		})
+		.await?
+		{
+			let iso_file_path = IsolatedFilePathData::try_from((location_id, &file_path))?;
+			if iso_file_path.is_root() {
+				continue;
+			}
+
+			let entry = if file_path.is_dir == Some(true) {
+				TreeEntry::Directory
+			} else {
+				TreeEntry::File(file_path.integrity_checksum.filter(|_| {
+					ChecksumAlgorithm::from_db(file_path.integrity_checksum_algorithm.as_deref())
+						== Some(algorithm)
+				}))
+			};
+			entries.push((iso_file_path.full_name(), entry));
+		}
+
+		let checksum = directory_checksum(&children_path, entries, &checksums, algorithm);
+		let tree_checksum = checksum
+			.as_ref()
+			.map(|checksum| encode_checksum(algorithm, checksum));
+		checksums.insert(children_path, checksum);
+
+		let Some((pub_id, stored)) = row else {
+			continue;
+		};
+		if tree_checksum == stored {
+			continue;
+		}
+
+		sync.write_ops(
+			db,
+			(
+				vec![sync.shared_update(
+					sync::file_path::SyncId {
+						pub_id: pub_id.clone(),
+					},
+					file_path::tree_checksum::NAME,
+					json!(&tree_checksum),
+				)],
+				db.file_path().update(
+					file_path::pub_id::equals(pub_id),
+					vec![file_path::tree_checksum::set(tree_checksum)],
+				),
+			),
+		)
+		.await?;
+	}
+
+	Ok(tree_checksum_roots(&checksums))
+}
+
+/// The rows of the location matching the `filter` a tree checksum is made of, directories and files
+/// not excluded from validation, a page at a time
+async fn find_tree_entries(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	filter: impl Fn() -> file_path::WhereParam,
+) -> Result<Vec<file_path_for_tree_checksum::Data>, ValidatorError> {
+	let mut found = vec![];
+	let mut last_id = None;
+
+	loop {
+		let file_paths = db
+			.file_path()
+			.find_many(
+				[
+					file_path::location_id::equals(Some(location_id)),
+					or(vec![file_path::is_dir::equals(Some(true)), not_excluded()]),
+					filter(),
+				]
+				.into_iter()
+				.chain(last_id.map(file_path::id::gt))
+				.collect(),
+			)
+			.order_by(file_path::id::order(SortOrder::Asc))
+			.take(PAGE_SIZE)
+			.select(file_path_for_tree_checksum::select())
+			.exec()
+			.await?;
+
+		let Some(last) = file_paths.last() else {
+			break;
+		};
+		last_id = Some(last.id);
+		found.extend(file_paths);
+	}
+
+	Ok(found)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	fn file(
+		materialized_path: &str,
+		name: &str,
+		checksum: Option<&str>,
+	) -> (String, String, TreeEntry) {
+		(
+			materialized_path.to_string(),
+			name.to_string(),
+			TreeEntry::File(checksum.map(str::to_string)),
+		)
+	}
+
+	fn directory(materialized_path: &str, name: &str) -> (String, String, TreeEntry) {
+		(
+			materialized_path.to_string(),
+			name.to_string(),
+			TreeEntry::Directory,
+		)
+	}
+
+	#[test]
+	fn test_tree_checksums() {
+		let algorithm = ChecksumAlgorithm::Blake3;
+		let entries = vec![
+			file("/", "a.txt", Some("aaaa")),
+			directory("/", "docs"),
+			file("/docs/", "b.txt", Some("bbbb")),
+			directory("/docs/", "empty"),
+			directory("/", "photos"),
+			file("/photos/", "c.jpg", None),
+		];
+
+		let checksums = tree_checksums(entries.clone(), algorithm);
+		assert!(checksums["/docs/"].is_some());
+		// an empty directory has a checksum of its own
+		assert!(checksums["/docs/empty/"].is_some());
+		// a file lacking a checksum leaves its directory and the ones above without one
+		assert_eq!(checksums["/photos/"], None);
+		assert_eq!(checksums["/"], None);
+		assert_eq!(
+			tree_checksum_roots(&checksums)
+				.into_keys()
+				.collect::<Vec<_>>(),
+			vec!["docs"]
+		);
+
+		// the order the entries come in doesn't matter
+		let mut reversed = entries.clone();
+		reversed.reverse();
+		assert_eq!(tree_checksums(reversed, algorithm), checksums);
+
+		let mut validated = entries.clone();
+		validated[5] = file("/photos/", "c.jpg", Some("cccc"));
+		let validated = tree_checksums(validated, algorithm);
+		assert_eq!(validated["/docs/"], checksums["/docs/"]);
+		assert_eq!(
+			tree_checksum_roots(&validated)
+				.into_keys()
+				.collect::<Vec<_>>(),
+			vec![""]
+		);
+
+		// a change deep down reaches every directory above it, but not its siblings
+		let mut modified = entries.clone();
+		modified[2] = file("/docs/", "b.txt", Some("dddd"));
+		modified[5] = file("/photos/", "c.jpg", Some("cccc"));
+		let modified = tree_checksums(modified, algorithm);
+		assert_ne!(modified["/docs/"], validated["/docs/"]);
+		assert_ne!(modified["/"], validated["/"]);
+		assert_eq!(modified["/photos/"], validated["/photos/"]);
+
+		// and so does renaming a file
+		let mut renamed = entries;
+		renamed[2] = file("/docs/", "renamed.txt", Some("bbbb"));
+		assert_ne!(
+			tree_checksums(renamed, algorithm)["/docs/"],
+			checksums["/docs/"]
+		);
+	}
+}
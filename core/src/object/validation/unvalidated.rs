@@ -0,0 +1,81 @@
+use crate::{
+	library::Library,
+	location::file_path_helper::IsolatedFilePathData,
+	prisma::{file_path, location, SortOrder},
+	util::db::maybe_missing,
+};
+
+use std::path::Path;
+
+use serde::Deserialize;
+use specta::Type;
+
+use super::{
+	step_source::{missing_checksum, scope_filters},
+	FileScope, ValidatorError,
+};
+
+/// A page of files, picking up after the last one of the previous page so pages stay cheap deep
+/// into huge locations
+#[derive(Deserialize, Type, Debug, Clone, Copy)]
+pub struct UnvalidatedPage {
+	/// id of the last file of the previous page, `None` for the first page
+	pub after: Option<file_path::id::Type>,
+	pub size: u32,
+}
+
+/// The files of the location, under `sub_path` if given, that a validator run with the
+/// library's default algorithm would compute a checksum for, by id, along with how many there
+/// are in total. Only the database is read, the sub path doesn't have to be on disk.
+pub async fn list_unvalidated(
+	library: &Library,
+	location: &location::Data,
+	sub_path: Option<&Path>,
+	page: UnvalidatedPage,
+) -> Result<(Vec<file_path::Data>, u32), ValidatorError> {
+	let sub_iso_file_path = match sub_path {
+		Some(sub_path) if sub_path != Path::new("") && sub_path != Path::new("/") => {
+			let location_path = maybe_missing(&location.path, "location.path")?;
+
+			Some(IsolatedFilePathData::new(
+				location.id,
+				location_path,
+				Path::new(location_path).join(sub_path),
+				true,
+			)?)
+		}
+		_ => None,
+	};
+
+	let filters = || {
+		scope_filters(
+			location.id,
+			FileScope {
+				sub_path: sub_iso_file_path.as_ref(),
+				..Default::default()
+			},
+			Some(missing_checksum(
+				library.config.default_checksum_algorithm,
+				false,
+				false,
+			)),
+		)
+	};
+
+	let total = library.db.file_path().count(filters()).exec().await?;
+	let file_paths = library
+		.db
+		.file_path()
+		.find_many(
+			filters()
+				.into_iter()
+				.chain(page.after.map(file_path::id::gt))
+				.collect(),
+		)
+		.order_by(file_path::id::order(SortOrder::Asc))
+		.take(page.size.into())
+		.exec()
+		.await?;
+
+	Ok((file_paths, total as u32))
+}
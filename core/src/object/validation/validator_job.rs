@@ -1,49 +1,771 @@
 use crate::{
-	extract_job_data,
+	api::CoreEvent,
+	extract_job_data_mut, invalidate_query,
 	job::{
-		JobError, JobInitData, JobReportUpdate, JobResult, JobState, StatefulJob, WorkerContext,
+		JobError, JobInitData, JobReportUpdate, JobResult, JobState, ProgressAggregator,
+		StatefulJob, WorkerContext,
 	},
 	library::Library,
 	location::file_path_helper::{
 		ensure_file_path_exists, ensure_sub_path_is_directory, ensure_sub_path_is_in_location,
 		file_path_for_object_validator, IsolatedFilePathData,
 	},
-	prisma::{file_path, location},
+	prisma::{file_path, location, object, tag, PrismaClient},
 	sync,
-	util::{
-		db::{chain_optional_iter, maybe_missing},
-		error::FileIOError,
-	},
+	util::{db::maybe_missing, error::FileIOError},
+	volume::{available_space, is_remote_path},
 };
 
 use std::{
+	cmp::Reverse,
+	collections::{BTreeMap, HashMap, HashSet, VecDeque},
+	future::Future,
 	hash::{Hash, Hasher},
+	ops::Range,
 	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, OnceLock,
+	},
+	time::{Duration, Instant},
 };
 
-use serde::{Deserialize, Serialize};
-use serde_json::json;
-use tracing::info;
+use chrono::{DateTime, Utc};
+use futures::{future::join_all, stream, StreamExt};
+use sd_file_ext::kind::ObjectKind;
+use serde::{de, Deserialize, Deserializer, Serialize};
+use tokio::{fs, io, task::spawn_blocking};
+use tracing::{error, info, warn};
+use url::Url;
 
-use super::{hash::file_checksum, ValidatorError};
+use super::{
+	acknowledgments, attest_audit, cross_check_cas, extension_mismatch,
+	hash::{
+		changed_during_read, checksums_match, is_memory_pressure, is_valid_checksum,
+		is_valid_digest_bits, short_read, truncate_checksum, ChangedDuringRead, ChecksumAlgorithm,
+		ReadOptions, ShortRead, HEAD_LEN, MIN_BLOCK_LEN,
+	},
+	is_perceptually_hashable,
+	manifest::{corrupt_ranges, missing_from_location},
+	merge_confirmed_duplicates, mirror_comparison, par2_recoverability, perceptual_hash, range_key,
+	record_verifications,
+	reflink::group_reflinks,
+	remove_from_stored_aggregate, send_failure_webhook, shared_extent_layout, size_mismatch,
+	sniff_content_type,
+	step_source::scope_filters,
+	telemetry, update_aggregate_checksum, update_tree_checksums, Acknowledgment, ChecksumConflict,
+	ChecksumStore, ChecksumWrite, Chunk, DuplicateStrategy, EventsPerSecond, ExtensionMismatch,
+	ExternalChecksum, ExternalChecksumSource, FileScope, FileValidationOutcome,
+	LibraryChecksumStore, LibraryStepSource, MirrorDivergence, ObjectValidatorReport, OutcomeTags,
+	RangeChecksum, RemoteChecksums, ReportSample, SignedManifest, StepSource, StoredChecksums,
+	SyncPacer, ValidationCallback, ValidationCompletedEvent, ValidationFailure,
+	ValidationFailuresPayload, ValidationTotals, ValidatorError, MAX_PERCEPTUAL_LEN,
+};
 
 // The Validator is able to:
 // - generate a full byte checksum for Objects in a Location
 // - generate checksums for all Objects missing without one
 // - compare two objects and return true if they are the same
-pub struct ObjectValidatorJob {}
+pub struct ObjectValidatorJob {
+	/// paces the sync operations of the run, kept between steps so the job can wait for it in
+	/// `wait_reason`
+	sync_pacer: OnceLock<SyncPacer>,
+}
+
+impl ObjectValidatorJob {
+	fn sync_pacer(&self, init: &ObjectValidatorJobInit) -> Option<&SyncPacer> {
+		init.sync_rate
+			.map(|sync_rate| self.sync_pacer.get_or_init(|| SyncPacer::new(sync_rate)))
+	}
+}
+
+/// Must be bumped, with a matching step in [`ObjectValidatorJobState::migrate`], whenever the
+/// state changes in a way that paused jobs from older versions can't be resumed as is
+const STATE_VERSION: u32 = 1;
+
+/// Files of each device a step holds for every one read at once from it, so devices read at their
+/// own pace through most of the step instead of waiting on each other after every file
+const FILES_PER_DEVICE_READ: usize = 8;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ObjectValidatorJobState {
+	/// States serialized before versioning existed decode as version 0
+	#[serde(default, deserialize_with = "deserialize_known_state_version")]
+	pub version: u32,
 	pub location_path: PathBuf,
+	#[serde(default)]
 	pub task_count: usize,
+	#[serde(default)]
+	pub report: ObjectValidatorReport,
+	/// the location is on a network or FUSE mount, so reads are tuned for latency
+	#[serde(default)]
+	pub remote_location: bool,
+	/// algorithm the steps were enumerated for, states from before it existed were all blake3
+	#[serde(default)]
+	pub algorithm: ChecksumAlgorithm,
+	/// checksums of the signed manifest, loaded once its signature was verified
+	#[serde(default)]
+	pub manifest: Option<HashMap<String, String>>,
+	/// chunks of the files listed in the manifest's chunk manifest, if it has one
+	#[serde(default)]
+	pub chunks: Option<HashMap<String, Vec<Chunk>>>,
+	/// contents of files the user acknowledged since the manifest was signed, which don't fail
+	/// for not matching it
+	#[serde(default)]
+	pub acknowledgments: HashMap<String, Acknowledgment>,
+	/// files sharing all their extents with a file left to validate, keyed by its id, they're
+	/// given its checksum once it's computed
+	#[serde(default)]
+	pub reflink_copies: HashMap<file_path::id::Type, Vec<file_path_for_object_validator::Data>>,
+	/// bytes of all the steps, progress is weighted by them. States from before it existed
+	/// report progress by task counts alone.
+	#[serde(default)]
+	pub total_bytes: u64,
+	#[serde(default)]
+	pub completed_bytes: u64,
+	/// times each file failed to be read, kept across resumes so retries stay within
+	/// [`ObjectValidatorJobInit::max_file_attempts`]
+	#[serde(default)]
+	pub attempts: HashMap<file_path::id::Type, u32>,
+	/// counts of the files done so far, in this session and the previous ones. States from before
+	/// it existed count from where they were resumed.
+	#[serde(default)]
+	pub totals: ValidationTotals,
+	/// files requeued for changing while they were read, so they're only requeued once
+	#[serde(default)]
+	pub requeued_in_flux: HashSet<file_path::id::Type>,
+}
+
+impl ObjectValidatorJobState {
+	/// Brings a state resumed from an older version up to date
+	fn migrate(&mut self, location_id: location::id::Type, sub_path: Option<&PathBuf>) {
+		if self.version == STATE_VERSION {
+			return;
+		}
+
+		if self.version < 1 {
+			// The report didn't exist yet, so it starts empty for the remaining steps
+			self.report.location_id = location_id;
+			self.report.sub_path = sub_path.cloned();
+		}
+
+		self.version = STATE_VERSION;
+	}
+
+	/// Switches to `algorithm`, returning if the steps left were enumerated for another one and
+	/// must be enumerated again. The files already validated are dropped from the report, as
+	/// they'll be checksummed again so the location doesn't end up with mixed algorithms.
+	fn switch_algorithm(&mut self, algorithm: ChecksumAlgorithm) -> bool {
+		if self.algorithm == algorithm {
+			return false;
+		}
+
+		self.algorithm = algorithm;
+		self.report = ObjectValidatorReport {
+			location_id: self.report.location_id,
+			sub_path: self.report.sub_path.take(),
+			modified_within: self.report.modified_within.take(),
+			object_kinds: self.report.object_kinds.take(),
+			max_file_attempts: self.report.max_file_attempts,
+			..Default::default()
+		};
+
+		true
+	}
+}
+
+// A state from a newer version can't be migrated back, failing to decode it makes the job
+// manager restart the job from its init data instead
+fn deserialize_known_state_version<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<u32, D::Error> {
+	let version = u32::deserialize(deserializer)?;
+	if version > STATE_VERSION {
+		return Err(de::Error::custom(format!(
+			"unknown validator state version: {version}"
+		)));
+	}
+
+	Ok(version)
 }
 
 // The validator can
+/// Built with [`ObjectValidatorJobInit::builder`], not a struct literal, so adding options doesn't
+/// break callers
 #[derive(Serialize, Deserialize, Debug)]
+#[non_exhaustive]
 pub struct ObjectValidatorJobInit {
 	pub location: location::Data,
 	pub sub_path: Option<PathBuf>,
+	/// only validate the files with these pub ids, like a selection in the explorer, within the
+	/// sub path and the other filters. They must all be files of the location.
+	#[serde(default, alias = "file_path_ids")]
+	pub file_path_pub_ids: Option<Vec<Vec<u8>>>,
+	/// read files without filling the page cache, useful for big archival scrubs
+	#[serde(default)]
+	pub bypass_page_cache: bool,
+	/// only validate a random subset of the files, for quick spot checks
+	#[serde(default)]
+	pub sample: Option<SampleSpec>,
+	/// also store a checksum of only the media payload of recognized formats, ignoring their tags
+	#[serde(default)]
+	pub media_normalize: bool,
+	/// import checksums computed by another tool instead of hashing those files again
+	#[serde(default)]
+	pub seed_from: Option<ExternalChecksumSource>,
+	/// how many files to read at once from each device, files are read one by one when 0
+	#[serde(default)]
+	pub per_device_concurrency: usize,
+	#[serde(default)]
+	pub order: StepOrder,
+	/// the library's default algorithm is used when not set
+	#[serde(default)]
+	pub algorithm: Option<ChecksumAlgorithm>,
+	/// store checksums truncated to this many bits, halving the space they take at 128 bits for
+	/// a few more collisions between different files, see [`truncate_checksum`]. Stored ones keep
+	/// their length, so changing it later only applies to files checksummed from then on.
+	#[serde(default)]
+	pub digest_bits: Option<usize>,
+	/// URL to POST the failures of each step to, for external monitoring
+	#[serde(default)]
+	pub on_failure_webhook: Option<Url>,
+	/// path to read the location from instead of its stored one, for drives mounted elsewhere
+	/// than when they were added
+	#[serde(default)]
+	pub location_path_override: Option<PathBuf>,
+	/// root of a mirror of the location, like the other side of a mirrored backup, whose copy of
+	/// each file is hashed too and compared, disagreements being reported without failing the
+	/// file. Files already checksummed are read again for it, and verified against their checksum.
+	#[serde(default)]
+	pub mirror_root: Option<PathBuf>,
+	/// files taking longer than this for a single read are failed instead of hanging the job,
+	/// defaults to no timeout, or 30 seconds on remote locations
+	#[serde(default)]
+	pub read_timeout: Option<Duration>,
+	/// what's done with files whose size changes while they're read, their checksum is never
+	/// stored as it's of none of their versions
+	#[serde(default)]
+	pub in_flux: InFluxPolicy,
+	/// what's done when another writer changed the checksum of a file while it was validated
+	#[serde(default)]
+	pub checksum_conflicts: ChecksumConflictPolicy,
+	/// called after each file is validated, it can't be persisted so a job resumed after a
+	/// restart goes on without it
+	#[serde(skip)]
+	pub on_file_validated: Option<ValidationCallback>,
+	/// remove the rows of files that vanished from disk, which changes the library so it's opt-in,
+	/// the job fails instead if the location's root is empty or missing, like an unmounted drive
+	#[serde(default)]
+	pub prune_missing: bool,
+	/// store the MIME type sniffed from the contents of the files we compute checksums for
+	#[serde(default)]
+	pub detect_content_type: bool,
+	/// also store a perceptual hash of the images, computed from the same read as their checksum,
+	/// to find the ones that look alike, see [`perceptual_distance`](super::perceptual_distance)
+	#[serde(default)]
+	pub perceptual: bool,
+	/// report the files whose contents, sniffed from the same read as their checksum, are of
+	/// another type than their extension claims
+	#[serde(default)]
+	pub flag_extension_mismatch: bool,
+	/// once validated, merge the objects of files confirmed byte identical into one, keeping the
+	/// tags and metadata of all of them, see [`merge_confirmed_duplicates`]
+	#[serde(default)]
+	pub merge_confirmed_duplicates: bool,
+	/// how the files confirmed duplicates are found when merging them
+	#[serde(default)]
+	pub duplicate_strategy: DuplicateStrategy,
+	/// once validated, report the files whose `cas_id` and checksum disagree about them being
+	/// the same, see [`cross_check_cas`]
+	#[serde(default)]
+	pub cross_check_cas: bool,
+	/// once validated, compute the tree checksum of every directory of the location from the
+	/// checksums of the files and directories within it, see [`update_tree_checksums`]
+	#[serde(default)]
+	pub tree_checksums: bool,
+	/// once validated, report for the failed files in a PAR2 recovery set whether it can still
+	/// repair them, see [`par2_recoverability`]
+	#[serde(default)]
+	pub par2_aware: bool,
+	/// audit every file against the checksums of a manifest signed by a trusted authority instead
+	/// of the ones stored in the library, nothing is stored and the run is aborted if the
+	/// signature doesn't match
+	#[serde(default)]
+	pub verify_against: Option<SignedManifest>,
+	/// once an audit against `verify_against` that every file passed is done, write a copy of the
+	/// manifest and an attestation of the audit, signed with the library's key, to this
+	/// directory, see [`attest_audit`]. Only audits of every file of the location or sub path
+	/// can be attested.
+	#[serde(default)]
+	pub attest_to: Option<PathBuf>,
+	/// leave empty files out, they all have the same checksum so they only clutter duplicate
+	/// reports. Always on when merging duplicates.
+	#[serde(default)]
+	pub skip_empty: bool,
+	/// only hash one of the files sharing all their extents on copy-on-write file systems, the
+	/// others get its checksum. Files are all hashed where we can't tell, only Linux is supported.
+	#[serde(default)]
+	pub dedup_reflinks: bool,
+	/// only validate files last modified within this window, for incremental audits after edits.
+	/// Files without a modification date are left out.
+	#[serde(default)]
+	pub modified_within: Option<Range<DateTime<Utc>>>,
+	/// only validate files whose object is of one of these kinds, within the location or sub
+	/// path. Files not identified yet have no kind, so they're left out.
+	#[serde(default)]
+	pub object_kinds: Option<Vec<ObjectKind>>,
+	/// only validate files whose object the user marked as a favorite, within the location or
+	/// sub path and the other filters
+	#[serde(default)]
+	pub favorites_only: bool,
+	/// only re-hash files with a checksum computed with this algorithm, to migrate them to
+	/// `algorithm`. Files without a checksum are left for later runs.
+	#[serde(default)]
+	pub migrate_from: Option<ChecksumAlgorithm>,
+	/// only checksum this byte range of each file, like the header of disk images, comparing it
+	/// with the checksum of the same range in the manifest or stored by an earlier run, and
+	/// storing it otherwise. Files shorter than the range fail.
+	#[serde(default)]
+	pub range: Option<Range<u64>>,
+	/// keep reads under this many bytes per second on average, pausing between steps, so long
+	/// runs in the background don't get in the way
+	#[serde(default)]
+	pub max_read_rate: Option<u64>,
+	/// emit the sync operations of the checksums and verifications stored at no more than this
+	/// rate on average, so peers of a busy library aren't flooded by a big run. The job waits
+	/// between steps while over it, still pausable and cancelable, and never drops operations, so
+	/// every one of them still gets to the peers.
+	#[serde(default)]
+	pub sync_rate: Option<EventsPerSecond>,
+	/// read each file up to this many times over the whole job, resumes included, retrying the
+	/// ones failing to be read in a later step. Files are read once when `None`.
+	#[serde(default)]
+	pub max_file_attempts: Option<u32>,
+	/// give files also reachable through another location of this node, nested in this one or
+	/// containing it, the checksum already computed there instead of hashing them again
+	#[serde(default)]
+	pub cross_location_dedup: bool,
+	/// reads failing for lack of memory are retried with buffers half as big, down to this many
+	/// bytes, [`MIN_BLOCK_LEN`] when `None`
+	#[serde(default)]
+	pub min_read_buffer_len: Option<usize>,
+	/// wait between steps while the volume of the library database, where checksums and
+	/// outcomes are written, has less than this many bytes free, instead of failing the writes.
+	/// The job resumes by itself once enough space is freed.
+	#[serde(default)]
+	pub min_free_space: Option<u64>,
+	/// tag the objects of files found corrupted with this tag
+	#[serde(default)]
+	pub failure_tag: Option<tag::id::Type>,
+	/// tag the objects of files found healthy with this tag, taking the failure tag off
+	#[serde(default)]
+	pub success_tag: Option<tag::id::Type>,
+	/// also hash the alternate data streams of files on NTFS and their resource fork on macOS,
+	/// which plain reads leave out, see [`ReadOptions::include_alt_streams`]. Checksums of files
+	/// with any differ from the ones computed without, so they're stored apart and checksums
+	/// computed the other way are computed again instead of being compared, it's best kept on
+	/// for the location. Manifests only hold checksums of main streams, it can't audit them.
+	#[serde(default)]
+	pub include_alt_streams: bool,
+	/// where to persist checksums instead of the library database, it can't be persisted so a
+	/// job resumed after a restart fails instead of storing them somewhere else
+	#[serde(skip)]
+	pub checksum_store: Option<Arc<dyn ChecksumStore>>,
+	/// whether a checksum store was set, kept when the store itself is lost on restart
+	#[serde(default)]
+	has_checksum_store: bool,
+	/// checksums the remote a location is mounted from keeps for its files, compared with the
+	/// manifest before falling back to reading the files when auditing. Like the checksum store,
+	/// it's lost when the job is resumed after a restart.
+	#[serde(skip)]
+	pub remote_checksums: Option<Arc<dyn RemoteChecksums>>,
+}
+
+impl ObjectValidatorJobInit {
+	/// A job resumed after a restart lost the checksum store it was started with
+	fn lost_checksum_store(&self) -> bool {
+		self.has_checksum_store && self.checksum_store.is_none()
+	}
+
+	fn algorithm(&self, library: &Library) -> ChecksumAlgorithm {
+		// the manifest's checksums can only be compared with ones computed the same way
+		if let Some(manifest) = &self.verify_against {
+			return manifest.algorithm;
+		}
+
+		self.algorithm
+			.unwrap_or(library.config.default_checksum_algorithm)
+	}
+
+	fn outcome_tags(&self) -> OutcomeTags {
+		OutcomeTags {
+			success: self.success_tag,
+			failure: self.failure_tag,
+		}
+	}
+
+	fn skips_empty(&self) -> bool {
+		self.skip_empty || self.merge_confirmed_duplicates
+	}
+
+	/// Every file of the location or sub path is audited against a manifest, whole
+	fn is_full_audit(&self) -> bool {
+		self.verify_against.is_some()
+			&& self.file_path_pub_ids.is_none()
+			&& self.sample.is_none()
+			&& self.modified_within.is_none()
+			&& self.object_kinds.is_none()
+			&& !self.favorites_only
+			&& self.range.is_none()
+	}
+
+	/// Validates all the files of `location` missing a checksum, see
+	/// [`ObjectValidatorJobInitBuilder`] for what else can be configured
+	pub fn builder(location: location::Data) -> ObjectValidatorJobInitBuilder {
+		ObjectValidatorJobInitBuilder {
+			init: Self {
+				location,
+				sub_path: None,
+				file_path_pub_ids: None,
+				bypass_page_cache: false,
+				sample: None,
+				media_normalize: false,
+				seed_from: None,
+				per_device_concurrency: 0,
+				order: StepOrder::default(),
+				algorithm: None,
+				digest_bits: None,
+				on_failure_webhook: None,
+				location_path_override: None,
+				mirror_root: None,
+				read_timeout: None,
+				in_flux: InFluxPolicy::default(),
+				checksum_conflicts: ChecksumConflictPolicy::default(),
+				on_file_validated: None,
+				prune_missing: false,
+				detect_content_type: false,
+				perceptual: false,
+				flag_extension_mismatch: false,
+				merge_confirmed_duplicates: false,
+				duplicate_strategy: DuplicateStrategy::default(),
+				cross_check_cas: false,
+				tree_checksums: false,
+				par2_aware: false,
+				verify_against: None,
+				attest_to: None,
+				skip_empty: false,
+				dedup_reflinks: false,
+				modified_within: None,
+				object_kinds: None,
+				favorites_only: false,
+				migrate_from: None,
+				range: None,
+				max_read_rate: None,
+				sync_rate: None,
+				max_file_attempts: None,
+				cross_location_dedup: false,
+				min_read_buffer_len: None,
+				min_free_space: None,
+				failure_tag: None,
+				success_tag: None,
+				include_alt_streams: false,
+				checksum_store: None,
+				has_checksum_store: false,
+				remote_checksums: None,
+			},
+		}
+	}
+}
+
+/// Builds an [`ObjectValidatorJobInit`], so callers only set the options they need and keep
+/// compiling as new ones are added. Each option is documented on its field.
+#[derive(Debug)]
+pub struct ObjectValidatorJobInitBuilder {
+	init: ObjectValidatorJobInit,
+}
+
+impl ObjectValidatorJobInitBuilder {
+	pub fn sub_path(mut self, sub_path: impl Into<PathBuf>) -> Self {
+		self.init.sub_path = Some(sub_path.into());
+		self
+	}
+
+	pub fn file_path_pub_ids(mut self, file_path_pub_ids: Vec<Vec<u8>>) -> Self {
+		self.init.file_path_pub_ids = Some(file_path_pub_ids);
+		self
+	}
+
+	pub fn bypass_page_cache(mut self, bypass_page_cache: bool) -> Self {
+		self.init.bypass_page_cache = bypass_page_cache;
+		self
+	}
+
+	pub fn sample(mut self, sample: SampleSpec) -> Self {
+		self.init.sample = Some(sample);
+		self
+	}
+
+	pub fn media_normalize(mut self, media_normalize: bool) -> Self {
+		self.init.media_normalize = media_normalize;
+		self
+	}
+
+	pub fn seed_from(mut self, source: ExternalChecksumSource) -> Self {
+		self.init.seed_from = Some(source);
+		self
+	}
+
+	pub fn per_device_concurrency(mut self, per_device_concurrency: usize) -> Self {
+		self.init.per_device_concurrency = per_device_concurrency;
+		self
+	}
+
+	pub fn order(mut self, order: StepOrder) -> Self {
+		self.init.order = order;
+		self
+	}
+
+	pub fn algorithm(mut self, algorithm: ChecksumAlgorithm) -> Self {
+		self.init.algorithm = Some(algorithm);
+		self
+	}
+
+	pub fn digest_bits(mut self, digest_bits: usize) -> Self {
+		self.init.digest_bits = Some(digest_bits);
+		self
+	}
+
+	pub fn on_failure_webhook(mut self, url: Url) -> Self {
+		self.init.on_failure_webhook = Some(url);
+		self
+	}
+
+	pub fn location_path_override(mut self, path: impl Into<PathBuf>) -> Self {
+		self.init.location_path_override = Some(path.into());
+		self
+	}
+
+	pub fn mirror_root(mut self, mirror_root: impl Into<PathBuf>) -> Self {
+		self.init.mirror_root = Some(mirror_root.into());
+		self
+	}
+
+	pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+		self.init.read_timeout = Some(read_timeout);
+		self
+	}
+
+	pub fn in_flux(mut self, in_flux: InFluxPolicy) -> Self {
+		self.init.in_flux = in_flux;
+		self
+	}
+
+	pub fn checksum_conflicts(mut self, policy: ChecksumConflictPolicy) -> Self {
+		self.init.checksum_conflicts = policy;
+		self
+	}
+
+	pub fn on_file_validated(mut self, callback: ValidationCallback) -> Self {
+		self.init.on_file_validated = Some(callback);
+		self
+	}
+
+	pub fn prune_missing(mut self, prune_missing: bool) -> Self {
+		self.init.prune_missing = prune_missing;
+		self
+	}
+
+	pub fn detect_content_type(mut self, detect_content_type: bool) -> Self {
+		self.init.detect_content_type = detect_content_type;
+		self
+	}
+
+	pub fn perceptual(mut self, perceptual: bool) -> Self {
+		self.init.perceptual = perceptual;
+		self
+	}
+
+	pub fn flag_extension_mismatch(mut self, flag_extension_mismatch: bool) -> Self {
+		self.init.flag_extension_mismatch = flag_extension_mismatch;
+		self
+	}
+
+	pub fn merge_confirmed_duplicates(mut self, merge_confirmed_duplicates: bool) -> Self {
+		self.init.merge_confirmed_duplicates = merge_confirmed_duplicates;
+		self
+	}
+
+	pub fn duplicate_strategy(mut self, strategy: DuplicateStrategy) -> Self {
+		self.init.duplicate_strategy = strategy;
+		self
+	}
+
+	pub fn cross_check_cas(mut self, cross_check_cas: bool) -> Self {
+		self.init.cross_check_cas = cross_check_cas;
+		self
+	}
+
+	pub fn tree_checksums(mut self, tree_checksums: bool) -> Self {
+		self.init.tree_checksums = tree_checksums;
+		self
+	}
+
+	pub fn par2_aware(mut self, par2_aware: bool) -> Self {
+		self.init.par2_aware = par2_aware;
+		self
+	}
+
+	pub fn verify_against(mut self, manifest: SignedManifest) -> Self {
+		self.init.verify_against = Some(manifest);
+		self
+	}
+
+	pub fn attest_to(mut self, dir: impl Into<PathBuf>) -> Self {
+		self.init.attest_to = Some(dir.into());
+		self
+	}
+
+	pub fn skip_empty(mut self, skip_empty: bool) -> Self {
+		self.init.skip_empty = skip_empty;
+		self
+	}
+
+	pub fn dedup_reflinks(mut self, dedup_reflinks: bool) -> Self {
+		self.init.dedup_reflinks = dedup_reflinks;
+		self
+	}
+
+	pub fn modified_within(mut self, window: Range<DateTime<Utc>>) -> Self {
+		self.init.modified_within = Some(window);
+		self
+	}
+
+	pub fn object_kinds(mut self, kinds: impl IntoIterator<Item = ObjectKind>) -> Self {
+		self.init.object_kinds = Some(kinds.into_iter().collect());
+		self
+	}
+
+	pub fn favorites_only(mut self, favorites_only: bool) -> Self {
+		self.init.favorites_only = favorites_only;
+		self
+	}
+
+	pub fn migrate_from(mut self, algorithm: ChecksumAlgorithm) -> Self {
+		self.init.migrate_from = Some(algorithm);
+		self
+	}
+
+	pub fn range(mut self, range: Range<u64>) -> Self {
+		self.init.range = Some(range);
+		self
+	}
+
+	pub fn max_read_rate(mut self, bytes_per_second: u64) -> Self {
+		self.init.max_read_rate = Some(bytes_per_second);
+		self
+	}
+
+	pub fn sync_rate(mut self, sync_rate: EventsPerSecond) -> Self {
+		self.init.sync_rate = Some(sync_rate);
+		self
+	}
+
+	pub fn max_file_attempts(mut self, max_file_attempts: u32) -> Self {
+		self.init.max_file_attempts = Some(max_file_attempts);
+		self
+	}
+
+	pub fn cross_location_dedup(mut self, cross_location_dedup: bool) -> Self {
+		self.init.cross_location_dedup = cross_location_dedup;
+		self
+	}
+
+	pub fn min_read_buffer_len(mut self, len: usize) -> Self {
+		self.init.min_read_buffer_len = Some(len);
+		self
+	}
+
+	pub fn min_free_space(mut self, bytes: u64) -> Self {
+		self.init.min_free_space = Some(bytes);
+		self
+	}
+
+	pub fn failure_tag(mut self, tag_id: tag::id::Type) -> Self {
+		self.init.failure_tag = Some(tag_id);
+		self
+	}
+
+	pub fn success_tag(mut self, tag_id: tag::id::Type) -> Self {
+		self.init.success_tag = Some(tag_id);
+		self
+	}
+
+	pub fn include_alt_streams(mut self, include_alt_streams: bool) -> Self {
+		self.init.include_alt_streams = include_alt_streams;
+		self
+	}
+
+	pub fn checksum_store(mut self, store: Arc<dyn ChecksumStore>) -> Self {
+		self.init.checksum_store = Some(store);
+		self.init.has_checksum_store = true;
+		self
+	}
+
+	pub fn remote_checksums(mut self, remote_checksums: Arc<dyn RemoteChecksums>) -> Self {
+		self.init.remote_checksums = Some(remote_checksums);
+		self
+	}
+
+	pub fn build(self) -> ObjectValidatorJobInit {
+		self.init
+	}
+}
+
+/// Order in which files are validated
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum StepOrder {
+	/// Whatever order the database returns them in
+	#[default]
+	Database,
+	/// Files the user accessed, or modified, most recently first. Files without any of these
+	/// dates are left for last.
+	RecentlyAccessed,
+}
+
+/// What's done with files changing while they're read, like downloads in progress
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InFluxPolicy {
+	/// Read them again in a later step, once, recording them as in flux if they're still changing
+	#[default]
+	Requeue,
+	/// Record them as in flux right away
+	Skip,
+}
+
+/// What's done when another writer, like the location watcher, changed the checksum of a file
+/// between the validator reading the file and storing its own. Checksums generated for files that
+/// had none are stored regardless.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumConflictPolicy {
+	/// Keep the other writer's, it's likely of a newer version of the file
+	#[default]
+	KeepOther,
+	/// Replace it with the validator's
+	Overwrite,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub enum SampleSize {
+	/// A fraction of the files, between 0 and 1
+	Fraction(f64),
+	Count(usize),
+}
+
+/// The same seed over the same files always picks the same sample
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct SampleSpec {
+	pub size: SampleSize,
+	pub seed: u64,
 }
 
 impl Hash for ObjectValidatorJobInit {
@@ -52,6 +774,10 @@ impl Hash for ObjectValidatorJobInit {
 		if let Some(ref sub_path) = self.sub_path {
 			sub_path.hash(state);
 		}
+		// other selections of the same location are other jobs
+		if let Some(ref file_path_pub_ids) = self.file_path_pub_ids {
+			file_path_pub_ids.hash(state);
+		}
 	}
 }
 
@@ -63,12 +789,15 @@ impl JobInitData for ObjectValidatorJobInit {
 impl StatefulJob for ObjectValidatorJob {
 	type Init = ObjectValidatorJobInit;
 	type Data = ObjectValidatorJobState;
-	type Step = file_path_for_object_validator::Data;
+	type Step = Vec<file_path_for_object_validator::Data>;
 
 	const NAME: &'static str = "object_validator";
+	const IS_BACKGROUND: bool = true;
 
 	fn new() -> Self {
-		Self {}
+		Self {
+			sync_pacer: OnceLock::new(),
+		}
 	}
 
 	async fn init(
@@ -76,64 +805,154 @@ impl StatefulJob for ObjectValidatorJob {
 		ctx: &mut WorkerContext,
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError> {
-		let Library { db, .. } = &ctx.library;
+		let location_path = match &state.init.location_path_override {
+			Some(path_override) => {
+				ensure_is_directory(path_override).await?;
+				path_override.clone()
+			}
+			None => maybe_missing(&state.init.location.path, "location.path").map(PathBuf::from)?,
+		};
+
+		if state.init.prune_missing {
+			ensure_location_root_present(&location_path).await?;
+		}
+
+		// a mirror that isn't mounted would have every file diverge
+		if let Some(mirror_root) = &state.init.mirror_root {
+			if !fs::metadata(mirror_root)
+				.await
+				.map_or(false, |metadata| metadata.is_dir())
+			{
+				return Err(ValidatorError::MirrorRootNotDirectory(
+					mirror_root.clone().into_boxed_path(),
+				)
+				.into());
+			}
+		}
 
-		let location_id = state.init.location.id;
+		let algorithm = state.init.algorithm(&ctx.library);
+		if let Some(digest_bits) = state
+			.init
+			.digest_bits
+			.filter(|&digest_bits| !is_valid_digest_bits(digest_bits, algorithm))
+		{
+			return Err(ValidatorError::InvalidDigestBits {
+				digest_bits,
+				algorithm,
+			}
+			.into());
+		}
 
-		let location_path =
-			maybe_missing(&state.init.location.path, "location.path").map(PathBuf::from)?;
+		let mut report = ObjectValidatorReport {
+			location_id: state.init.location.id,
+			sub_path: state.init.sub_path.clone(),
+			modified_within: state.init.modified_within.clone(),
+			object_kinds: state.init.object_kinds.clone(),
+			max_file_attempts: state.init.max_file_attempts,
+			..Default::default()
+		};
 
-		let maybe_sub_iso_file_path = match &state.init.sub_path {
-			Some(sub_path) if sub_path != Path::new("") && sub_path != Path::new("/") => {
-				let full_path = ensure_sub_path_is_in_location(&location_path, sub_path)
-					.await
-					.map_err(ValidatorError::from)?;
-				ensure_sub_path_is_directory(&location_path, sub_path)
-					.await
-					.map_err(ValidatorError::from)?;
+		if let Some(range) = state.init.range.as_ref().filter(|range| range.is_empty()) {
+			return Err(ValidatorError::EmptyRange {
+				start: range.start,
+				end: range.end,
+			}
+			.into());
+		}
 
-				let sub_iso_file_path =
-					IsolatedFilePathData::new(location_id, &location_path, &full_path, true)
-						.map_err(ValidatorError::from)?;
+		if state.init.attest_to.is_some() && !state.init.is_full_audit() {
+			return Err(ValidatorError::AttestationNeedsFullAudit.into());
+		}
 
-				ensure_file_path_exists(
-					sub_path,
-					&sub_iso_file_path,
-					db,
-					ValidatorError::SubPathNotFound,
-				)
-				.await?;
+		if state.init.include_alt_streams && state.init.verify_against.is_some() {
+			return Err(ValidatorError::AltStreamsAgainstManifest.into());
+		}
 
-				Some(sub_iso_file_path)
+		let (manifest, chunks, acknowledgments) = match &state.init.verify_against {
+			Some(manifest) => {
+				let content = manifest.read_verified().await?;
+				(
+					Some(manifest.parse(&content)?),
+					manifest.load_chunks().await?,
+					acknowledgments(
+						&ctx.library.db,
+						state.init.location.id,
+						manifest.date_signed(&content)?,
+					)
+					.await?,
+				)
 			}
-			_ => None,
+			None => (None, None, HashMap::new()),
 		};
 
-		state.steps.extend(
-			db.file_path()
-				.find_many(chain_optional_iter(
-					[
-						file_path::location_id::equals(Some(state.init.location.id)),
-						file_path::is_dir::equals(Some(false)),
-						file_path::integrity_checksum::equals(None),
-					],
-					[maybe_sub_iso_file_path.and_then(|iso_sub_path| {
-						iso_sub_path
-							.materialized_path_for_children()
-							.map(file_path::materialized_path::starts_with)
-					})],
-				))
-				.select(file_path_for_object_validator::select())
-				.exec()
-				.await?,
-		);
+		state
+			.init
+			.outcome_tags()
+			.ensure_exist(&ctx.library.db)
+			.await?;
+
+		let mut reflink_copies = HashMap::new();
+		state.steps = enumerate_steps(
+			&ctx.library,
+			&state.init,
+			self.sync_pacer(&state.init),
+			&location_path,
+			algorithm,
+			manifest.as_ref(),
+			&mut report,
+			&mut reflink_copies,
+		)
+		.await?;
+
+		if state.init.location_path_override.is_some() {
+			ensure_holds_location_files(
+				state.init.location.id,
+				&location_path,
+				state.steps.iter().flatten(),
+			)
+			.await?;
+
+			info!(
+				"Validating location {} at {} instead of its stored path",
+				state.init.location.id,
+				location_path.display()
+			);
+		}
+
+		let remote_location = is_remote_path(&location_path).await;
+		if remote_location {
+			info!(
+				"Location {} is on a network or FUSE mount, tuning reads for it",
+				location_path.display()
+			);
+		}
 
+		let total_bytes = steps_len(&state.steps);
 		state.data = Some(ObjectValidatorJobState {
+			version: STATE_VERSION,
 			location_path,
 			task_count: state.steps.len(),
+			report,
+			remote_location,
+			algorithm,
+			manifest,
+			chunks,
+			acknowledgments,
+			reflink_copies,
+			total_bytes,
+			completed_bytes: 0,
+			attempts: HashMap::new(),
+			totals: ValidationTotals::default(),
+			requeued_in_flux: HashSet::new(),
 		});
 
-		ctx.progress(vec![JobReportUpdate::TaskCount(state.steps.len())]);
+		ctx.progress(vec![
+			JobReportUpdate::TaskCount(state.steps.len()),
+			JobReportUpdate::ByteProgress {
+				completed: 0,
+				total: total_bytes,
+			},
+		]);
 
 		Ok(())
 	}
@@ -143,66 +962,4674 @@ impl StatefulJob for ObjectValidatorJob {
 		ctx: &mut WorkerContext,
 		state: &mut JobState<Self>,
 	) -> Result<(), JobError> {
-		let Library { db, sync, .. } = &ctx.library;
+		let Library { db, .. } = &ctx.library;
 
-		let file_path = &state.steps[0];
-		let data = extract_job_data!(state);
+		// the checksums would end up in the library instead, where the store's users don't look
+		if state.init.lost_checksum_store() {
+			return Err(ValidatorError::ChecksumStoreLost.into());
+		}
 
-		// this is to skip files that already have checksums
-		// i'm unsure what the desired behaviour is in this case
-		// we can also compare old and new checksums here
-		// This if is just to make sure, we already queried objects where integrity_checksum is null
-		if file_path.integrity_checksum.is_none() {
-			let full_path = data.location_path.join(IsolatedFilePathData::try_from((
-				state.init.location.id,
-				file_path,
-			))?);
-			let checksum = file_checksum(&full_path)
-				.await
-				.map_err(|e| ValidatorError::FileIO(FileIOError::from((full_path, e))))?;
+		let algorithm = state.init.algorithm(&ctx.library);
+		let data = extract_job_data_mut!(state);
+		data.migrate(state.init.location.id, state.init.sub_path.as_ref());
 
-			sync.write_op(
-				db,
-				sync.shared_update(
-					sync::file_path::SyncId {
-						pub_id: file_path.pub_id.clone(),
-					},
-					file_path::integrity_checksum::NAME,
-					json!(&checksum),
-				),
-				db.file_path().update(
-					file_path::pub_id::equals(file_path.pub_id.clone()),
-					vec![file_path::integrity_checksum::set(Some(checksum))],
-				),
+		let previous_algorithm = data.algorithm;
+		if data.switch_algorithm(algorithm) {
+			warn!(
+				"Validator resumed with {} but its files were listed for {}, recomputing all of them",
+				algorithm.as_str(),
+				previous_algorithm.as_str()
+			);
+
+			state.steps = enumerate_steps(
+				&ctx.library,
+				&state.init,
+				self.sync_pacer(&state.init),
+				&data.location_path,
+				algorithm,
+				data.manifest.as_ref(),
+				&mut data.report,
+				&mut data.reflink_copies,
 			)
 			.await?;
+			// this step is removed from the queue once we're done with it, even if there's
+			// nothing left to validate
+			if state.steps.is_empty() {
+				state.steps.push_back(vec![]);
+			}
+			state.step_number = 0;
+			data.task_count = state.steps.len();
+			data.total_bytes = steps_len(&state.steps);
+			data.completed_bytes = 0;
+
+			ctx.progress(vec![
+				JobReportUpdate::TaskCount(state.steps.len()),
+				JobReportUpdate::ByteProgress {
+					completed: 0,
+					total: data.total_bytes,
+				},
+			]);
 		}
 
-		ctx.progress(vec![JobReportUpdate::CompletedTaskCount(
-			state.step_number + 1,
-		)]);
+		let library_store =
+			LibraryChecksumStore::new(&ctx.library).with_sync_pacer(self.sync_pacer(&state.init));
+		let stored_file_paths;
+		let (store, file_paths): (&dyn ChecksumStore, _) =
+			match state.init.checksum_store.as_deref() {
+				Some(store) => {
+					stored_file_paths = with_stored_checksums(store, &state.steps[0]).await?;
+					(store, &stored_file_paths)
+				}
+				None => (&library_store, &state.steps[0]),
+			};
+		let step_started_at = Instant::now();
+		let step_len = total_len(file_paths);
+		let mut read_len = 0u64;
 
-		Ok(())
-	}
+		let mut errors = vec![];
+		let mut failures = vec![];
+		let mut requeued = vec![];
+		let mut healthy_objects = vec![];
+		let mut failed_objects = vec![];
+		// only added to the totals once the step is done, which is when its results are kept in
+		// the state, so a step interrupted halfway isn't counted twice once it's run again
+		let mut step_totals = ValidationTotals::default();
 
-	async fn finalize(
-		&mut self,
-		_ctx: &mut WorkerContext,
-		state: &mut JobState<Self>,
-	) -> JobResult {
-		let data = extract_job_data!(state);
-		info!(
-			"finalizing validator job at {}{}: {} tasks",
-			data.location_path.display(),
-			state
-				.init
-				.sub_path
-				.as_ref()
-				.map(|p| format!("{}", p.display()))
-				.unwrap_or_default(),
-			data.task_count
-		);
+		// Files in the same step are either alone or spread across devices, each device reading
+		// its files within the per device concurrency limit
+		let source = &LibraryStepSource(db);
+		let options = ValidationOptions::new(&state.init, data);
+		let init = &state.init;
+		let data_ref: &ObjectValidatorJobState = data;
+		// files of different devices are validated concurrently, their byte progress coalesced
+		let progress = &ctx.progress_aggregator(ProgressAggregator::DEFAULT_INTERVAL);
+		let validated_len = &AtomicU64::new(0);
+		let validated_files = validate_per_device(
+			file_paths,
+			init.per_device_concurrency,
+			|file_path| async move {
+				let validated = match (&init.range, &data_ref.manifest) {
+					(Some(range), manifest) => {
+						validate_range(
+							source,
+							init.location.id,
+							&data_ref.location_path,
+							file_path,
+							range,
+							match manifest {
+								Some(manifest) => RangeBaseline::Manifest(manifest),
+								None => RangeBaseline::Store(store),
+							},
+							options,
+						)
+						.await
+					}
+					(None, Some(manifest)) => {
+						verify_file(
+							source,
+							init.location.id,
+							&data_ref.location_path,
+							file_path,
+							ManifestAudit {
+								manifest,
+								chunks: data_ref.chunks.as_ref(),
+								acknowledgments: Some(&data_ref.acknowledgments),
+								remote_checksums: init.remote_checksums.as_deref(),
+							},
+							options,
+						)
+						.await
+					}
+					(None, None) => match &init.mirror_root {
+						Some(mirror_root) => {
+							validate_with_mirror(
+								source,
+								init.location.id,
+								&data_ref.location_path,
+								file_path,
+								mirror_root,
+								options,
+							)
+							.await
+						}
+						None => {
+							validate_file(
+								source,
+								init.location.id,
+								&data_ref.location_path,
+								file_path,
+								options,
+							)
+							.await
+						}
+					},
+				};
+
+				let len = size_in_bytes(file_path).unwrap_or_default();
+				let validated_len = validated_len.fetch_add(len, Ordering::Relaxed) + len;
+				if data_ref.total_bytes > 0 {
+					progress.progress(vec![JobReportUpdate::ByteProgress {
+						completed: data_ref.completed_bytes.saturating_add(validated_len),
+						total: data_ref.total_bytes,
+					}]);
+				}
+
+				validated
+			},
+		)
+		.await;
+
+		// Files failing because the whole location went away, like a drive unplugged mid-step,
+		// aren't recorded: the step is run again once the location is back, see `wait_reason`
+		let any_failed = validated_files
+			.iter()
+			.any(|validated_file| match validated_file {
+				Ok(Some(file)) => {
+					file.read_failed
+						|| matches!(
+							file.outcome,
+							FileValidationOutcome::Failed { .. } | FileValidationOutcome::Pruned
+						)
+				}
+				Ok(None) => false,
+				Err(_) => true,
+			});
+		if any_failed
+			&& location_unavailable_reason(&data.location_path)
+				.await
+				.is_some()
+		{
+			warn!(
+				"Location {} became unavailable during a step, running it again once it's back",
+				data.location_path.display()
+			);
+			let step = state.steps[0].clone();
+			state.steps.insert(1, step);
+			data.task_count += 1;
+			data.total_bytes += step_len;
+			progress.progress(vec![JobReportUpdate::TaskCount(data.task_count)]);
+			progress.flush();
+
+			return Ok(());
+		}
+
+		// an unmounted drive leaves its mount point behind, in which every file looks vanished
+		if validated_files.iter().any(|validated_file| {
+			matches!(validated_file, Ok(Some(file)) if file.outcome == FileValidationOutcome::Pruned)
+		}) {
+			ensure_location_root_present(&data.location_path).await?;
+		}
+
+		for (file_path, validated_file) in file_paths.iter().zip(validated_files) {
+			let copies = data
+				.reflink_copies
+				.remove(&file_path.id)
+				.unwrap_or_default();
+
+			// a file failing to be validated doesn't lose the others of the step
+			let validated_file = match validated_file {
+				Ok(validated_file) => validated_file,
+				Err(e) => {
+					let relative_path =
+						IsolatedFilePathData::try_from((state.init.location.id, file_path))
+							.map_or_else(
+								|_| format!("file path {}", file_path.id),
+								|iso_file_path| iso_file_path.to_string(),
+							);
+					error!("Failed to validate {relative_path}: {e:#?}");
+
+					let outcome = FileValidationOutcome::Failed {
+						reason: e.to_string(),
+					};
+					errors.push(format!("{relative_path}: {e}"));
+					failed_objects.extend(object_id(file_path));
+					// their checksum can't be copied, so they're validated on their own
+					requeued.extend(copies);
+					step_totals.record(&outcome);
+					data.report.files.insert(relative_path, outcome);
+					continue;
+				}
+			};
+
+			let Some(ValidatedFile {
+				relative_path,
+				outcome,
+				checksum,
+				content_checksum,
+				content_type,
+				perceptual_hash,
+				range_checksum,
+				extension_mismatch,
+				reduced_block_len,
+				corrupt_ranges,
+				read_failed,
+				mirror,
+				live_len,
+			}) = validated_file
+			else {
+				// audited files are only skipped for being empty
+				if data.manifest.is_some() {
+					data.report.skipped_empty += 1;
+				}
+				step_totals.skipped += 1;
+				requeued.extend(copies);
+				continue;
+			};
+
+			// the size on disk is the one read, the indexer's may be wrong
+			let stored_len = size_in_bytes(file_path);
+			let file_len = live_len.or(stored_len).unwrap_or_default();
+			read_len = read_len.saturating_add(file_len);
+			if let Some(mismatch) = size_mismatch(stored_len, live_len) {
+				warn!(
+					"{relative_path} is {} bytes long but was indexed as {} bytes long",
+					mismatch.actual, mismatch.stored
+				);
+				data.report
+					.size_mismatches
+					.insert(relative_path.clone(), mismatch);
+			}
+
+			if outcome == FileValidationOutcome::InFlux {
+				if state.init.in_flux == InFluxPolicy::Requeue
+					&& data.requeued_in_flux.insert(file_path.id)
+				{
+					warn!("{relative_path} changed while it was read, reading it again later");
+					if !copies.is_empty() {
+						data.reflink_copies.insert(file_path.id, copies);
+					}
+					requeued.push(file_path.clone());
+					continue;
+				}
+
+				warn!("{relative_path} is changing, leaving it for a later run");
+				data.report.in_flux.insert(relative_path.clone());
+			}
+
+			if !read_failed {
+				data.attempts.remove(&file_path.id);
+			} else if let Some(max_file_attempts) = state.init.max_file_attempts {
+				let attempts = record_attempt(&mut data.attempts, file_path.id);
+				if attempts < max_file_attempts {
+					warn!(
+						"Failed to read {relative_path}, retrying it later ({attempts}/{max_file_attempts})"
+					);
+					// the copies are given its checksum once it's read after all
+					if !copies.is_empty() {
+						data.reflink_copies.insert(file_path.id, copies);
+					}
+					requeued.push(file_path.clone());
+					continue;
+				}
+
+				data.report.attempts_exhausted.insert(relative_path.clone());
+			}
+
+			// requeued files are only counted once they're read for good, and gone ones weren't
+			if outcome != FileValidationOutcome::Pruned {
+				step_totals.bytes = step_totals.bytes.saturating_add(file_len);
+			}
+
+			match mirror {
+				Some(Ok(())) => data.report.mirror_matched += 1,
+				Some(Err(divergence)) => {
+					warn!("{relative_path} disagrees with its mirror copy: {divergence:?}");
+					data.report
+						.mirror_divergences
+						.insert(relative_path.clone(), divergence);
+				}
+				None => {}
+			}
+
+			// a buggy hasher or a truncated read must not end up stored as the file's checksum, it
+			// fails on its own like any file failing to be validated
+			if let Some(invalid) = [
+				(&checksum, data.algorithm),
+				(&content_checksum, ChecksumAlgorithm::Blake3),
+			]
+			.into_iter()
+			.find_map(|(checksum, algorithm)| {
+				checksum
+					.as_ref()
+					.filter(|checksum| !is_valid_checksum(checksum, algorithm))
+			}) {
+				let e = ValidatorError::InvalidChecksum {
+					path: data.location_path.join(&relative_path).into_boxed_path(),
+					checksum: invalid.clone(),
+				};
+				error!("Failed to validate {relative_path}: {e}");
+
+				let outcome = FileValidationOutcome::Failed {
+					reason: e.to_string(),
+				};
+				errors.push(format!("{relative_path}: {e}"));
+				failed_objects.extend(object_id(file_path));
+				requeued.extend(copies);
+				step_totals.record(&outcome);
+				data.report.files.insert(relative_path, outcome);
+				continue;
+			}
+
+			let checksums = StoredChecksums {
+				checksum: checksum
+					.map(|checksum| truncate_checksum(&checksum, state.init.digest_bits)),
+				algorithm: data.algorithm,
+				include_alt_streams: state.init.include_alt_streams,
+				checksum_source: None,
+				content_checksum,
+				content_type: content_type.map(str::to_string),
+				perceptual_hash,
+				range_checksum,
+			};
+
+			match (&outcome, &checksums.checksum) {
+				(FileValidationOutcome::Checksummed, Some(_)) => {
+					healthy_objects.extend(copies.iter().filter_map(object_id));
+					step_totals.validated += copies.len() as u64;
+					store_reflink_copies(
+						store,
+						state.init.location.id,
+						copies,
+						&relative_path,
+						&checksums,
+						&mut data.report,
+					)
+					.await?;
+				}
+				// their checksum can't be copied, so they're validated on their own
+				_ => requeued.extend(copies),
+			}
+
+			if outcome == FileValidationOutcome::Pruned {
+				prune_file_path(&ctx.library, file_path).await?;
+			} else if data.manifest.is_none() {
+				store_checksums(
+					store,
+					file_path,
+					checksums,
+					state.init.checksum_conflicts,
+					&relative_path,
+					&mut data.report,
+				)
+				.await?;
+			}
+
+			if let Some(callback) = &state.init.on_file_validated {
+				if let Err(e) = callback.call(file_path, &outcome).await {
+					error!("Validation callback failed for {relative_path}: {e}");
+					if callback.fails_step_on_error() {
+						errors.push(format!("{relative_path}: validation callback failed: {e}"));
+					}
+				}
+			}
+
+			match &outcome {
+				FileValidationOutcome::Checksummed
+				| FileValidationOutcome::Verified
+				| FileValidationOutcome::VerifiedRemotely
+				| FileValidationOutcome::Acknowledged { .. } => {
+					healthy_objects.extend(object_id(file_path));
+				}
+				outcome if outcome.is_failure() => failed_objects.extend(object_id(file_path)),
+				_ => {}
+			}
 
-		Ok(Some(serde_json::to_value(&state.init)?))
+			if let FileValidationOutcome::Failed { reason } = &outcome {
+				errors.push(format!("{relative_path}: {reason}"));
+				failures.push(ValidationFailure {
+					path: data.location_path.join(&relative_path),
+					reason: reason.clone(),
+				});
+			}
+
+			if let Some(block_len) = reduced_block_len {
+				data.report
+					.memory_fallbacks
+					.insert(relative_path.clone(), block_len);
+			}
+
+			if let Some(ranges) = corrupt_ranges {
+				data.report
+					.corrupt_ranges
+					.insert(relative_path.clone(), ranges);
+			}
+
+			if let Some(mismatch) = extension_mismatch {
+				data.report
+					.extension_mismatches
+					.insert(relative_path.clone(), mismatch);
+			}
+
+			step_totals.record(&outcome);
+			data.report.files.insert(relative_path, outcome);
+		}
+
+		let outcome_tags = state.init.outcome_tags();
+		if !outcome_tags.is_empty() {
+			outcome_tags
+				.apply(
+					&ctx.library,
+					healthy_objects.iter().copied(),
+					failed_objects.iter().copied(),
+				)
+				.await?;
+		}
+		record_verifications(
+			&ctx.library,
+			self.sync_pacer(&state.init),
+			Utc::now(),
+			healthy_objects,
+			failed_objects,
+		)
+		.await?;
+
+		if !requeued.is_empty() {
+			data.task_count += requeued.len();
+			data.total_bytes = data.total_bytes.saturating_add(total_len(&requeued));
+			state
+				.steps
+				.extend(requeued.into_iter().map(|copy| vec![copy]));
+
+			progress.progress(vec![JobReportUpdate::TaskCount(data.task_count)]);
+		}
+
+		if let (Some(url), false) = (&state.init.on_failure_webhook, failures.is_empty()) {
+			// the webhook is only for monitoring, it failing doesn't fail the validation
+			if let Err(e) = send_failure_webhook(
+				url,
+				&ValidationFailuresPayload {
+					job_id: ctx.job_id,
+					library_id: ctx.library.id,
+					location_id: state.init.location.id,
+					failures: &failures,
+				},
+			)
+			.await
+			{
+				warn!("Failed to send validation failures to {url}: {e}");
+			}
+		}
+
+		if let Some(delay) = state.init.max_read_rate.and_then(|max_read_rate| {
+			read_rate_delay(read_len, max_read_rate, step_started_at.elapsed())
+		}) {
+			tokio::time::sleep(delay).await;
+		}
+
+		data.total_bytes = data
+			.total_bytes
+			.saturating_sub(step_len)
+			.saturating_add(read_len);
+		data.completed_bytes = data.completed_bytes.saturating_add(read_len);
+		data.totals.add(step_totals);
+		let mut updates = vec![
+			JobReportUpdate::CompletedTaskCount(state.step_number + 1),
+			JobReportUpdate::Message(data.totals.summary()),
+		];
+		if data.total_bytes > 0 {
+			updates.push(JobReportUpdate::ByteProgress {
+				completed: data.completed_bytes,
+				total: data.total_bytes,
+			});
+		}
+		progress.progress(updates);
+		progress.flush();
+
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(JobError::StepCompletedWithErrors(errors))
+		}
+	}
+
+	async fn wait_reason(&self, ctx: &WorkerContext, state: &JobState<Self>) -> Option<String> {
+		if let Some(reason) = match &state.data {
+			Some(data) => location_unavailable_reason(&data.location_path).await,
+			None => None,
+		} {
+			return Some(reason);
+		}
+
+		// the sync operations of the last steps went over the rate
+		if let Some(sync_pacer) = self.sync_pacer(&state.init) {
+			if let Some(delay) = sync_pacer.delay() {
+				return Some(format!(
+					"Paused: pacing sync operations to {} per second, resuming in {}s",
+					sync_pacer.rate().0,
+					delay.as_secs() + 1
+				));
+			}
+		}
+
+		let min_free_space = state.init.min_free_space?;
+		let libraries_dir = ctx.library.config().data_directory().join("libraries");
+
+		low_free_space_reason(
+			&libraries_dir,
+			available_space(&libraries_dir).await?,
+			min_free_space,
+		)
+	}
+
+	async fn finalize(&mut self, ctx: &mut WorkerContext, state: &mut JobState<Self>) -> JobResult {
+		let data = extract_job_data_mut!(state);
+		info!(
+			"finalizing validator job at {}{}: {} tasks",
+			data.location_path.display(),
+			state
+				.init
+				.sub_path
+				.as_ref()
+				.map(|p| format!("{}", p.display()))
+				.unwrap_or_default(),
+			data.task_count
+		);
+
+		data.report.extrapolate_sample();
+		data.report.failures_by_directory = data.report.group_failures_by_directory();
+
+		if state.init.par2_aware {
+			let failed = data
+				.report
+				.files
+				.iter()
+				.filter(|(_, outcome)| matches!(outcome, FileValidationOutcome::Failed { .. }))
+				.map(|(relative_path, _)| relative_path.clone())
+				.collect::<Vec<_>>();
+			if !failed.is_empty() {
+				data.report.par2_recoverability =
+					par2_recoverability(data.location_path.clone(), failed).await;
+			}
+		}
+
+		if state.init.merge_confirmed_duplicates {
+			data.report.merged_objects = merge_confirmed_duplicates(
+				&ctx.library,
+				state.init.location.id,
+				state.init.duplicate_strategy,
+			)
+			.await?;
+			if data.report.merged_objects > 0 {
+				info!("Merged {} duplicate objects", data.report.merged_objects);
+				invalidate_query!(ctx.library, "search.objects");
+			}
+		}
+
+		if !state.init.outcome_tags().is_empty() {
+			invalidate_query!(ctx.library, "tags.getForObject");
+		}
+
+		// for the verification dates and results of their objects
+		if !data.report.files.is_empty() {
+			invalidate_query!(ctx.library, "search.paths");
+			invalidate_query!(ctx.library, "search.objects");
+		}
+
+		if data.report.pruned_count() > 0 {
+			ctx.library.orphan_remover.invoke().await;
+			invalidate_query!(ctx.library, "search.paths");
+		}
+
+		// checksums stored without being synced by this job, or earlier ones, are synced now
+		data.report.sync_deferred = LibraryChecksumStore::new(&ctx.library)
+			.with_sync_pacer(self.sync_pacer(&state.init))
+			.sync_deferred()
+			.await?;
+		if data.report.sync_deferred > 0 {
+			warn!(
+				"Checksums of {} files are only stored on this node, as syncing them failed",
+				data.report.sync_deferred
+			);
+		}
+
+		// audits leave the library as it was
+		if data.manifest.is_none() {
+			data.report.aggregate_checksum =
+				update_aggregate_checksum(&ctx.library, &state.init.location, data.algorithm)
+					.await?;
+			invalidate_query!(ctx.library, "locations.list");
+
+			if state.init.tree_checksums {
+				data.report.tree_checksum_roots =
+					update_tree_checksums(&ctx.library, state.init.location.id, data.algorithm)
+						.await?;
+				invalidate_query!(ctx.library, "search.paths");
+			}
+		}
+
+		if state.init.cross_check_cas {
+			data.report.cas_disagreements =
+				cross_check_cas(&ctx.library, state.init.location.id, data.algorithm).await?;
+			if !data.report.cas_disagreements.is_empty() {
+				warn!(
+					"Found {} groups of files whose cas_id and checksum disagree in location {}",
+					data.report.cas_disagreements.len(),
+					state.init.location.id
+				);
+			}
+		}
+
+		if let (Some(dir), Some(manifest), Some(checksums)) = (
+			&state.init.attest_to,
+			&state.init.verify_against,
+			&data.manifest,
+		) {
+			match attest_audit(
+				&ctx.library,
+				&data.location_path,
+				manifest,
+				checksums,
+				&data.report,
+				dir,
+			)
+			.await?
+			{
+				Some(attestation) => {
+					info!(
+						"Attested all {} files of location {} matched the manifest to {}",
+						attestation.files,
+						state.init.location.id,
+						dir.display()
+					);
+					data.report.attested_to = Some(dir.clone());
+				}
+				None => error!(
+					"Not attesting the audit of location {}, not all of its files matched the manifest",
+					state.init.location.id
+				),
+			}
+		}
+
+		ctx.library.emit(CoreEvent::ValidationCompleted(
+			ValidationCompletedEvent::new(
+				ctx.job_id,
+				ctx.library.id,
+				&data.report,
+				Utc::now() - ctx.started_at,
+			),
+		));
+
+		Ok(Some(serde_json::to_value(&data.report)?))
+	}
+}
+
+async fn prune_file_path(
+	library: &Library,
+	file_path: &file_path_for_object_validator::Data,
+) -> Result<(), JobError> {
+	let Library { db, sync, .. } = library;
+
+	remove_from_stored_aggregate(library, &file_path.pub_id).await?;
+
+	sync.write_op(
+		db,
+		sync.shared_delete(sync::file_path::SyncId {
+			pub_id: file_path.pub_id.clone(),
+		}),
+		db.file_path()
+			.delete(file_path::pub_id::equals(file_path.pub_id.clone())),
+	)
+	.await?;
+
+	Ok(())
+}
+
+/// Stores the checksums of a file only if its checksum is still the one it was read with, those
+/// generated for a file that had none being stored regardless. Conflicts with another writer are
+/// reported and settled per `policy`.
+async fn store_checksums(
+	store: &dyn ChecksumStore,
+	file_path: &file_path_for_object_validator::Data,
+	checksums: StoredChecksums,
+	policy: ChecksumConflictPolicy,
+	relative_path: &str,
+	report: &mut ObjectValidatorReport,
+) -> Result<(), ValidatorError> {
+	if file_path.integrity_checksum.is_none() {
+		return store.put(file_path, checksums).await;
+	}
+
+	let ours = checksums.checksum.clone();
+	let ChecksumWrite::Conflict { found } =
+		store.compare_and_put(file_path, checksums.clone()).await?
+	else {
+		return Ok(());
+	};
+
+	let overwritten = policy == ChecksumConflictPolicy::Overwrite;
+	let settled = if overwritten { "replacing" } else { "keeping" };
+	warn!(
+		"The checksum of {relative_path} was changed to {} while it was validated, {settled} it",
+		found.as_deref().unwrap_or("none")
+	);
+	if overwritten {
+		store.put(file_path, checksums).await?;
+	}
+
+	report.checksum_conflicts.insert(
+		relative_path.to_string(),
+		ChecksumConflict {
+			ours,
+			theirs: found,
+			overwritten,
+		},
+	);
+
+	Ok(())
+}
+
+/// Gives the copies of a file sharing all its extents the checksums computed for it
+async fn store_reflink_copies(
+	store: &dyn ChecksumStore,
+	location_id: location::id::Type,
+	copies: Vec<file_path_for_object_validator::Data>,
+	of: &str,
+	checksums: &StoredChecksums,
+	report: &mut ObjectValidatorReport,
+) -> Result<(), JobError> {
+	for copy in copies {
+		store.put(&copy, checksums.clone()).await?;
+		report.files.insert(
+			IsolatedFilePathData::try_from((location_id, &copy))?.to_string(),
+			FileValidationOutcome::ReflinkCopy { of: of.to_string() },
+		);
+	}
+
+	Ok(())
+}
+
+/// The library's rows don't hold the checksums persisted elsewhere, so they're replaced by the
+/// ones in `store` to tell which files already have one
+async fn with_stored_checksums(
+	store: &dyn ChecksumStore,
+	file_paths: &[file_path_for_object_validator::Data],
+) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError> {
+	let mut with_stored = Vec::with_capacity(file_paths.len());
+	for file_path in file_paths {
+		let stored = store.get(file_path).await?.unwrap_or_default();
+		with_stored.push(file_path_for_object_validator::Data {
+			integrity_checksum_algorithm: stored
+				.checksum
+				.is_some()
+				.then(|| stored.algorithm.db_name(stored.include_alt_streams)),
+			integrity_checksum: stored.checksum,
+			content_checksum: stored.content_checksum,
+			..file_path.clone()
+		});
+	}
+
+	Ok(with_stored)
+}
+
+async fn ensure_is_directory(path: &Path) -> Result<(), ValidatorError> {
+	match fs::metadata(path).await {
+		Ok(metadata) if metadata.is_dir() => Ok(()),
+		Err(e) if e.kind() != io::ErrorKind::NotFound => Err(FileIOError::from((path, e)).into()),
+		_ => Err(ValidatorError::LocationPathOverrideNotDirectory(
+			path.into(),
+		)),
+	}
+}
+
+/// Makes sure a location path override isn't some unrelated directory, by checking that at least
+/// one of the first few files we're about to validate is there
+async fn ensure_holds_location_files(
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_paths: impl Iterator<Item = &file_path_for_object_validator::Data>,
+) -> Result<(), ValidatorError> {
+	const FILES_TO_CHECK: usize = 8;
+
+	let mut checked_any = false;
+	for file_path in file_paths.take(FILES_TO_CHECK) {
+		checked_any = true;
+
+		let full_path =
+			location_path.join(IsolatedFilePathData::try_from((location_id, file_path))?);
+		if fs::metadata(&full_path).await.is_ok() {
+			return Ok(());
+		}
+	}
+
+	if checked_any {
+		Err(ValidatorError::LocationPathOverrideMismatch(
+			location_path.into(),
+		))
+	} else {
+		// nothing to validate, so nothing will be read from it either
+		Ok(())
+	}
+}
+
+/// Lists the files missing a checksum for `algorithm`, or all of them to audit them against
+/// `manifest`, already split into steps
+async fn enumerate_steps(
+	library: &Library,
+	init: &ObjectValidatorJobInit,
+	sync_pacer: Option<&SyncPacer>,
+	location_path: &Path,
+	algorithm: ChecksumAlgorithm,
+	manifest: Option<&HashMap<String, String>>,
+	report: &mut ObjectValidatorReport,
+	reflink_copies: &mut HashMap<file_path::id::Type, Vec<file_path_for_object_validator::Data>>,
+) -> Result<VecDeque<Vec<file_path_for_object_validator::Data>>, JobError> {
+	let Library { db, .. } = library;
+
+	let location_id = init.location.id;
+
+	let maybe_sub_iso_file_path = match &init.sub_path {
+		Some(sub_path) if sub_path != Path::new("") && sub_path != Path::new("/") => {
+			let full_path = ensure_sub_path_is_in_location(location_path, sub_path)
+				.await
+				.map_err(ValidatorError::from)?;
+			ensure_sub_path_is_directory(location_path, sub_path)
+				.await
+				.map_err(ValidatorError::from)?;
+
+			let sub_iso_file_path =
+				IsolatedFilePathData::new(location_id, location_path, &full_path, true)
+					.map_err(ValidatorError::from)?;
+
+			ensure_file_path_exists(
+				sub_path,
+				&sub_iso_file_path,
+				db,
+				ValidatorError::SubPathNotFound,
+			)
+			.await?;
+
+			Some(sub_iso_file_path)
+		}
+		_ => None,
+	};
+
+	if let Some(file_path_pub_ids) = &init.file_path_pub_ids {
+		ensure_file_paths_in_location(db, location_id, file_path_pub_ids).await?;
+	}
+
+	let source = LibraryStepSource(db);
+	let scope = FileScope {
+		sub_path: maybe_sub_iso_file_path.as_ref(),
+		pub_ids: init.file_path_pub_ids.as_deref(),
+		modified_within: init.modified_within.as_ref(),
+		object_kinds: init.object_kinds.as_deref(),
+		favorites_only: init.favorites_only,
+		checksummed_with: init.migrate_from,
+		include_alt_streams: init.include_alt_streams,
+		excluded: false,
+	};
+
+	if init.favorites_only {
+		report.favorites = Some(
+			db.file_path()
+				.count(scope_filters(location_id, scope, None))
+				.exec()
+				.await? as usize,
+		);
+	}
+
+	report.excluded = source.count_excluded(location_id, scope).await?;
+
+	// audits and checksums of byte ranges are compared with what the files had, so they're all
+	// listed instead of only those missing a checksum
+	let whole_files = manifest.is_none() && init.range.is_none();
+
+	let file_paths = if whole_files {
+		// every file is compared with its mirror copy, not only the ones missing a checksum
+		let file_paths = if init.mirror_root.is_some() {
+			source.all_file_paths(location_id, scope).await?
+		} else {
+			source
+				.file_paths(location_id, scope, algorithm, init.media_normalize)
+				.await?
+		};
+		if init.skips_empty() {
+			let file_paths =
+				stat_empty_files(&source, location_id, location_path, file_paths, report).await?;
+			let (file_paths, skipped) = skip_empty_files(file_paths);
+			report.skipped_empty = skipped;
+			file_paths
+		} else {
+			file_paths
+		}
+	} else {
+		let file_paths = source.all_file_paths(location_id, scope).await?;
+		// files outside the window or the selection, of other kinds or not favorites aren't
+		// missing, they just weren't listed, nor are excluded ones. Manifests of byte ranges are
+		// keyed by range, not by path.
+		if let (Some(manifest), None, None, None, false, None) = (
+			manifest,
+			&init.file_path_pub_ids,
+			&init.modified_within,
+			&init.object_kinds,
+			init.favorites_only,
+			&init.range,
+		) {
+			let excluded = source.excluded_file_paths(location_id, scope).await?;
+			report_missing_from_location(
+				location_id,
+				manifest,
+				file_paths.iter().chain(&excluded),
+				maybe_sub_iso_file_path.as_ref(),
+				report,
+			)?;
+		}
+		file_paths
+	};
+
+	let (mut file_paths, collisions) =
+		split_case_collisions(location_id, location_path, file_paths).await?;
+	for (relative_path, with) in collisions {
+		warn!(
+			"{relative_path} is the same file as {with} in location {location_id}, only validating the latter"
+		);
+		report
+			.files
+			.insert(relative_path, FileValidationOutcome::CaseCollision { with });
+	}
+
+	// imported and copied checksums are stored like computed ones, in the job's store
+	let library_store = LibraryChecksumStore::new(library).with_sync_pacer(sync_pacer);
+	let store = init
+		.checksum_store
+		.as_deref()
+		.unwrap_or(&library_store as &dyn ChecksumStore);
+
+	// the library's checksums are not trusted when auditing, so neither are imported ones, which
+	// are of whole files anyway, and only cover their main stream
+	if let Some(source) = init
+		.seed_from
+		.as_ref()
+		.filter(|_| whole_files && !init.include_alt_streams)
+	{
+		file_paths = seed_checksums(
+			store,
+			location_id,
+			source,
+			file_paths,
+			algorithm,
+			init.media_normalize,
+			report,
+		)
+		.await?;
+	}
+
+	if init.cross_location_dedup && whole_files {
+		file_paths = copy_cross_location_checksums(
+			library,
+			store,
+			init,
+			location_path,
+			file_paths,
+			algorithm,
+			report,
+		)
+		.await?;
+	}
+
+	report.sample = init.sample.is_some().then(|| ReportSample {
+		population: file_paths.len(),
+		..Default::default()
+	});
+
+	let mut file_paths = match &init.sample {
+		Some(spec) => sample_file_paths(file_paths, spec),
+		None => file_paths,
+	};
+
+	reflink_copies.clear();
+	if init.dedup_reflinks && whole_files {
+		file_paths = split_reflinks(location_id, location_path, file_paths, reflink_copies).await?;
+	}
+
+	if init.order == StepOrder::RecentlyAccessed {
+		order_by_recent_access(&mut file_paths);
+	}
+
+	Ok(if init.per_device_concurrency > 0 {
+		device_batches(
+			file_paths,
+			init.per_device_concurrency
+				.saturating_mul(FILES_PER_DEVICE_READ),
+		)
+		.into()
+	} else {
+		file_paths
+			.into_iter()
+			.map(|file_path| vec![file_path])
+			.collect()
+	})
+}
+
+/// Fails if any of the pub ids isn't one of a file path of the location, the selection must have
+/// come from another location or be stale
+async fn ensure_file_paths_in_location(
+	db: &PrismaClient,
+	location_id: location::id::Type,
+	file_path_pub_ids: &[Vec<u8>],
+) -> Result<(), ValidatorError> {
+	let found = db
+		.file_path()
+		.count(vec![
+			file_path::location_id::equals(Some(location_id)),
+			file_path::pub_id::in_vec(file_path_pub_ids.to_vec()),
+		])
+		.exec()
+		.await? as usize;
+
+	let requested = file_path_pub_ids.iter().collect::<HashSet<_>>().len();
+	if found < requested {
+		return Err(ValidatorError::FilePathsNotInLocation {
+			location_id,
+			count: requested - found,
+		});
+	}
+
+	Ok(())
+}
+
+/// Gives the files the indexer found to be empty their size on disk, so the ones it got wrong
+/// aren't skipped for being empty, reporting them
+async fn stat_empty_files(
+	source: &impl StepSource,
+	location_id: location::id::Type,
+	location_path: &Path,
+	mut file_paths: Vec<file_path_for_object_validator::Data>,
+	report: &mut ObjectValidatorReport,
+) -> Result<Vec<file_path_for_object_validator::Data>, JobError> {
+	for file_path in &mut file_paths {
+		if size_in_bytes(file_path) != Some(0) {
+			continue;
+		}
+
+		let iso_file_path = IsolatedFilePathData::try_from((location_id, &*file_path))?;
+		let full_path = extended_length_path(location_path.join(&iso_file_path));
+		if let Some(mismatch) = size_mismatch(Some(0), source.file_len(&full_path).await.ok()) {
+			warn!(
+				"{iso_file_path} is {} bytes long but was indexed as empty",
+				mismatch.actual
+			);
+			report
+				.size_mismatches
+				.insert(iso_file_path.to_string(), mismatch);
+			file_path.size_in_bytes_bytes = Some(mismatch.actual.to_be_bytes().to_vec());
+		}
+	}
+
+	Ok(file_paths)
+}
+
+/// Drops the files the indexer found to be empty, returning how many there were. Those the
+/// indexer didn't record the size of are kept.
+fn skip_empty_files(
+	file_paths: Vec<file_path_for_object_validator::Data>,
+) -> (Vec<file_path_for_object_validator::Data>, usize) {
+	let mut skipped = 0;
+	let file_paths = file_paths
+		.into_iter()
+		.filter(|file_path| {
+			let empty = size_in_bytes(file_path) == Some(0);
+			skipped += usize::from(empty);
+			!empty
+		})
+		.collect();
+
+	(file_paths, skipped)
+}
+
+/// Files removed from the location since the manifest was signed are deviations too
+fn report_missing_from_location<'a>(
+	location_id: location::id::Type,
+	manifest: &HashMap<String, String>,
+	file_paths: impl IntoIterator<Item = &'a file_path_for_object_validator::Data>,
+	maybe_sub_iso_file_path: Option<&IsolatedFilePathData<'_>>,
+	report: &mut ObjectValidatorReport,
+) -> Result<(), JobError> {
+	let listed = file_paths
+		.into_iter()
+		.map(|file_path| {
+			IsolatedFilePathData::try_from((location_id, file_path))
+				.map(|iso_file_path| iso_file_path.to_string())
+		})
+		.collect::<Result<HashSet<_>, _>>()?;
+	let sub_path = maybe_sub_iso_file_path.map(ToString::to_string);
+
+	for relative_path in missing_from_location(manifest, &listed, sub_path.as_deref()) {
+		warn!("{relative_path} is listed in the manifest but missing from location {location_id}");
+		report.files.insert(
+			relative_path.to_string(),
+			FileValidationOutcome::Failed {
+				reason: "listed in the manifest but missing from the location".to_string(),
+			},
+		);
+	}
+
+	Ok(())
+}
+
+#[cfg(unix)]
+type FileIdentity = (u64, u64);
+#[cfg(not(unix))]
+type FileIdentity = PathBuf;
+
+async fn file_identity(path: &Path) -> Option<FileIdentity> {
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::MetadataExt;
+
+		fs::metadata(path)
+			.await
+			.ok()
+			.map(|metadata| (metadata.dev(), metadata.ino()))
+	}
+
+	#[cfg(not(unix))]
+	{
+		fs::canonicalize(path).await.ok()
+	}
+}
+
+/// On case insensitive file systems, file paths only differing in case are the same file. Only
+/// the first of them is kept, so we don't hash the same bytes twice and store conflicting rows.
+/// Returns the kept file paths and the relative paths of the dropped ones, along with the
+/// relative path of the file path they collide with.
+async fn split_case_collisions(
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_paths: Vec<file_path_for_object_validator::Data>,
+) -> Result<
+	(
+		Vec<file_path_for_object_validator::Data>,
+		Vec<(String, String)>,
+	),
+	ValidatorError,
+> {
+	let relative_paths = file_paths
+		.iter()
+		.map(|file_path| {
+			IsolatedFilePathData::try_from((location_id, file_path)).map(|iso| iso.to_string())
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let mut by_lowercase_path = HashMap::<_, Vec<_>>::new();
+	for (index, relative_path) in relative_paths.iter().enumerate() {
+		by_lowercase_path
+			.entry(relative_path.to_lowercase())
+			.or_default()
+			.push(index);
+	}
+
+	let mut collisions = BTreeMap::new();
+	for indexes in by_lowercase_path
+		.into_values()
+		.filter(|indexes| indexes.len() > 1)
+	{
+		let mut seen = Vec::<(FileIdentity, usize)>::with_capacity(indexes.len());
+		for index in indexes {
+			let Some(identity) = file_identity(&location_path.join(&relative_paths[index])).await
+			else {
+				continue;
+			};
+
+			match seen
+				.iter()
+				.find(|(seen_identity, _)| *seen_identity == identity)
+			{
+				Some((_, kept)) => {
+					collisions.insert(index, relative_paths[*kept].clone());
+				}
+				None => seen.push((identity, index)),
+			}
+		}
+	}
+
+	if collisions.is_empty() {
+		return Ok((file_paths, vec![]));
+	}
+
+	let mut kept = Vec::with_capacity(file_paths.len() - collisions.len());
+	let mut dropped = Vec::with_capacity(collisions.len());
+	for (index, (file_path, relative_path)) in
+		file_paths.into_iter().zip(relative_paths).enumerate()
+	{
+		match collisions.remove(&index) {
+			Some(with) => dropped.push((relative_path, with)),
+			None => kept.push(file_path),
+		}
+	}
+
+	Ok((kept, dropped))
+}
+
+/// Keeps one of each set of files sharing all their extents, the others are left in `copies`,
+/// keyed by the id of the kept one, so they're given its checksum instead of being read
+async fn split_reflinks(
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_paths: Vec<file_path_for_object_validator::Data>,
+	copies: &mut HashMap<file_path::id::Type, Vec<file_path_for_object_validator::Data>>,
+) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError> {
+	let mut with_layouts = Vec::with_capacity(file_paths.len());
+	for file_path in file_paths {
+		let iso_file_path = IsolatedFilePathData::try_from((location_id, &file_path))?;
+		let layout = shared_extent_layout(&location_path.join(&iso_file_path)).await;
+		with_layouts.push((file_path, layout));
+	}
+
+	Ok(group_reflinks(with_layouts)
+		.into_iter()
+		.map(|(kept, kept_copies)| {
+			if !kept_copies.is_empty() {
+				copies.insert(kept.id, kept_copies);
+			}
+			kept
+		})
+		.collect())
+}
+
+/// Groups files by their backing device, each step taking up to `per_device_concurrency` files
+/// from every device so drives are read in parallel without thrashing any of them.
+/// Files with an unknown device are grouped together.
+fn device_batches(
+	file_paths: Vec<file_path_for_object_validator::Data>,
+	per_device_concurrency: usize,
+) -> Vec<Vec<file_path_for_object_validator::Data>> {
+	let mut by_device = BTreeMap::<_, VecDeque<_>>::new();
+	for file_path in file_paths {
+		by_device
+			.entry(file_path.device.clone())
+			.or_default()
+			.push_back(file_path);
+	}
+
+	let mut batches = vec![];
+	while !by_device.is_empty() {
+		let mut batch = vec![];
+		by_device.retain(|_, device_file_paths| {
+			let take = per_device_concurrency.min(device_file_paths.len());
+			batch.extend(device_file_paths.drain(..take));
+			!device_file_paths.is_empty()
+		});
+		batches.push(batch);
+	}
+
+	batches
+}
+
+/// Runs `validate` on every file, up to `per_device_concurrency` at once from each device, one at
+/// a time when 0, every device going through its files at its own pace. The results are in the
+/// order of `file_paths`.
+async fn validate_per_device<'a, T, Fut>(
+	file_paths: &'a [file_path_for_object_validator::Data],
+	per_device_concurrency: usize,
+	validate: impl Fn(&'a file_path_for_object_validator::Data) -> Fut,
+) -> Vec<T>
+where
+	Fut: Future<Output = T>,
+{
+	let mut by_device = BTreeMap::<_, Vec<_>>::new();
+	for (index, file_path) in file_paths.iter().enumerate() {
+		by_device
+			.entry(&file_path.device)
+			.or_default()
+			.push((index, file_path));
+	}
+
+	let validate = &validate;
+	let mut validated = join_all(by_device.into_values().map(|device_file_paths| {
+		stream::iter(device_file_paths)
+			.map(|(index, file_path)| async move { (index, validate(file_path).await) })
+			.buffer_unordered(per_device_concurrency.max(1))
+			.collect::<Vec<_>>()
+	}))
+	.await
+	.into_iter()
+	.flatten()
+	.collect::<Vec<_>>();
+
+	validated.sort_by_key(|(index, _)| *index);
+	validated
+		.into_iter()
+		.map(|(_, validated)| validated)
+		.collect()
+}
+
+/// Stores the external checksums we can use and returns the file paths still needing validation
+async fn seed_checksums(
+	store: &dyn ChecksumStore,
+	location_id: location::id::Type,
+	source: &ExternalChecksumSource,
+	file_paths: Vec<file_path_for_object_validator::Data>,
+	algorithm: ChecksumAlgorithm,
+	media_normalize: bool,
+	report: &mut ObjectValidatorReport,
+) -> Result<Vec<file_path_for_object_validator::Data>, JobError> {
+	let external_checksums = source.load().await?;
+	let mut remaining = Vec::with_capacity(file_paths.len());
+
+	for mut file_path in file_paths {
+		let relative_path = IsolatedFilePathData::try_from((location_id, &file_path))?.to_string();
+
+		let Some(checksum) = external_checksums
+			.get(&relative_path)
+			.and_then(|checksum| checksum.as_integrity_checksum(algorithm))
+			.filter(|_| !has_checksum_for(&file_path, algorithm, false))
+		else {
+			remaining.push(file_path);
+			continue;
+		};
+
+		let seeded = store
+			.compare_and_put(
+				&file_path,
+				StoredChecksums {
+					checksum: Some(checksum.to_string()),
+					algorithm,
+					checksum_source: Some(source.name().to_string()),
+					..Default::default()
+				},
+			)
+			.await?;
+		// another writer stored a checksum meanwhile, so the file is hashed like the others
+		if let ChecksumWrite::Conflict { .. } = seeded {
+			remaining.push(file_path);
+			continue;
+		}
+
+		report.files.insert(
+			relative_path,
+			FileValidationOutcome::Seeded {
+				source: source.name().to_string(),
+			},
+		);
+
+		// the media content checksum still needs to be computed from the file
+		if media_normalize && file_path.content_checksum.is_none() {
+			file_path.integrity_checksum = Some(checksum.to_string());
+			file_path.integrity_checksum_algorithm = Some(algorithm.as_str().to_string());
+			remaining.push(file_path);
+		}
+	}
+
+	Ok(remaining)
+}
+
+/// Gives the files also reachable through other locations of this node overlapping this one the
+/// checksum already computed there, returning the files still needing to be hashed
+async fn copy_cross_location_checksums(
+	library: &Library,
+	store: &dyn ChecksumStore,
+	init: &ObjectValidatorJobInit,
+	location_path: &Path,
+	file_paths: Vec<file_path_for_object_validator::Data>,
+	algorithm: ChecksumAlgorithm,
+	report: &mut ObjectValidatorReport,
+) -> Result<Vec<file_path_for_object_validator::Data>, JobError> {
+	let Library { db, .. } = library;
+
+	let location_id = init.location.id;
+	let custom_store = init.checksum_store.as_deref();
+
+	// locations pointing at the same directory through symlinks have to be told apart by their
+	// canonical paths
+	let location_path = fs::canonicalize(location_path)
+		.await
+		.map_err(|e| ValidatorError::from(FileIOError::from((location_path, e))))?;
+
+	let other_locations = db
+		.location()
+		.find_many(vec![
+			location::id::not(location_id),
+			location::node_id::equals(Some(library.node_local_id)),
+		])
+		.exec()
+		.await?;
+
+	// absolute path of every checksummed file of the overlapping locations, to the location and
+	// path it was found at
+	let mut twins = HashMap::new();
+	for other_location in other_locations {
+		// offline locations can't be told to overlap
+		let Some(other_path) = &other_location.path else {
+			continue;
+		};
+		let Ok(other_path) = fs::canonicalize(other_path).await else {
+			continue;
+		};
+		if !locations_overlap(&location_path, &other_path) {
+			continue;
+		}
+
+		let other_file_paths = db
+			.file_path()
+			.find_many(vec![
+				file_path::location_id::equals(Some(other_location.id)),
+				file_path::is_dir::equals(Some(false)),
+			])
+			.select(file_path_for_object_validator::select())
+			.exec()
+			.await?;
+		let other_file_paths = match custom_store {
+			Some(store) => with_stored_checksums(store, &other_file_paths).await?,
+			None => other_file_paths,
+		};
+
+		for twin in other_file_paths
+			.into_iter()
+			.filter(|twin| has_checksum_for(twin, algorithm, init.include_alt_streams))
+		{
+			let relative_path = IsolatedFilePathData::try_from((other_location.id, &twin))
+				.map_err(ValidatorError::from)?;
+			twins.entry(other_path.join(&relative_path)).or_insert((
+				other_location.id,
+				relative_path.to_string(),
+				twin,
+			));
+		}
+	}
+
+	if twins.is_empty() {
+		return Ok(file_paths);
+	}
+
+	let mut remaining = Vec::with_capacity(file_paths.len());
+	for mut file_path in file_paths {
+		let relative_path = IsolatedFilePathData::try_from((location_id, &file_path))
+			.map_err(ValidatorError::from)?;
+
+		let Some((other_location_id, other_relative_path, twin)) = twins
+			.get(&location_path.join(&relative_path))
+			.filter(|_| !has_checksum_for(&file_path, algorithm, init.include_alt_streams))
+		else {
+			remaining.push(file_path);
+			continue;
+		};
+
+		store
+			.put(
+				&file_path,
+				StoredChecksums {
+					checksum: twin.integrity_checksum.clone(),
+					algorithm,
+					include_alt_streams: init.include_alt_streams,
+					checksum_source: None,
+					content_checksum: twin.content_checksum.clone(),
+					content_type: None,
+					perceptual_hash: None,
+					range_checksum: None,
+				},
+			)
+			.await?;
+
+		report.files.insert(
+			relative_path.to_string(),
+			FileValidationOutcome::CrossLocationCopy {
+				location_id: *other_location_id,
+				path: other_relative_path.clone(),
+			},
+		);
+		report.cross_location_duplicates += 1;
+
+		// the media content checksum still needs to be computed from the file
+		if init.media_normalize
+			&& file_path.content_checksum.is_none()
+			&& twin.content_checksum.is_none()
+		{
+			file_path.integrity_checksum = twin.integrity_checksum.clone();
+			file_path.integrity_checksum_algorithm = twin.integrity_checksum_algorithm.clone();
+			remaining.push(file_path);
+		}
+	}
+
+	Ok(remaining)
+}
+
+/// Whether one of the canonical paths is inside the other, so their locations share files
+fn locations_overlap(location_path: &Path, other_path: &Path) -> bool {
+	location_path.starts_with(other_path) || other_path.starts_with(location_path)
+}
+
+/// How long to wait after reading `len` bytes in `elapsed` to average `max_read_rate` bytes per
+/// second
+fn read_rate_delay(len: u64, max_read_rate: u64, elapsed: Duration) -> Option<Duration> {
+	if max_read_rate == 0 {
+		return None;
+	}
+
+	Duration::from_secs_f64(len as f64 / max_read_rate as f64)
+		.checked_sub(elapsed)
+		.filter(|delay| !delay.is_zero())
+}
+
+/// Shown in the job's progress while it waits for the location to come back, when its path can't
+/// be reached as a whole, like a removable drive that was unplugged, unlike a single file missing
+async fn location_unavailable_reason(location_path: &Path) -> Option<String> {
+	match fs::metadata(location_path).await {
+		Ok(metadata) if metadata.is_dir() => None,
+		Ok(_) => Some(format!(
+			"Waiting for location: {} isn't a directory anymore",
+			location_path.display()
+		)),
+		Err(e) => Some(format!(
+			"Waiting for location: {} is unavailable ({e}), resuming once it's back",
+			location_path.display()
+		)),
+	}
+}
+
+/// Files are only pruned from a location root that's there and holds something: a drive that
+/// isn't mounted leaves an empty directory where every file would look vanished
+async fn ensure_location_root_present(location_path: &Path) -> Result<(), ValidatorError> {
+	let missing = || ValidatorError::PruneLocationRootMissing(location_path.into());
+
+	let mut read_dir = fs::read_dir(location_path).await.map_err(|_| missing())?;
+	match read_dir.next_entry().await {
+		Ok(Some(_)) => Ok(()),
+		_ => Err(missing()),
+	}
+}
+
+/// Shown in the job's progress while it waits for space to be freed
+fn low_free_space_reason(path: &Path, available: u64, min_free_space: u64) -> Option<String> {
+	(available < min_free_space).then(|| {
+		format!(
+			"Paused: {available} bytes free on the volume of {}, waiting for {min_free_space}",
+			path.display()
+		)
+	})
+}
+
+fn object_id(file_path: &file_path_for_object_validator::Data) -> Option<object::id::Type> {
+	file_path.object.as_ref().map(|object| object.id)
+}
+
+/// Checksums stored without an algorithm were computed before we supported others than blake3.
+/// Those computed with `include_alt_streams` set otherwise aren't comparable to ours, so they
+/// don't count.
+pub(super) fn has_checksum_for(
+	file_path: &file_path_for_object_validator::Data,
+	algorithm: ChecksumAlgorithm,
+	include_alt_streams: bool,
+) -> bool {
+	file_path.integrity_checksum.is_some()
+		&& ChecksumAlgorithm::from_db_in_mode(
+			file_path.integrity_checksum_algorithm.as_deref(),
+			include_alt_streams,
+		) == Some(algorithm)
+}
+
+fn order_by_recent_access(file_paths: &mut [file_path_for_object_validator::Data]) {
+	// stable sort, so files with the same dates keep the database order
+	file_paths.sort_by_key(|file_path| {
+		Reverse(
+			file_path
+				.object
+				.as_ref()
+				.and_then(|object| object.date_accessed)
+				.or(file_path.date_modified),
+		)
+	});
+}
+
+/// Picks the files with the lowest seeded hashes of their pub ids, so a sample doesn't depend on
+/// the order the database returns them
+fn sample_file_paths(
+	mut file_paths: Vec<file_path_for_object_validator::Data>,
+	spec: &SampleSpec,
+) -> Vec<file_path_for_object_validator::Data> {
+	let count = match spec.size {
+		SampleSize::Fraction(fraction) => {
+			(file_paths.len() as f64 * fraction.clamp(0.0, 1.0)).ceil() as usize
+		}
+		SampleSize::Count(count) => count,
+	};
+
+	file_paths.sort_by_cached_key(|file_path| {
+		let mut hasher = blake3::Hasher::new();
+		hasher.update(&spec.seed.to_le_bytes());
+		hasher.update(&file_path.pub_id);
+		*hasher.finalize().as_bytes()
+	});
+	file_paths.truncate(count);
+
+	file_paths
+}
+
+/// How each file is validated, taken from the job's init and what we found out about the location
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct ValidationOptions {
+	pub(super) read: ReadOptions,
+	pub(super) media_normalize: bool,
+	pub(super) prune_missing: bool,
+	pub(super) detect_content_type: bool,
+	pub(super) perceptual: bool,
+	pub(super) flag_extension_mismatch: bool,
+	pub(super) skip_empty: bool,
+	pub(super) min_block_len: usize,
+	/// see [`ObjectValidatorJobInit::migrate_from`]
+	pub(super) migrate_from: Option<ChecksumAlgorithm>,
+}
+
+impl ValidationOptions {
+	fn new(init: &ObjectValidatorJobInit, data: &ObjectValidatorJobState) -> Self {
+		Self {
+			read: ReadOptions {
+				algorithm: data.algorithm,
+				bypass_page_cache: init.bypass_page_cache,
+				remote: data.remote_location,
+				read_timeout: init.read_timeout,
+				block_len: None,
+				include_alt_streams: init.include_alt_streams,
+			},
+			media_normalize: init.media_normalize,
+			prune_missing: init.prune_missing,
+			detect_content_type: init.detect_content_type,
+			perceptual: init.perceptual,
+			flag_extension_mismatch: init.flag_extension_mismatch,
+			skip_empty: init.skips_empty(),
+			min_block_len: init.min_read_buffer_len.unwrap_or(MIN_BLOCK_LEN),
+			migrate_from: init.migrate_from,
+		}
+	}
+}
+
+pub(super) struct ValidatedFile {
+	relative_path: String,
+	pub(super) outcome: FileValidationOutcome,
+	/// new checksum to be stored for the file
+	pub(super) checksum: Option<String>,
+	/// new media content checksum to be stored for the file
+	pub(super) content_checksum: Option<String>,
+	/// MIME type sniffed while computing the checksum
+	pub(super) content_type: Option<&'static str>,
+	/// perceptual hash of the image, decoded from the same read as its checksum
+	pub(super) perceptual_hash: Option<String>,
+	/// new checksum of the byte range to be stored for the file
+	range_checksum: Option<RangeChecksum>,
+	/// the type sniffed while computing the checksum contradicts the file's extension
+	extension_mismatch: Option<ExtensionMismatch>,
+	/// length of the reads when they had to be shortened for lack of memory
+	reduced_block_len: Option<usize>,
+	/// the `(offset, len)` byte ranges that differ from the chunk manifest, for corrupted files
+	/// it lists
+	corrupt_ranges: Option<Vec<(u64, u64)>>,
+	/// the file failed as it couldn't be read, which may not happen when trying again
+	read_failed: bool,
+	/// how it compares with its copy under the mirror root, `Ok` when they agree
+	mirror: Option<Result<(), MirrorDivergence>>,
+	/// size of the file on disk, `None` if it couldn't be statted
+	live_len: Option<u64>,
+}
+
+/// Windows can't open paths longer than `MAX_PATH` unless they have the `\\?\` extended-length
+/// prefix, which also turns off the parsing of `/` separators and of `.` and `..` components,
+/// so the path is rebuilt from its components
+#[cfg(target_os = "windows")]
+pub(super) fn extended_length_path(path: PathBuf) -> PathBuf {
+	use std::{
+		ffi::OsString,
+		path::{Component, Prefix},
+	};
+
+	let mut components = path.components();
+	let mut extended = match components.next() {
+		Some(Component::Prefix(prefix)) => match prefix.kind() {
+			Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:\", char::from(letter))),
+			Prefix::UNC(server, share) => {
+				let mut extended = OsString::from(r"\\?\UNC\");
+				extended.push(server);
+				extended.push(r"\");
+				extended.push(share);
+				extended.push(r"\");
+				PathBuf::from(extended)
+			}
+			// already extended, or a device path
+			_ => return path,
+		},
+		// relative paths can't be extended
+		_ => return path,
+	};
+
+	for component in components {
+		match component {
+			Component::Normal(name) => extended.push(name),
+			Component::ParentDir => {
+				extended.pop();
+			}
+			_ => {}
+		}
+	}
+
+	extended
+}
+
+#[cfg(not(target_os = "windows"))]
+pub(super) fn extended_length_path(path: PathBuf) -> PathBuf {
+	path
+}
+
+/// Counts a failed read of the file, returning how many it had so far
+fn record_attempt(
+	attempts: &mut HashMap<file_path::id::Type, u32>,
+	file_path_id: file_path::id::Type,
+) -> u32 {
+	let attempts = attempts.entry(file_path_id).or_default();
+	*attempts += 1;
+	*attempts
+}
+
+fn size_in_bytes(file_path: &file_path_for_object_validator::Data) -> Option<u64> {
+	file_path
+		.size_in_bytes_bytes
+		.as_deref()
+		.and_then(|bytes| bytes.try_into().ok())
+		.map(u64::from_be_bytes)
+}
+
+/// Bytes to read for the steps, files of unknown size count for nothing
+fn steps_len<'a>(
+	steps: impl IntoIterator<Item = &'a Vec<file_path_for_object_validator::Data>>,
+) -> u64 {
+	total_len(steps.into_iter().flatten())
+}
+
+/// Sum of the sizes of the files, saturating instead of overflowing with sizes recorded wrong
+fn total_len<'a>(
+	file_paths: impl IntoIterator<Item = &'a file_path_for_object_validator::Data>,
+) -> u64 {
+	file_paths
+		.into_iter()
+		.filter_map(size_in_bytes)
+		.fold(0, u64::saturating_add)
+}
+
+/// Validates a single file, returning `None` if it was skipped
+pub(super) async fn validate_file(
+	source: &impl StepSource,
+	location_id: location::id::Type,
+	location_path: impl AsRef<Path>,
+	file_path: &file_path_for_object_validator::Data,
+	options: ValidationOptions,
+) -> Result<Option<ValidatedFile>, JobError> {
+	// this is to skip files that already have checksums
+	// i'm unsure what the desired behaviour is in this case
+	// we can also compare old and new checksums here
+	// This if is just to make sure, we already queried objects where integrity_checksum is null
+	// or computed with another algorithm
+	let needs_checksum = !has_checksum_for(
+		file_path,
+		options.read.algorithm,
+		options.read.include_alt_streams,
+	);
+	let needs_content_checksum = options.media_normalize && file_path.content_checksum.is_none();
+	if !needs_checksum && !needs_content_checksum {
+		return Ok(None);
+	}
+
+	let iso_file_path = IsolatedFilePathData::try_from((location_id, file_path))?;
+	let full_path = extended_length_path(location_path.as_ref().join(&iso_file_path));
+	let live_len = source.file_len(&full_path).await.ok();
+
+	let mut outcome = FileValidationOutcome::Checksummed;
+	let mut fail = |e: io::Error| {
+		if options.prune_missing && e.kind() == io::ErrorKind::NotFound {
+			outcome = FileValidationOutcome::Pruned;
+			return;
+		}
+
+		if let Some(&ChangedDuringRead { before, after }) = changed_during_read(&e) {
+			warn!(
+				"{:#?}",
+				ValidatorError::FileChangedDuringRead {
+					path: full_path.clone().into_boxed_path(),
+					before,
+					after,
+				}
+			);
+			outcome = FileValidationOutcome::InFlux;
+			return;
+		}
+
+		let reason = e.to_string();
+		let e = if e.kind() == io::ErrorKind::TimedOut {
+			ValidatorError::ReadTimeout(full_path.clone().into_boxed_path())
+		} else if let Some(&ShortRead { expected, got }) = short_read(&e) {
+			ValidatorError::ShortRead {
+				path: full_path.clone().into_boxed_path(),
+				expected,
+				got,
+			}
+		} else {
+			ValidatorError::FileIO(FileIOError::from((&full_path, e)))
+		};
+		error!("Failed to validate file: {e:#?}");
+		telemetry::record_failure(options.read.algorithm);
+		outcome = FileValidationOutcome::Failed { reason };
+	};
+
+	let len = live_len.or(size_in_bytes(file_path));
+
+	// images are read whole into memory to be decoded without a second read
+	let perceptual_len = len
+		.filter(|&len| {
+			options.perceptual && len <= MAX_PERCEPTUAL_LEN && is_perceptually_hashable(&full_path)
+		})
+		.map(|len| len as usize);
+	let head_len = match perceptual_len {
+		Some(len) => Some(len.max(HEAD_LEN)),
+		None => {
+			(options.detect_content_type || options.flag_extension_mismatch).then_some(HEAD_LEN)
+		}
+	};
+
+	// the checksum a migrated file had is checked from the same reads, so corruption since it was
+	// computed isn't blessed with a checksum in the new algorithm
+	let migrating_from = options
+		.migrate_from
+		.filter(|&from| has_checksum_for(file_path, from, options.read.include_alt_streams));
+	let mut migration_mismatch = None;
+
+	let mut reduced_block_len = None;
+	let (checksum, head) = if needs_checksum {
+		let started_at = Instant::now();
+		let (checksum, block_len) =
+			file_checksums_with_fallback(source, &full_path, options, head_len, migrating_from)
+				.await;
+		reduced_block_len = block_len;
+
+		if checksum.is_ok() {
+			telemetry::record_file_hashed(options.read.algorithm, len, started_at.elapsed());
+		}
+
+		match (checksum.map_err(&mut fail), &file_path.integrity_checksum) {
+			(Ok((_, _, Some(previous))), Some(stored)) if !checksums_match(stored, &previous) => {
+				error!(
+					"Checksum of {} doesn't match the stored one, not migrating it",
+					full_path.display()
+				);
+				migration_mismatch = Some(format!(
+					"checksum {previous} doesn't match {stored} from the library"
+				));
+				(None, None)
+			}
+			(Ok((checksum, head, _)), _) => (Some(checksum), head),
+			(Err(()), _) => (None, None),
+		}
+	} else {
+		(None, None)
+	};
+
+	let content_type = head
+		.as_deref()
+		.filter(|_| options.detect_content_type)
+		.and_then(sniff_content_type);
+
+	let extension_mismatch = head
+		.as_deref()
+		.filter(|_| options.flag_extension_mismatch)
+		.and_then(|head| extension_mismatch(file_path.extension.as_deref()?, head));
+	if let Some(ExtensionMismatch { claimed, detected }) = &extension_mismatch {
+		warn!(
+			"{} holds {detected} contents but has a .{claimed} extension",
+			full_path.display()
+		);
+	}
+
+	let perceptual_hash = match head.filter(|_| perceptual_len.is_some()) {
+		Some(contents) => {
+			let hash = spawn_blocking(move || perceptual_hash(&contents))
+				.await
+				.ok()
+				.flatten();
+			if hash.is_none() {
+				warn!(
+					"Couldn't decode {} to compute its perceptual hash",
+					full_path.display()
+				);
+			}
+			hash
+		}
+		None => None,
+	};
+
+	// no point in reading the file again if it just failed
+	let content_checksum = if needs_content_checksum && (checksum.is_some() || !needs_checksum) {
+		source
+			.content_checksum(&full_path)
+			.await
+			.map_err(&mut fail)
+			.ok()
+	} else {
+		None
+	};
+
+	// all failures so far are errors reading the file
+	let read_failed = matches!(outcome, FileValidationOutcome::Failed { .. });
+	if let Some(reason) = migration_mismatch {
+		outcome = FileValidationOutcome::Failed { reason };
+	}
+
+	Ok(Some(ValidatedFile {
+		relative_path: iso_file_path.to_string(),
+		read_failed,
+		outcome,
+		checksum,
+		content_checksum,
+		content_type,
+		perceptual_hash,
+		range_checksum: None,
+		extension_mismatch,
+		reduced_block_len,
+		corrupt_ranges: None,
+		mirror: None,
+		live_len,
+	}))
+}
+
+/// Computes the checksum of a file, and keeps its first `head_len` bytes if given, retrying
+/// with smaller reads down to the `min_block_len` of `options` while they fail for lack of
+/// memory, and once if the file wasn't read whole. Also returns the read length that was used
+/// if it had to be shortened.
+pub(super) async fn file_checksum_with_fallback(
+	source: &impl StepSource,
+	path: &Path,
+	options: ValidationOptions,
+	head_len: Option<usize>,
+) -> (Result<(String, Option<Vec<u8>>), io::Error>, Option<usize>) {
+	let (checksum, reduced_block_len) =
+		file_checksums_with_fallback(source, path, options, head_len, None).await;
+
+	(
+		checksum.map(|(checksum, head, _)| (checksum, head)),
+		reduced_block_len,
+	)
+}
+
+/// Same as [`file_checksum_with_fallback`], also computing the checksum with `previous` from the
+/// same reads if given, see [`StepSource::file_checksums_migrating`]. The file's first bytes
+/// aren't kept then, they were handed over when it was first checksummed.
+async fn file_checksums_with_fallback(
+	source: &impl StepSource,
+	path: &Path,
+	options: ValidationOptions,
+	head_len: Option<usize>,
+	previous: Option<ChecksumAlgorithm>,
+) -> (
+	Result<(String, Option<Vec<u8>>, Option<String>), io::Error>,
+	Option<usize>,
+) {
+	let mut read = options.read;
+	let mut reduced_block_len = None;
+	let mut retried_short_read = false;
+
+	loop {
+		let checksum = match (previous, head_len) {
+			(Some(previous), _) => source
+				.file_checksums_migrating(path, read, previous)
+				.await
+				.map(|(checksum, previous)| (checksum, None, Some(previous))),
+			(None, Some(head_len)) => source
+				.file_checksum_and_head(path, read, head_len)
+				.await
+				.map(|(checksum, head)| (checksum, Some(head), None)),
+			(None, None) => source
+				.file_checksum(path, read)
+				.await
+				.map(|checksum| (checksum, None, None)),
+		};
+
+		match checksum {
+			Err(e) if is_memory_pressure(&e) => {
+				let Some(smaller) = read.with_smaller_blocks(options.min_block_len) else {
+					return (Err(e), reduced_block_len);
+				};
+				warn!(
+					"Reading {} failed for lack of memory, retrying with {} bytes reads: {e}",
+					path.display(),
+					smaller.block_len.unwrap_or_default()
+				);
+				reduced_block_len = smaller.block_len;
+				read = smaller;
+			}
+			// flaky devices may hand the whole file over the next time
+			Err(e) if !retried_short_read && short_read(&e).is_some() => {
+				warn!("Reading {} ended early, retrying: {e}", path.display());
+				retried_short_read = true;
+			}
+			checksum => return (checksum, reduced_block_len),
+		}
+	}
+}
+
+/// Validates a file and compares it with its copy under `mirror_root`. Files already checksummed
+/// are read again for the comparison, and verified against their stored checksum while at it.
+async fn validate_with_mirror(
+	source: &impl StepSource,
+	location_id: location::id::Type,
+	location_path: &Path,
+	file_path: &file_path_for_object_validator::Data,
+	mirror_root: &Path,
+	options: ValidationOptions,
+) -> Result<Option<ValidatedFile>, JobError> {
+	let stored = file_path.integrity_checksum.as_deref().filter(|_| {
+		has_checksum_for(
+			file_path,
+			options.read.algorithm,
+			options.read.include_alt_streams,
+		)
+	});
+
+	let validated = match stored {
+		Some(_) => {
+			validate_file(
+				source,
+				location_id,
+				location_path,
+				&file_path_for_object_validator::Data {
+					integrity_checksum: None,
+					..file_path.clone()
+				},
+				options,
+			)
+			.await?
+		}
+		None => validate_file(source, location_id, location_path, file_path, options).await?,
+	};
+	let Some(mut validated) = validated else {
+		return Ok(None);
+	};
+
+	validated.mirror =
+		compare_with_mirror(source, location_path, mirror_root, &validated, options.read).await;
+
+	if let (Some(stored), FileValidationOutcome::Checksummed) = (stored, &validated.outcome) {
+		// the checksum was only computed again to be compared, the stored one is kept
+		if let Some(checksum) = validated.checksum.take() {
+			validated.outcome = if checksums_match(stored, &checksum) {
+				FileValidationOutcome::Verified
+			} else {
+				error!(
+					"Checksum of {} doesn't match the stored one",
+					location_path.join(&validated.relative_path).display()
+				);
+				FileValidationOutcome::Failed {
+					reason: format!("checksum {checksum} doesn't match {stored} from the library"),
+				}
+			};
+		}
+	}
+
+	Ok(Some(validated))
+}
+
+/// Hashes the copy of the validated file under `mirror_root` the same way and compares it with the
+/// location's, see [`mirror_comparison`]
+async fn compare_with_mirror(
+	source: &impl StepSource,
+	location_path: &Path,
+	mirror_root: &Path,
+	validated: &ValidatedFile,
+	read: ReadOptions,
+) -> Option<Result<(), MirrorDivergence>> {
+	if validated.outcome == FileValidationOutcome::InFlux {
+		return None;
+	}
+
+	let mirror_path = extended_length_path(mirror_root.join(&validated.relative_path));
+	let mirror_checksum = source.file_checksum(&mirror_path, read).await;
+
+	let missing_on_primary = validated.checksum.is_none()
+		&& matches!(
+			fs::metadata(extended_length_path(location_path.join(&validated.relative_path))).await,
+			Err(e) if e.kind() == io::ErrorKind::NotFound
+		);
+
+	mirror_comparison(
+		validated.checksum.as_deref(),
+		mirror_checksum,
+		missing_on_primary,
+	)
+}
+
+/// Whether the file still has the contents the user acknowledged, `checksum` being the one just
+/// computed for the manifest. Acknowledgments made with another algorithm need the file read again.
+async fn has_acknowledged_contents(
+	source: &impl StepSource,
+	full_path: &Path,
+	checksum: &str,
+	acknowledgment: &Acknowledgment,
+	options: ValidationOptions,
+) -> bool {
+	if acknowledgment.algorithm == options.read.algorithm {
+		return acknowledgment.checksum == checksum;
+	}
+
+	let read = ReadOptions {
+		algorithm: acknowledgment.algorithm,
+		..options.read
+	};
+	match source.file_checksum(full_path, read).await {
+		Ok(checksum) => acknowledgment.checksum == checksum,
+		Err(e) => {
+			warn!(
+				"Failed to compare {} with its acknowledged contents: {e}",
+				full_path.display()
+			);
+			false
+		}
+	}
+}
+
+/// What files are checked against when auditing a location
+#[derive(Clone, Copy)]
+struct ManifestAudit<'a> {
+	manifest: &'a HashMap<String, String>,
+	/// to locate the damage in files that don't match the manifest
+	chunks: Option<&'a HashMap<String, Vec<Chunk>>>,
+	acknowledgments: Option<&'a HashMap<String, Acknowledgment>>,
+	remote_checksums: Option<&'a dyn RemoteChecksums>,
+}
+
+/// Compares a file's checksum with the one the manifest lists for it, returning `None` if it was
+/// skipped. The sizes in the library aren't trusted either, so empty files can only be skipped
+/// once their checksum confirmed it.
+async fn verify_file(
+	source: &impl StepSource,
+	location_id: location::id::Type,
+	location_path: impl AsRef<Path>,
+	file_path: &file_path_for_object_validator::Data,
+	audit: ManifestAudit<'_>,
+	options: ValidationOptions,
+) -> Result<Option<ValidatedFile>, JobError> {
+	let iso_file_path = IsolatedFilePathData::try_from((location_id, file_path))?;
+	let relative_path = iso_file_path.to_string();
+	let full_path = extended_length_path(location_path.as_ref().join(&iso_file_path));
+	let live_len = source.file_len(&full_path).await.ok();
+
+	let mut reduced_block_len = None;
+	let mut corrupt_ranges = None;
+	let mut read_failed = false;
+	let outcome = match audit.manifest.get(&relative_path) {
+		None => FileValidationOutcome::Failed {
+			reason: "not listed in the manifest".to_string(),
+		},
+		Some(expected) => {
+			let remote_checksum = match audit.remote_checksums {
+				Some(remote_checksums) => remote_checksums
+					.checksum(&relative_path, options.read.algorithm)
+					.await
+					.unwrap_or_else(|e| {
+						// the file can still be read instead
+						warn!("Failed to get the remote checksum of {relative_path}: {e}");
+						None
+					}),
+				None => None,
+			};
+			let from_remote = remote_checksum.is_some();
+
+			let checksum = match remote_checksum {
+				Some(checksum) => Ok((checksum, None)),
+				None => {
+					let (checksum, block_len) =
+						file_checksum_with_fallback(source, &full_path, options, None).await;
+					reduced_block_len = block_len;
+					checksum
+				}
+			};
+
+			match checksum {
+				Ok((checksum, _))
+					if options.skip_empty
+						&& checksums_match(expected, &checksum)
+						&& checksum == options.read.algorithm.empty_checksum() =>
+				{
+					return Ok(None);
+				}
+				Ok((checksum, _)) if checksums_match(expected, &checksum) => {
+					if from_remote {
+						FileValidationOutcome::VerifiedRemotely
+					} else {
+						FileValidationOutcome::Verified
+					}
+				}
+				Ok((checksum, _)) => {
+					let acknowledgment = match audit
+						.acknowledgments
+						.and_then(|acknowledgments| acknowledgments.get(&relative_path))
+					{
+						Some(acknowledgment) => has_acknowledged_contents(
+							source,
+							&full_path,
+							&checksum,
+							acknowledgment,
+							options,
+						)
+						.await
+						.then_some(acknowledgment),
+						None => None,
+					};
+
+					if let Some(Acknowledgment { at, by, .. }) = acknowledgment {
+						info!(
+							"{} doesn't match the manifest, but its contents were acknowledged",
+							full_path.display()
+						);
+						FileValidationOutcome::Acknowledged {
+							at: *at,
+							by: by.clone(),
+						}
+					} else {
+						error!(
+							"Checksum of {} doesn't match the manifest",
+							full_path.display()
+						);
+						let file_chunks =
+							audit.chunks.and_then(|chunks| chunks.get(&relative_path));
+						if let Some(chunks) = file_chunks {
+							let read = ReadOptions {
+								block_len: reduced_block_len.or(options.read.block_len),
+								..options.read
+							};
+							corrupt_ranges =
+								locate_corruption(source, &full_path, chunks, read).await;
+						}
+						FileValidationOutcome::Failed {
+							reason: format!(
+								"{}checksum {checksum} doesn't match {expected} from the manifest",
+								if from_remote { "remote " } else { "" }
+							),
+						}
+					}
+				}
+				Err(e) if changed_during_read(&e).is_some() => {
+					warn!("{} changed while it was read: {e}", full_path.display());
+					FileValidationOutcome::InFlux
+				}
+				Err(e) => {
+					let reason = e.to_string();
+					error!(
+						"Failed to verify file: {:#?}",
+						ValidatorError::FileIO(FileIOError::from((&full_path, e)))
+					);
+					read_failed = true;
+					FileValidationOutcome::Failed { reason }
+				}
+			}
+		}
+	};
+
+	Ok(Some(ValidatedFile {
+		relative_path,
+		outcome,
+		checksum: None,
+		content_checksum: None,
+		content_type: None,
+		perceptual_hash: None,
+		range_checksum: None,
+		extension_mismatch: None,
+		reduced_block_len,
+		corrupt_ranges,
+		read_failed,
+		mirror: None,
+		live_len,
+	}))
+}
+
+/// The byte ranges of a file that don't match its chunks, `None` if they couldn't be read
+async fn locate_corruption(
+	source: &impl StepSource,
+	path: &Path,
+	chunks: &[Chunk],
+	options: ReadOptions,
+) -> Option<Vec<(u64, u64)>> {
+	let ranges = chunks
+		.iter()
+		.map(|chunk| (chunk.offset, chunk.len))
+		.collect::<Vec<_>>();
+
+	match source.range_checksums(path, options, &ranges).await {
+		Ok(checksums) => Some(corrupt_ranges(chunks, &checksums)),
+		Err(e) => {
+			warn!("Failed to read the chunks of {}: {e}", path.display());
+			None
+		}
+	}
+}
+
+/// What the checksum of a byte range of a file is compared with
+#[derive(Clone, Copy)]
+enum RangeBaseline<'a> {
+	/// listing them by [`range_key`]
+	Manifest(&'a HashMap<String, String>),
+	/// the checksum an earlier run stored for the same range, the new one is stored if there's
+	/// none
+	Store(&'a dyn ChecksumStore),
+}
+
+/// Checksums the byte `range` of a file, comparing it with the checksum `baseline` has for it
+async fn validate_range(
+	source: &impl StepSource,
+	location_id: location::id::Type,
+	location_path: impl AsRef<Path>,
+	file_path: &file_path_for_object_validator::Data,
+	range: &Range<u64>,
+	baseline: RangeBaseline<'_>,
+	options: ValidationOptions,
+) -> Result<Option<ValidatedFile>, JobError> {
+	let iso_file_path = IsolatedFilePathData::try_from((location_id, file_path))?;
+	let full_path = extended_length_path(location_path.as_ref().join(&iso_file_path));
+	let live_len = source.file_len(&full_path).await.ok();
+
+	let mut validated = ValidatedFile {
+		relative_path: iso_file_path.to_string(),
+		outcome: FileValidationOutcome::Checksummed,
+		checksum: None,
+		content_checksum: None,
+		content_type: None,
+		perceptual_hash: None,
+		range_checksum: None,
+		extension_mismatch: None,
+		reduced_block_len: None,
+		corrupt_ranges: None,
+		read_failed: false,
+		mirror: None,
+		live_len,
+	};
+
+	// reads past the end only hash the bytes the file has, which would go unnoticed
+	if let Some(len) = live_len
+		.or(size_in_bytes(file_path))
+		.filter(|&len| len < range.end)
+	{
+		validated.outcome = FileValidationOutcome::Failed {
+			reason: format!(
+				"byte range {}-{} ends past the end of the file, {len} bytes long",
+				range.start, range.end
+			),
+		};
+		return Ok(Some(validated));
+	}
+
+	let expected = match baseline {
+		RangeBaseline::Manifest(manifest) => {
+			let Some(expected) = manifest.get(&range_key(&validated.relative_path, range)) else {
+				validated.outcome = FileValidationOutcome::Failed {
+					reason: "byte range not listed in the manifest".to_string(),
+				};
+				return Ok(Some(validated));
+			};
+			Some(expected.clone())
+		}
+		RangeBaseline::Store(store) => store
+			.get(file_path)
+			.await?
+			.and_then(|stored| stored.range_checksum)
+			.filter(|stored| stored.range == *range && stored.algorithm == options.read.algorithm)
+			.map(|stored| stored.checksum),
+	};
+
+	let checksum = source
+		.range_checksums(
+			&full_path,
+			options.read,
+			&[(range.start, range.end - range.start)],
+		)
+		.await
+		.and_then(|checksums| {
+			checksums
+				.into_iter()
+				.next()
+				.ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))
+		});
+
+	validated.outcome = match (checksum, expected) {
+		(Ok(checksum), Some(expected)) if checksum == expected => FileValidationOutcome::Verified,
+		(Ok(checksum), Some(expected)) => {
+			error!(
+				"Checksum of bytes {}-{} of {} doesn't match",
+				range.start,
+				range.end,
+				full_path.display()
+			);
+			FileValidationOutcome::Failed {
+				reason: format!(
+					"checksum {checksum} of byte range {}-{} doesn't match {expected}",
+					range.start, range.end
+				),
+			}
+		}
+		(Ok(checksum), None) => {
+			validated.range_checksum = Some(RangeChecksum {
+				range: range.clone(),
+				algorithm: options.read.algorithm,
+				checksum,
+			});
+			FileValidationOutcome::Checksummed
+		}
+		(Err(e), _) => {
+			let reason = e.to_string();
+			error!(
+				"Failed to validate file: {:#?}",
+				ValidatorError::FileIO(FileIOError::from((&full_path, e)))
+			);
+			validated.read_failed = true;
+			FileValidationOutcome::Failed { reason }
+		}
+	};
+
+	Ok(Some(validated))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use crate::object::validation::SizeMismatch;
+
+	use tempfile::tempdir;
+
+	/// Serves a synthetic file set, files without a checksum here fail to be read
+	#[derive(Default)]
+	struct FakeStepSource {
+		file_paths: Vec<file_path_for_object_validator::Data>,
+		checksums: HashMap<PathBuf, String>,
+		heads: HashMap<PathBuf, Vec<u8>>,
+		/// reads longer than this fail for lack of memory
+		max_block_len: Option<usize>,
+		/// checksums of byte ranges, keyed by path and offset
+		ranges: HashMap<(PathBuf, u64), String>,
+		/// how many more reads of these files end early
+		short_reads: std::sync::Mutex<HashMap<PathBuf, usize>>,
+		/// files growing while they're read
+		changing: HashSet<PathBuf>,
+		/// sizes on disk, files without one here fail to be stat'ed
+		lens: HashMap<PathBuf, u64>,
+	}
+
+	#[async_trait::async_trait]
+	impl StepSource for FakeStepSource {
+		async fn file_paths(
+			&self,
+			_: location::id::Type,
+			_: FileScope<'_>,
+			_: ChecksumAlgorithm,
+			_: bool,
+		) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError> {
+			Ok(self.file_paths.clone())
+		}
+
+		async fn all_file_paths(
+			&self,
+			_: location::id::Type,
+			_: FileScope<'_>,
+		) -> Result<Vec<file_path_for_object_validator::Data>, ValidatorError> {
+			Ok(self.file_paths.clone())
+		}
+
+		async fn file_checksum(
+			&self,
+			path: &Path,
+			options: ReadOptions,
+		) -> Result<String, io::Error> {
+			if let Some(max_block_len) = self.max_block_len {
+				if options.block_len.map_or(true, |len| len > max_block_len) {
+					return Err(io::Error::from(io::ErrorKind::OutOfMemory));
+				}
+			}
+
+			if self.changing.contains(path) {
+				return Err(io::Error::new(
+					io::ErrorKind::Other,
+					ChangedDuringRead {
+						before: 1024,
+						after: 2048,
+					},
+				));
+			}
+
+			if let Some(short_reads) = self
+				.short_reads
+				.lock()
+				.unwrap()
+				.get_mut(path)
+				.filter(|short_reads| **short_reads > 0)
+			{
+				*short_reads -= 1;
+				return Err(io::Error::new(
+					io::ErrorKind::UnexpectedEof,
+					ShortRead {
+						expected: 1024,
+						got: 512,
+					},
+				));
+			}
+
+			self.checksums
+				.get(path)
+				.map(|checksum| match options.algorithm {
+					ChecksumAlgorithm::Blake3 => checksum.clone(),
+					algorithm => format!("{}:{checksum}", algorithm.as_str()),
+				})
+				.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+		}
+
+		async fn file_checksum_and_head(
+			&self,
+			path: &Path,
+			options: ReadOptions,
+			head_len: usize,
+		) -> Result<(String, Vec<u8>), io::Error> {
+			let checksum = self.file_checksum(path, options).await?;
+			let mut head = self.heads.get(path).cloned().unwrap_or_default();
+			head.truncate(head_len);
+			Ok((checksum, head))
+		}
+
+		async fn file_checksums_migrating(
+			&self,
+			path: &Path,
+			options: ReadOptions,
+			previous: ChecksumAlgorithm,
+		) -> Result<(String, String), io::Error> {
+			Ok((
+				self.file_checksum(path, options).await?,
+				self.file_checksum(
+					path,
+					ReadOptions {
+						algorithm: previous,
+						..options
+					},
+				)
+				.await?,
+			))
+		}
+
+		async fn content_checksum(&self, path: &Path) -> Result<String, io::Error> {
+			self.file_checksum(path, ReadOptions::default())
+				.await
+				.map(|checksum| format!("content:{checksum}"))
+		}
+
+		async fn range_checksums(
+			&self,
+			path: &Path,
+			_: ReadOptions,
+			ranges: &[(u64, u64)],
+		) -> Result<Vec<String>, io::Error> {
+			ranges
+				.iter()
+				.map(|&(offset, _)| {
+					self.ranges
+						.get(&(path.to_path_buf(), offset))
+						.cloned()
+						.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+				})
+				.collect()
+		}
+
+		async fn file_len(&self, path: &Path) -> Result<u64, io::Error> {
+			self.lens
+				.get(path)
+				.copied()
+				.ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+		}
+	}
+
+	fn fake_file_path(
+		name: &str,
+		integrity_checksum: Option<&str>,
+	) -> file_path_for_object_validator::Data {
+		file_path_for_object_validator::Data {
+			id: 0,
+			pub_id: name.as_bytes().to_vec(),
+			materialized_path: Some("/".to_string()),
+			is_dir: Some(false),
+			name: Some(name.to_string()),
+			extension: Some("txt".to_string()),
+			integrity_checksum: integrity_checksum.map(str::to_string),
+			integrity_checksum_algorithm: None,
+			content_checksum: None,
+			size_in_bytes_bytes: None,
+			device: None,
+			date_modified: None,
+			object: None,
+		}
+	}
+
+	#[test]
+	fn test_state_migration() {
+		#[derive(Serialize)]
+		struct UnversionedState {
+			location_path: PathBuf,
+			task_count: usize,
+		}
+
+		let old_state = rmp_serde::to_vec_named(&UnversionedState {
+			location_path: PathBuf::from("/location"),
+			task_count: 3,
+		})
+		.unwrap();
+
+		let mut state = rmp_serde::from_slice::<ObjectValidatorJobState>(&old_state).unwrap();
+		assert_eq!(state.version, 0);
+
+		state.migrate(1, Some(&PathBuf::from("sub")));
+		assert_eq!(state.version, STATE_VERSION);
+		assert_eq!(state.task_count, 3);
+		assert_eq!(state.report.location_id, 1);
+		assert_eq!(state.report.sub_path, Some(PathBuf::from("sub")));
+		assert_eq!(state.totals, ValidationTotals::default());
+
+		state.version = STATE_VERSION + 1;
+		let newer_state = rmp_serde::to_vec_named(&state).unwrap();
+		assert!(rmp_serde::from_slice::<ObjectValidatorJobState>(&newer_state).is_err());
+	}
+
+	#[tokio::test]
+	async fn test_resume_with_another_algorithm() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("validated.txt"), "123".to_string()),
+				(location_path.join("pending.txt"), "456".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+
+		let options_for = |state: &ObjectValidatorJobState| ValidationOptions {
+			read: ReadOptions {
+				algorithm: state.algorithm,
+				..Default::default()
+			},
+			..Default::default()
+		};
+
+		let mut state = ObjectValidatorJobState {
+			version: STATE_VERSION,
+			location_path: location_path.to_path_buf(),
+			task_count: 2,
+			report: ObjectValidatorReport {
+				location_id: 1,
+				..Default::default()
+			},
+			remote_location: false,
+			algorithm: ChecksumAlgorithm::Blake3,
+			manifest: None,
+			chunks: None,
+			acknowledgments: HashMap::new(),
+			reflink_copies: HashMap::new(),
+			total_bytes: 0,
+			completed_bytes: 0,
+			attempts: HashMap::new(),
+			totals: ValidationTotals::default(),
+			requeued_in_flux: HashSet::new(),
+		};
+
+		// the first file is validated with blake3 before pausing
+		let validated = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("validated", None),
+			options_for(&state),
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(validated.checksum.as_deref(), Some("123"));
+		state
+			.report
+			.files
+			.insert(validated.relative_path, validated.outcome);
+
+		let paused = rmp_serde::to_vec_named(&state).unwrap();
+
+		// resuming with the same algorithm keeps going where we stopped
+		let mut resumed = rmp_serde::from_slice::<ObjectValidatorJobState>(&paused).unwrap();
+		assert!(!resumed.switch_algorithm(ChecksumAlgorithm::Blake3));
+		assert_eq!(resumed.report.files.len(), 1);
+
+		// after the default changed every file must be recomputed
+		let mut resumed = rmp_serde::from_slice::<ObjectValidatorJobState>(&paused).unwrap();
+		assert!(resumed.switch_algorithm(ChecksumAlgorithm::Sha256));
+		assert_eq!(resumed.algorithm, ChecksumAlgorithm::Sha256);
+		assert!(resumed.report.files.is_empty());
+		assert_eq!(resumed.report.location_id, 1);
+		assert!(!resumed.switch_algorithm(ChecksumAlgorithm::Sha256));
+
+		let options = options_for(&resumed);
+		let recomputed = validate_file(
+			&source,
+			1,
+			location_path,
+			&file_path_for_object_validator::Data {
+				integrity_checksum_algorithm: Some("blake3".to_string()),
+				..fake_file_path("validated", Some("123"))
+			},
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(recomputed.checksum.as_deref(), Some("sha256:123"));
+
+		let pending = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("pending", None),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(pending.checksum.as_deref(), Some("sha256:456"));
+
+		// files already checksummed with the new algorithm are skipped
+		assert!(validate_file(
+			&source,
+			1,
+			location_path,
+			&file_path_for_object_validator::Data {
+				integrity_checksum_algorithm: Some("sha256".to_string()),
+				..fake_file_path("pending", Some("sha256:456"))
+			},
+			options,
+		)
+		.await
+		.unwrap()
+		.is_none());
+	}
+
+	#[tokio::test]
+	async fn test_location_path_override() {
+		let dir = tempdir().unwrap();
+		let location_path = dir.path();
+		fs::write(location_path.join("present.txt"), b"spacedrive")
+			.await
+			.unwrap();
+
+		assert!(ensure_is_directory(location_path).await.is_ok());
+		assert!(matches!(
+			ensure_is_directory(&location_path.join("present.txt")).await,
+			Err(ValidatorError::LocationPathOverrideNotDirectory(_))
+		));
+		assert!(matches!(
+			ensure_is_directory(&location_path.join("missing")).await,
+			Err(ValidatorError::LocationPathOverrideNotDirectory(_))
+		));
+
+		let missing = fake_file_path("missing", None);
+		let present = fake_file_path("present", None);
+		assert!(
+			ensure_holds_location_files(1, location_path, [&missing, &present].into_iter())
+				.await
+				.is_ok()
+		);
+		assert!(matches!(
+			ensure_holds_location_files(1, location_path, [&missing].into_iter()).await,
+			Err(ValidatorError::LocationPathOverrideMismatch(_))
+		));
+		assert!(
+			ensure_holds_location_files(1, location_path, [].into_iter())
+				.await
+				.is_ok()
+		);
+	}
+
+	#[cfg(unix)]
+	#[tokio::test]
+	async fn test_split_case_collisions() {
+		let dir = tempdir().unwrap();
+		let location_path = dir.path();
+
+		// a hard link is what a case insensitive file system looks like to us, two paths for
+		// the same file
+		fs::write(location_path.join("Photo.txt"), b"photo")
+			.await
+			.unwrap();
+		fs::hard_link(
+			location_path.join("Photo.txt"),
+			location_path.join("photo.txt"),
+		)
+		.await
+		.unwrap();
+		fs::write(location_path.join("Notes.txt"), b"notes")
+			.await
+			.unwrap();
+		fs::write(location_path.join("notes.txt"), b"other notes")
+			.await
+			.unwrap();
+
+		let (kept, collisions) = split_case_collisions(
+			1,
+			location_path,
+			vec![
+				fake_file_path("Photo", None),
+				fake_file_path("Notes", None),
+				fake_file_path("photo", None),
+				fake_file_path("notes", None),
+			],
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(
+			kept.into_iter()
+				.map(|file_path| file_path.name.unwrap())
+				.collect::<Vec<_>>(),
+			vec!["Photo", "Notes", "notes"]
+		);
+		assert_eq!(
+			collisions,
+			vec![("photo.txt".to_string(), "Photo.txt".to_string())]
+		);
+	}
+
+	#[test]
+	fn test_order_by_recent_access() {
+		let date = |day: u32| {
+			DateTime::parse_from_rfc3339(&format!("2023-06-{day:02}T00:00:00Z")).unwrap()
+		};
+		let file_path = |name: &str, accessed: Option<u32>, modified: Option<u32>| {
+			file_path_for_object_validator::Data {
+				date_modified: modified.map(date),
+				object: Some(file_path_for_object_validator::object::Data {
+					id: 1,
+					date_accessed: accessed.map(date),
+				}),
+				..fake_file_path(name, None)
+			}
+		};
+
+		let mut file_paths = vec![
+			file_path("untracked", None, None),
+			file_path("old", Some(1), Some(20)),
+			file_path("modified", None, Some(10)),
+			fake_file_path("orphan", None),
+			file_path("recent", Some(15), Some(2)),
+		];
+		order_by_recent_access(&mut file_paths);
+
+		assert_eq!(
+			file_paths
+				.into_iter()
+				.map(|file_path| file_path.name.unwrap())
+				.collect::<Vec<_>>(),
+			vec!["recent", "modified", "old", "untracked", "orphan"]
+		);
+	}
+
+	#[test]
+	fn test_size_in_bytes() {
+		let file_path = |size_in_bytes_bytes| file_path_for_object_validator::Data {
+			size_in_bytes_bytes,
+			..fake_file_path("file", None)
+		};
+
+		assert_eq!(
+			size_in_bytes(&file_path(Some(4096u64.to_be_bytes().to_vec()))),
+			Some(4096)
+		);
+		assert_eq!(size_in_bytes(&file_path(None)), None);
+		assert_eq!(size_in_bytes(&file_path(Some(vec![1, 2, 3]))), None);
+	}
+
+	#[test]
+	fn test_device_batches() {
+		let file_path_on = |name: &str, device: Option<u64>| file_path_for_object_validator::Data {
+			device: device.map(|device| device.to_le_bytes().to_vec()),
+			..fake_file_path(name, None)
+		};
+
+		let batches = device_batches(
+			vec![
+				file_path_on("a1", Some(1)),
+				file_path_on("a2", Some(1)),
+				file_path_on("a3", Some(1)),
+				file_path_on("b1", Some(2)),
+				file_path_on("unknown", None),
+			],
+			2,
+		)
+		.into_iter()
+		.map(|batch| {
+			batch
+				.into_iter()
+				.map(|file_path| file_path.name.unwrap())
+				.collect::<Vec<_>>()
+		})
+		.collect::<Vec<_>>();
+
+		assert_eq!(batches, vec![vec!["unknown", "a1", "a2", "b1"], vec!["a3"]]);
+	}
+
+	#[tokio::test]
+	async fn test_validate_per_device() {
+		let file_path_on = |name: &str, device: u64| file_path_for_object_validator::Data {
+			device: Some(device.to_le_bytes().to_vec()),
+			..fake_file_path(name, None)
+		};
+		let file_paths = (0..6)
+			.map(|i| file_path_on(&format!("a{i}"), 1))
+			.chain([file_path_on("b0", 2)])
+			.collect::<Vec<_>>();
+
+		// reads in flight on each device, and the most there ever were
+		let in_flight = std::sync::Mutex::new(HashMap::<_, (usize, usize)>::new());
+		let validated = validate_per_device(&file_paths, 2, |file_path| {
+			let in_flight = &in_flight;
+			async move {
+				let device = file_path.device.clone();
+				{
+					let mut in_flight = in_flight.lock().unwrap();
+					let (now, max) = in_flight.entry(device.clone()).or_default();
+					*now += 1;
+					*max = (*max).max(*now);
+				}
+				tokio::time::sleep(Duration::from_millis(10)).await;
+				in_flight.lock().unwrap().get_mut(&device).unwrap().0 -= 1;
+
+				file_path.name.clone().unwrap()
+			}
+		})
+		.await;
+
+		assert_eq!(
+			validated,
+			file_paths
+				.iter()
+				.map(|file_path| file_path.name.clone().unwrap())
+				.collect::<Vec<_>>()
+		);
+		let in_flight = in_flight.into_inner().unwrap();
+		assert_eq!(in_flight[&Some(1u64.to_le_bytes().to_vec())].1, 2);
+		assert_eq!(in_flight[&Some(2u64.to_le_bytes().to_vec())].1, 1);
+	}
+
+	#[test]
+	fn test_sample_file_paths() {
+		let file_paths = (0..100)
+			.map(|i| fake_file_path(&format!("file_{i}"), None))
+			.collect::<Vec<_>>();
+		let sample = |size, seed| {
+			sample_file_paths(file_paths.clone(), &SampleSpec { size, seed })
+				.into_iter()
+				.map(|file_path| file_path.pub_id)
+				.collect::<Vec<_>>()
+		};
+
+		assert_eq!(sample(SampleSize::Count(10), 42).len(), 10);
+		assert_eq!(sample(SampleSize::Fraction(0.015), 42).len(), 2);
+		assert_eq!(sample(SampleSize::Count(1000), 42).len(), 100);
+
+		assert_eq!(
+			sample(SampleSize::Count(10), 42),
+			sample(SampleSize::Count(10), 42)
+		);
+		assert_ne!(
+			sample(SampleSize::Count(10), 42),
+			sample(SampleSize::Count(10), 7)
+		);
+
+		// the order of the query results doesn't change the sample
+		let mut reversed = file_paths.clone();
+		reversed.reverse();
+		assert_eq!(
+			sample_file_paths(
+				reversed,
+				&SampleSpec {
+					size: SampleSize::Count(10),
+					seed: 42
+				}
+			)
+			.into_iter()
+			.map(|file_path| file_path.pub_id)
+			.collect::<Vec<_>>(),
+			sample(SampleSize::Count(10), 42)
+		);
+	}
+
+	#[tokio::test]
+	async fn test_validate_file() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			file_paths: vec![
+				fake_file_path("new", None),
+				fake_file_path("done", Some("abc")),
+				fake_file_path("unreadable", None),
+			],
+			checksums: [(location_path.join("new.txt"), "123".to_string())]
+				.into_iter()
+				.collect(),
+			..Default::default()
+		};
+
+		let mut results = vec![];
+		for file_path in source
+			.file_paths(1, FileScope::default(), ChecksumAlgorithm::Blake3, false)
+			.await
+			.unwrap()
+		{
+			results.push(
+				validate_file(
+					&source,
+					1,
+					location_path,
+					&file_path,
+					ValidationOptions::default(),
+				)
+				.await
+				.unwrap()
+				.map(|validated| {
+					(
+						validated.relative_path,
+						validated.outcome,
+						validated.checksum,
+					)
+				}),
+			);
+		}
+
+		assert_eq!(
+			results,
+			vec![
+				Some((
+					"new.txt".to_string(),
+					FileValidationOutcome::Checksummed,
+					Some("123".to_string())
+				)),
+				None,
+				Some((
+					"unreadable.txt".to_string(),
+					FileValidationOutcome::Failed {
+						reason: io::Error::from(io::ErrorKind::NotFound).to_string()
+					},
+					None
+				)),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_validate_file_migrating() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("intact.txt"), "123".to_string()),
+				(location_path.join("corrupted.txt"), "456".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let options = ValidationOptions {
+			read: ReadOptions {
+				algorithm: ChecksumAlgorithm::Sha256,
+				..Default::default()
+			},
+			migrate_from: Some(ChecksumAlgorithm::Blake3),
+			..Default::default()
+		};
+
+		let intact = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("intact", Some("123")),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(intact.outcome, FileValidationOutcome::Checksummed);
+		assert_eq!(intact.checksum.as_deref(), Some("sha256:123"));
+
+		// its contents changed since it was checksummed, it isn't given a checksum of them
+		let corrupted = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("corrupted", Some("abc")),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(
+			corrupted.outcome,
+			FileValidationOutcome::Failed {
+				reason: "checksum 456 doesn't match abc from the library".to_string()
+			}
+		);
+		assert_eq!(corrupted.checksum, None);
+		assert!(!corrupted.read_failed);
+	}
+
+	#[tokio::test]
+	async fn test_validate_with_mirror() {
+		let location_path = Path::new("/location");
+		let mirror_root = Path::new("/mirror");
+		let source = FakeStepSource {
+			checksums: [
+				("in_sync.txt", "aaaa", "aaaa"),
+				("desynced.txt", "bbbb", "cccc"),
+				("corrupted.txt", "dddd", "eeee"),
+			]
+			.into_iter()
+			.flat_map(|(name, primary, mirror)| {
+				[
+					(location_path.join(name), primary.to_string()),
+					(mirror_root.join(name), mirror.to_string()),
+				]
+			})
+			.collect(),
+			..Default::default()
+		};
+		let source = &source;
+
+		// every file of the location is already checksummed, they're still compared
+		let validate = |name: &'static str, stored: &'static str| async move {
+			validate_with_mirror(
+				source,
+				1,
+				location_path,
+				&fake_file_path(name, Some(stored)),
+				mirror_root,
+				ValidationOptions::default(),
+			)
+			.await
+			.unwrap()
+			.unwrap()
+		};
+
+		let in_sync = validate("in_sync", "aaaa").await;
+		assert_eq!(in_sync.outcome, FileValidationOutcome::Verified);
+		assert_eq!(in_sync.mirror, Some(Ok(())));
+		// the stored checksum is kept
+		assert_eq!(in_sync.checksum, None);
+
+		let desynced = validate("desynced", "bbbb").await;
+		assert_eq!(desynced.outcome, FileValidationOutcome::Verified);
+		assert_eq!(
+			desynced.mirror,
+			Some(Err(MirrorDivergence::Differs {
+				primary: "bbbb".to_string(),
+				mirror: "cccc".to_string(),
+			}))
+		);
+
+		// the location's copy no longer matching its checksum fails it, the mirror having the
+		// contents it had
+		let corrupted = validate("corrupted", "eeee").await;
+		assert_eq!(
+			corrupted.outcome,
+			FileValidationOutcome::Failed {
+				reason: "checksum dddd doesn't match eeee from the library".to_string()
+			}
+		);
+		assert_eq!(corrupted.checksum, None);
+		assert!(!corrupted.read_failed);
+		assert!(matches!(
+			corrupted.mirror,
+			Some(Err(MirrorDivergence::Differs { .. }))
+		));
+
+		// files missing a checksum are given one, as without a mirror
+		let new = validate_with_mirror(
+			source,
+			1,
+			location_path,
+			&fake_file_path("in_sync", None),
+			mirror_root,
+			ValidationOptions::default(),
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(new.outcome, FileValidationOutcome::Checksummed);
+		assert_eq!(new.checksum.as_deref(), Some("aaaa"));
+		assert_eq!(new.mirror, Some(Ok(())));
+	}
+
+	#[tokio::test]
+	async fn test_validate_file_prune_missing() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource::default();
+		let options = ValidationOptions {
+			prune_missing: true,
+			..Default::default()
+		};
+
+		let validated = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("vanished", None),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+
+		assert_eq!(validated.outcome, FileValidationOutcome::Pruned);
+		assert!(!validated.outcome.is_failure());
+		assert_eq!(validated.checksum, None);
+	}
+
+	#[tokio::test]
+	async fn test_ensure_location_root_present() {
+		let dir = tempdir().unwrap();
+
+		// where an unmounted drive was, nothing gets pruned
+		assert!(matches!(
+			ensure_location_root_present(dir.path()).await,
+			Err(ValidatorError::PruneLocationRootMissing(_))
+		));
+		assert!(matches!(
+			ensure_location_root_present(&dir.path().join("missing")).await,
+			Err(ValidatorError::PruneLocationRootMissing(_))
+		));
+
+		fs::write(dir.path().join("file.txt"), b"file")
+			.await
+			.unwrap();
+		assert!(ensure_location_root_present(dir.path()).await.is_ok());
+	}
+
+	#[tokio::test]
+	async fn test_validate_file_in_flux() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [(location_path.join("download.txt"), "123".to_string())]
+				.into_iter()
+				.collect(),
+			changing: [location_path.join("download.txt")].into_iter().collect(),
+			..Default::default()
+		};
+
+		let validated = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("download", None),
+			ValidationOptions::default(),
+		)
+		.await
+		.unwrap()
+		.unwrap();
+
+		// nothing is stored for it, and it isn't corrupted for changing
+		assert_eq!(validated.outcome, FileValidationOutcome::InFlux);
+		assert!(!validated.outcome.is_failure());
+		assert!(!validated.read_failed);
+		assert_eq!(validated.checksum, None);
+	}
+
+	#[tokio::test]
+	async fn test_validate_file_media_normalize() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("new.txt"), "123".to_string()),
+				(location_path.join("done.txt"), "456".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let options = ValidationOptions {
+			media_normalize: true,
+			..Default::default()
+		};
+
+		let new = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("new", None),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(new.checksum.as_deref(), Some("123"));
+		assert_eq!(new.content_checksum.as_deref(), Some("content:123"));
+
+		// only the content checksum is missing
+		let done = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("done", Some("abc")),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(done.outcome, FileValidationOutcome::Checksummed);
+		assert_eq!(done.checksum, None);
+		assert_eq!(done.content_checksum.as_deref(), Some("content:456"));
+	}
+
+	#[tokio::test]
+	async fn test_validate_file_detect_content_type() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("image.txt"), "123".to_string()),
+				(location_path.join("empty.txt"), "456".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			heads: [(
+				location_path.join("image.txt"),
+				vec![0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A],
+			)]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let options = ValidationOptions {
+			detect_content_type: true,
+			..Default::default()
+		};
+
+		let mut content_types = vec![];
+		for name in ["image", "empty"] {
+			let validated = validate_file(
+				&source,
+				1,
+				location_path,
+				&fake_file_path(name, None),
+				options,
+			)
+			.await
+			.unwrap()
+			.unwrap();
+			assert_eq!(validated.outcome, FileValidationOutcome::Checksummed);
+			content_types.push(validated.content_type);
+		}
+
+		// the extension says text, but the contents are a png
+		assert_eq!(content_types, vec![Some("image/png"), None]);
+	}
+
+	#[tokio::test]
+	async fn test_validate_file_perceptual_hash() {
+		let mut png = std::io::Cursor::new(vec![]);
+		image::DynamicImage::ImageLuma8(image::GrayImage::from_fn(64, 64, |x, _| {
+			image::Luma([(x * 4) as u8])
+		}))
+		.write_to(&mut png, image::ImageOutputFormat::Png)
+		.unwrap();
+		let png = png.into_inner();
+
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: ["image.png", "broken.png", "notes.txt"]
+				.into_iter()
+				.map(|name| (location_path.join(name), "123".to_string()))
+				.collect(),
+			heads: [
+				(location_path.join("image.png"), png.clone()),
+				(
+					location_path.join("broken.png"),
+					png[..png.len() / 2].to_vec(),
+				),
+				(location_path.join("notes.txt"), png.clone()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let options = ValidationOptions {
+			perceptual: true,
+			..Default::default()
+		};
+
+		let mut perceptual_hashes = vec![];
+		for (name, extension) in [("image", "png"), ("broken", "png"), ("notes", "txt")] {
+			let validated = validate_file(
+				&source,
+				1,
+				location_path,
+				&file_path_for_object_validator::Data {
+					extension: Some(extension.to_string()),
+					size_in_bytes_bytes: Some((png.len() as u64).to_be_bytes().to_vec()),
+					..fake_file_path(name, None)
+				},
+				options,
+			)
+			.await
+			.unwrap()
+			.unwrap();
+			// failing to decode an image doesn't fail its validation
+			assert_eq!(validated.outcome, FileValidationOutcome::Checksummed);
+			assert_eq!(validated.checksum.as_deref(), Some("123"));
+			perceptual_hashes.push(validated.perceptual_hash);
+		}
+
+		// brightening from left to right sets every bit
+		assert_eq!(
+			perceptual_hashes,
+			vec![Some("ffffffffffffffff".to_string()), None, None]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_validate_file_extension_mismatch() {
+		const ZIP: &[u8] = &[0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00];
+
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: ["photo.jpg", "archive.zip"]
+				.into_iter()
+				.map(|name| (location_path.join(name), "123".to_string()))
+				.collect(),
+			heads: ["photo.jpg", "archive.zip"]
+				.into_iter()
+				.map(|name| (location_path.join(name), ZIP.to_vec()))
+				.collect(),
+			..Default::default()
+		};
+
+		let mut mismatches = vec![];
+		for flag_extension_mismatch in [false, true] {
+			for (name, extension) in [("photo", "jpg"), ("archive", "zip")] {
+				let validated = validate_file(
+					&source,
+					1,
+					location_path,
+					&file_path_for_object_validator::Data {
+						extension: Some(extension.to_string()),
+						..fake_file_path(name, None)
+					},
+					ValidationOptions {
+						flag_extension_mismatch,
+						..Default::default()
+					},
+				)
+				.await
+				.unwrap()
+				.unwrap();
+				// a mismatch is only reported, the file still validates
+				assert_eq!(validated.outcome, FileValidationOutcome::Checksummed);
+				mismatches.push(validated.extension_mismatch);
+			}
+		}
+
+		assert_eq!(
+			mismatches,
+			vec![
+				None,
+				None,
+				Some(ExtensionMismatch {
+					claimed: "jpg".to_string(),
+					detected: "application/zip".to_string(),
+				}),
+				None,
+			]
+		);
+	}
+
+	/// Audits against `manifest` alone
+	fn audit(manifest: &HashMap<String, String>) -> ManifestAudit<'_> {
+		ManifestAudit {
+			manifest,
+			chunks: None,
+			acknowledgments: None,
+			remote_checksums: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_verify_file() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("intact.txt"), "aaaa".to_string()),
+				(location_path.join("tampered.txt"), "bbbb".to_string()),
+				(location_path.join("unlisted.txt"), "cccc".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let manifest = [
+			("intact.txt", "aaaa"),
+			("tampered.txt", "ffff"),
+			("unreadable.txt", "dddd"),
+		]
+		.into_iter()
+		.map(|(path, checksum)| (path.to_string(), checksum.to_string()))
+		.collect::<HashMap<_, _>>();
+
+		let mut outcomes = vec![];
+		for name in ["intact", "tampered", "unlisted", "unreadable"] {
+			// the checksums stored in the library are never trusted
+			let validated = verify_file(
+				&source,
+				1,
+				location_path,
+				&fake_file_path(name, Some("aaaa")),
+				audit(&manifest),
+				ValidationOptions::default(),
+			)
+			.await
+			.unwrap()
+			.unwrap();
+			assert_eq!(validated.checksum, None);
+			outcomes.push(validated.outcome);
+		}
+
+		assert_eq!(outcomes[0], FileValidationOutcome::Verified);
+		assert_eq!(
+			outcomes[1],
+			FileValidationOutcome::Failed {
+				reason: "checksum bbbb doesn't match ffff from the manifest".to_string()
+			}
+		);
+		assert_eq!(
+			outcomes[2],
+			FileValidationOutcome::Failed {
+				reason: "not listed in the manifest".to_string()
+			}
+		);
+		assert!(outcomes[3].is_failure());
+	}
+
+	#[tokio::test]
+	async fn test_verify_file_acknowledged() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("edited.txt"), "bbbb".to_string()),
+				(location_path.join("edited again.txt"), "cccc".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let manifest = [("edited.txt", "aaaa"), ("edited again.txt", "aaaa")]
+			.into_iter()
+			.map(|(path, checksum)| (path.to_string(), checksum.to_string()))
+			.collect::<HashMap<_, _>>();
+		let at = Utc::now();
+		let acknowledgments = [("edited.txt", "bbbb"), ("edited again.txt", "bbbb")]
+			.into_iter()
+			.map(|(path, checksum)| {
+				(
+					path.to_string(),
+					Acknowledgment {
+						algorithm: ChecksumAlgorithm::Blake3,
+						checksum: checksum.to_string(),
+						at,
+						by: Some("my node".to_string()),
+					},
+				)
+			})
+			.collect::<HashMap<_, _>>();
+
+		let mut outcomes = vec![];
+		for name in ["edited", "edited again"] {
+			let validated = verify_file(
+				&source,
+				1,
+				location_path,
+				&fake_file_path(name, None),
+				ManifestAudit {
+					acknowledgments: Some(&acknowledgments),
+					..audit(&manifest)
+				},
+				ValidationOptions::default(),
+			)
+			.await
+			.unwrap()
+			.unwrap();
+			outcomes.push(validated.outcome);
+		}
+
+		assert_eq!(
+			outcomes,
+			vec![
+				FileValidationOutcome::Acknowledged {
+					at,
+					by: Some("my node".to_string()),
+				},
+				// changed since it was acknowledged
+				FileValidationOutcome::Failed {
+					reason: "checksum cccc doesn't match aaaa from the manifest".to_string()
+				},
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_verify_file_locates_corruption() {
+		let location_path = Path::new("/location");
+		let tampered = location_path.join("tampered.txt");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("intact.txt"), "aaaa".to_string()),
+				(tampered.clone(), "bbbb".to_string()),
+				(location_path.join("unreadable.txt"), "cccc".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			ranges: [(0, "a"), (10, "x"), (20, "c")]
+				.into_iter()
+				.map(|(offset, checksum)| ((tampered.clone(), offset), checksum.to_string()))
+				.collect(),
+			..Default::default()
+		};
+		let manifest = [
+			("intact.txt", "aaaa"),
+			("tampered.txt", "ffff"),
+			("unreadable.txt", "ffff"),
+		]
+		.into_iter()
+		.map(|(path, checksum)| (path.to_string(), checksum.to_string()))
+		.collect::<HashMap<_, _>>();
+		let file_chunks = [(0, "a"), (10, "b"), (20, "c")]
+			.into_iter()
+			.map(|(offset, checksum)| Chunk {
+				offset,
+				len: 10,
+				checksum: checksum.to_string(),
+			})
+			.collect::<Vec<_>>();
+		let chunks = ["intact.txt", "tampered.txt", "unreadable.txt"]
+			.into_iter()
+			.map(|path| (path.to_string(), file_chunks.clone()))
+			.collect::<HashMap<_, _>>();
+
+		let mut validated = vec![];
+		for name in ["intact", "tampered", "unreadable"] {
+			validated.push(
+				verify_file(
+					&source,
+					1,
+					location_path,
+					&fake_file_path(name, None),
+					ManifestAudit {
+						chunks: Some(&chunks),
+						..audit(&manifest)
+					},
+					ValidationOptions::default(),
+				)
+				.await
+				.unwrap()
+				.unwrap(),
+			);
+		}
+
+		assert_eq!(validated[0].outcome, FileValidationOutcome::Verified);
+		assert_eq!(validated[0].corrupt_ranges, None);
+		assert!(validated[1].outcome.is_failure());
+		assert_eq!(validated[1].corrupt_ranges, Some(vec![(10, 10)]));
+		// the chunks couldn't be read, the file still fails
+		assert!(validated[2].outcome.is_failure());
+		assert_eq!(validated[2].corrupt_ranges, None);
+	}
+
+	/// Remote checksums keyed by path relative to the location, paths without one fail
+	struct FakeRemoteChecksums(HashMap<&'static str, Option<&'static str>>);
+
+	#[async_trait::async_trait]
+	impl RemoteChecksums for FakeRemoteChecksums {
+		async fn checksum(
+			&self,
+			relative_path: &str,
+			_: ChecksumAlgorithm,
+		) -> Result<Option<String>, io::Error> {
+			self.0
+				.get(relative_path)
+				.map(|checksum| checksum.map(str::to_string))
+				.ok_or_else(|| io::Error::from(io::ErrorKind::ConnectionRefused))
+		}
+	}
+
+	#[tokio::test]
+	async fn test_verify_file_remotely() {
+		let location_path = Path::new("/location");
+		// the files that must be read
+		let source = FakeStepSource {
+			checksums: [
+				(
+					location_path.join("no_remote_checksum.txt"),
+					"bbbb".to_string(),
+				),
+				(location_path.join("remote_failed.txt"), "cccc".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let remote = FakeRemoteChecksums(
+			[
+				("intact.txt", Some("aaaa")),
+				("tampered.txt", Some("eeee")),
+				("no_remote_checksum.txt", None),
+			]
+			.into_iter()
+			.collect(),
+		);
+		let manifest = [
+			("intact.txt", "aaaa"),
+			("tampered.txt", "ffff"),
+			("no_remote_checksum.txt", "bbbb"),
+			("remote_failed.txt", "cccc"),
+		]
+		.into_iter()
+		.map(|(path, checksum)| (path.to_string(), checksum.to_string()))
+		.collect::<HashMap<_, _>>();
+
+		let mut outcomes = vec![];
+		for name in ["intact", "tampered", "no_remote_checksum", "remote_failed"] {
+			outcomes.push(
+				verify_file(
+					&source,
+					1,
+					location_path,
+					&fake_file_path(name, None),
+					ManifestAudit {
+						remote_checksums: Some(&remote),
+						..audit(&manifest)
+					},
+					ValidationOptions::default(),
+				)
+				.await
+				.unwrap()
+				.unwrap()
+				.outcome,
+			);
+		}
+
+		assert_eq!(
+			outcomes,
+			vec![
+				FileValidationOutcome::VerifiedRemotely,
+				FileValidationOutcome::Failed {
+					reason: "remote checksum eeee doesn't match ffff from the manifest".to_string()
+				},
+				FileValidationOutcome::Verified,
+				FileValidationOutcome::Verified,
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_memory_pressure_fallback() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [(location_path.join("file.txt"), "123".to_string())]
+				.into_iter()
+				.collect(),
+			max_block_len: Some(200 * 1024),
+			..Default::default()
+		};
+		let options = ValidationOptions {
+			min_block_len: 100 * 1024,
+			..Default::default()
+		};
+
+		let validated = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("file", None),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(validated.outcome, FileValidationOutcome::Checksummed);
+		assert_eq!(validated.checksum.as_deref(), Some("123"));
+		// 1MiB, 512KiB and 256KiB reads failed
+		assert_eq!(validated.reduced_block_len, Some(128 * 1024));
+
+		// without memory for reads as short as the floor, the file fails
+		let validated = validate_file(
+			&source,
+			1,
+			location_path,
+			&fake_file_path("file", None),
+			ValidationOptions {
+				min_block_len: 256 * 1024,
+				..options
+			},
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert!(validated.outcome.is_failure());
+		assert_eq!(validated.reduced_block_len, Some(256 * 1024));
+	}
+
+	#[tokio::test]
+	async fn test_short_read_retry() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("flaky.txt"), "123".to_string()),
+				(location_path.join("broken.txt"), "456".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			short_reads: std::sync::Mutex::new(
+				[
+					(location_path.join("flaky.txt"), 1),
+					(location_path.join("broken.txt"), 2),
+				]
+				.into_iter()
+				.collect(),
+			),
+			..Default::default()
+		};
+
+		let mut outcomes = vec![];
+		for name in ["flaky", "broken"] {
+			let validated = validate_file(
+				&source,
+				1,
+				location_path,
+				&fake_file_path(name, None),
+				ValidationOptions::default(),
+			)
+			.await
+			.unwrap()
+			.unwrap();
+			outcomes.push((validated.outcome, validated.checksum));
+		}
+
+		// a partial read never ends up stored as the checksum
+		assert_eq!(
+			outcomes,
+			vec![
+				(FileValidationOutcome::Checksummed, Some("123".to_string())),
+				(
+					FileValidationOutcome::Failed {
+						reason: "read 512 bytes of a file of 1024 bytes".to_string()
+					},
+					None
+				),
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn test_skip_empty_files() {
+		let sized = |name, size: Option<u64>| file_path_for_object_validator::Data {
+			size_in_bytes_bytes: size.map(|size| size.to_be_bytes().to_vec()),
+			..fake_file_path(name, None)
+		};
+
+		let (file_paths, skipped) = skip_empty_files(vec![
+			sized("empty", Some(0)),
+			sized("full", Some(10)),
+			sized("unknown", None),
+		]);
+		assert_eq!(skipped, 1);
+		assert_eq!(
+			file_paths
+				.iter()
+				.map(|file_path| file_path.name.as_deref().unwrap())
+				.collect::<Vec<_>>(),
+			vec!["full", "unknown"]
+		);
+
+		// when auditing, only files the manifest also lists as empty are skipped
+		let empty = ChecksumAlgorithm::Blake3.empty_checksum();
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			checksums: [
+				(location_path.join("empty.txt"), empty.to_string()),
+				(location_path.join("emptied.txt"), empty.to_string()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let manifest = [("empty.txt", empty), ("emptied.txt", "aaaa")]
+			.into_iter()
+			.map(|(path, checksum)| (path.to_string(), checksum.to_string()))
+			.collect::<HashMap<_, _>>();
+		let options = ValidationOptions {
+			skip_empty: true,
+			..Default::default()
+		};
+
+		assert!(verify_file(
+			&source,
+			1,
+			location_path,
+			&sized("empty", Some(0)),
+			audit(&manifest),
+			options
+		)
+		.await
+		.unwrap()
+		.is_none());
+		assert!(verify_file(
+			&source,
+			1,
+			location_path,
+			&sized("emptied", Some(0)),
+			audit(&manifest),
+			options
+		)
+		.await
+		.unwrap()
+		.unwrap()
+		.outcome
+		.is_failure());
+	}
+
+	#[test]
+	fn test_init_builder() {
+		let location = |id| location::Data {
+			id,
+			pub_id: vec![],
+			name: None,
+			path: Some("/location".to_string()),
+			total_capacity: None,
+			available_capacity: None,
+			is_archived: None,
+			generate_preview_media: None,
+			sync_preview_media: None,
+			hidden: None,
+			date_created: None,
+			aggregate_checksum: None,
+			aggregate_checksum_algorithm: None,
+			node_id: None,
+			node: None,
+			file_paths: None,
+			indexer_rules: None,
+		};
+		let hash = |init: &ObjectValidatorJobInit| {
+			let mut hasher = std::collections::hash_map::DefaultHasher::new();
+			init.hash(&mut hasher);
+			hasher.finish()
+		};
+
+		let init = ObjectValidatorJobInit::builder(location(1))
+			.sub_path("docs")
+			.algorithm(ChecksumAlgorithm::Sha256)
+			.skip_empty(true)
+			.object_kinds([ObjectKind::Video, ObjectKind::Image])
+			.favorites_only(true)
+			.max_file_attempts(3)
+			.build();
+		assert_eq!(init.sub_path, Some(PathBuf::from("docs")));
+		assert_eq!(init.max_file_attempts, Some(3));
+		assert_eq!(
+			init.object_kinds,
+			Some(vec![ObjectKind::Video, ObjectKind::Image])
+		);
+		assert_eq!(init.algorithm, Some(ChecksumAlgorithm::Sha256));
+		assert!(init.skip_empty);
+		assert!(init.favorites_only);
+		assert_eq!(init.order, StepOrder::Database);
+		assert!(!init.prune_missing);
+
+		// options don't make it another job, only what is validated does
+		let same_files = ObjectValidatorJobInit::builder(location(1))
+			.sub_path("docs")
+			.build();
+		assert_eq!(hash(&init), hash(&same_files));
+		assert_ne!(
+			hash(&init),
+			hash(&ObjectValidatorJobInit::builder(location(1)).build())
+		);
+		assert_ne!(
+			hash(&init),
+			hash(
+				&ObjectValidatorJobInit::builder(location(2))
+					.sub_path("docs")
+					.build()
+			)
+		);
+
+		// nor do other selections
+		let selection = |pub_ids: &[u8]| {
+			ObjectValidatorJobInit::builder(location(1))
+				.file_path_pub_ids(pub_ids.iter().map(|pub_id| vec![*pub_id]).collect())
+				.build()
+		};
+		assert_eq!(
+			selection(&[1, 2]).file_path_pub_ids,
+			Some(vec![vec![1], vec![2]])
+		);
+		assert_eq!(hash(&selection(&[1, 2])), hash(&selection(&[1, 2])));
+		assert_ne!(hash(&selection(&[1, 2])), hash(&selection(&[1, 3])));
+		assert_ne!(
+			hash(&selection(&[1, 2])),
+			hash(&ObjectValidatorJobInit::builder(location(1)).build())
+		);
+
+		// only audits of every file can be attested
+		let manifest = SignedManifest {
+			path: PathBuf::from("/manifest.txt"),
+			signature_path: PathBuf::from("/manifest.txt.sig"),
+			public_key: String::new(),
+			algorithm: ChecksumAlgorithm::Blake3,
+			chunks_path: None,
+		};
+		let audit = ObjectValidatorJobInit::builder(location(1))
+			.sub_path("docs")
+			.verify_against(manifest.clone())
+			.attest_to("/attestations")
+			.build();
+		assert_eq!(audit.attest_to, Some(PathBuf::from("/attestations")));
+		assert!(audit.is_full_audit());
+		assert!(!ObjectValidatorJobInit::builder(location(1))
+			.verify_against(manifest)
+			.favorites_only(true)
+			.build()
+			.is_full_audit());
+		assert!(!ObjectValidatorJobInit::builder(location(1))
+			.build()
+			.is_full_audit());
+
+		// a job with a checksum store can't be resumed without it
+		let with_store = ObjectValidatorJobInit::builder(location(1))
+			.checksum_store(Arc::new(MemoryChecksumStore::default()))
+			.build();
+		assert!(!with_store.lost_checksum_store());
+		let resumed = serde_json::from_value::<ObjectValidatorJobInit>(
+			serde_json::to_value(&with_store).unwrap(),
+		)
+		.unwrap();
+		assert!(resumed.checksum_store.is_none());
+		assert!(resumed.lost_checksum_store());
+		assert!(!ObjectValidatorJobInit::builder(location(1))
+			.build()
+			.lost_checksum_store());
+	}
+
+	#[test]
+	fn test_locations_overlap() {
+		let location_path = Path::new("/photos/2023");
+
+		assert!(locations_overlap(location_path, Path::new("/photos")));
+		assert!(locations_overlap(
+			location_path,
+			Path::new("/photos/2023/trip")
+		));
+		assert!(locations_overlap(location_path, location_path));
+		// only whole components are compared
+		assert!(!locations_overlap(
+			location_path,
+			Path::new("/photos/2023-old")
+		));
+		assert!(!locations_overlap(location_path, Path::new("/videos")));
+	}
+
+	#[test]
+	fn test_read_rate_delay() {
+		let mib = 1024 * 1024;
+
+		assert_eq!(
+			read_rate_delay(10 * mib, 4 * mib, Duration::from_millis(500)),
+			Some(Duration::from_millis(2000))
+		);
+		// the step was slower than the limit already
+		assert_eq!(read_rate_delay(mib, mib, Duration::from_secs(2)), None);
+		assert_eq!(read_rate_delay(mib, 0, Duration::ZERO), None);
+	}
+
+	#[test]
+	fn test_steps_len() {
+		let sized = |name, len: u64| file_path_for_object_validator::Data {
+			size_in_bytes_bytes: Some(len.to_be_bytes().to_vec()),
+			..fake_file_path(name, None)
+		};
+
+		assert_eq!(
+			steps_len(&VecDeque::from([
+				vec![sized("a", 10), sized("b", 4096)],
+				vec![],
+				vec![fake_file_path("unknown size", None), sized("c", 1 << 40)],
+			])),
+			4106 + (1 << 40)
+		);
+
+		// sizes recorded wrong don't overflow the total
+		assert_eq!(total_len(&[sized("a", u64::MAX), sized("b", 10)]), u64::MAX);
+	}
+
+	#[tokio::test]
+	async fn test_size_on_disk() {
+		let location_path = Path::new("/location");
+		let huge = 5 << 40;
+		let source = FakeStepSource {
+			checksums: ["huge.png", "emptied.txt", "empty.txt"]
+				.into_iter()
+				.map(|name| (location_path.join(name), "123".to_string()))
+				.collect(),
+			lens: [("huge.png", huge), ("emptied.txt", 10), ("empty.txt", 0)]
+				.into_iter()
+				.map(|(name, len)| (location_path.join(name), len))
+				.collect(),
+			..Default::default()
+		};
+		let sized = |name, extension: &str, size: u64| file_path_for_object_validator::Data {
+			extension: Some(extension.to_string()),
+			size_in_bytes_bytes: Some(size.to_be_bytes().to_vec()),
+			..fake_file_path(name, None)
+		};
+
+		// a file indexed small but terabytes long on disk isn't read whole to be decoded
+		let validated = validate_file(
+			&source,
+			1,
+			location_path,
+			&sized("huge", "png", 1024),
+			ValidationOptions {
+				perceptual: true,
+				..Default::default()
+			},
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(validated.live_len, Some(huge));
+		assert_eq!(validated.perceptual_hash, None);
+		assert_eq!(
+			size_mismatch(Some(1024), validated.live_len),
+			Some(SizeMismatch {
+				stored: 1024,
+				actual: huge,
+			})
+		);
+		assert_eq!(size_mismatch(Some(huge), Some(huge)), None);
+		assert_eq!(size_mismatch(None, Some(huge)), None);
+
+		// files wrongly indexed as empty aren't skipped for it
+		let mut report = ObjectValidatorReport::default();
+		let file_paths = stat_empty_files(
+			&source,
+			1,
+			location_path,
+			vec![
+				sized("emptied", "txt", 0),
+				sized("empty", "txt", 0),
+				sized("huge", "png", 1024),
+			],
+			&mut report,
+		)
+		.await
+		.unwrap();
+		let (file_paths, skipped) = skip_empty_files(file_paths);
+		assert_eq!(skipped, 1);
+		assert_eq!(
+			file_paths
+				.iter()
+				.map(|file_path| (file_path.name.as_deref().unwrap(), size_in_bytes(file_path)))
+				.collect::<Vec<_>>(),
+			vec![("emptied", Some(10)), ("huge", Some(1024))]
+		);
+		assert_eq!(
+			report.size_mismatches,
+			BTreeMap::from([(
+				"emptied.txt".to_string(),
+				SizeMismatch {
+					stored: 0,
+					actual: 10,
+				}
+			)])
+		);
+	}
+
+	#[test]
+	fn test_attempts_survive_resumes() {
+		let mut state = ObjectValidatorJobState {
+			version: STATE_VERSION,
+			location_path: PathBuf::from("/location"),
+			task_count: 1,
+			report: ObjectValidatorReport::default(),
+			remote_location: false,
+			algorithm: ChecksumAlgorithm::Blake3,
+			manifest: None,
+			chunks: None,
+			acknowledgments: HashMap::new(),
+			reflink_copies: HashMap::new(),
+			total_bytes: 0,
+			completed_bytes: 0,
+			attempts: HashMap::new(),
+			totals: ValidationTotals::default(),
+			requeued_in_flux: HashSet::new(),
+		};
+
+		assert_eq!(record_attempt(&mut state.attempts, 7), 1);
+		assert_eq!(record_attempt(&mut state.attempts, 7), 2);
+		assert_eq!(record_attempt(&mut state.attempts, 8), 1);
+
+		let paused = rmp_serde::to_vec_named(&state).unwrap();
+		let mut resumed = rmp_serde::from_slice::<ObjectValidatorJobState>(&paused).unwrap();
+		assert_eq!(record_attempt(&mut resumed.attempts, 7), 3);
+		assert_eq!(record_attempt(&mut resumed.attempts, 8), 2);
+	}
+
+	#[cfg(target_os = "windows")]
+	#[test]
+	fn test_extended_length_path() {
+		for (path, expected) in [
+			(r"C:\location\docs/a.txt", r"\\?\C:\location\docs\a.txt"),
+			(r"C:\location\.\docs\..\a.txt", r"\\?\C:\location\a.txt"),
+			(
+				r"\\nas\share\location/a.txt",
+				r"\\?\UNC\nas\share\location\a.txt",
+			),
+			(r"\\?\C:\location\a.txt", r"\\?\C:\location\a.txt"),
+			(r"location\a.txt", r"location\a.txt"),
+		] {
+			assert_eq!(
+				extended_length_path(PathBuf::from(path)),
+				PathBuf::from(expected)
+			);
+		}
+	}
+
+	#[cfg(target_os = "windows")]
+	#[tokio::test]
+	async fn test_checksum_deeply_nested_file() {
+		use super::super::hash::file_checksum_with;
+
+		let location = tempfile::tempdir().unwrap();
+		// well over MAX_PATH
+		let relative_path = format!("{}deep.txt", "nested directory/".repeat(20));
+		let full_path = extended_length_path(location.path().join(&relative_path));
+
+		tokio::fs::create_dir_all(full_path.parent().unwrap())
+			.await
+			.unwrap();
+		tokio::fs::write(&full_path, b"spacedrive").await.unwrap();
+
+		assert_eq!(
+			file_checksum_with(&full_path, ReadOptions::default())
+				.await
+				.unwrap(),
+			blake3::hash(b"spacedrive").to_hex().to_string()
+		);
+	}
+
+	#[test]
+	fn test_low_free_space_reason() {
+		let path = Path::new("/data/libraries");
+
+		assert_eq!(low_free_space_reason(path, 1024, 1024), None);
+		assert_eq!(low_free_space_reason(path, 4096, 1024), None);
+		assert_eq!(
+			low_free_space_reason(path, 512, 1024).as_deref(),
+			Some("Paused: 512 bytes free on the volume of /data/libraries, waiting for 1024")
+		);
+	}
+
+	#[tokio::test]
+	async fn test_location_unavailable_reason() {
+		let dir = tempdir().unwrap();
+		let location_path = dir.path().join("drive");
+		fs::create_dir(&location_path).await.unwrap();
+		assert_eq!(location_unavailable_reason(&location_path).await, None);
+
+		// a single file going away is left to its own validation
+		fs::write(location_path.join("a.txt"), b"a").await.unwrap();
+		fs::remove_file(location_path.join("a.txt")).await.unwrap();
+		assert_eq!(location_unavailable_reason(&location_path).await, None);
+
+		fs::remove_dir(&location_path).await.unwrap();
+		assert!(location_unavailable_reason(&location_path)
+			.await
+			.unwrap()
+			.starts_with("Waiting for location: "));
+
+		// and back again, like a drive plugged in again
+		fs::create_dir(&location_path).await.unwrap();
+		assert_eq!(location_unavailable_reason(&location_path).await, None);
+	}
+
+	/// Keeps checksums in memory, keyed by file path pub id
+	#[derive(Default)]
+	struct MemoryChecksumStore(std::sync::Mutex<HashMap<Vec<u8>, StoredChecksums>>);
+
+	#[async_trait::async_trait]
+	impl ChecksumStore for MemoryChecksumStore {
+		async fn get(
+			&self,
+			file_path: &file_path_for_object_validator::Data,
+		) -> Result<Option<StoredChecksums>, ValidatorError> {
+			Ok(self.0.lock().unwrap().get(&file_path.pub_id).cloned())
+		}
+
+		async fn put(
+			&self,
+			file_path: &file_path_for_object_validator::Data,
+			checksums: StoredChecksums,
+		) -> Result<(), ValidatorError> {
+			self.0
+				.lock()
+				.unwrap()
+				.insert(file_path.pub_id.clone(), checksums);
+			Ok(())
+		}
+
+		async fn compare_and_put(
+			&self,
+			file_path: &file_path_for_object_validator::Data,
+			checksums: StoredChecksums,
+		) -> Result<ChecksumWrite, ValidatorError> {
+			let mut stored = self.0.lock().unwrap();
+			let found = stored
+				.get(&file_path.pub_id)
+				.and_then(|stored| stored.checksum.clone());
+			if found != file_path.integrity_checksum {
+				return Ok(ChecksumWrite::Conflict { found });
+			}
+
+			stored.insert(file_path.pub_id.clone(), checksums);
+			Ok(ChecksumWrite::Stored)
+		}
+	}
+
+	#[tokio::test]
+	async fn test_checksum_store() {
+		let store = MemoryChecksumStore::default();
+		let checksums = StoredChecksums {
+			checksum: Some("aaaa".to_string()),
+			algorithm: ChecksumAlgorithm::Sha256,
+			..Default::default()
+		};
+
+		let original = fake_file_path("original", None);
+		let copy = fake_file_path("copy", None);
+		let mut report = ObjectValidatorReport::default();
+		store_reflink_copies(
+			&store,
+			1,
+			vec![copy.clone()],
+			"original.txt",
+			&checksums,
+			&mut report,
+		)
+		.await
+		.unwrap();
+
+		assert_eq!(store.get(&copy).await.unwrap(), Some(checksums));
+		assert_eq!(
+			report.files["copy.txt"],
+			FileValidationOutcome::ReflinkCopy {
+				of: "original.txt".to_string()
+			}
+		);
+
+		// the checksums in the library rows are replaced by the ones in the store
+		let with_stored = with_stored_checksums(
+			&store,
+			&[
+				copy,
+				file_path_for_object_validator::Data {
+					integrity_checksum: Some("bbbb".to_string()),
+					..original
+				},
+			],
+		)
+		.await
+		.unwrap();
+		assert_eq!(with_stored[0].integrity_checksum.as_deref(), Some("aaaa"));
+		assert_eq!(
+			with_stored[0].integrity_checksum_algorithm.as_deref(),
+			Some("sha256")
+		);
+		assert_eq!(with_stored[1].integrity_checksum, None);
+		assert!(!has_checksum_for(
+			&with_stored[1],
+			ChecksumAlgorithm::Blake3,
+			false
+		));
+
+		// checksums covering the other streams are only ever compared with each other
+		let with_streams = fake_file_path("with_streams", None);
+		store
+			.put(
+				&with_streams,
+				StoredChecksums {
+					checksum: Some("cccc".to_string()),
+					algorithm: ChecksumAlgorithm::Sha256,
+					include_alt_streams: true,
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+		let with_stored = with_stored_checksums(&store, &[with_streams])
+			.await
+			.unwrap();
+		assert_eq!(
+			with_stored[0].integrity_checksum_algorithm.as_deref(),
+			Some("sha256+streams")
+		);
+		assert!(has_checksum_for(
+			&with_stored[0],
+			ChecksumAlgorithm::Sha256,
+			true
+		));
+		assert!(!has_checksum_for(
+			&with_stored[0],
+			ChecksumAlgorithm::Sha256,
+			false
+		));
+	}
+
+	#[tokio::test]
+	async fn test_checksum_conflicts() {
+		let store = MemoryChecksumStore::default();
+		let checksums = |checksum: &str| StoredChecksums {
+			checksum: Some(checksum.to_string()),
+			..Default::default()
+		};
+		let read = fake_file_path("file", Some("aaaa"));
+		let mut report = ObjectValidatorReport::default();
+
+		// still the checksum the file was read with
+		store.put(&read, checksums("aaaa")).await.unwrap();
+		store_checksums(
+			&store,
+			&read,
+			checksums("bbbb"),
+			ChecksumConflictPolicy::KeepOther,
+			"file.txt",
+			&mut report,
+		)
+		.await
+		.unwrap();
+		assert_eq!(store.get(&read).await.unwrap(), Some(checksums("bbbb")));
+		assert!(report.checksum_conflicts.is_empty());
+
+		// the watcher stored another one meanwhile
+		for (policy, kept) in [
+			(ChecksumConflictPolicy::KeepOther, "cccc"),
+			(ChecksumConflictPolicy::Overwrite, "bbbb"),
+		] {
+			store.put(&read, checksums("cccc")).await.unwrap();
+			store_checksums(
+				&store,
+				&read,
+				checksums("bbbb"),
+				policy,
+				"file.txt",
+				&mut report,
+			)
+			.await
+			.unwrap();
+			assert_eq!(store.get(&read).await.unwrap(), Some(checksums(kept)));
+			assert_eq!(
+				report.checksum_conflicts["file.txt"],
+				ChecksumConflict {
+					ours: Some("bbbb".to_string()),
+					theirs: Some("cccc".to_string()),
+					overwritten: policy == ChecksumConflictPolicy::Overwrite,
+				}
+			);
+		}
+
+		// checksums generated for files that had none are stored regardless
+		let generated = fake_file_path("generated", None);
+		store.put(&generated, checksums("cccc")).await.unwrap();
+		let mut report = ObjectValidatorReport::default();
+		store_checksums(
+			&store,
+			&generated,
+			checksums("bbbb"),
+			ChecksumConflictPolicy::KeepOther,
+			"generated.txt",
+			&mut report,
+		)
+		.await
+		.unwrap();
+		assert_eq!(
+			store.get(&generated).await.unwrap(),
+			Some(checksums("bbbb"))
+		);
+		assert!(report.checksum_conflicts.is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_validate_range() {
+		let location_path = Path::new("/location");
+		let source = FakeStepSource {
+			ranges: [
+				((location_path.join("image.txt"), 512), "header".to_string()),
+				((location_path.join("other.txt"), 512), "other".to_string()),
+			]
+			.into_iter()
+			.collect(),
+			..Default::default()
+		};
+		let range = 512..1024;
+		let image = file_path_for_object_validator::Data {
+			size_in_bytes_bytes: Some(4096u64.to_be_bytes().to_vec()),
+			..fake_file_path("image", None)
+		};
+		let options = ValidationOptions::default();
+		let store = MemoryChecksumStore::default();
+
+		// the first run stores the checksum of the range
+		let validated = validate_range(
+			&source,
+			1,
+			location_path,
+			&image,
+			&range,
+			RangeBaseline::Store(&store),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(validated.outcome, FileValidationOutcome::Checksummed);
+		let range_checksum = validated.range_checksum.unwrap();
+		assert_eq!(range_checksum.checksum, "header");
+		store
+			.put(
+				&image,
+				StoredChecksums {
+					range_checksum: Some(range_checksum),
+					..Default::default()
+				},
+			)
+			.await
+			.unwrap();
+
+		// later ones compare with it, the same row now reading as `other` changed in that range
+		for (name, outcome) in [
+			("image", FileValidationOutcome::Verified),
+			(
+				"other",
+				FileValidationOutcome::Failed {
+					reason: "checksum other of byte range 512-1024 doesn't match header"
+						.to_string(),
+				},
+			),
+		] {
+			let file_path = file_path_for_object_validator::Data {
+				name: Some(name.to_string()),
+				..image.clone()
+			};
+			let validated = validate_range(
+				&source,
+				1,
+				location_path,
+				&file_path,
+				&range,
+				RangeBaseline::Store(&store),
+				options,
+			)
+			.await
+			.unwrap()
+			.unwrap();
+			assert_eq!(validated.outcome, outcome);
+			assert_eq!(validated.range_checksum, None);
+		}
+
+		// another range is computed again
+		let validated = validate_range(
+			&source,
+			1,
+			location_path,
+			&image,
+			&(512..2048),
+			RangeBaseline::Store(&store),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(validated.outcome, FileValidationOutcome::Checksummed);
+
+		let manifest = [("image.txt#512-1024".to_string(), "header".to_string())]
+			.into_iter()
+			.collect::<HashMap<_, _>>();
+		let validated = validate_range(
+			&source,
+			1,
+			location_path,
+			&image,
+			&range,
+			RangeBaseline::Manifest(&manifest),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(validated.outcome, FileValidationOutcome::Verified);
+
+		let short = file_path_for_object_validator::Data {
+			size_in_bytes_bytes: Some(1000u64.to_be_bytes().to_vec()),
+			..image
+		};
+		let validated = validate_range(
+			&source,
+			1,
+			location_path,
+			&short,
+			&range,
+			RangeBaseline::Manifest(&manifest),
+			options,
+		)
+		.await
+		.unwrap()
+		.unwrap();
+		assert_eq!(
+			validated.outcome,
+			FileValidationOutcome::Failed {
+				reason: "byte range 512-1024 ends past the end of the file, 1000 bytes long"
+					.to_string()
+			}
+		);
 	}
 }
@@ -0,0 +1,134 @@
+//! When an object's files were last validated and how it went, stored on the object itself so the
+//! explorer can show, filter and sort by it, like listing the least recently verified objects
+//! first. Synced, so every device knows when the object was last checked on any of them.
+
+use crate::{library::Library, prisma::object, sync};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use specta::Type;
+use tracing::warn;
+
+use super::{outcome_tags::by_status, SyncPacer, ValidatorError};
+
+/// How the last validation of an object's files went, stored as `object.last_verified_result`
+#[derive(Serialize, Deserialize, Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum VerificationResult {
+	/// Every file of the object validated matched its checksum
+	Healthy = 0,
+	/// At least one of the files of the object validated didn't
+	Corrupted = 1,
+}
+
+impl VerificationResult {
+	pub fn from_db(result: i32) -> Option<Self> {
+		match result {
+			0 => Some(Self::Healthy),
+			1 => Some(Self::Corrupted),
+			_ => None,
+		}
+	}
+}
+
+/// Records on the objects of healthy and corrupted files that they were verified `at`, an object
+/// with both being corrupted. Their sync operations are counted against `sync_pacer` when given.
+pub async fn record_verifications(
+	library: &Library,
+	sync_pacer: Option<&SyncPacer>,
+	at: DateTime<Utc>,
+	healthy: impl IntoIterator<Item = object::id::Type>,
+	failed: impl IntoIterator<Item = object::id::Type>,
+) -> Result<(), ValidatorError> {
+	let Library { db, sync, .. } = library;
+
+	let (healthy, failed) = by_status(healthy, failed);
+	if healthy.is_empty() && failed.is_empty() {
+		return Ok(());
+	}
+
+	let objects = db
+		.object()
+		.find_many(vec![object::id::in_vec(
+			healthy.iter().chain(&failed).copied().collect(),
+		)])
+		.select(object::select!({ id pub_id }))
+		.exec()
+		.await?;
+
+	let at = DateTime::<FixedOffset>::from(at);
+	let results = objects
+		.into_iter()
+		.map(|object| {
+			let result = if failed.binary_search(&object.id).is_ok() {
+				VerificationResult::Corrupted
+			} else {
+				VerificationResult::Healthy
+			} as i32;
+			(object, result)
+		})
+		.collect::<Vec<_>>();
+
+	let updates = || {
+		results
+			.iter()
+			.map(|(object, result)| {
+				db.object().update(
+					object::id::equals(object.id),
+					vec![
+						object::last_verified_at::set(Some(at)),
+						object::last_verified_result::set(Some(*result)),
+					],
+				)
+			})
+			.collect::<Vec<_>>()
+	};
+
+	let ops = results
+		.iter()
+		.flat_map(|(object, result)| {
+			let sync_id = || sync::object::SyncId {
+				pub_id: object.pub_id.clone(),
+			};
+			[
+				sync.shared_update(sync_id(), object::last_verified_at::NAME, json!(at)),
+				sync.shared_update(sync_id(), object::last_verified_result::NAME, json!(result)),
+			]
+		})
+		.collect::<Vec<_>>();
+
+	let ops_len = ops.len();
+	match sync.write_ops(db, (ops, updates())).await {
+		Ok(_) => {
+			if let Some(sync_pacer) = sync_pacer {
+				sync_pacer.record(ops_len);
+			}
+		}
+		Err(e) => {
+			// like checksums, they're still stored on this node when only syncing them fails, the
+			// next validation of the objects syncs them
+			db._batch(updates()).await?;
+			warn!(
+				"Couldn't sync the verifications of {} objects: {e}",
+				results.len()
+			);
+		}
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_verification_result_db_round_trip() {
+		for result in [VerificationResult::Healthy, VerificationResult::Corrupted] {
+			assert_eq!(VerificationResult::from_db(result as i32), Some(result));
+		}
+		assert_eq!(VerificationResult::from_db(2), None);
+	}
+}
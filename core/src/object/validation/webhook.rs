@@ -0,0 +1,98 @@
+use crate::prisma::location;
+
+use std::{path::PathBuf, time::Duration};
+
+use serde::Serialize;
+use url::Url;
+use uuid::Uuid;
+
+/// A slow or unreachable webhook must not hold the validator back for long
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ValidationFailure {
+	pub path: PathBuf,
+	pub reason: String,
+}
+
+/// Body POSTed to the `on_failure_webhook` of a validation, once per step with failures
+#[derive(Serialize, Debug)]
+pub struct ValidationFailuresPayload<'a> {
+	pub job_id: Uuid,
+	pub library_id: Uuid,
+	pub location_id: location::id::Type,
+	pub failures: &'a [ValidationFailure],
+}
+
+pub async fn send_failure_webhook(
+	url: &Url,
+	payload: &ValidationFailuresPayload<'_>,
+) -> Result<(), reqwest::Error> {
+	reqwest::Client::new()
+		.post(url.clone())
+		.timeout(WEBHOOK_TIMEOUT)
+		.json(payload)
+		.send()
+		.await?
+		.error_for_status()
+		.map(|_| ())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used, clippy::panic)]
+mod tests {
+	use super::*;
+
+	use tokio::{
+		io::{AsyncReadExt, AsyncWriteExt},
+		net::TcpListener,
+	};
+
+	#[tokio::test]
+	async fn test_send_failure_webhook() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = Url::parse(&format!("http://{}/hook", listener.local_addr().unwrap())).unwrap();
+
+		let server = tokio::spawn(async move {
+			let (mut stream, _) = listener.accept().await.unwrap();
+			let mut request = vec![];
+			let mut buffer = [0; 1024];
+			// the client waits for our response, so we stop reading at the end of the json body
+			while !request.ends_with(b"]}") {
+				let read = stream.read(&mut buffer).await.unwrap();
+				assert_ne!(
+					read, 0,
+					"connection closed before the whole request was sent"
+				);
+				request.extend_from_slice(&buffer[..read]);
+			}
+			stream
+				.write_all(b"HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\n\r\n")
+				.await
+				.unwrap();
+			String::from_utf8(request).unwrap()
+		});
+
+		let failures = [ValidationFailure {
+			path: PathBuf::from("/location/file.txt"),
+			reason: "unreadable".to_string(),
+		}];
+		let result = send_failure_webhook(
+			&url,
+			&ValidationFailuresPayload {
+				job_id: Uuid::nil(),
+				library_id: Uuid::nil(),
+				location_id: 1,
+				failures: &failures,
+			},
+		)
+		.await;
+
+		// error statuses are reported, so the job can log them
+		assert!(result.unwrap_err().status().is_some());
+
+		let request = server.await.unwrap();
+		assert!(request.starts_with("POST /hook HTTP/1.1"));
+		assert!(request.contains(r#""path":"/location/file.txt","reason":"unreadable""#));
+	}
+}
@@ -26,7 +26,7 @@ use tokio::{
 	fs::File,
 	io::{AsyncReadExt, AsyncWriteExt, BufReader},
 	sync::{broadcast, oneshot, Mutex},
-	time::sleep,
+	time::{sleep, timeout},
 };
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
@@ -34,15 +34,22 @@ use uuid::Uuid;
 use crate::{
 	library::{Library, LibraryManager, SubscriberEvent},
 	node::{NodeConfig, NodeConfigManager, Platform},
+	object::validation::{local_checksums_of_objects, PeerChecksum},
 	p2p::{NodeInformation, OperatingSystem, SyncRequestError, SPACEDRIVE_APP_ID},
 	sync::SyncMessage,
 };
 
-use super::{Header, PeerMetadata};
+use super::{
+	checksums_payload_from_stream, checksums_payload_to_bytes, ChecksumsRequestError, Header,
+	PeerMetadata,
+};
 
 /// The amount of time to wait for a Spacedrop request to be accepted or rejected before it's automatically rejected
 const SPACEDROP_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// The amount of time to wait for a peer to answer a request for its checksums before it's considered unavailable
+const CHECKSUMS_TIMEOUT: Duration = Duration::from_secs(30);
+
 /// TODO: P2P event for the frontend
 #[derive(Debug, Clone, Type, Serialize)]
 #[serde(tag = "type")]
@@ -311,6 +318,81 @@ impl P2PManager {
 											);
 										}
 									}
+									Header::Checksums(library_id) => {
+										let mut stream = match event.stream {
+											SpaceTimeStream::Unicast(stream) => stream,
+											_ => {
+												error!("Received checksums request from peer '{}' but it's not a unicast stream!", event.peer_id);
+												return;
+											}
+										};
+
+										// Dropping the stream without answering tells the peer we can't
+										let Some(library) = library_manager.get_library(library_id).await else {
+											warn!("error answering checksums request. no library by id '{library_id}' found!");
+											return;
+										};
+
+										// Only nodes paired with the library can know its objects' checksums
+										match library
+											.db
+											.node()
+											.count(vec![node::node_peer_id::equals(Some(
+												event.peer_id.to_string(),
+											))])
+											.exec()
+											.await
+										{
+											Ok(0) => {
+												warn!("Received checksums request for library '{library_id}' from peer '{}' which isn't paired with it!", event.peer_id);
+												return;
+											}
+											Ok(_) => {}
+											Err(e) => {
+												error!("error checking the nodes of library '{library_id}': {e:#?}");
+												return;
+											}
+										}
+
+										let object_pub_ids: Vec<Vec<u8>> = match timeout(
+											CHECKSUMS_TIMEOUT,
+											checksums_payload_from_stream(&mut stream),
+										)
+										.await
+										{
+											Ok(Ok(object_pub_ids)) => object_pub_ids,
+											Ok(Err(e)) => {
+												error!("error reading checksums request from peer '{}': {e}", event.peer_id);
+												return;
+											}
+											Err(_) => {
+												error!("timed out reading checksums request from peer '{}'", event.peer_id);
+												return;
+											}
+										};
+
+										let checksums = match local_checksums_of_objects(
+											&library,
+											object_pub_ids,
+										)
+										.await
+										{
+											Ok(checksums) => checksums,
+											Err(e) => {
+												error!("error fetching checksums for library '{library_id}': {e:#?}");
+												return;
+											}
+										};
+
+										match checksums_payload_to_bytes(&checksums) {
+											Ok(bytes) => {
+												if let Err(e) = stream.write_all(&bytes).await {
+													error!("error sending checksums to peer '{}': {e}", event.peer_id);
+												}
+											}
+											Err(e) => error!("error encoding checksums: {e}"),
+										}
+									}
 								}
 							});
 						}
@@ -518,6 +600,31 @@ impl P2PManager {
 		}
 	}
 
+	/// Asks `peer_id` for the checksums it stored for its copies of the objects
+	pub async fn request_checksums(
+		&self,
+		peer_id: PeerId,
+		library_id: Uuid,
+		object_pub_ids: Vec<Vec<u8>>,
+	) -> Result<Vec<PeerChecksum>, ChecksumsRequestError> {
+		let mut bytes = Header::Checksums(library_id).to_bytes();
+		bytes.extend(checksums_payload_to_bytes(&object_pub_ids)?);
+
+		timeout(CHECKSUMS_TIMEOUT, async {
+			let mut stream = self
+				.manager
+				.stream(peer_id)
+				.await
+				.map_err(|_| ChecksumsRequestError::Unreachable)?;
+
+			stream.write_all(&bytes).await?;
+
+			checksums_payload_from_stream(&mut stream).await
+		})
+		.await
+		.map_err(|_| ChecksumsRequestError::Timeout)?
+	}
+
 	pub async fn ping(&self) {
 		self.manager.broadcast(Header::Ping.to_bytes()).await;
 	}
@@ -1,5 +1,6 @@
 use std::string::FromUtf8Error;
 
+use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 use tokio::io::{AsyncRead, AsyncReadExt};
 use uuid::Uuid;
@@ -19,6 +20,7 @@ pub enum Header {
 	Spacedrop(SpaceblockRequest),
 	Pair(Uuid),
 	Sync(Uuid),
+	Checksums(Uuid),
 }
 
 #[derive(Debug, Error)]
@@ -31,6 +33,26 @@ pub enum SyncRequestError {
 	PayloadLenIoError(std::io::Error),
 }
 
+#[derive(Debug, Error)]
+pub enum ChecksumsRequestError {
+	#[error("couldn't open a stream to the peer")]
+	Unreachable,
+	#[error("timed out waiting for the peer's checksums")]
+	Timeout,
+	#[error("io error: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("payload is too long: <len={0}>")]
+	PayloadTooLong(usize),
+	#[error("error encoding payload: {0}")]
+	Encode(#[from] rmp_serde::encode::Error),
+	#[error("error decoding payload: {0}")]
+	Decode(#[from] rmp_serde::decode::Error),
+}
+
+/// The most a checksums request or response can hold, a response is around a hundred bytes per
+/// checksum
+pub const MAX_CHECKSUMS_PAYLOAD_LEN: u32 = 16 * 1024 * 1024;
+
 #[derive(Debug, Error)]
 pub enum HeaderError {
 	#[error("io error reading discriminator: {0}")]
@@ -82,6 +104,17 @@ impl Header {
 					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
 				))
 			}
+			4 => {
+				let mut uuid = [0u8; 16];
+				stream
+					.read_exact(&mut uuid)
+					.await
+					.map_err(SyncRequestError::LibraryIdIoError)?;
+
+				Ok(Self::Checksums(
+					Uuid::from_slice(&uuid).map_err(SyncRequestError::ErrorDecodingLibraryId)?,
+				))
+			}
 			d => Err(HeaderError::InvalidDiscriminator(d)),
 		}
 	}
@@ -104,10 +137,44 @@ impl Header {
 				bytes.extend_from_slice(uuid.as_bytes());
 				bytes
 			}
+			Self::Checksums(library_id) => {
+				let mut bytes = vec![4];
+				bytes.extend_from_slice(library_id.as_bytes());
+				bytes
+			}
 		}
 	}
 }
 
+/// Encodes the payload of a checksums request or response, prefixed by its length
+pub fn checksums_payload_to_bytes(
+	payload: &impl Serialize,
+) -> Result<Vec<u8>, ChecksumsRequestError> {
+	let buf = rmp_serde::to_vec_named(payload)?;
+	if buf.len() > MAX_CHECKSUMS_PAYLOAD_LEN as usize {
+		return Err(ChecksumsRequestError::PayloadTooLong(buf.len()));
+	}
+
+	let mut bytes = Vec::with_capacity(4 + buf.len());
+	bytes.extend_from_slice(&(buf.len() as u32).to_le_bytes());
+	bytes.extend(buf);
+	Ok(bytes)
+}
+
+pub async fn checksums_payload_from_stream<T: DeserializeOwned>(
+	stream: &mut (impl AsyncRead + Unpin),
+) -> Result<T, ChecksumsRequestError> {
+	let len = stream.read_u32_le().await?;
+	if len > MAX_CHECKSUMS_PAYLOAD_LEN {
+		return Err(ChecksumsRequestError::PayloadTooLong(len as usize));
+	}
+
+	let mut buf = vec![0; len as usize];
+	stream.read_exact(&mut buf).await?;
+
+	Ok(rmp_serde::from_slice(&buf)?)
+}
+
 #[derive(Debug, Error)]
 pub enum NodeInformationError {
 	#[error("io error decoding node information library pub_id: {0}")]
@@ -247,6 +314,23 @@ mod tests {
 		assert_eq!(original, info);
 	}
 
+	#[tokio::test]
+	async fn test_checksums_payload() {
+		let object_pub_ids = vec![vec![1u8; 16], vec![2u8; 16]];
+
+		let buf = checksums_payload_to_bytes(&object_pub_ids).unwrap();
+		let mut cursor = std::io::Cursor::new(buf);
+		let decoded: Vec<Vec<u8>> = checksums_payload_from_stream(&mut cursor).await.unwrap();
+		assert_eq!(decoded, object_pub_ids);
+
+		// the length is checked before anything is allocated for the payload
+		let mut cursor = std::io::Cursor::new(u32::MAX.to_le_bytes().to_vec());
+		assert!(matches!(
+			checksums_payload_from_stream::<Vec<Vec<u8>>>(&mut cursor).await,
+			Err(ChecksumsRequestError::PayloadTooLong(len)) if len == u32::MAX as usize
+		));
+	}
+
 	// TODO: Unit test it because binary protocols are error prone
 	// #[test]
 	// fn test_proto() {
@@ -1,6 +1,6 @@
 #![allow(clippy::unwrap_used, clippy::panic)] // TODO: Brendan remove this once you've got error handling here
 
-use crate::prisma::*;
+use crate::{object::validation::update_content_index, prisma::*};
 
 use std::{collections::HashMap, sync::Arc};
 
@@ -185,7 +185,8 @@ impl SyncManager {
 						.flat_map(|(k, v)| file_path::SetParam::deserialize(&k, v))
 						.collect();
 
-					db.file_path()
+					let file_path = db
+						.file_path()
 						.upsert(
 							file_path::pub_id::equals(id.pub_id.clone()),
 							file_path::create(id.pub_id, data.clone()),
@@ -193,11 +194,16 @@ impl SyncManager {
 						)
 						.exec()
 						.await?;
+
+					// the content index follows the checksums synced from peers too
+					update_content_index(db, file_path.id, file_path.integrity_checksum.as_deref())
+						.await?;
 				}
 				SharedOperationData::Update { field, value } => {
 					let data = vec![file_path::SetParam::deserialize(&field, value).unwrap()];
 
-					db.file_path()
+					let file_path = db
+						.file_path()
 						.upsert(
 							file_path::pub_id::equals(id.pub_id.clone()),
 							file_path::create(id.pub_id, data.clone()),
@@ -205,8 +211,23 @@ impl SyncManager {
 						)
 						.exec()
 						.await?;
+
+					if field == file_path::integrity_checksum::NAME {
+						update_content_index(
+							db,
+							file_path.id,
+							file_path.integrity_checksum.as_deref(),
+						)
+						.await?;
+					}
+				}
+				SharedOperationData::Delete => {
+					// the file path may have been deleted here already, or never synced
+					db.file_path()
+						.delete_many(vec![file_path::pub_id::equals(id.pub_id)])
+						.exec()
+						.await?;
 				}
-				_ => todo!(),
 			},
 			ModelSyncData::Location(id, shared_op) => match shared_op {
 				SharedOperationData::Create(data) => {
@@ -266,7 +287,27 @@ impl SyncManager {
 						.exec()
 						.await?;
 				}
-				_ => todo!(),
+				SharedOperationData::Delete => {
+					// like a merge does here, objects still holding files on this node are kept,
+					// and their assignments go with the ones deleted
+					let deleted = || {
+						vec![
+							object::pub_id::equals(id.pub_id.clone()),
+							object::file_paths::none(vec![]),
+						]
+					};
+
+					db._batch((
+						db.tag_on_object()
+							.delete_many(vec![tag_on_object::object::is(deleted())]),
+						db.label_on_object()
+							.delete_many(vec![label_on_object::object::is(deleted())]),
+						db.object_in_space()
+							.delete_many(vec![object_in_space::object::is(deleted())]),
+						db.object().delete_many(deleted()),
+					))
+					.await?;
+				}
 			},
 			ModelSyncData::Tag(id, shared_op) => match shared_op {
 				SharedOperationData::Create(data) => {
@@ -303,6 +344,33 @@ impl SyncManager {
 						.await?;
 				}
 			},
+			ModelSyncData::TagOnObject(tag_pub_id, object_pub_id, relation_op) => {
+				let tag_where = || tag::pub_id::equals(tag_pub_id.as_bytes().to_vec());
+				let object_where = || object::pub_id::equals(object_pub_id.as_bytes().to_vec());
+				let assignment = || {
+					vec![
+						tag_on_object::tag::is(vec![tag_where()]),
+						tag_on_object::object::is(vec![object_where()]),
+					]
+				};
+
+				match relation_op {
+					// tagging an object again is a no-op
+					RelationOperationData::Create => {
+						if db.tag_on_object().count(assignment()).exec().await? == 0 {
+							db.tag_on_object()
+								.create(tag_where(), object_where(), vec![])
+								.exec()
+								.await?;
+						}
+					}
+					// assignments have no fields of their own
+					RelationOperationData::Update { .. } => {}
+					RelationOperationData::Delete => {
+						db.tag_on_object().delete_many(assignment()).exec().await?;
+					}
+				}
+			}
 		}
 
 		if let CRDTOperationType::Shared(shared_op) = op.typ {
@@ -380,4 +448,33 @@ impl SyncManager {
 			},
 		}))
 	}
+	pub fn shared_delete<
+		TSyncId: SyncId<ModelTypes = TModel>,
+		TModel: SyncType<Marker = SharedSyncType>,
+	>(
+		&self,
+		id: TSyncId,
+	) -> CRDTOperation {
+		self.new_op(CRDTOperationType::Shared(SharedOperation {
+			model: TModel::MODEL.to_string(),
+			record_id: json!(id),
+			data: SharedOperationData::Delete,
+		}))
+	}
+	pub fn relation_create(&self, relation: &str, item: Uuid, group: Uuid) -> CRDTOperation {
+		self.new_op(CRDTOperationType::Relation(RelationOperation {
+			relation_item: item,
+			relation_group: group,
+			relation: relation.to_string(),
+			data: RelationOperationData::Create,
+		}))
+	}
+	pub fn relation_delete(&self, relation: &str, item: Uuid, group: Uuid) -> CRDTOperation {
+		self.new_op(CRDTOperationType::Relation(RelationOperation {
+			relation_item: item,
+			relation_group: group,
+			relation: relation.to_string(),
+			data: RelationOperationData::Delete,
+		}))
+	}
 }
@@ -123,6 +123,9 @@ impl InitConfig {
 								description: lib.description,
 								identity: Identity::new().to_bytes(),
 								node_id: node_pub_id,
+								default_checksum_algorithm: Default::default(),
+								validate_on_access: false,
+								checksum_on_change: false,
 							},
 							node_cfg.clone(),
 						)
@@ -171,12 +174,13 @@ impl InitConfig {
 					path: loc.path.clone().into(),
 					dry_run: false,
 					indexer_rules_ids: Vec::new(),
+					validate_after_indexing: None,
 				}
 				.create(&library)
 				.await?;
 				match location {
 					Some(location) => {
-						scan_location(&library, location).await?;
+						scan_location(&library, location, None).await?;
 					}
 					None => {
 						warn!(
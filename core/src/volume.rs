@@ -6,7 +6,7 @@ use crate::{
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 use specta::Type;
-use std::{fmt::Display, process::Command};
+use std::{fmt::Display, path::Path, process::Command};
 use sysinfo::{DiskExt, System, SystemExt};
 use thiserror::Error;
 
@@ -150,6 +150,51 @@ pub fn get_volumes() -> Result<Vec<Volume>, VolumeError> {
 		.collect::<Result<Vec<_>, _>>()
 }
 
+/// File systems where reads go over the network or through a userspace (FUSE) driver
+const REMOTE_FILE_SYSTEMS: &[&str] = &[
+	"nfs", "nfs4", "cifs", "smbfs", "smb2", "afpfs", "webdav", "davfs", "9p", "sshfs",
+];
+
+fn is_remote_file_system(file_system: &str) -> bool {
+	let file_system = file_system.to_lowercase();
+	file_system.starts_with("fuse") || REMOTE_FILE_SYSTEMS.contains(&file_system.as_str())
+}
+
+/// Checks if a path is on a network or FUSE mount, where reads are slow and may stall
+pub async fn is_remote_path(path: impl AsRef<Path>) -> bool {
+	let path = path.as_ref().to_path_buf();
+
+	tokio::task::spawn_blocking(move || {
+		get_volumes()
+			.unwrap_or_default()
+			.into_iter()
+			.filter(|volume| path.starts_with(&volume.mount_point))
+			// the most specific mount point is the one the path lives in
+			.max_by_key(|volume| volume.mount_point.len())
+			.and_then(|volume| volume.file_system)
+			.map_or(false, |file_system| is_remote_file_system(&file_system))
+	})
+	.await
+	.unwrap_or(false)
+}
+
+/// Free space left on the volume of a path, `None` if it isn't on any volume we know of
+pub async fn available_space(path: impl AsRef<Path>) -> Option<u64> {
+	let path = path.as_ref().to_path_buf();
+
+	tokio::task::spawn_blocking(move || {
+		get_volumes()
+			.unwrap_or_default()
+			.into_iter()
+			.filter(|volume| path.starts_with(&volume.mount_point))
+			.max_by_key(|volume| volume.mount_point.len())
+			.map(|volume| volume.available_capacity)
+	})
+	.await
+	.ok()
+	.flatten()
+}
+
 // #[test]
 // fn test_get_volumes() {
 //   let volumes = get_volumes()?;
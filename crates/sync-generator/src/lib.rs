@@ -54,10 +54,22 @@ impl<'a> ModelSyncType<'a> {
 			.flat_map(|name| model.fields().find(|f| f.name() == name))
 			.collect();
 
+		// the relation fields pointing at the item and the group of a relation model
+		let relation_field = |name| {
+			attr.field(name)
+				.and_then(AttributeFieldValue::as_single)
+				.and_then(|name| model.fields().find(|f| f.name() == name))
+				.map(|field| vec![field])
+		};
+
 		Some(match attr.name {
 			"local" => Self::Local { id },
 			// "owned" => Self::Owned { id },
 			"shared" => Self::Shared { id },
+			"relation" => Self::Relation {
+				item: relation_field("item")?,
+				group: relation_field("group")?,
+			},
 			_ => return None,
 		})
 	}
@@ -254,8 +266,14 @@ impl PrismaGenerator for SDSyncGenerator {
 							_ => return None,
 						};
 
-						let variant = quote! {
-							#model_name_pascal(#model_name_snake::SyncId, sd_sync::#data_type)
+						let variant = match a {
+							// relations are keyed by the pub_ids of their item and group
+							ModelSyncType::Relation { .. } => quote! {
+								#model_name_pascal(::uuid::Uuid, ::uuid::Uuid, sd_sync::#data_type)
+							},
+							_ => quote! {
+								#model_name_pascal(#model_name_snake::SyncId, sd_sync::#data_type)
+							},
 						};
 
 						let op_type_enum = quote!(sd_sync::CRDTOperationType);
@@ -275,12 +293,15 @@ impl PrismaGenerator for SDSyncGenerator {
 										Self::#model_name_pascal(serde_json::from_value(op.record_id).ok()?, op.data)
 								}
 							}
-							// ModelSyncType::Relation { .. } => {
-							// 	quote! {
-							// 		(#model_name_str, sd_sync::CRDTOperation::Relation(op)) =>
-							// 			Self::#model_name_pascal()
-							// 	}
-							// }
+							ModelSyncType::Relation { .. } => {
+								let cond =
+									quote!(if op.relation == prisma::#model_name_snake::NAME);
+
+								quote! {
+									#op_type_enum::Relation(op) #cond =>
+										Self::#model_name_pascal(op.relation_item, op.relation_group, op.data)
+								}
+							}
 							_ => return None,
 						};
 
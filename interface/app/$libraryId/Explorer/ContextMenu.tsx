@@ -1,4 +1,4 @@
-import { Clipboard, FileX, Image, Plus, Repeat, Share, ShieldCheck } from 'phosphor-react';
+import { Clipboard, FileX, Heart, Image, Plus, Repeat, Share, ShieldCheck } from 'phosphor-react';
 import { PropsWithChildren, useMemo } from 'react';
 import { useLibraryMutation } from '@sd/client';
 import { ContextMenu as CM, ModifierKeys } from '@sd/ui';
@@ -147,11 +147,33 @@ export default (props: PropsWithChildren) => {
 				<CM.Item
 					onClick={() =>
 						store.locationId &&
-						objectValidator.mutate({ id: store.locationId, path: params.path ?? '' })
+						objectValidator.mutate({
+							id: store.locationId,
+							path: params.path ?? '',
+							favorites_only: false,
+							file_path_pub_ids: null,
+							verify_against: null,
+							remote_checksums_url: null
+						})
 					}
 					label="Generate Checksums"
 					icon={ShieldCheck}
 				/>
+				<CM.Item
+					onClick={() =>
+						store.locationId &&
+						objectValidator.mutate({
+							id: store.locationId,
+							path: params.path ?? '',
+							favorites_only: true,
+							file_path_pub_ids: null,
+							verify_against: null,
+							remote_checksums_url: null
+						})
+					}
+					label="Verify Favorites"
+					icon={Heart}
+				/>
 			</CM.SubMenu>
 		</CM.Root>
 	);
@@ -6,6 +6,7 @@ import {
 	Plus,
 	Scissors,
 	Share,
+	ShieldCheck,
 	TagSimple,
 	Trash,
 	TrashSimple
@@ -52,7 +53,9 @@ export default ({ data }: Props) => {
 	const copyFiles = useLibraryMutation('files.copyFiles');
 
 	const removeFromRecents = useLibraryMutation('files.removeAccessTime');
+	const setExcludedFromValidation = useLibraryMutation('files.setExcludedFromValidation');
 	const generateThumbnails = useLibraryMutation('jobs.generateThumbsForLocation');
+	const objectValidator = useLibraryMutation('jobs.objectValidator');
 	const fullRescan = useLibraryMutation('locations.fullRescan');
 
 	if (!data) return null;
@@ -93,6 +96,22 @@ export default ({ data }: Props) => {
 				/>
 			)}
 
+			{data.type == 'Path' && !data.item.is_dir && (
+				<ContextMenu.Item
+					label={
+						data.item.exclude_from_validation
+							? 'Include in integrity checks'
+							: 'Exclude from integrity checks'
+					}
+					onClick={() =>
+						setExcludedFromValidation.mutate({
+							file_path_ids: [data.item.id],
+							excluded: !data.item.exclude_from_validation
+						})
+					}
+				/>
+			)}
+
 			<ContextMenu.Item
 				label="Cut"
 				keybind={keybind([ModifierKeys.Control], ['X'])}
@@ -260,6 +279,22 @@ export default ({ data }: Props) => {
 					icon={Package}
 					disabled
 				/>
+				{data.type == 'Path' && !data.item.is_dir && (
+					<ContextMenu.Item
+						onClick={() => {
+							objectValidator.mutate({
+								id: getExplorerStore().locationId!,
+								path: '',
+								favorites_only: false,
+								file_path_pub_ids: [data.item.pub_id],
+								verify_against: null,
+								remote_checksums_url: null
+							});
+						}}
+						label="Verify Integrity"
+						icon={ShieldCheck}
+					/>
+				)}
 				<ContextMenu.Item
 					variant="danger"
 					label="Secure delete"
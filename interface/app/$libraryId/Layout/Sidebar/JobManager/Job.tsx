@@ -98,7 +98,11 @@ function Job({ job, className, isChild }: JobProps) {
 		>
 			{isRunning && (
 				<div className="my-1 ml-1.5 w-[335px]">
-					<ProgressBar value={realtimeUpdate?.completed_task_count || 0} total={realtimeUpdate?.task_count || 0} />
+					{realtimeUpdate?.byte_progress != null ? (
+						<ProgressBar value={realtimeUpdate.byte_progress} total={1} />
+					) : (
+						<ProgressBar value={realtimeUpdate?.completed_task_count || 0} total={realtimeUpdate?.task_count || 0} />
+					)}
 				</div>
 			)}
 		</JobContainer>
@@ -1,6 +1,6 @@
 import { MaybeUndefined, useBridgeMutation, useLibraryContext } from '@sd/client';
 import { Button, Input, Switch, Tooltip, dialogManager } from '@sd/ui';
-import { useZodForm, z } from '@sd/ui/src/forms';
+import { Switch as FormSwitch, useZodForm, z } from '@sd/ui/src/forms';
 import { useDebouncedFormWatch } from '~/hooks';
 import { Heading } from '../Layout';
 import Setting from '../Setting';
@@ -9,7 +9,9 @@ import DeleteLibraryDialog from '../node/libraries/DeleteDialog';
 const schema = z.object({
 	id: z.string(),
 	name: z.string().min(1),
-	description: z.string().nullable()
+	description: z.string().nullable(),
+	validate_on_access: z.boolean(),
+	checksum_on_change: z.boolean()
 });
 
 // TODO: With some extra upstream Specta work this should be able to be removed
@@ -33,7 +35,10 @@ export const Component = () => {
 		editLibrary.mutate({
 			id: library.uuid,
 			name: value.name ?? null,
-			description: toMaybeUndefined(value.description)
+			description: toMaybeUndefined(value.description),
+			default_checksum_algorithm: null,
+			validate_on_access: value.validate_on_access ?? null,
+			checksum_on_change: value.checksum_on_change ?? null
 		})
 	);
 
@@ -61,6 +66,26 @@ export const Component = () => {
 				</div>
 			</div>
 
+			<Setting
+				mini
+				title="Validate On Access"
+				description="Verify the checksum of files in the background when they're opened or previewed, flagging the ones that got corrupted."
+			>
+				<div className="ml-3 flex items-center">
+					<FormSwitch {...form.register('validate_on_access')} size="md" />
+				</div>
+			</Setting>
+
+			<Setting
+				mini
+				title="Checksum On Change"
+				description="Compute the checksum of files in the background when they're created or modified, once they stop changing, keeping checksums current without running a validation."
+			>
+				<div className="ml-3 flex items-center">
+					<FormSwitch {...form.register('checksum_on_change')} size="md" />
+				</div>
+			</Setting>
+
 			<Setting
 				mini
 				title="Encrypt Library"
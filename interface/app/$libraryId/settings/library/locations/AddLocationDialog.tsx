@@ -9,8 +9,8 @@ import {
 	useLibraryMutation,
 	useLibraryQuery
 } from '@sd/client';
-import { Dialog, UseDialogProps, useDialog } from '@sd/ui';
-import { ErrorMessage, Input, useZodForm, z } from '@sd/ui/src/forms';
+import { Dialog, Label, UseDialogProps, useDialog } from '@sd/ui';
+import { ErrorMessage, Input, Switch, useZodForm, z } from '@sd/ui/src/forms';
 import { showAlertDialog } from '~/components';
 import { useCallbackToWatchForm } from '~/hooks';
 import { Platform, usePlatform } from '~/util/Platform';
@@ -33,7 +33,8 @@ const isRemoteErrorFormMessage = (message: unknown): message is RemoteErrorFormM
 const schema = z.object({
 	path: z.string().min(1),
 	method: z.enum(Object.keys(REMOTE_ERROR_FORM_MESSAGE) as UnionToTuple<RemoteErrorFormMessage>),
-	indexerRulesIds: z.array(z.number())
+	indexerRulesIds: z.array(z.number()),
+	validateAfterIndexing: z.boolean()
 });
 
 type SchemaType = z.infer<typeof schema>;
@@ -74,25 +75,41 @@ export const AddLocationDialog = ({
 		[listIndexerRules.data]
 	);
 
-	const form = useZodForm({ schema, defaultValues: { path, method, indexerRulesIds } });
+	const form = useZodForm({
+		schema,
+		defaultValues: { path, method, indexerRulesIds, validateAfterIndexing: false }
+	});
 
 	useEffect(() => {
 		// Update form values when default value changes and the user hasn't made any changes
 		if (!form.formState.isDirty)
 			form.reset(
-				{ path, method: form.getValues().method, indexerRulesIds },
+				{
+					path,
+					method: form.getValues().method,
+					indexerRulesIds,
+					validateAfterIndexing: form.getValues().validateAfterIndexing
+				},
 				{ keepErrors: true }
 			);
 	}, [form, path, indexerRulesIds]);
 
 	const addLocation = useCallback(
-		async ({ path, method, indexerRulesIds }: SchemaType, dryRun = false) => {
+		async (
+			{ path, method, indexerRulesIds, validateAfterIndexing }: SchemaType,
+			dryRun = false
+		) => {
+			const validate_after_indexing = validateAfterIndexing
+				? { algorithm: null, max_read_rate: null }
+				: null;
+
 			switch (method) {
 				case 'CREATE':
 					await createLocation.mutateAsync({
 						path,
 						dry_run: dryRun,
-						indexer_rules_ids: indexerRulesIds
+						indexer_rules_ids: indexerRulesIds,
+						validate_after_indexing
 					});
 					break;
 				case 'NEED_RELINK':
@@ -111,7 +128,8 @@ export const AddLocationDialog = ({
 					await addLocationToLibrary.mutateAsync({
 						path,
 						dry_run: dryRun,
-						indexer_rules_ids: indexerRulesIds
+						indexer_rules_ids: indexerRulesIds,
+						validate_after_indexing
 					});
 					break;
 				default:
@@ -253,6 +271,10 @@ export const AddLocationDialog = ({
 							)}
 							control={form.control}
 						/>
+						<label className="mt-3 flex flex-row">
+							<Label className="grow">Compute file checksums once indexed</Label>
+							<Switch {...form.register('validateAfterIndexing')} size="sm" />
+						</label>
 					</div>
 				)}
 			</div>
@@ -5,7 +5,11 @@ export type Procedures = {
     queries: 
         { key: "buildInfo", input: never, result: BuildInfo } | 
         { key: "categories.list", input: LibraryArgs<null>, result: { [key in Category]: number } } | 
-        { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; file_paths: FilePath[]; media_data: MediaData | null } | null } | 
+        { key: "files.auditStoredChecksums", input: LibraryArgs<number>, result: StoredChecksumsAudit } | 
+        { key: "files.get", input: LibraryArgs<GetArgs>, result: { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; last_verified_at: string | null; last_verified_result: number | null; file_paths: FilePath[]; media_data: MediaData | null } | null } | 
+        { key: "files.getChecksumStatus", input: LibraryArgs<number[]>, result: { [key: number]: ChecksumStatus } } | 
+        { key: "files.listUnvalidated", input: LibraryArgs<ListUnvalidatedArgs>, result: UnvalidatedFiles } | 
+        { key: "files.verifyArchive", input: LibraryArgs<VerifyArchiveArgs>, result: ArchiveVerification } | 
         { key: "invalidation.test-invalidate", input: never, result: number } | 
         { key: "jobs.isActive", input: LibraryArgs<null>, result: boolean } | 
         { key: "jobs.reports", input: LibraryArgs<null>, result: JobGroups } | 
@@ -16,9 +20,10 @@ export type Procedures = {
         { key: "locations.indexer_rules.get", input: LibraryArgs<number>, result: IndexerRule } | 
         { key: "locations.indexer_rules.list", input: LibraryArgs<null>, result: IndexerRule[] } | 
         { key: "locations.indexer_rules.listForLocation", input: LibraryArgs<number>, result: IndexerRule[] } | 
-        { key: "locations.list", input: LibraryArgs<null>, result: { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; date_created: string | null; node_id: number | null; node: Node | null }[] } | 
+        { key: "locations.list", input: LibraryArgs<null>, result: { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; date_created: string | null; aggregate_checksum: string | null; aggregate_checksum_algorithm: string | null; node_id: number | null; node: Node | null }[] } | 
         { key: "nodeState", input: never, result: NodeState } | 
         { key: "nodes.listLocations", input: LibraryArgs<string | null>, result: ExplorerItem[] } | 
+        { key: "p2p.compareChecksums", input: LibraryArgs<CompareChecksumsArgs>, result: PeerChecksumReport } | 
         { key: "search.objects", input: LibraryArgs<ObjectSearchArgs>, result: SearchData<ExplorerItem> } | 
         { key: "search.paths", input: LibraryArgs<FilePathSearchArgs>, result: SearchData<ExplorerItem> } | 
         { key: "sync.messages", input: LibraryArgs<null>, result: CRDTOperation[] } | 
@@ -27,6 +32,7 @@ export type Procedures = {
         { key: "tags.list", input: LibraryArgs<null>, result: Tag[] } | 
         { key: "volumes.list", input: never, result: Volume[] },
     mutations: 
+        { key: "files.acknowledgeChecksumMismatch", input: LibraryArgs<AcknowledgeChecksumMismatchArgs>, result: null } | 
         { key: "files.copyFiles", input: LibraryArgs<FileCopierJobInit>, result: null } | 
         { key: "files.cutFiles", input: LibraryArgs<FileCutterJobInit>, result: null } | 
         { key: "files.deleteFiles", input: LibraryArgs<FileDeleterJobInit>, result: null } | 
@@ -34,10 +40,12 @@ export type Procedures = {
         { key: "files.eraseFiles", input: LibraryArgs<FileEraserJobInit>, result: null } | 
         { key: "files.removeAccessTime", input: LibraryArgs<number[]>, result: null } | 
         { key: "files.renameFile", input: LibraryArgs<RenameFileArgs>, result: null } | 
+        { key: "files.setExcludedFromValidation", input: LibraryArgs<SetExcludedFromValidationArgs>, result: null } | 
         { key: "files.setFavorite", input: LibraryArgs<SetFavoriteArgs>, result: null } | 
         { key: "files.setNote", input: LibraryArgs<SetNoteArgs>, result: null } | 
         { key: "files.updateAccessTime", input: LibraryArgs<number>, result: null } | 
         { key: "invalidation.test-invalidate-mutation", input: LibraryArgs<null>, result: null } | 
+        { key: "jobs.checksumCoverage", input: LibraryArgs<null>, result: null } | 
         { key: "jobs.clear", input: LibraryArgs<string>, result: null } | 
         { key: "jobs.clearAll", input: LibraryArgs<null>, result: null } | 
         { key: "jobs.generateThumbsForLocation", input: LibraryArgs<GenerateThumbsForLocationArgs>, result: null } | 
@@ -45,6 +53,8 @@ export type Procedures = {
         { key: "jobs.objectValidator", input: LibraryArgs<ObjectValidatorArgs>, result: null } | 
         { key: "jobs.pause", input: LibraryArgs<string>, result: null } | 
         { key: "jobs.resume", input: LibraryArgs<string>, result: null } | 
+        { key: "jobs.setLowPower", input: LibraryArgs<boolean>, result: null } | 
+        { key: "jobs.validateThumbnails", input: LibraryArgs<null>, result: null } | 
         { key: "library.create", input: CreateLibraryArgs, result: LibraryConfigWrapped } | 
         { key: "library.delete", input: string, result: null } | 
         { key: "library.edit", input: EditLibraryArgs, result: null } | 
@@ -65,6 +75,7 @@ export type Procedures = {
         { key: "tags.delete", input: LibraryArgs<number>, result: null } | 
         { key: "tags.update", input: LibraryArgs<TagUpdateArgs>, result: null },
     subscriptions: 
+        { key: "files.streamChecksum", input: LibraryArgs<number>, result: ChecksumStreamEvent } | 
         { key: "invalidation.listen", input: never, result: InvalidateOperationEvent[] } | 
         { key: "jobs.newThumbnail", input: LibraryArgs<null>, result: string[] } | 
         { key: "jobs.progress", input: LibraryArgs<string>, result: JobProgressEvent } | 
@@ -75,6 +86,18 @@ export type Procedures = {
         { key: "sync.newMessage", input: LibraryArgs<null>, result: CRDTOperation }
 };
 
+export type AcknowledgeChecksumMismatchArgs = { file_path_id: number; checksum: string }
+
+/**
+ * How an entry of an archive compares with the file the library has at its path
+ */
+export type ArchiveEntryOutcome = "Matched" | { Mismatched: { expected: string; actual: string } } | "NoStoredChecksum" | "MissingFromArchive" | { HardLink: { target: string } }
+
+/**
+ * Outcome of each entry, keyed by its path relative to the location
+ */
+export type ArchiveVerification = { entries: { [key: string]: ArchiveEntryOutcome } }
+
 export type BuildInfo = { version: string; commit: string }
 
 export type CRDTOperation = { node: string; timestamp: number; id: string; typ: CRDTOperationType }
@@ -88,11 +111,36 @@ export type Category = "Recents" | "Favorites" | "Photos" | "Videos" | "Movies"
 
 export type ChangeNodeNameArgs = { name: string | null }
 
+/**
+ * Algorithm used to compute a file's `integrity_checksum`
+ */
+export type ChecksumAlgorithm = "Blake3" | "Sha256"
+
+/**
+ * What's wrong with a stored `integrity_checksum`
+ */
+export type ChecksumProblem = "UnknownAlgorithm" | "InvalidEncoding" | "WrongLength" | "AlgorithmWithoutChecksum"
+
+export type ChecksumProblemCounts = { unknown_algorithm: number; invalid_encoding: number; wrong_length: number; algorithm_without_checksum: number }
+
+/**
+ * Whether a file's checksum can still be trusted, for badges in the UI
+ */
+export type ChecksumStatus = "Missing" | "Stale" | "Current"
+
+/**
+ * Progress of checksumming a single file, bytes are `f64` as rspc doesn't support
+ * bigints, which is exact for files of up to 8 PiB
+ */
+export type ChecksumStreamEvent = { type: "Progress"; bytes_done: number; total: number } | { type: "Done"; checksum: string; algorithm: ChecksumAlgorithm } | { type: "Failed"; reason: string }
+
+export type CompareChecksumsArgs = { peer_id: PeerId; location_id: number }
+
 export type CreateLibraryArgs = { name: string }
 
 export type DiskType = "SSD" | "HDD" | "Removable"
 
-export type EditLibraryArgs = { id: string; name: string | null; description: MaybeUndefined<string> }
+export type EditLibraryArgs = { id: string; name: string | null; description: MaybeUndefined<string>; default_checksum_algorithm: ChecksumAlgorithm | null; validate_on_access: boolean | null; checksum_on_change: boolean | null }
 
 export type ExplorerItem = { type: "Path"; has_local_thumbnail: boolean; thumbnail_key: string[] | null; item: FilePathWithObject } | { type: "Object"; has_local_thumbnail: boolean; thumbnail_key: string[] | null; item: ObjectWithFilePaths } | { type: "Location"; has_local_thumbnail: boolean; thumbnail_key: string[] | null; item: Location }
 
@@ -104,7 +152,7 @@ export type FileDeleterJobInit = { location_id: number; file_path_ids: number[]
 
 export type FileEraserJobInit = { location_id: number; file_path_ids: number[]; passes: string }
 
-export type FilePath = { id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; inode: number[] | null; device: number[] | null; object_id: number | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null }
+export type FilePath = { id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; content_checksum: string | null; integrity_checksum_source: string | null; integrity_checksum_algorithm: string | null; content_type: string | null; exclude_from_validation: boolean | null; perceptual_hash: string | null; range_checksum: string | null; acknowledged_checksum: string | null; acknowledged_by: string | null; tree_checksum: string | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; inode: number[] | null; device: number[] | null; object_id: number | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null; date_checksummed: string | null; date_acknowledged: string | null }
 
 export type FilePathFilterArgs = { locationId?: number | null; search?: string | null; extension?: string | null; createdAt?: OptionalRange<string>; path?: string | null; object?: ObjectFilterArgs | null }
 
@@ -112,7 +160,7 @@ export type FilePathSearchArgs = { take?: number | null; order?: FilePathSearchO
 
 export type FilePathSearchOrdering = { name: SortOrder } | { sizeInBytes: SortOrder } | { dateCreated: SortOrder } | { dateModified: SortOrder } | { dateIndexed: SortOrder } | { object: ObjectSearchOrdering }
 
-export type FilePathWithObject = { id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; inode: number[] | null; device: number[] | null; object_id: number | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null; object: Object | null }
+export type FilePathWithObject = { id: number; pub_id: number[]; is_dir: boolean | null; cas_id: string | null; integrity_checksum: string | null; content_checksum: string | null; integrity_checksum_source: string | null; integrity_checksum_algorithm: string | null; content_type: string | null; exclude_from_validation: boolean | null; perceptual_hash: string | null; range_checksum: string | null; acknowledged_checksum: string | null; acknowledged_by: string | null; tree_checksum: string | null; location_id: number | null; materialized_path: string | null; name: string | null; extension: string | null; size_in_bytes: string | null; size_in_bytes_bytes: number[] | null; inode: number[] | null; device: number[] | null; object_id: number | null; key_id: number | null; date_created: string | null; date_modified: string | null; date_indexed: string | null; date_checksummed: string | null; date_acknowledged: string | null; object: Object | null }
 
 export type FromPattern = { pattern: string; replace_all: boolean }
 
@@ -136,13 +184,18 @@ export type IndexerRule = { id: number; pub_id: number[]; name: string | null; d
  */
 export type IndexerRuleCreateArgs = { name: string; dry_run: boolean; rules: ([RuleKind, string[]])[] }
 
+/**
+ * How the files of a new location are validated right after its initial indexing
+ */
+export type InitialValidation = { algorithm: ChecksumAlgorithm | null; max_read_rate: number | null }
+
 export type InvalidateOperationEvent = { key: string; arg: any; result: any | null }
 
 export type JobGroup = { id: string; action: string; status: JobStatus; created_at: string; jobs: JobReport[] }
 
 export type JobGroups = { groups: JobGroup[]; index: { [key: string]: number } }
 
-export type JobProgressEvent = { id: string; task_count: number; completed_task_count: number; message: string; estimated_completion: string }
+export type JobProgressEvent = { id: string; task_count: number; completed_task_count: number; byte_progress: number | null; message: string; estimated_completion: string }
 
 export type JobReport = { id: string; name: string; action: string | null; data: number[] | null; metadata: any | null; is_background: boolean; errors_text: string[]; created_at: string | null; started_at: string | null; completed_at: string | null; parent_id: string | null; status: JobStatus; task_count: number; completed_task_count: number; message: string; estimated_completion: string }
 
@@ -157,14 +210,16 @@ export type LibraryConfigWrapped = { uuid: string; config: SanitisedLibraryConfi
 
 export type LightScanArgs = { location_id: number; sub_path: string }
 
-export type Location = { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; date_created: string | null; node_id: number | null }
+export type ListUnvalidatedArgs = { location_id: number; sub_path: string | null; page: UnvalidatedPage }
+
+export type Location = { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; date_created: string | null; aggregate_checksum: string | null; aggregate_checksum_algorithm: string | null; node_id: number | null }
 
 /**
  * `LocationCreateArgs` is the argument received from the client using `rspc` to create a new location.
  * It has the actual path and a vector of indexer rules ids, to create many-to-many relationships
  * between the location and indexer rules.
  */
-export type LocationCreateArgs = { path: string; dry_run: boolean; indexer_rules_ids: number[] }
+export type LocationCreateArgs = { path: string; dry_run: boolean; indexer_rules_ids: number[]; validate_after_indexing: InitialValidation | null }
 
 /**
  * `LocationUpdateArgs` is the argument received from the client using `rspc` to update a location.
@@ -176,7 +231,9 @@ export type LocationCreateArgs = { path: string; dry_run: boolean; indexer_rules
  */
 export type LocationUpdateArgs = { id: number; name: string | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; indexer_rules_ids: number[] }
 
-export type LocationWithIndexerRules = { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; date_created: string | null; node_id: number | null; indexer_rules: { indexer_rule: IndexerRule }[] }
+export type LocationWithIndexerRules = { id: number; pub_id: number[]; name: string | null; path: string | null; total_capacity: number | null; available_capacity: number | null; is_archived: boolean | null; generate_preview_media: boolean | null; sync_preview_media: boolean | null; hidden: boolean | null; date_created: string | null; aggregate_checksum: string | null; aggregate_checksum_algorithm: string | null; node_id: number | null; indexer_rules: { indexer_rule: IndexerRule }[] }
+
+export type MalformedChecksum = { file_path_id: number; checksum: string | null; algorithm: string | null; problem: ChecksumProblem }
 
 export type MaybeNot<T> = T | { not: T }
 
@@ -188,19 +245,19 @@ export type Node = { id: number; pub_id: number[]; name: string; platform: numbe
 
 export type NodeState = ({ id: string; name: string; p2p_port: number | null; p2p_email: string | null; p2p_img_url: string | null }) & { data_path: string }
 
-export type Object = { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null }
+export type Object = { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; last_verified_at: string | null; last_verified_result: number | null }
 
-export type ObjectFilterArgs = { favorite?: boolean | null; hidden?: ObjectHiddenFilter; dateAccessed?: MaybeNot<string | null> | null; kind?: number[]; tags?: number[]; category?: Category | null }
+export type ObjectFilterArgs = { favorite?: boolean | null; hidden?: ObjectHiddenFilter; dateAccessed?: MaybeNot<string | null> | null; kind?: number[]; tags?: number[]; category?: Category | null; lastVerifiedResult?: VerificationResult | null }
 
 export type ObjectHiddenFilter = "exclude" | "include"
 
 export type ObjectSearchArgs = { take?: number | null; order?: ObjectSearchOrdering | null; cursor?: number[] | null; filter?: ObjectFilterArgs }
 
-export type ObjectSearchOrdering = { dateAccessed: SortOrder }
+export type ObjectSearchOrdering = { dateAccessed: SortOrder } | { lastVerifiedAt: SortOrder }
 
-export type ObjectValidatorArgs = { id: number; path: string }
+export type ObjectValidatorArgs = { id: number; path: string; favorites_only: boolean; file_path_pub_ids: number[][] | null; verify_against: SignedManifest | null; remote_checksums_url: string | null }
 
-export type ObjectWithFilePaths = { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; file_paths: FilePath[] }
+export type ObjectWithFilePaths = { id: number; pub_id: number[]; kind: number | null; key_id: number | null; hidden: boolean | null; favorite: boolean | null; important: boolean | null; note: string | null; date_created: string | null; date_accessed: string | null; last_verified_at: string | null; last_verified_result: number | null; file_paths: FilePath[] }
 
 /**
  * Represents the operating system which the remote peer is running.
@@ -215,6 +272,17 @@ export type OptionalRange<T> = { from: T | null; to: T | null }
  */
 export type P2PEvent = { type: "DiscoveredPeer"; peer_id: PeerId; metadata: PeerMetadata } | { type: "SpacedropRequest"; id: string; peer_id: PeerId; name: string }
 
+/**
+ * Comparison of a location's checksums with a peer's.
+ * Counts are `u32` as rspc doesn't support bigints.
+ */
+export type PeerChecksumReport = { location_id: number; compared: number; matched: number; divergences: { [key: string]: PeerDivergence }; unavailable: string | null }
+
+/**
+ * How the peer's checksums of a file's object disagree with the local one
+ */
+export type PeerDivergence = { type: "Differs"; local: string; remote: string } | { type: "OtherAlgorithm"; local: ChecksumAlgorithm; remote: ChecksumAlgorithm } | { type: "MissingOnPeer" }
+
 export type PeerId = string
 
 export type PeerMetadata = { name: string; operating_system: OperatingSystem | null; version: string | null; email: string | null; img_url: string | null }
@@ -233,12 +301,14 @@ export type RenameOne = { from_file_path_id: number; to: string }
 
 export type RuleKind = "AcceptFilesByGlob" | "RejectFilesByGlob" | "AcceptIfChildrenDirectoriesArePresent" | "RejectIfChildrenDirectoriesArePresent"
 
-export type SanitisedLibraryConfig = { name: string; description: string | null; node_id: string }
+export type SanitisedLibraryConfig = { name: string; description: string | null; node_id: string; default_checksum_algorithm: ChecksumAlgorithm; validate_on_access: boolean; checksum_on_change: boolean }
 
 export type SanitisedNodeConfig = { id: string; name: string; p2p_port: number | null; p2p_email: string | null; p2p_img_url: string | null }
 
 export type SearchData<T> = { cursor: number[] | null; items: T[] }
 
+export type SetExcludedFromValidationArgs = { file_path_ids: number[]; excluded: boolean }
+
 export type SetFavoriteArgs = { id: number; favorite: boolean }
 
 export type SetNoteArgs = { id: number; note: string | null }
@@ -247,12 +317,16 @@ export type SharedOperation = { record_id: any; model: string; data: SharedOpera
 
 export type SharedOperationData = { c: { [key: string]: any } } | { u: { field: string; value: any } } | "d"
 
+export type SignedManifest = { path: string; signature_path: string; public_key: string; algorithm: ChecksumAlgorithm; chunks_path: string | null }
+
 export type SortOrder = "Asc" | "Desc"
 
 export type SpacedropArgs = { peer_id: PeerId; file_path: string[] }
 
 export type Statistics = { id: number; date_captured: string; total_object_count: number; library_db_size: string; total_bytes_used: string; total_bytes_capacity: string; total_unique_bytes: string; total_bytes_free: string; preview_media_bytes: string }
 
+export type StoredChecksumsAudit = { malformed: MalformedChecksum[]; counts: ChecksumProblemCounts }
+
 export type Tag = { id: number; pub_id: number[]; name: string | null; color: string | null; redundancy_goal: number | null; date_created: string | null; date_modified: string | null }
 
 export type TagAssignArgs = { object_ids: number[]; tag_id: number; unassign: boolean }
@@ -261,4 +335,19 @@ export type TagCreateArgs = { name: string; color: string }
 
 export type TagUpdateArgs = { id: number; name: string | null; color: string | null }
 
+export type UnvalidatedFiles = { file_paths: FilePath[]; total: number }
+
+/**
+ * A page of files, picking up after the last one of the previous page so pages stay cheap deep
+ * into huge locations
+ */
+export type UnvalidatedPage = { after: number | null; size: number }
+
+/**
+ * How the last validation of an object's files went, stored as `object.last_verified_result`
+ */
+export type VerificationResult = "Healthy" | "Corrupted"
+
+export type VerifyArchiveArgs = { location_id: number; archive_path: string; root: string | null }
+
 export type Volume = { name: string; mount_point: string; total_capacity: string; available_capacity: string; is_removable: boolean; disk_type: DiskType | null; file_system: string | null; is_root_filesystem: boolean }